@@ -0,0 +1,11 @@
+-- Dead-letter queue for outbox tasks that exhausted their attempts
+CREATE TABLE IF NOT EXISTS failed_outbox (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    outbox_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    ref_id INTEGER NOT NULL,
+    attempt INTEGER NOT NULL,
+    last_error TEXT,
+    failed_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,2 @@
+-- Permalink to the original Telegram message (public chats only)
+ALTER TABLE resources ADD COLUMN source_url TEXT;
@@ -0,0 +1,2 @@
+-- Most recent failure reason for a task in backoff
+ALTER TABLE outbox ADD COLUMN last_error TEXT;
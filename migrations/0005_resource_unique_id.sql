@@ -0,0 +1,6 @@
+-- Telegram identity of a resource (file unique_id, or chat+message key for text)
+-- so re-delivered updates are recognised instead of inserted twice. The same
+-- file forwarded again in a later message is a new resource.
+ALTER TABLE resources ADD COLUMN unique_id TEXT;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_resources_user_unique_id
+    ON resources(user_id, tg_message_id, unique_id) WHERE unique_id IS NOT NULL;
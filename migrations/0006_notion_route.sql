@@ -0,0 +1,3 @@
+-- Chat id of the `notion.routes` entry a batch/resource syncs to (NULL = default databases)
+ALTER TABLE batches ADD COLUMN notion_route INTEGER;
+ALTER TABLE resources ADD COLUMN notion_route INTEGER;
@@ -0,0 +1,2 @@
+-- When the user sent the Telegram message (as opposed to when it was stored)
+ALTER TABLE resources ADD COLUMN tg_date TEXT;
@@ -0,0 +1,2 @@
+-- Local thumbnail generated for a video at save time
+ALTER TABLE resources ADD COLUMN thumb_path TEXT;
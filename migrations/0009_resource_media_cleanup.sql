@@ -0,0 +1,4 @@
+-- Set once the local media file was uploaded to Notion (not linked externally)
+ALTER TABLE resources ADD COLUMN media_uploaded INTEGER NOT NULL DEFAULT 0;
+-- When the local media file and thumbnail were deleted after syncing
+ALTER TABLE resources ADD COLUMN media_purged_at TEXT;
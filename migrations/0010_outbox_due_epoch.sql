@@ -0,0 +1,3 @@
+-- outbox.due_at becomes Unix seconds (UTC) computed by the application, so
+-- due checks no longer depend on SQLite's clock or its datetime text format
+UPDATE outbox SET due_at = CAST(strftime('%s', due_at) AS INTEGER);
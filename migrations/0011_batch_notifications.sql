@@ -0,0 +1,10 @@
+-- Chat told once the batch's Notion page exists (app.notify_on_sync)
+ALTER TABLE batches ADD COLUMN notify_chat_id INTEGER;
+-- "Batch synced" messages the outbox worker leaves for the bot to send
+CREATE TABLE IF NOT EXISTS notifications (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    chat_id INTEGER NOT NULL,
+    batch_id INTEGER NOT NULL REFERENCES batches(id) ON DELETE CASCADE,
+    page_id TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,3 @@
+-- Chat the batch was opened in / the resource was sent from, for replies
+ALTER TABLE batches ADD COLUMN chat_id INTEGER;
+ALTER TABLE resources ADD COLUMN chat_id INTEGER;
@@ -0,0 +1,53 @@
+-- One resource per Telegram message. Message ids are only unique within a
+-- chat, so the chat is part of the key; media groups arrive as one message
+-- per item, so each item still gets its own row. Rows without a chat (saved
+-- before chat_id was recorded, or imported) are not constrained.
+--
+-- The table is rebuilt without its UNIQUE(user_id, tg_message_id, kind,
+-- content), which ignored the chat and rejected a message from a second chat
+-- with the same id and text. Every row keeps its id and data. The per-file
+-- index from 0005 goes with the old table: the message key covers it.
+CREATE TABLE resources_rebuilt (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    batch_id INTEGER REFERENCES batches(id) ON DELETE SET NULL,
+    kind TEXT NOT NULL,
+    content TEXT NOT NULL,
+    tg_message_id INTEGER NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    notion_page_id TEXT,
+    sequence INTEGER,
+    text TEXT,
+    media_name TEXT,
+    media_url TEXT,
+    source_url TEXT,
+    unique_id TEXT,
+    notion_route INTEGER,
+    tg_date TEXT,
+    thumb_path TEXT,
+    media_uploaded INTEGER NOT NULL DEFAULT 0,
+    media_purged_at TEXT,
+    chat_id INTEGER
+);
+INSERT INTO resources_rebuilt (
+    id, user_id, batch_id, kind, content, tg_message_id, created_at,
+    notion_page_id, sequence, text, media_name, media_url, source_url,
+    unique_id, notion_route, tg_date, thumb_path, media_uploaded,
+    media_purged_at, chat_id
+)
+SELECT
+    id, user_id, batch_id, kind, content, tg_message_id, created_at,
+    notion_page_id, sequence, text, media_name, media_url, source_url,
+    unique_id, notion_route, tg_date, thumb_path, media_uploaded,
+    media_purged_at, chat_id
+FROM resources;
+-- Ids of deleted rows stay retired, as they would in the old table
+UPDATE sqlite_sequence
+SET seq = (SELECT seq FROM sqlite_sequence WHERE name = 'resources')
+WHERE name = 'resources_rebuilt'
+  AND EXISTS (SELECT 1 FROM sqlite_sequence WHERE name = 'resources');
+DROP TABLE resources;
+ALTER TABLE resources_rebuilt RENAME TO resources;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_resources_user_message
+    ON resources(user_id, chat_id, tg_message_id);
@@ -0,0 +1,4 @@
+-- Pixel size and play time (seconds) Telegram reports for photos and videos
+ALTER TABLE resources ADD COLUMN width INTEGER;
+ALTER TABLE resources ADD COLUMN height INTEGER;
+ALTER TABLE resources ADD COLUMN duration INTEGER;
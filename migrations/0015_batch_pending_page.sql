@@ -0,0 +1,2 @@
+-- Main page created for a batch whose read-back check has not passed yet
+ALTER TABLE batches ADD COLUMN pending_notion_page_id TEXT;
@@ -0,0 +1,22 @@
+-- Full-text index over resources.text (app.full_text_search). Kept out of
+-- migrations/ because FTS5 is an optional SQLite module; every statement is
+-- idempotent so it can run on each start.
+CREATE VIRTUAL TABLE IF NOT EXISTS resources_fts USING fts5(
+    text,
+    content = 'resources',
+    content_rowid = 'id',
+    tokenize = 'porter unicode61'
+);
+
+CREATE TRIGGER IF NOT EXISTS resources_fts_insert AFTER INSERT ON resources BEGIN
+    INSERT INTO resources_fts (rowid, text) VALUES (new.id, new.text);
+END;
+
+CREATE TRIGGER IF NOT EXISTS resources_fts_delete AFTER DELETE ON resources BEGIN
+    INSERT INTO resources_fts (resources_fts, rowid, text) VALUES ('delete', old.id, old.text);
+END;
+
+CREATE TRIGGER IF NOT EXISTS resources_fts_update AFTER UPDATE OF text ON resources BEGIN
+    INSERT INTO resources_fts (resources_fts, rowid, text) VALUES ('delete', old.id, old.text);
+    INSERT INTO resources_fts (rowid, text) VALUES (new.id, new.text);
+END;
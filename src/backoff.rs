@@ -0,0 +1,122 @@
+//! Retry delay helpers for the outbox.
+
+use rand::Rng;
+
+/// Delay before the first retry.
+pub const BASE_BACKOFF_SECS: i64 = 5;
+
+/// Attempts past this stop doubling the delay (`5s * 2^10` is about 85 minutes).
+pub const MAX_BACKOFF_SHIFT: i32 = 10;
+
+/// Maximum relative deviation applied to a backoff delay (±20%).
+pub const JITTER_RATIO: f64 = 0.2;
+
+/// Delay before retrying after `attempt` failures: `5s * 2^attempt`, doubling
+/// at most [`MAX_BACKOFF_SHIFT`] times and bounded by `cap` when it is positive.
+pub fn compute_backoff_secs(attempt: i32, cap: i64) -> i64 {
+    let shift = attempt.clamp(0, MAX_BACKOFF_SHIFT);
+    capped(BASE_BACKOFF_SECS << shift, cap)
+}
+
+/// [`compute_backoff_secs`] with jitter applied before capping, so the cap
+/// is never exceeded.
+pub fn jittered_backoff_secs(attempt: i32, cap: i64, jitter: &dyn Jitter) -> i64 {
+    capped(apply_jitter(compute_backoff_secs(attempt, 0), jitter), cap)
+}
+
+fn capped(secs: i64, cap: i64) -> i64 {
+    if cap <= 0 {
+        secs
+    } else {
+        secs.min(cap)
+    }
+}
+
+/// Source of jitter samples; injectable so backoff is testable deterministically.
+pub trait Jitter: Send + Sync {
+    /// Return a sample in `[-1.0, 1.0]`.
+    fn sample(&self) -> f64;
+}
+
+/// Default jitter backed by the thread-local RNG.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomJitter;
+
+impl Jitter for RandomJitter {
+    fn sample(&self) -> f64 {
+        rand::thread_rng().gen_range(-1.0..=1.0)
+    }
+}
+
+/// Spread `secs` by up to ±`JITTER_RATIO` so tasks queued together don't retry together.
+pub fn apply_jitter(secs: i64, jitter: &dyn Jitter) -> i64 {
+    let sample = jitter.sample().clamp(-1.0, 1.0);
+    let delta = (secs as f64 * JITTER_RATIO * sample).round() as i64;
+    (secs + delta).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixed(f64);
+
+    impl Jitter for Fixed {
+        fn sample(&self) -> f64 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_until_the_shift_clamp() {
+        let expected = [
+            5, 10, 20, 40, 80, 160, 320, 640, 1280, 2560, 5120, 5120, 5120,
+        ];
+        for (attempt, secs) in expected.into_iter().enumerate() {
+            assert_eq!(compute_backoff_secs(attempt as i32, 0), secs);
+        }
+        assert_eq!(compute_backoff_secs(i32::MAX, 0), 5120);
+        assert_eq!(compute_backoff_secs(-1, 0), 5);
+    }
+
+    #[test]
+    fn backoff_is_bounded_by_a_positive_cap() {
+        for attempt in 0..=12 {
+            let uncapped = compute_backoff_secs(attempt, 0);
+            assert_eq!(compute_backoff_secs(attempt, 60), uncapped.min(60));
+            assert_eq!(compute_backoff_secs(attempt, -1), uncapped);
+        }
+        assert_eq!(compute_backoff_secs(3, 60), 40);
+        assert_eq!(compute_backoff_secs(4, 60), 60);
+    }
+
+    #[test]
+    fn jitter_is_applied_before_the_cap() {
+        assert_eq!(jittered_backoff_secs(3, 0, &Fixed(1.0)), 48);
+        assert_eq!(jittered_backoff_secs(3, 45, &Fixed(1.0)), 45);
+        assert_eq!(jittered_backoff_secs(3, 45, &Fixed(-1.0)), 32);
+        assert_eq!(jittered_backoff_secs(12, 60, &Fixed(-1.0)), 60);
+    }
+
+    #[test]
+    fn jitter_stays_within_twenty_percent() {
+        for base in [5_i64, 40, 320, 5120] {
+            let lo = apply_jitter(base, &Fixed(-1.0));
+            let hi = apply_jitter(base, &Fixed(1.0));
+            assert_eq!(apply_jitter(base, &Fixed(0.0)), base);
+            assert_eq!(lo, (base as f64 * 0.8).round() as i64);
+            assert_eq!(hi, (base as f64 * 1.2).round() as i64);
+
+            for _ in 0..100 {
+                let v = apply_jitter(base, &RandomJitter);
+                assert!((lo..=hi).contains(&v), "{} outside [{}, {}]", v, lo, hi);
+            }
+        }
+    }
+
+    #[test]
+    fn out_of_range_samples_are_clamped() {
+        assert_eq!(apply_jitter(100, &Fixed(5.0)), 120);
+        assert_eq!(apply_jitter(100, &Fixed(-5.0)), 80);
+    }
+}
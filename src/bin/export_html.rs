@@ -1,14 +1,24 @@
 use anyhow::{anyhow, Context, Result};
 use clap::Parser;
-use reqwest::Url;
+use hyper::header::{ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, RANGE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::future::Future;
+use std::io::SeekFrom;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tg_watchbot::config::{self, Config};
-use tg_watchbot::notion::NotionClient;
+use tg_watchbot::notion::{cache, NotionClient};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
 
 #[derive(Debug, Parser)]
 #[command(
-    about = "Export a batch from Notion to a local HTML. Images render via Notion URLs; videos are downloaded to html/video but not rendered."
+    about = "Export a batch from Notion to a local HTML. Images render via Notion URLs; videos are downloaded to html/video but not rendered; audio plays from html/audio."
 )]
 struct Args {
     /// Path to YAML config file
@@ -16,146 +26,504 @@ struct Args {
     config: PathBuf,
 
     /// Unique key to identify a main table row (e.g., slug or custom property value)
+    #[arg(long, required_unless_present = "all", conflicts_with = "all")]
+    key: Option<String>,
+
+    /// Export every main table row into html/<key>/ and link them from html/index.html.
+    #[arg(long)]
+    all: bool,
+
+    /// Skip downloading videos and audio; still validates rows and writes index.html/style.css.
     #[arg(long)]
-    key: String,
+    no_download: bool,
+
+    /// Give up on a video download attempt when connecting takes, or the
+    /// server sends nothing for, this many seconds. A slow download that keeps
+    /// making progress is never cut off.
+    #[arg(long, default_value_t = 300)]
+    download_timeout_secs: u64,
+
+    /// Keep running and re-export every SECS seconds, downloading only videos
+    /// that are not on disk yet. Stop with Ctrl-C.
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    watch: Option<u64>,
+
+    /// After exporting, serve the html/ folder on http://localhost:PORT so
+    /// videos play over HTTP instead of file://. Stop with Ctrl-C.
+    #[arg(long, value_name = "PORT")]
+    serve: Option<u16>,
+
+    /// Ignore the cached Notion property names and fetch the schemas again,
+    /// e.g. after renaming a property in Notion
+    #[arg(long)]
+    refresh_notion_ids: bool,
 }
 
+/// Extra attempts for a video download that fails with a 5xx, timeout or
+/// dropped connection.
+const DOWNLOAD_RETRIES: u32 = 3;
+const DOWNLOAD_RETRY_DELAY: Duration = Duration::from_millis(500);
+/// Print download progress each time this many more bytes are on disk.
+const PROGRESS_EVERY: u64 = 16 * 1024 * 1024;
+/// Bytes read from disk per chunk of a file served by `--serve`.
+const SERVE_CHUNK: usize = 64 * 1024;
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
     let cfg = config::load(Some(&args.config))?;
-    run(&cfg, &args.key).await
+    let timeout = Duration::from_secs(args.download_timeout_secs);
+    let key = args.key.as_deref();
+    // Bind before exporting so a busy port fails fast
+    let server = args
+        .serve
+        .map(|port| {
+            let server = bind_static_server(html_dir(&cfg), port)?;
+            println!(
+                "Serving {} at http://localhost:{}/ (Ctrl-C to stop)",
+                html_dir(&cfg).display(),
+                port
+            );
+            Ok::<_, anyhow::Error>(server)
+        })
+        .transpose()?;
+    match args.watch {
+        Some(secs) => {
+            if let Some(server) = server {
+                tokio::spawn(async move {
+                    if let Err(err) = server.await {
+                        eprintln!("Preview server stopped: {:#}", err);
+                    }
+                });
+            }
+            let interval = Duration::from_secs(secs);
+            let refresh = args.refresh_notion_ids;
+            watch(&cfg, key, !args.no_download, timeout, interval, refresh).await
+        }
+        None => {
+            let refresh = args.refresh_notion_ids;
+            run(&cfg, key, !args.no_download, timeout, true, refresh).await?;
+            let Some(server) = server else {
+                return Ok(());
+            };
+            tokio::select! {
+                res = server => res,
+                res = tokio::signal::ctrl_c() => {
+                    res.context("failed to listen for Ctrl-C")?;
+                    println!("Stopped serving");
+                    Ok(())
+                }
+            }
+        }
+    }
 }
 
-async fn run(cfg: &Config, key: &str) -> Result<()> {
-    let notion = NotionClient::new(cfg.notion.token.clone(), cfg.notion.version.clone());
+/// Folder every export is written under.
+fn html_dir(cfg: &Config) -> PathBuf {
+    PathBuf::from(cfg.app.resolved_data_dir()).join("html")
+}
 
-    // Determine filter operator for the unique property by inspecting schema
-    let main_schema = notion
-        .retrieve_database(&cfg.notion.databases.main.id)
-        .await
-        .context("failed to retrieve main database schema")?;
-    // Resolve unique property (accept name or id from config) and its type
-    let unique_prop_cfg = &cfg.notion.databases.main.fields.unique;
-    let (unique_prop_name, unique_prop_type) =
-        resolve_prop_name_and_type(&main_schema, unique_prop_cfg).ok_or_else(|| {
+/// Re-run the export every `interval` until Ctrl-C. A failed pass is reported
+/// and retried on the next tick. Only the first pass clears the video folder
+/// (and honours `refresh`), so later passes fetch just the videos that are new.
+async fn watch(
+    cfg: &Config,
+    key: Option<&str>,
+    download: bool,
+    download_timeout: Duration,
+    interval: Duration,
+    refresh: bool,
+) -> Result<()> {
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let mut first = true;
+    loop {
+        let pass = async move {
+            let refresh = refresh && first;
+            if let Err(err) = run(cfg, key, download, download_timeout, first, refresh).await {
+                eprintln!("Export failed: {:#}", err);
+            }
+            println!("Next export in {}s (Ctrl-C to stop)", interval.as_secs());
+            tokio::time::sleep(interval).await;
+        };
+        tokio::select! {
+            res = &mut ctrl_c => {
+                res.context("failed to listen for Ctrl-C")?;
+                println!("Stopped watching");
+                return Ok(());
+            }
+            _ = pass => {}
+        }
+        first = false;
+    }
+}
+
+/// Names (not ids) of the configured properties, as queries and page JSON use
+/// them. Cached under the same key as the Notion property IDs.
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportProps {
+    unique_prop_name: String,
+    unique_prop_type: String,
+    rel_prop: String,
+    order_prop: String,
+    text_prop: String,
+    media_prop: String,
+}
+
+impl ExportProps {
+    /// Served from `{data_dir}/notion_export_props.json` unless `refresh` is set
+    /// or the configured databases or field names changed.
+    async fn cached(notion: &NotionClient, cfg: &Config, refresh: bool) -> Result<Self> {
+        let path = cache::export_cache_path(&cfg.app.resolved_data_dir());
+        let key = cache::cache_key(cfg);
+        if !refresh {
+            if let Some(props) = cache::load(&path, &key) {
+                return Ok(props);
+            }
+        }
+        let props = Self::fetch(notion, cfg).await?;
+        if let Err(err) = cache::store(&path, &key, &props) {
+            eprintln!("Failed to cache Notion property names: {:#}", err);
+        }
+        Ok(props)
+    }
+
+    async fn fetch(notion: &NotionClient, cfg: &Config) -> Result<Self> {
+        // Determine filter operator for the unique property by inspecting schema
+        let main_schema = notion
+            .retrieve_database(&cfg.notion.databases.main.id)
+            .await
+            .context("failed to retrieve main database schema")?;
+        // Resolve unique property (accept name or id from config) and its type
+        let unique_prop_cfg = &cfg.notion.databases.main.fields.unique;
+        let (unique_prop_name, unique_prop_type) =
+            resolve_prop_name_and_type(&main_schema, unique_prop_cfg).ok_or_else(|| {
+                anyhow!(
+                    "unique property '{}' not found by name or id in main database",
+                    unique_prop_cfg
+                )
+            })?;
+
+        // Resolve resource database property names from config (which may contain ids)
+        let res_schema = notion
+            .retrieve_database(&cfg.notion.databases.resource.id)
+            .await
+            .context("failed to retrieve resource database schema")?;
+        let rel_prop =
+            resolve_prop_name(&res_schema, &cfg.notion.databases.resource.fields.relation)
+                .ok_or_else(|| anyhow!("resource relation property not found (by name or id)"))?;
+        let order_prop =
+            resolve_prop_name(&res_schema, &cfg.notion.databases.resource.fields.order)
+                .ok_or_else(|| anyhow!("resource order property not found (by name or id)"))?;
+        tg_watchbot::notion::check_order_property(
+            &res_schema,
+            &cfg.notion.databases.resource.fields.order,
+        )?;
+        let text_prop = resolve_prop_name(&res_schema, &cfg.notion.databases.resource.fields.text)
+            .ok_or_else(|| anyhow!("resource text property not found (by name or id)"))?;
+        let media_prop =
+            resolve_prop_name(&res_schema, &cfg.notion.databases.resource.fields.media)
+                .ok_or_else(|| anyhow!("resource media property not found (by name or id)"))?;
+
+        Ok(Self {
+            unique_prop_name,
+            unique_prop_type,
+            rel_prop,
+            order_prop,
+            text_prop,
+            media_prop,
+        })
+    }
+}
+
+/// Resolved property names and clients shared by every page of one export pass.
+struct Exporter<'a> {
+    cfg: &'a Config,
+    notion: NotionClient,
+    http: reqwest::Client,
+    download_timeout: Duration,
+    unique_prop_name: String,
+    unique_prop_type: String,
+    rel_prop: String,
+    order_prop: String,
+    text_prop: String,
+    media_prop: String,
+}
+
+/// Export once: the row matching `key`, or every row when `key` is `None`.
+/// `clear_videos` empties each `video` folder before downloading; `refresh`
+/// re-fetches the database schemas instead of using the cached property names.
+async fn run(
+    cfg: &Config,
+    key: Option<&str>,
+    download: bool,
+    download_timeout: Duration,
+    clear_videos: bool,
+    refresh: bool,
+) -> Result<()> {
+    let exporter = Exporter::new(cfg, download_timeout, refresh).await?;
+    let html_dir = html_dir(cfg);
+    match key {
+        Some(key) => {
+            let main_page_id = exporter.find_main_page(key).await?;
+            exporter
+                .export_page(key, &main_page_id, &html_dir, download, clear_videos)
+                .await
+        }
+        None => exporter.export_all(&html_dir, download, clear_videos).await,
+    }
+}
+
+impl<'a> Exporter<'a> {
+    async fn new(cfg: &'a Config, download_timeout: Duration, refresh: bool) -> Result<Self> {
+        let notion = NotionClient::new(cfg.notion.token.clone(), cfg.notion.version.clone());
+        let ExportProps {
+            unique_prop_name,
+            unique_prop_type,
+            rel_prop,
+            order_prop,
+            text_prop,
+            media_prop,
+        } = ExportProps::cached(&notion, cfg, refresh).await?;
+
+        let http = reqwest::Client::builder()
+            .user_agent("tg-watchbot/export-html")
+            .no_proxy()
+            .connect_timeout(download_timeout)
+            .build()?;
+
+        Ok(Self {
+            cfg,
+            notion,
+            http,
+            download_timeout,
+            unique_prop_name,
+            unique_prop_type,
+            rel_prop,
+            order_prop,
+            text_prop,
+            media_prop,
+        })
+    }
+
+    /// Query the main DB for the page matching the key and return its id.
+    async fn find_main_page(&self, key: &str) -> Result<String> {
+        let unique_filter =
+            build_unique_filter(&self.unique_prop_name, &self.unique_prop_type, key);
+        let main_res = self
+            .notion
+            .query_database(
+                &self.cfg.notion.databases.main.id,
+                Some(unique_filter),
+                None,
+                None,
+            )
+            .await?;
+        let main_page = main_res.results.first().ok_or_else(|| {
             anyhow!(
-                "unique property '{}' not found by name or id in main database",
-                unique_prop_cfg
+                "no main row matched key '{}' on property '{}'",
+                key,
+                self.cfg.notion.databases.main.fields.unique
             )
         })?;
+        main_page
+            .get("id")
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("main page missing id"))
+    }
 
-    let http = reqwest::Client::builder()
-        .user_agent("tg-watchbot/export-html")
-        .no_proxy()
-        .build()?;
+    /// Page through the whole database `db_id` with an optional filter and sort.
+    async fn query_all(
+        &self,
+        db_id: &str,
+        filter: Option<Value>,
+        sorts: Option<Value>,
+    ) -> Result<Vec<Value>> {
+        let mut items: Vec<Value> = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let page = self
+                .notion
+                .query_database(db_id, filter.clone(), sorts.clone(), cursor.as_deref())
+                .await?;
+            items.extend(page.results);
+            match page.next_cursor.filter(|_| page.has_more) {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        Ok(items)
+    }
 
-    // Query main DB for the page matching the key
-    let unique_filter = build_unique_filter(&unique_prop_name, &unique_prop_type, key);
-    let q_main = json!({
-        "filter": unique_filter,
-        "page_size": 1
-    });
-    let main_res = notion_post_json(
-        &http,
-        &cfg.notion.token,
-        &cfg.notion.version,
-        &format!("v1/databases/{}/query", cfg.notion.databases.main.id),
-        q_main,
-    )
-    .await?;
-    let results = main_res
-        .get("results")
-        .and_then(|v| v.as_array())
-        .ok_or_else(|| anyhow!("invalid Notion response for main query"))?;
-    let main_page = results.first().ok_or_else(|| {
-        anyhow!(
-            "no main row matched key '{}' on property '{}'",
-            key,
-            unique_prop_cfg
-        )
-    })?;
-    let main_page_id = main_page
-        .get("id")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| anyhow!("main page missing id"))?;
-
-    // Query resource DB for related items ordered by order ascending
-    // Resolve resource database property names from config (which may contain ids)
-    let res_schema = notion
-        .retrieve_database(&cfg.notion.databases.resource.id)
-        .await
-        .context("failed to retrieve resource database schema")?;
-    let rel_prop =
-        resolve_prop_name(&res_schema, &cfg.notion.databases.resource.fields.relation)
-            .ok_or_else(|| anyhow!("resource relation property not found (by name or id)"))?;
-    let order_prop = resolve_prop_name(&res_schema, &cfg.notion.databases.resource.fields.order)
-        .ok_or_else(|| anyhow!("resource order property not found (by name or id)"))?;
-    let text_prop = resolve_prop_name(&res_schema, &cfg.notion.databases.resource.fields.text)
-        .ok_or_else(|| anyhow!("resource text property not found (by name or id)"))?;
-    let media_prop = resolve_prop_name(&res_schema, &cfg.notion.databases.resource.fields.media)
-        .ok_or_else(|| anyhow!("resource media property not found (by name or id)"))?;
-
-    let q_res = json!({
-        "filter": { "property": rel_prop, "relation": { "contains": main_page_id } },
-        "sorts": [ { "property": order_prop, "direction": "ascending" } ],
-        "page_size": 100
-    });
-    let res_res = notion_post_json(
-        &http,
-        &cfg.notion.token,
-        &cfg.notion.version,
-        &format!("v1/databases/{}/query", cfg.notion.databases.resource.id),
-        q_res,
-    )
-    .await?;
-    let items = res_res
-        .get("results")
-        .and_then(|v| v.as_array())
-        .ok_or_else(|| anyhow!("invalid Notion response for resource query"))?;
-
-    // Map to presentation: sequence (order), maybe text, else files (urls with names)
-    let mut rows: Vec<Row> = Vec::new();
-    for page in items {
-        let props = page.get("properties").and_then(|v| v.as_object());
-        let Some(props) = props else { continue };
-
-        let ord = extract_title_number(props.get(&order_prop)).unwrap_or(0);
-        let text = extract_rich_text(props.get(&text_prop));
-        let files = extract_files(props.get(&media_prop));
-        rows.push(Row {
-            ord,
-            text,
-            files,
-            video_local_rel: None,
-        });
+    /// Export every main row with a key into `html_dir/<slug>/`, then write
+    /// `html_dir/index.html` linking to each. A failed batch is reported and
+    /// left out of the index; the others are still exported.
+    async fn export_all(
+        &self,
+        html_dir: &std::path::Path,
+        download: bool,
+        clear_videos: bool,
+    ) -> Result<()> {
+        let pages = self
+            .query_all(&self.cfg.notion.databases.main.id, None, None)
+            .await?;
+        let mut used = HashSet::new();
+        let mut exported = Vec::new();
+        for page in &pages {
+            let Some(main_page_id) = page.get("id").and_then(|v| v.as_str()) else {
+                continue;
+            };
+            let key = page
+                .get("properties")
+                .and_then(|props| props.get(&self.unique_prop_name))
+                .and_then(|v| extract_key(v, &self.unique_prop_type));
+            let Some(key) = key else {
+                eprintln!("Skipping main page {}: no key", main_page_id);
+                continue;
+            };
+            let dir = unique_dir_name(&key, &mut used);
+            let out_dir = html_dir.join(&dir);
+            match self
+                .export_page(&key, main_page_id, &out_dir, download, clear_videos)
+                .await
+            {
+                Ok(()) => exported.push((key, dir)),
+                Err(err) => eprintln!("Export of '{}' failed: {:#}", key, err),
+            }
+        }
+
+        let index_path = html_dir.join("index.html");
+        write_atomically(&index_path, render_all_index(&exported).as_bytes())
+            .await
+            .with_context(|| format!("failed to write {}", index_path.display()))?;
+        println!("================================");
+        println!(
+            "Exported {} of {} batch(es); index: {}",
+            exported.len(),
+            pages.len(),
+            absolute_path(&index_path).display()
+        );
+        Ok(())
     }
 
-    // Build HTML
-    let out_dir = PathBuf::from(cfg.app.resolved_data_dir()).join("html");
-    let static_dir = out_dir.join("static");
-    tokio::fs::create_dir_all(&static_dir)
-        .await
-        .with_context(|| format!("failed to create {}", static_dir.display()))?;
+    /// Export the resources of `main_page_id` to `out_dir/index.html`, with
+    /// videos in `out_dir/video/` and audio in `out_dir/audio/`.
+    async fn export_page(
+        &self,
+        key: &str,
+        main_page_id: &str,
+        out_dir: &std::path::Path,
+        download: bool,
+        clear_videos: bool,
+    ) -> Result<()> {
+        // Query resource DB for related items ordered by order ascending
+        let res_filter =
+            json!({ "property": self.rel_prop, "relation": { "contains": main_page_id } });
+        let res_sorts = json!([ { "property": self.order_prop, "direction": "ascending" } ]);
+        let items = self
+            .query_all(
+                &self.cfg.notion.databases.resource.id,
+                Some(res_filter),
+                Some(res_sorts),
+            )
+            .await?;
+
+        // Map to presentation: sequence (order), maybe text, else files (urls with names)
+        let mut rows: Vec<Row> = Vec::new();
+        for page in &items {
+            let props = page.get("properties").and_then(|v| v.as_object());
+            let Some(props) = props else { continue };
 
-    // Ensure video directory exists and is empty before any downloads
-    let video_dir = out_dir.join("video");
-    if video_dir.exists() {
-        tokio::fs::remove_dir_all(&video_dir)
+            let ord = extract_order_number(props.get(&self.order_prop)).unwrap_or(0);
+            let text = extract_rich_text(props.get(&self.text_prop));
+            let files = extract_files(props.get(&self.media_prop));
+            rows.push(Row {
+                ord,
+                text,
+                files,
+                video_local_rel: None,
+                audio_local_rel: None,
+            });
+        }
+
+        // Build HTML
+        let static_dir = out_dir.join("static");
+        tokio::fs::create_dir_all(&static_dir)
             .await
-            .with_context(|| format!("failed to clear {}", video_dir.display()))?;
+            .with_context(|| format!("failed to create {}", static_dir.display()))?;
+
+        // Ensure video directory exists and is empty before any downloads
+        let video_dir = out_dir.join("video");
+        if download {
+            if clear_videos && video_dir.exists() {
+                tokio::fs::remove_dir_all(&video_dir)
+                    .await
+                    .with_context(|| format!("failed to clear {}", video_dir.display()))?;
+            }
+            tokio::fs::create_dir_all(&video_dir)
+                .await
+                .with_context(|| format!("failed to create {}", video_dir.display()))?;
+        }
+
+        let audio_dir = out_dir.join("audio");
+        let videos = process_rows(
+            &self.http,
+            self.download_timeout,
+            &mut rows,
+            &video_dir,
+            &audio_dir,
+            download,
+        )
+        .await?;
+
+        let index_html = render_html(key, &rows);
+        let index_path = out_dir.join("index.html");
+        write_atomically(&index_path, index_html.as_bytes())
+            .await
+            .with_context(|| format!("failed to write {}", index_path.display()))?;
+
+        let style_css = DEFAULT_STYLE;
+        let css_path = static_dir.join("style.css");
+        tokio::fs::write(&css_path, style_css)
+            .await
+            .with_context(|| format!("failed to write {}", css_path.display()))?;
+
+        println!("Wrote {} and {}", index_path.display(), css_path.display());
+        if !download {
+            println!(
+                "Skipped downloads: {} video(s) would have been downloaded",
+                videos
+            );
+        }
+
+        println!("================================");
+        println!("Index full path: {}", absolute_path(&index_path).display());
+        println!("Video full path: {}", absolute_path(&video_dir).display());
+        Ok(())
     }
-    tokio::fs::create_dir_all(&video_dir)
-        .await
-        .with_context(|| format!("failed to create {}", video_dir.display()))?;
+}
 
+/// Validate each row's files and download its video (if any) into `video_dir`
+/// and its audio into `audio_dir`. With `download` false nothing is written,
+/// but `video_local_rel` is still set to where the video would go; audio then
+/// plays from its Notion URL. Returns the number of video rows.
+async fn process_rows(
+    http: &reqwest::Client,
+    idle_timeout: Duration,
+    rows: &mut [Row],
+    video_dir: &std::path::Path,
+    audio_dir: &std::path::Path,
+    download: bool,
+) -> Result<usize> {
+    let mut videos = 0;
     for r in rows.iter_mut() {
         if r.text.is_some() {
             continue;
         }
 
         // Enforce strict file rules. Invalid cases cause immediate error.
-        match r.files.len() {
+        let video = match r.files.len() {
             0 => {
                 return Err(anyhow!("row #{} has no files and no text (invalid)", r.ord));
             }
@@ -163,26 +531,25 @@ async fn run(cfg: &Config, key: &str) -> Result<()> {
                 let f = &r.files[0];
                 if looks_like_image(&f.name) || looks_like_image_url(&f.url) {
                     // Image only: render via URL; nothing to download.
+                    None
                 } else if looks_like_video(&f.name) || looks_like_video_url(&f.url) {
-                    // Video only: download to html/video/{order}.{ext}
-                    let ext = derive_video_ext(&f.name, &f.url);
-                    let file_name = format!("{}.{}", r.ord, ext);
-                    let dest = video_dir.join(&file_name);
-                    let mut need = true;
-                    if let Ok(meta) = std::fs::metadata(&dest) {
-                        if meta.len() > 0 {
-                            need = false;
+                    Some(f)
+                } else if looks_like_audio(&f.name) || looks_like_audio_url(&f.url) {
+                    // Audio only: keep a local copy when downloading
+                    if download {
+                        let file_name = format!("{}.{}", r.ord, derive_audio_ext(&f.name, &f.url));
+                        let dest = audio_dir.join(&file_name);
+                        if needs_download(download, dest.exists()) {
+                            download_file_to(http, idle_timeout, &f.url, &dest)
+                                .await
+                                .with_context(|| format!("failed to download audio {}", f.url))?;
                         }
+                        r.audio_local_rel = Some(format!("audio/{}", file_name));
                     }
-                    if need {
-                        download_file_to(&http, &f.url, &dest)
-                            .await
-                            .with_context(|| format!("failed to download video {}", f.url))?;
-                    }
-                    r.video_local_rel = Some(format!("video/{}", file_name));
+                    None
                 } else {
                     return Err(anyhow!(
-                        "row #{} has one file but not image/video: {}",
+                        "row #{} has one file but not image/video/audio: {}",
                         r.ord,
                         f.name
                     ));
@@ -204,21 +571,7 @@ async fn run(cfg: &Config, key: &str) -> Result<()> {
                     ));
                 }
                 // Ignore thumbnail; download video
-                let ext = derive_video_ext(&second.name, &second.url);
-                let file_name = format!("{}.{}", r.ord, ext);
-                let dest = video_dir.join(&file_name);
-                let mut need = true;
-                if let Ok(meta) = std::fs::metadata(&dest) {
-                    if meta.len() > 0 {
-                        need = false;
-                    }
-                }
-                if need {
-                    download_file_to(&http, &second.url, &dest)
-                        .await
-                        .with_context(|| format!("failed to download video {}", second.url))?;
-                }
-                r.video_local_rel = Some(format!("video/{}", file_name));
+                Some(second)
             }
             _ => {
                 return Err(anyhow!(
@@ -227,26 +580,38 @@ async fn run(cfg: &Config, key: &str) -> Result<()> {
                     r.files.len()
                 ));
             }
+        };
+        let Some(f) = video else { continue };
+
+        // Video: download to html/video/{order}.{ext}
+        let ext = derive_video_ext(&f.name, &f.url);
+        let file_name = format!("{}.{}", r.ord, ext);
+        let dest = video_dir.join(&file_name);
+        if needs_download(download, dest.exists()) {
+            download_file_to(http, idle_timeout, &f.url, &dest)
+                .await
+                .with_context(|| format!("failed to download video {}", f.url))?;
         }
+        videos += 1;
+        r.video_local_rel = Some(format!("video/{}", file_name));
     }
+    Ok(videos)
+}
 
-    let index_html = render_html(key, &rows);
-    let index_path = out_dir.join("index.html");
-    tokio::fs::write(&index_path, index_html)
-        .await
-        .with_context(|| format!("failed to write {}", index_path.display()))?;
-
-    let style_css = DEFAULT_STYLE;
-    let css_path = static_dir.join("style.css");
-    tokio::fs::write(&css_path, style_css)
-        .await
-        .with_context(|| format!("failed to write {}", css_path.display()))?;
-
-    println!("Wrote {} and {}", index_path.display(), css_path.display());
+/// Downloads are renamed into place only once complete, so a video already on
+/// disk is whole and is never fetched again.
+fn needs_download(download: bool, on_disk: bool) -> bool {
+    download && !on_disk
+}
 
-    println!("================================");
-    println!("Index full path: {}", absolute_path(&index_path).display());
-    println!("Video full path: {}", absolute_path(&video_dir).display());
+/// Write via a sibling temp file and rename, so readers see the old or the new
+/// content but never a partial file.
+async fn write_atomically(path: &std::path::Path, contents: &[u8]) -> Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+    tokio::fs::write(&tmp, contents).await?;
+    tokio::fs::rename(&tmp, path).await?;
     Ok(())
 }
 
@@ -274,6 +639,16 @@ fn render_html(key: &str, rows: &[Row]) -> String {
                 }
             }
             // Intentionally do not render videos in HTML; they are saved to html/video/ only.
+            for f in &r.files {
+                if looks_like_audio(&f.name) || looks_like_audio_url(&f.url) {
+                    let src = r.audio_local_rel.as_deref().unwrap_or(&f.url);
+                    section.push_str(&format!(
+                        "<audio controls src=\"{}\" title=\"{}\"></audio>",
+                        html_attr(src),
+                        html_attr(&f.name)
+                    ));
+                }
+            }
         }
         section.push_str("</div>\n");
         body.push_str(&section);
@@ -303,6 +678,69 @@ fn render_html(key: &str, rows: &[Row]) -> String {
     )
 }
 
+/// Top-level page for `--all`: one link per exported `(key, dir)`.
+fn render_all_index(batches: &[(String, String)]) -> String {
+    let mut list = String::new();
+    for (key, dir) in batches {
+        list.push_str(&format!(
+            "<li><a href=\"{}/index.html\">{}</a></li>\n",
+            html_attr(dir),
+            html_escape(key)
+        ));
+    }
+    format!(
+        r#"<!doctype html>
+<html lang="zh-CN">
+  <head>
+    <meta charset="utf-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>Batches</title>
+  </head>
+  <body>
+    <header>
+      <h1 class="noselect">Batches</h1>
+    </header>
+    <main>
+      <ul>
+      {}</ul>
+    </main>
+  </body>
+</html>"#,
+        list
+    )
+}
+
+/// Directory name for a batch key: letters, digits, `-` and `_` are kept and
+/// every other run of characters becomes a single `-`.
+fn slugify(key: &str) -> String {
+    let mut slug = String::new();
+    for c in key.chars() {
+        if c.is_alphanumeric() || c == '-' || c == '_' {
+            slug.push(c);
+        } else if !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_matches('-');
+    if slug.is_empty() {
+        "batch".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+/// [`slugify`] `key`, adding `-2`, `-3`, ... when another key already took the name.
+fn unique_dir_name(key: &str, used: &mut HashSet<String>) -> String {
+    let base = slugify(key);
+    let mut dir = base.clone();
+    let mut n = 1;
+    while !used.insert(dir.clone()) {
+        n += 1;
+        dir = format!("{}-{}", base, n);
+    }
+    dir
+}
+
 #[derive(Debug, Clone)]
 struct Row {
     ord: i64,
@@ -310,6 +748,8 @@ struct Row {
     files: Vec<FileEntry>,
     // If present, relative path under html/ pointing to downloaded video (e.g., "video/2.mp4")
     video_local_rel: Option<String>,
+    // Likewise for downloaded audio (e.g., "audio/3.mp3"); unset plays the Notion URL
+    audio_local_rel: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -328,8 +768,12 @@ fn absolute_path(p: &std::path::Path) -> std::path::PathBuf {
     }
 }
 
-fn extract_title_number(v: Option<&Value>) -> Option<i64> {
+fn extract_order_number(v: Option<&Value>) -> Option<i64> {
     let v = v?;
+    if let Some(n) = v.get("number").and_then(|n| n.as_i64()) {
+        return Some(n);
+    }
+    // Pages synced before order became a number property stored it as a "#N" title
     let title = v.get("title")?.as_array()?;
     let text = title
         .iter()
@@ -341,6 +785,26 @@ fn extract_title_number(v: Option<&Value>) -> Option<i64> {
     digits.parse::<i64>().ok()
 }
 
+/// Plain text of a unique-key property value; see [`build_unique_filter`] for
+/// the supported types.
+fn extract_key(v: &Value, prop_type: &str) -> Option<String> {
+    let text = match prop_type {
+        "title" | "rich_text" => v
+            .get(prop_type)?
+            .as_array()?
+            .iter()
+            .filter_map(|t| t.get("plain_text").and_then(|s| s.as_str()))
+            .collect::<String>(),
+        "formula" => v.get("formula")?.get("string")?.as_str()?.to_string(),
+        _ => return extract_rich_text(Some(v)),
+    };
+    if text.trim().is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
 fn extract_rich_text(v: Option<&Value>) -> Option<String> {
     let v = v?;
     let arr = v.get("rich_text")?.as_array()?;
@@ -395,30 +859,6 @@ fn extract_files(v: Option<&Value>) -> Vec<FileEntry> {
     out
 }
 
-async fn notion_post_json(
-    http: &reqwest::Client,
-    token: &str,
-    version: &str,
-    path: &str,
-    body: Value,
-) -> Result<Value> {
-    let base = Url::parse("https://api.notion.com/")?;
-    let url = base.join(path)?;
-    let res = http
-        .post(url)
-        .header("Authorization", format!("Bearer {}", token))
-        .header("Notion-Version", version)
-        .json(&body)
-        .send()
-        .await?;
-    if !res.status().is_success() {
-        let status = res.status();
-        let text = res.text().await.unwrap_or_default();
-        return Err(anyhow!("notion error {}: {}", status, text));
-    }
-    Ok(res.json::<Value>().await?)
-}
-
 fn looks_like_image(name: &str) -> bool {
     let n = name.to_ascii_lowercase();
     n.ends_with(".jpg")
@@ -435,12 +875,21 @@ fn looks_like_video(name: &str) -> bool {
         || n.ends_with(".mkv")
         || n.ends_with(".webm")
 }
+fn looks_like_audio(name: &str) -> bool {
+    let n = name.to_ascii_lowercase();
+    AUDIO_EXTS
+        .iter()
+        .any(|ext| n.ends_with(&format!(".{}", ext)))
+}
 fn looks_like_image_url(url: &str) -> bool {
     looks_like_image(url)
 }
 fn looks_like_video_url(url: &str) -> bool {
     looks_like_video(url)
 }
+fn looks_like_audio_url(url: &str) -> bool {
+    looks_like_audio(url)
+}
 
 fn html_escape(s: &str) -> String {
     s.replace('&', "&amp;")
@@ -509,7 +958,8 @@ main {
 }
 
 img,
-video {
+video,
+audio {
   max-width: 100%;
   display: block;
   margin: 8px 0;
@@ -550,24 +1000,333 @@ fn derive_video_ext(name: &str, url: &str) -> &'static str {
     "mp4"
 }
 
-async fn download_file_to(http: &reqwest::Client, url: &str, dest: &std::path::Path) -> Result<()> {
-    let start = std::time::Instant::now();
-    println!("Downloading {}", dest.display());
+const AUDIO_EXTS: [&str; 8] = ["mp3", "m4a", "ogg", "oga", "opus", "wav", "flac", "aac"];
 
-    let res = http.get(url).send().await?;
-    if !res.status().is_success() {
-        return Err(anyhow!("download error {} for {}", res.status(), url));
+fn derive_audio_ext(name: &str, url: &str) -> &'static str {
+    let lower = name.to_ascii_lowercase();
+    if let Some(ext) = AUDIO_EXTS
+        .iter()
+        .find(|ext| lower.ends_with(&format!(".{}", ext)))
+    {
+        return ext;
     }
-    let bytes = res.bytes().await?;
+    let lower_u = url.to_ascii_lowercase();
+    AUDIO_EXTS
+        .iter()
+        .find(|ext| lower_u.contains(&format!(".{}", ext)))
+        .unwrap_or(&"mp3")
+}
+
+/// Download `url` to `dest` through a `.part` file, retrying transient
+/// failures. An attempt fails once no data arrives for `idle_timeout`.
+async fn download_file_to(
+    http: &reqwest::Client,
+    idle_timeout: Duration,
+    url: &str,
+    dest: &std::path::Path,
+) -> Result<()> {
+    let start = std::time::Instant::now();
+    println!("Downloading {}", dest.display());
     if let Some(p) = dest.parent() {
         tokio::fs::create_dir_all(p).await.ok();
     }
-    tokio::fs::write(dest, &bytes).await?;
+    let mut part_name = dest.file_name().unwrap_or_default().to_os_string();
+    part_name.push(".part");
+    let part = dest.with_file_name(part_name);
+
+    let attempts = DOWNLOAD_RETRIES + 1;
+    let mut attempt = 1;
+    loop {
+        match download_once(http, idle_timeout, url, &part).await {
+            Ok(()) => break,
+            Err(DownloadError::Transient(err)) if attempt < attempts => {
+                println!("Download attempt {} failed ({:#}); retrying", attempt, err);
+                tokio::time::sleep(DOWNLOAD_RETRY_DELAY * attempt).await;
+                attempt += 1;
+            }
+            Err(DownloadError::Transient(err)) | Err(DownloadError::Fatal(err)) => {
+                tokio::fs::remove_file(&part).await.ok();
+                return Err(err.context(format!("gave up after {} attempt(s)", attempt)));
+            }
+        }
+    }
+    tokio::fs::rename(&part, dest).await?;
 
     println!("Downloaded in {:?}ms", start.elapsed().as_millis());
     Ok(())
 }
 
+/// Whether a failed download is worth another attempt.
+enum DownloadError {
+    Transient(anyhow::Error),
+    Fatal(anyhow::Error),
+}
+
+impl From<reqwest::Error> for DownloadError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() || err.is_connect() || err.is_body() || err.is_request() {
+            DownloadError::Transient(err.into())
+        } else {
+            DownloadError::Fatal(err.into())
+        }
+    }
+}
+
+/// Progress of a running download, e.g. `32.0 MiB of 100.0 MiB (32%)`.
+fn progress_line(written: u64, expected: Option<u64>) -> String {
+    const MIB: f64 = 1024.0 * 1024.0;
+    let done = format!("{:.1} MiB", written as f64 / MIB);
+    match expected.filter(|&total| total > 0) {
+        Some(total) => format!(
+            "{} of {:.1} MiB ({}%)",
+            done,
+            total as f64 / MIB,
+            written * 100 / total
+        ),
+        None => done,
+    }
+}
+
+/// Stream `url` into `part` one chunk at a time, so memory use does not grow
+/// with the file; fails when fewer bytes arrive than announced, or when the
+/// server stays silent for `idle_timeout`.
+async fn download_once(
+    http: &reqwest::Client,
+    idle_timeout: Duration,
+    url: &str,
+    part: &std::path::Path,
+) -> std::result::Result<(), DownloadError> {
+    use tokio::io::AsyncWriteExt;
+
+    let stalled = || {
+        DownloadError::Transient(anyhow!(
+            "no data from {} for {}s",
+            url,
+            idle_timeout.as_secs_f32()
+        ))
+    };
+    let mut res = tokio::time::timeout(idle_timeout, http.get(url).send())
+        .await
+        .map_err(|_| stalled())??;
+    let status = res.status();
+    if !status.is_success() {
+        let err = anyhow!("download error {} for {}", status, url);
+        return Err(if status.is_server_error() || status.as_u16() == 429 {
+            DownloadError::Transient(err)
+        } else {
+            DownloadError::Fatal(err)
+        });
+    }
+    let expected = res.content_length();
+    let mut file = tokio::fs::File::create(part)
+        .await
+        .map_err(|e| DownloadError::Fatal(e.into()))?;
+    let mut written = 0u64;
+    let mut next_report = PROGRESS_EVERY;
+    while let Some(chunk) = tokio::time::timeout(idle_timeout, res.chunk())
+        .await
+        .map_err(|_| stalled())??
+    {
+        file.write_all(&chunk)
+            .await
+            .map_err(|e| DownloadError::Fatal(e.into()))?;
+        written += chunk.len() as u64;
+        if written >= next_report {
+            println!("  {}", progress_line(written, expected));
+            next_report = written + PROGRESS_EVERY;
+        }
+    }
+    file.flush()
+        .await
+        .map_err(|e| DownloadError::Fatal(e.into()))?;
+    if let Some(expected) = expected.filter(|&n| n != written) {
+        return Err(DownloadError::Transient(anyhow!(
+            "truncated download: got {} of {} bytes",
+            written,
+            expected
+        )));
+    }
+    Ok(())
+}
+
+/// Bind `127.0.0.1:{port}` and return the server serving files under `root`.
+fn bind_static_server(root: PathBuf, port: u16) -> Result<impl Future<Output = Result<()>>> {
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    let make_svc = make_service_fn(move |_conn| {
+        let root = root.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let root = root.clone();
+                async move { Ok::<_, Infallible>(serve_static(&root, &req).await) }
+            }))
+        }
+    });
+    let server = Server::try_bind(&addr)
+        .with_context(|| format!("failed to bind {}", addr))?
+        .serve(make_svc);
+    Ok(async move { server.await.context("preview server failed") })
+}
+
+/// Answer a preview request with a file under `root`. `/` and other folders
+/// map to their `index.html`; a single `Range` is honoured so videos can seek.
+async fn serve_static(root: &Path, req: &Request<Body>) -> Response<Body> {
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        return plain(StatusCode::METHOD_NOT_ALLOWED, "method not allowed");
+    }
+    let Some(mut path) = static_path(root, req.uri().path()) else {
+        return plain(StatusCode::NOT_FOUND, "not found");
+    };
+    if path.is_dir() {
+        path.push("index.html");
+    }
+    let Ok(mut file) = tokio::fs::File::open(&path).await else {
+        return plain(StatusCode::NOT_FOUND, "not found");
+    };
+    let len = match file.metadata().await {
+        Ok(meta) if meta.is_file() => meta.len(),
+        _ => return plain(StatusCode::NOT_FOUND, "not found"),
+    };
+    let range = req
+        .headers()
+        .get(RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| parse_range(v, len));
+
+    let mut response = Response::builder()
+        .header(CONTENT_TYPE, content_type(&path))
+        .header(ACCEPT_RANGES, "bytes");
+    let (start, count) = match range {
+        Some((start, end)) => {
+            response = response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, len));
+            (start, end - start + 1)
+        }
+        None => (0, len),
+    };
+    let response = response.header(CONTENT_LENGTH, count);
+    if req.method() == Method::HEAD || count == 0 {
+        return response.body(Body::empty()).expect("valid response");
+    }
+    if start > 0 && file.seek(SeekFrom::Start(start)).await.is_err() {
+        return plain(StatusCode::INTERNAL_SERVER_ERROR, "read failed");
+    }
+
+    // Stream the file so large videos are never held in memory
+    let (mut tx, body) = Body::channel();
+    tokio::spawn(async move {
+        let mut remaining = count;
+        let mut buf = vec![0u8; SERVE_CHUNK];
+        while remaining > 0 {
+            let want = remaining.min(SERVE_CHUNK as u64) as usize;
+            match file.read(&mut buf[..want]).await {
+                Ok(0) | Err(_) => return tx.abort(),
+                Ok(n) => {
+                    let chunk = hyper::body::Bytes::copy_from_slice(&buf[..n]);
+                    if tx.send_data(chunk).await.is_err() {
+                        return;
+                    }
+                    remaining -= n as u64;
+                }
+            }
+        }
+    });
+    response.body(body).expect("valid response")
+}
+
+/// `root` joined with the decoded URL `path`, or `None` when the path is
+/// malformed or would leave `root`.
+fn static_path(root: &Path, path: &str) -> Option<PathBuf> {
+    let decoded = percent_decode(path)?;
+    let mut out = root.to_path_buf();
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => return None,
+            s if s.contains('\\') || s.contains('\0') => return None,
+            s => out.push(s),
+        }
+    }
+    Some(out)
+}
+
+/// Decode `%XX` escapes; `None` for a bad escape or invalid UTF-8.
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = s.get(i + 1..i + 3)?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+/// The inclusive byte range of a single-range `Range` header for a file of
+/// `len` bytes; `None` when multi-range, malformed or unsatisfiable, in which
+/// case the whole file is sent.
+fn parse_range(header: &str, len: u64) -> Option<(u64, u64)> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') || len == 0 {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let n: u64 = suffix.parse().ok()?;
+            if n == 0 {
+                return None;
+            }
+            (len.saturating_sub(n), len - 1)
+        }
+        (start, "") => (start.parse().ok()?, len - 1),
+        (start, end) => (start.parse().ok()?, end.parse::<u64>().ok()?.min(len - 1)),
+    };
+    (start <= end).then_some((start, end))
+}
+
+fn content_type(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match ext.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" => "text/javascript; charset=utf-8",
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "mp4" | "m4v" => "video/mp4",
+        "webm" => "video/webm",
+        "mov" => "video/quicktime",
+        "mkv" => "video/x-matroska",
+        "mp3" => "audio/mpeg",
+        "m4a" => "audio/mp4",
+        "ogg" | "oga" | "opus" => "audio/ogg",
+        "wav" => "audio/wav",
+        "flac" => "audio/flac",
+        _ => "application/octet-stream",
+    }
+}
+
+fn plain(status: StatusCode, body: &'static str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "text/plain")
+        .body(Body::from(body))
+        .expect("valid response")
+}
+
 /// Build a Notion database query filter for a unique property.
 /// Supports: title, rich_text, formula(string output).
 fn build_unique_filter(prop_name: &str, prop_type: &str, key: &str) -> Value {
@@ -612,3 +1371,443 @@ fn resolve_prop_name_and_type(
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDLE: Duration = Duration::from_secs(5);
+
+    fn file(name: &str) -> FileEntry {
+        FileEntry {
+            name: name.to_string(),
+            url: format!("http://127.0.0.1:9/{}", name),
+        }
+    }
+
+    fn row(ord: i64, text: Option<&str>, files: Vec<FileEntry>) -> Row {
+        Row {
+            ord,
+            text: text.map(str::to_string),
+            files,
+            video_local_rel: None,
+            audio_local_rel: None,
+        }
+    }
+
+    /// Serve `responses` (raw HTTP) one per connection, in order; returns the
+    /// base URL and the number of requests seen.
+    async fn serve_in_order(
+        responses: Vec<&'static str>,
+    ) -> (String, std::sync::Arc<std::sync::atomic::AtomicUsize>) {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let hits = std::sync::Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let n = counter.fetch_add(1, Ordering::SeqCst);
+                let mut buf = [0u8; 4096];
+                let _ = stream.read(&mut buf).await;
+                let response = responses.get(n).copied().unwrap_or(responses[0]);
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = stream.shutdown().await;
+            }
+        });
+        (url, hits)
+    }
+
+    #[tokio::test]
+    async fn download_retries_server_error_then_writes_complete_file() {
+        let (url, hits) = serve_in_order(vec![
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Length: 11\r\nConnection: close\r\n\r\nvideo-bytes",
+        ])
+        .await;
+        let tmp = tempfile::tempdir().unwrap();
+        let dest = tmp.path().join("video").join("3.mp4");
+
+        download_file_to(
+            &reqwest::Client::new(),
+            IDLE,
+            &format!("{}/v.mp4", url),
+            &dest,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 2);
+        assert_eq!(std::fs::read(&dest).unwrap(), b"video-bytes");
+        assert!(!tmp.path().join("video").join("3.mp4.part").exists());
+    }
+
+    #[tokio::test]
+    async fn download_streams_large_body_to_disk() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // 3 MiB sent in 64 KiB writes, so the client sees many chunks
+        let body: Vec<u8> = (0..3 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/big.mp4", listener.local_addr().unwrap());
+        let served = body.clone();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            let _ = stream.read(&mut buf).await;
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                served.len()
+            );
+            stream.write_all(head.as_bytes()).await.unwrap();
+            for chunk in served.chunks(64 * 1024) {
+                stream.write_all(chunk).await.unwrap();
+            }
+            let _ = stream.shutdown().await;
+        });
+        let tmp = tempfile::tempdir().unwrap();
+        let dest = tmp.path().join("big.mp4");
+
+        download_file_to(&reqwest::Client::new(), IDLE, &url, &dest)
+            .await
+            .unwrap();
+
+        assert!(std::fs::read(&dest).unwrap() == body);
+        assert!(!tmp.path().join("big.mp4.part").exists());
+    }
+
+    #[tokio::test]
+    async fn download_retries_when_the_body_stalls() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/v.mp4", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            // First attempt: half the body, then silence
+            let (mut stalled, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            let _ = stalled.read(&mut buf).await;
+            stalled
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 11\r\n\r\nvideo")
+                .await
+                .unwrap();
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let _ = stream.read(&mut buf).await;
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 11\r\nConnection: close\r\n\r\nvideo-bytes")
+                .await
+                .unwrap();
+            let _ = stream.shutdown().await;
+            drop(stalled);
+        });
+        let tmp = tempfile::tempdir().unwrap();
+        let dest = tmp.path().join("3.mp4");
+
+        download_file_to(
+            &reqwest::Client::new(),
+            Duration::from_millis(200),
+            &url,
+            &dest,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(std::fs::read(&dest).unwrap(), b"video-bytes");
+    }
+
+    #[test]
+    fn progress_line_shows_share_when_size_is_known() {
+        let mib = 1024 * 1024;
+        assert_eq!(
+            progress_line(32 * mib, Some(128 * mib)),
+            "32.0 MiB of 128.0 MiB (25%)"
+        );
+        assert_eq!(progress_line(mib / 2, None), "0.5 MiB");
+        assert_eq!(progress_line(0, Some(0)), "0.0 MiB");
+    }
+
+    #[tokio::test]
+    async fn download_gives_up_on_client_error_without_leaving_a_file() {
+        let (url, hits) = serve_in_order(vec![
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ])
+        .await;
+        let tmp = tempfile::tempdir().unwrap();
+        let dest = tmp.path().join("3.mp4");
+
+        let err = download_file_to(
+            &reqwest::Client::new(),
+            IDLE,
+            &format!("{}/v.mp4", url),
+            &dest,
+        )
+        .await
+        .unwrap_err();
+
+        assert!(format!("{:#}", err).contains("404"));
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert!(!dest.exists());
+        assert!(!tmp.path().join("3.mp4.part").exists());
+    }
+
+    #[tokio::test]
+    async fn process_rows_without_download_writes_nothing() {
+        let tmp = tempfile::tempdir().unwrap();
+        let video_dir = tmp.path().join("video");
+        let http = reqwest::Client::new();
+        let mut rows = vec![
+            row(1, Some("hello"), vec![]),
+            row(2, None, vec![file("a.jpg")]),
+            row(3, None, vec![file("b.mp4")]),
+            row(4, None, vec![file("c.jpg"), file("c.mov")]),
+        ];
+
+        let videos = process_rows(
+            &http,
+            IDLE,
+            &mut rows,
+            &video_dir,
+            &tmp.path().join("audio"),
+            false,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(videos, 2);
+        assert!(!video_dir.exists());
+        assert_eq!(rows[1].video_local_rel, None);
+        assert_eq!(rows[2].video_local_rel.as_deref(), Some("video/3.mp4"));
+        assert_eq!(rows[3].video_local_rel.as_deref(), Some("video/4.mov"));
+    }
+
+    #[tokio::test]
+    async fn process_rows_still_validates_without_download() {
+        let tmp = tempfile::tempdir().unwrap();
+        let http = reqwest::Client::new();
+        let mut rows = vec![row(5, None, vec![file("b.mp4"), file("a.jpg")])];
+
+        let err = process_rows(&http, IDLE, &mut rows, tmp.path(), tmp.path(), false)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("must be [image, video]"));
+    }
+
+    #[tokio::test]
+    async fn audio_only_row_is_valid_and_plays_from_url_or_local_copy() {
+        let tmp = tempfile::tempdir().unwrap();
+        let audio_dir = tmp.path().join("audio");
+        std::fs::create_dir_all(&audio_dir).unwrap();
+        std::fs::write(audio_dir.join("7.ogg"), b"already here").unwrap();
+        let http = reqwest::Client::new();
+        let mut rows = vec![row(6, None, vec![file("voice.mp3")])];
+
+        let videos = process_rows(&http, IDLE, &mut rows, tmp.path(), &audio_dir, false)
+            .await
+            .unwrap();
+        assert_eq!(videos, 0);
+        assert_eq!(rows[0].audio_local_rel, None);
+        let html = render_html("k", &rows);
+        assert!(html.contains(
+            "<audio controls src=\"http://127.0.0.1:9/voice.mp3\" title=\"voice.mp3\"></audio>"
+        ));
+
+        // Port 9 refuses connections, so only the copy on disk can be used
+        let mut rows = vec![row(7, None, vec![file("note.ogg")])];
+        process_rows(&http, IDLE, &mut rows, tmp.path(), &audio_dir, true)
+            .await
+            .unwrap();
+        assert_eq!(rows[0].audio_local_rel.as_deref(), Some("audio/7.ogg"));
+        assert!(render_html("k", &rows).contains("<audio controls src=\"audio/7.ogg\""));
+
+        let mut rows = vec![row(8, None, vec![file("notes.txt")])];
+        let err = process_rows(&http, IDLE, &mut rows, tmp.path(), &audio_dir, false)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("not image/video/audio"));
+    }
+
+    #[test]
+    fn only_missing_videos_are_downloaded() {
+        assert!(needs_download(true, false));
+        assert!(!needs_download(true, true), "existing video is kept");
+        assert!(!needs_download(false, false), "--no-download wins");
+        assert!(!needs_download(false, true));
+    }
+
+    #[tokio::test]
+    async fn process_rows_keeps_existing_videos_and_skips_their_download() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::write(tmp.path().join("3.mp4"), b"already here").unwrap();
+        let http = reqwest::Client::new();
+        // Port 9 refuses connections, so any download attempt would fail
+        let mut rows = vec![row(3, None, vec![file("b.mp4")])];
+
+        let videos = process_rows(&http, IDLE, &mut rows, tmp.path(), tmp.path(), true)
+            .await
+            .unwrap();
+
+        assert_eq!(videos, 1);
+        assert_eq!(rows[0].video_local_rel.as_deref(), Some("video/3.mp4"));
+        assert_eq!(
+            std::fs::read(tmp.path().join("3.mp4")).unwrap(),
+            b"already here"
+        );
+    }
+
+    #[tokio::test]
+    async fn write_atomically_replaces_file_without_leftovers() {
+        let tmp = tempfile::tempdir().unwrap();
+        let index = tmp.path().join("index.html");
+        std::fs::write(&index, "old").unwrap();
+
+        write_atomically(&index, b"new").await.unwrap();
+
+        assert_eq!(std::fs::read_to_string(&index).unwrap(), "new");
+        assert!(!tmp.path().join("index.html.tmp").exists());
+    }
+
+    #[test]
+    fn slugify_replaces_unsafe_characters() {
+        assert_eq!(slugify("trip-2024_01"), "trip-2024_01");
+        assert_eq!(slugify("a/b\\c: d?"), "a-b-c-d");
+        assert_eq!(slugify("../etc"), "etc");
+        assert_eq!(slugify("旅行 日记"), "旅行-日记");
+        assert_eq!(slugify("///"), "batch");
+
+        let mut used = HashSet::new();
+        assert_eq!(unique_dir_name("a/b", &mut used), "a-b");
+        assert_eq!(unique_dir_name("a b", &mut used), "a-b-2");
+        assert_eq!(unique_dir_name("a?b", &mut used), "a-b-3");
+    }
+
+    #[test]
+    fn all_index_links_each_batch_directory() {
+        let html = render_all_index(&[
+            ("trip".to_string(), "trip".to_string()),
+            ("a<b".to_string(), "a-b".to_string()),
+        ]);
+        assert!(html.contains("<li><a href=\"trip/index.html\">trip</a></li>"));
+        assert!(html.contains("<li><a href=\"a-b/index.html\">a&lt;b</a></li>"));
+        assert_eq!(html.matches("<li>").count(), 2);
+    }
+
+    async fn get(root: &Path, path: &str, range: Option<&str>) -> (StatusCode, String, Vec<u8>) {
+        let mut req = Request::get(path);
+        if let Some(range) = range {
+            req = req.header(RANGE, range);
+        }
+        let res = serve_static(root, &req.body(Body::empty()).unwrap()).await;
+        let status = res.status();
+        let ctype = res.headers()[CONTENT_TYPE].to_str().unwrap().to_string();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        (status, ctype, body.to_vec())
+    }
+
+    #[tokio::test]
+    async fn static_server_serves_index_and_videos_with_content_types() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::write(tmp.path().join("index.html"), "<h1>trip</h1>").unwrap();
+        std::fs::create_dir_all(tmp.path().join("video")).unwrap();
+        std::fs::write(tmp.path().join("video/3.mp4"), b"0123456789").unwrap();
+        std::fs::create_dir_all(tmp.path().join("旅行")).unwrap();
+        std::fs::write(tmp.path().join("旅行/index.html"), "<h1>旅行</h1>").unwrap();
+
+        let (status, ctype, body) = get(tmp.path(), "/", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(ctype, "text/html; charset=utf-8");
+        assert_eq!(body, b"<h1>trip</h1>");
+        assert_eq!(get(tmp.path(), "/index.html", None).await.2, body);
+
+        let (status, ctype, body) = get(tmp.path(), "/video/3.mp4", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(ctype, "video/mp4");
+        assert_eq!(body, b"0123456789");
+
+        let (status, ctype, body) = get(tmp.path(), "/video/3.mp4", Some("bytes=2-5")).await;
+        assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(ctype, "video/mp4");
+        assert_eq!(body, b"2345");
+
+        let (status, _, body) = get(tmp.path(), "/%E6%97%85%E8%A1%8C/", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(String::from_utf8(body).unwrap(), "<h1>旅行</h1>");
+    }
+
+    #[tokio::test]
+    async fn static_server_stays_inside_its_root() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().join("html");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(tmp.path().join("config.yaml"), "secret").unwrap();
+
+        assert_eq!(
+            get(&root, "/../config.yaml", None).await.0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            get(&root, "/%2E%2E/config.yaml", None).await.0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            get(&root, "/missing.mp4", None).await.0,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn parse_range_handles_open_and_suffix_ranges() {
+        assert_eq!(parse_range("bytes=0-", 10), Some((0, 9)));
+        assert_eq!(parse_range("bytes=4-100", 10), Some((4, 9)));
+        assert_eq!(parse_range("bytes=-3", 10), Some((7, 9)));
+        assert_eq!(parse_range("bytes=12-", 10), None);
+        assert_eq!(parse_range("bytes=0-1,4-5", 10), None);
+        assert_eq!(parse_range("items=0-1", 10), None);
+    }
+
+    #[test]
+    fn extract_key_reads_title_and_formula_values() {
+        let title = json!({ "title": [ { "plain_text": "tr" }, { "plain_text": "ip" } ] });
+        assert_eq!(extract_key(&title, "title").as_deref(), Some("trip"));
+        let formula = json!({ "formula": { "string": "k-1" } });
+        assert_eq!(extract_key(&formula, "formula").as_deref(), Some("k-1"));
+        assert_eq!(extract_key(&json!({ "title": [] }), "title"), None);
+    }
+
+    #[tokio::test]
+    async fn property_names_are_cached_until_refresh() {
+        const MAIN: &str = "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nConnection: close\r\n\r\n\
+            {\"id\":\"m\",\"title\":[],\"properties\":{\"Key\":{\"id\":\"unique\",\"type\":\"rich_text\"}}}";
+        const RESOURCE: &str =
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nConnection: close\r\n\r\n\
+            {\"id\":\"r\",\"title\":[],\"properties\":{\
+            \"Main\":{\"id\":\"rel-parent\",\"type\":\"relation\"},\
+            \"No\":{\"id\":\"res-order\",\"type\":\"number\"},\
+            \"Text\":{\"id\":\"res-text\",\"type\":\"rich_text\"},\
+            \"Media\":{\"id\":\"res-media\",\"type\":\"files\"}}}";
+        let (url, hits) = serve_in_order(vec![MAIN, RESOURCE, MAIN, RESOURCE]).await;
+        let tmp = tempfile::tempdir().unwrap();
+        let mut cfg: Config = serde_yaml::from_str(config::example()).unwrap();
+        cfg.app.data_dir = tmp.path().to_string_lossy().into_owned();
+        let notion = NotionClient::with_base_url(
+            "token".into(),
+            "2022-06-28".into(),
+            reqwest::Url::parse(&url).unwrap(),
+        );
+        let hits = || hits.load(std::sync::atomic::Ordering::SeqCst);
+
+        let props = ExportProps::cached(&notion, &cfg, false).await.unwrap();
+        assert_eq!(props.unique_prop_name, "Key");
+        assert_eq!(props.unique_prop_type, "rich_text");
+        assert_eq!(props.media_prop, "Media");
+        assert_eq!(hits(), 2);
+
+        let cached = ExportProps::cached(&notion, &cfg, false).await.unwrap();
+        assert_eq!(cached.rel_prop, "Main");
+        assert_eq!(hits(), 2);
+
+        ExportProps::cached(&notion, &cfg, true).await.unwrap();
+        assert_eq!(hits(), 4);
+    }
+}
@@ -0,0 +1,323 @@
+use anyhow::{anyhow, Context, Result};
+use clap::Parser;
+use serde::Deserialize;
+use std::path::PathBuf;
+use tracing::info;
+
+use tg_watchbot::config;
+use tg_watchbot::db;
+use tg_watchbot::logging;
+use tg_watchbot::model::ResourceKind;
+
+#[derive(Debug, Parser)]
+#[command(
+    author,
+    version,
+    about = "Backfill committed batches and their resources from a JSON file"
+)]
+struct Args {
+    /// Path to YAML config file
+    #[arg(long, default_value = "config.yaml")]
+    config: PathBuf,
+
+    /// JSON document describing the batches to import
+    #[arg(long)]
+    from_json: PathBuf,
+
+    /// Telegram user id that owns the imported batches
+    #[arg(long)]
+    user: i64,
+}
+
+/// `{"batches": [{"title": "Trip", "items": [{"kind": "text", "text": "..."}]}]}`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ImportDoc {
+    batches: Vec<ImportBatch>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ImportBatch {
+    /// Batches without a title get `notion.default_title` when synced.
+    #[serde(default)]
+    title: Option<String>,
+    items: Vec<ImportItem>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ImportItem {
+    kind: ImportKind,
+    /// The text of a `text` item, or the caption of a photo or video.
+    #[serde(default)]
+    text: Option<String>,
+    /// Where a photo or video lives; linked from Notion, not downloaded.
+    #[serde(default)]
+    media_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum ImportKind {
+    Text,
+    Photo,
+    Video,
+}
+
+impl ImportKind {
+    fn resource_kind(self) -> ResourceKind {
+        match self {
+            ImportKind::Text => ResourceKind::Text,
+            ImportKind::Photo => ResourceKind::Photo,
+            ImportKind::Video => ResourceKind::Video,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct ImportSummary {
+    batches: usize,
+    resources: usize,
+}
+
+/// Parse `raw`; syntax and schema errors name the line and column.
+fn parse_doc(raw: &str) -> Result<ImportDoc> {
+    serde_json::from_str(raw).map_err(|err| anyhow!("invalid import file: {}", err))
+}
+
+/// Every problem in `doc`, as `batch N item M: ...` (1-based).
+fn validate_doc(doc: &ImportDoc) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (b, batch) in doc.batches.iter().enumerate() {
+        if batch.items.is_empty() {
+            problems.push(format!("batch {}: has no items", b + 1));
+        }
+        for (i, item) in batch.items.iter().enumerate() {
+            let at = format!("batch {} item {}", b + 1, i + 1);
+            let has_text = item.text.as_deref().is_some_and(|t| !t.trim().is_empty());
+            match (item.kind, item.media_url.as_deref()) {
+                (ImportKind::Text, None) if !has_text => {
+                    problems.push(format!("{}: text item needs text", at))
+                }
+                (ImportKind::Text, Some(_)) => {
+                    problems.push(format!("{}: text item cannot have media_url", at))
+                }
+                (ImportKind::Photo | ImportKind::Video, None) => {
+                    problems.push(format!("{}: media item needs media_url", at))
+                }
+                (ImportKind::Photo | ImportKind::Video, Some(url))
+                    if !url.starts_with("http://") && !url.starts_with("https://") =>
+                {
+                    problems.push(format!("{}: media_url must be an http(s) URL", at))
+                }
+                _ => {}
+            }
+        }
+    }
+    problems
+}
+
+/// Create each batch, add its items and commit it so the outbox pushes it
+/// like a batch sent through Telegram. Imported resources come from no
+/// Telegram message: they get message id 0 and no chat, which the per-chat
+/// message index does not constrain.
+async fn import(pool: &db::Pool, tg_user_id: i64, doc: &ImportDoc) -> Result<ImportSummary> {
+    let problems = validate_doc(doc);
+    if !problems.is_empty() {
+        return Err(anyhow!("invalid import file:\n{}", problems.join("\n")));
+    }
+    let user_id = db::get_or_create_user(pool, tg_user_id, None, None).await?;
+    if db::current_open_batch_id(pool, user_id).await?.is_some() {
+        return Err(anyhow!(
+            "user {} has an open batch; commit or roll it back first",
+            tg_user_id
+        ));
+    }
+    let mut summary = ImportSummary::default();
+    for (b, batch) in doc.batches.iter().enumerate() {
+        let title = batch.title.as_deref().filter(|t| !t.trim().is_empty());
+        let batch_id = db::open_batch_routed(pool, user_id, None, None, title).await?;
+        for (i, item) in batch.items.iter().enumerate() {
+            let content = match item.kind {
+                ImportKind::Text => item.text.as_deref().unwrap_or_default(),
+                ImportKind::Photo | ImportKind::Video => {
+                    item.media_url.as_deref().unwrap_or_default()
+                }
+            };
+            let origin = db::ResourceOrigin {
+                media_url: item.media_url.as_deref(),
+                text: item.text.as_deref(),
+                ..Default::default()
+            };
+            let inserted = db::insert_resource_from(
+                pool,
+                user_id,
+                Some(batch_id),
+                item.kind.resource_kind(),
+                content,
+                0,
+                &origin,
+            )
+            .await;
+            if let Err(err) = inserted {
+                db::rollback_batch(pool, user_id).await?;
+                return Err(err.context(format!("batch {} item {}", b + 1, i + 1)));
+            }
+            summary.resources += 1;
+        }
+        db::commit_batch(pool, user_id, title).await?;
+        summary.batches += 1;
+    }
+    Ok(summary)
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    logging::init_tracing_from_env()?;
+
+    let args = Args::parse();
+    let cfg = config::load(Some(&args.config))?;
+    let raw = std::fs::read_to_string(&args.from_json)
+        .with_context(|| format!("failed to read {}", args.from_json.display()))?;
+    let doc = parse_doc(&raw)?;
+
+    let data_dir = cfg.app.resolved_data_dir();
+    let database_url = std::env::var("DATABASE_URL")
+        .unwrap_or_else(|_| format!("sqlite://{}/watchbot.db", data_dir));
+    let pool = db::init_pool_with(&database_url, cfg.app.db_max_connections).await?;
+    db::run_migrations(&pool).await?;
+
+    let summary = import(&pool, args.user, &doc).await?;
+    info!(
+        batches = summary.batches,
+        resources = summary.resources,
+        "Imported batches; the bot's outbox worker syncs them to Notion"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn setup_pool() -> db::Pool {
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        db::run_migrations(&pool).await.unwrap();
+        pool
+    }
+
+    #[tokio::test]
+    async fn imports_committed_batches_with_outbox_tasks() {
+        let pool = setup_pool().await;
+        let doc = parse_doc(
+            r#"{"batches": [
+                {"title": "Trip", "items": [
+                    {"kind": "text", "text": "Day one"},
+                    {"kind": "photo", "media_url": "https://cdn.example/a.jpg", "text": "Beach"}
+                ]},
+                {"items": [{"kind": "video", "media_url": "https://cdn.example/b.mp4"}]}
+            ]}"#,
+        )
+        .unwrap();
+
+        let summary = import(&pool, 5, &doc).await.unwrap();
+        assert_eq!(
+            summary,
+            ImportSummary {
+                batches: 2,
+                resources: 3
+            }
+        );
+
+        let batches: Vec<(String, Option<String>)> =
+            sqlx::query_as("SELECT state, title FROM batches ORDER BY id")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(
+            batches,
+            vec![
+                ("COMMITTED".to_string(), Some("Trip".to_string())),
+                ("COMMITTED".to_string(), None)
+            ]
+        );
+        let resources: Vec<(String, Option<String>, Option<String>, i64)> = sqlx::query_as(
+            "SELECT kind, text, media_url, tg_message_id FROM resources ORDER BY id",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            resources,
+            vec![
+                ("text".to_string(), Some("Day one".to_string()), None, 0),
+                (
+                    "photo".to_string(),
+                    Some("Beach".to_string()),
+                    Some("https://cdn.example/a.jpg".to_string()),
+                    0
+                ),
+                (
+                    "video".to_string(),
+                    None,
+                    Some("https://cdn.example/b.mp4".to_string()),
+                    0
+                ),
+            ]
+        );
+        let kinds: Vec<String> = sqlx::query_scalar("SELECT kind FROM outbox ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            kinds,
+            vec![
+                "push_batch",
+                "push_resource",
+                "push_resource",
+                "push_batch",
+                "push_resource"
+            ]
+        );
+
+        // Importing again adds every item again rather than matching old ones
+        import(&pool, 5, &doc).await.unwrap();
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM resources")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 6);
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_documents_without_writing() {
+        let err = parse_doc("{\"batches\": [\n  {\"items\": [{\"kind\": \"audio\"}]}\n]}")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("line 2"), "{}", err);
+
+        let pool = setup_pool().await;
+        let doc = parse_doc(
+            r#"{"batches": [
+                {"items": [
+                    {"kind": "text"},
+                    {"kind": "photo"},
+                    {"kind": "video", "media_url": "file:///tmp/b.mp4"}
+                ]},
+                {"items": []}
+            ]}"#,
+        )
+        .unwrap();
+        let err = import(&pool, 5, &doc).await.unwrap_err().to_string();
+        assert!(err.contains("batch 1 item 1: text item needs text"));
+        assert!(err.contains("batch 1 item 2: media item needs media_url"));
+        assert!(err.contains("batch 1 item 3: media_url must be an http(s) URL"));
+        assert!(err.contains("batch 2: has no items"));
+        let batches: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM batches")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(batches, 0);
+    }
+}
@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use clap::Parser;
 use serde_json::to_string_pretty;
 use std::collections::HashSet;
@@ -11,8 +11,14 @@ use tracing::{error, info, warn};
 use tg_watchbot::config;
 use tg_watchbot::db;
 use tg_watchbot::handlers;
-use tg_watchbot::model::BatchState;
-use tg_watchbot::notion::{build_main_page_request, build_resource_page_request, NotionIds};
+use tg_watchbot::logging;
+use tg_watchbot::model::{BatchState, OutboxKind};
+use tg_watchbot::notion::{
+    build_main_page_request, build_resource_page_request, build_text_properties, NotionIds,
+    ResourceMeta,
+};
+use tg_watchbot::outbox;
+use tg_watchbot::thumbnail::{self, FfmpegStatus};
 
 #[derive(Debug, Parser)]
 #[command(
@@ -28,15 +34,59 @@ struct Args {
     /// When set, print Notion payloads instead of sending them.
     #[arg(long)]
     dry_run_notion: bool,
+
+    /// Print at most this many payloads per message; the rest wait for the next one
+    #[arg(long, requires = "dry_run_notion")]
+    limit: Option<usize>,
+
+    /// Skip the first N pending payloads of every flush
+    #[arg(long, default_value_t = 0, requires = "dry_run_notion")]
+    offset: usize,
+
+    /// Only print payloads of this outbox kind (push_batch or push_resource)
+    #[arg(long, value_parser = parse_only_kind, requires = "dry_run_notion")]
+    only_kind: Option<OutboxKind>,
+}
+
+fn parse_only_kind(raw: &str) -> Result<OutboxKind> {
+    match OutboxKind::parse(raw) {
+        Some(kind @ (OutboxKind::PushBatch | OutboxKind::PushResource)) => Ok(kind),
+        _ => Err(anyhow!(
+            "expected push_batch or push_resource, got '{}'",
+            raw
+        )),
+    }
+}
+
+/// Which pending tasks one dry-run flush prints.
+#[derive(Debug, Clone, Copy, Default)]
+struct PrintSlice {
+    offset: usize,
+    limit: Option<usize>,
+    only_kind: Option<OutboxKind>,
+}
+
+/// The `(id, kind, ref_id)` tasks to print: unseen ones of the wanted kind,
+/// after skipping `offset` and capped at `limit`. Tasks left out stay unseen.
+fn select_tasks(
+    tasks: Vec<(i64, String, i64)>,
+    seen: &HashSet<i64>,
+    slice: &PrintSlice,
+) -> Vec<(i64, String, i64)> {
+    tasks
+        .into_iter()
+        .filter(|(id, _, _)| !seen.contains(id))
+        .filter(|(_, kind, _)| {
+            slice.only_kind.is_none() || OutboxKind::parse(kind) == slice.only_kind
+        })
+        .skip(slice.offset)
+        .take(slice.limit.unwrap_or(usize::MAX))
+        .collect()
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .with_target(false)
-        .compact()
-        .init();
+    logging::init_tracing_from_env()?;
 
     let args = Args::parse();
     let cfg = config::load(Some(&args.config))?;
@@ -46,14 +96,21 @@ async fn main() -> Result<()> {
         cfg.notion.token.clone(),
         cfg.notion.version.clone(),
     );
-    let notion_ids = Arc::new(notion_client.resolve_property_ids(&cfg).await?);
+    let notion_ids = Arc::new(
+        notion_client
+            .resolve_property_ids_cached(&cfg, false)
+            .await?,
+    );
 
     let data_dir = cfg.app.resolved_data_dir();
     let database_url = std::env::var("DATABASE_URL")
         .unwrap_or_else(|_| format!("sqlite://{}/watchbot.db", data_dir));
 
-    let pool = db::init_pool(&database_url).await?;
+    let pool = db::init_pool_with(&database_url, cfg.app.db_max_connections).await?;
     db::run_migrations(&pool).await?;
+    if cfg.app.full_text_search {
+        db::enable_fts(&pool).await?;
+    }
 
     let dry_run_state: Option<Arc<Mutex<HashSet<i64>>>> = if args.dry_run_notion {
         Some(Arc::new(Mutex::new(HashSet::new())))
@@ -61,32 +118,81 @@ async fn main() -> Result<()> {
         None
     };
 
+    let ffmpeg = thumbnail::ffmpeg_status().await;
+    if ffmpeg == FfmpegStatus::Missing {
+        warn!("ffmpeg missing; video messages will be rejected");
+    }
+    let handler_opts = Arc::new(handlers::HandlerOptions {
+        data_dir: data_dir.clone(),
+        notion_ids: Some(notion_ids.as_ref().clone()),
+        ffmpeg,
+        thumbnail_format: cfg.app.thumbnail_format,
+        max_media_bytes: cfg.app.max_media_bytes,
+        max_batch_items: cfg.app.max_batch_items,
+        global_sequence: cfg.app.global_sequence,
+        full_text_search: cfg.app.full_text_search,
+        // This binary pushes batches itself, not through the outbox worker
+        notify_on_sync: false,
+        ack_mode: cfg.app.ack_mode,
+        admin_users: cfg.telegram.admin_users.clone(),
+        // Replayed /commit messages have nobody to press the buttons
+        confirm_commit: false,
+        rollback_mode: cfg.app.rollback_mode,
+        notion_routes: notion_client.resolve_route_ids_cached(&cfg, false).await?,
+        user_locks: handlers::UserLocks::default(),
+        // Nothing waits on it here; batches are pushed inline
+        outbox_signal: outbox::OutboxSignal::default(),
+    });
+
     let bot = Bot::new(cfg.telegram.bot_token.clone());
     let allowed = cfg.telegram.allowed_users.clone();
     let dry_run_flag = args.dry_run_notion;
+    let print_slice = PrintSlice {
+        offset: args.offset,
+        limit: args.limit,
+        only_kind: args.only_kind,
+    };
+    let default_title: Arc<str> = Arc::from(cfg.notion.default_title());
+    let parse_markdown = cfg.notion.parse_markdown;
+    let main_page_icon: Option<Arc<str>> = cfg.notion.main_page_icon.as_deref().map(Arc::from);
 
     info!(database_url=%database_url, data_dir=%data_dir, "starting ingest-only telegram bot");
     teloxide::repl(bot, move |bot: Bot, msg: Message| {
         let pool = pool.clone();
-        let data_dir = data_dir.clone();
+        let handler_opts = handler_opts.clone();
         let allowed = allowed.clone();
         let notion_ids = notion_ids.clone();
         let dry_run_state = dry_run_state.clone();
+        let default_title = default_title.clone();
+        let main_page_icon = main_page_icon.clone();
         async move {
+            match handlers::reply_whoami(&bot, &msg, &allowed).await {
+                Ok(true) => return respond(()),
+                Ok(false) => {}
+                Err(err) => error!(?err, "failed to answer /whoami"),
+            }
             if let Some(from) = msg.from() {
-                let uid = from.id.0 as i64;
-                if !allowed.is_empty() && !allowed.contains(&uid) {
+                if !handlers::is_allowed(from.id.0 as i64, &allowed) {
                     return respond(());
                 }
             }
 
-            if let Err(err) = handlers::handle_update(&bot, &pool, &data_dir, &msg).await {
+            if let Err(err) = handlers::handle_update(&bot, &pool, &handler_opts, &msg).await {
                 error!(?err, "failed to ingest message");
             }
 
             if dry_run_flag {
                 if let Some(state) = &dry_run_state {
-                    if let Err(err) = print_pending_notion_payloads(&pool, &notion_ids, state).await
+                    if let Err(err) = print_pending_notion_payloads(
+                        &pool,
+                        &notion_ids,
+                        &default_title,
+                        main_page_icon.as_deref(),
+                        parse_markdown,
+                        &print_slice,
+                        state,
+                    )
+                    .await
                     {
                         warn!(?err, "failed to render Notion dry-run payloads");
                     }
@@ -103,6 +209,10 @@ async fn main() -> Result<()> {
 async fn print_pending_notion_payloads(
     pool: &sqlx::SqlitePool,
     notion_ids: &NotionIds,
+    default_title: &str,
+    main_page_icon: Option<&str>,
+    parse_markdown: bool,
+    slice: &PrintSlice,
     printed_ids: &Arc<Mutex<HashSet<i64>>>,
 ) -> Result<()> {
     let tasks = db::list_due_outbox(pool).await?;
@@ -110,36 +220,40 @@ async fn print_pending_notion_payloads(
         return Ok(());
     }
 
-    let mut new_tasks = Vec::new();
-    {
+    let new_tasks = {
         let mut seen = printed_ids.lock().await;
-        for (id, kind, ref_id) in tasks {
-            if seen.insert(id) {
-                new_tasks.push((id, kind, ref_id));
-            }
-        }
-    }
+        let tasks = tasks
+            .into_iter()
+            .map(|(id, kind, ref_id, _last_error)| (id, kind, ref_id))
+            .collect();
+        let selected = select_tasks(tasks, &seen, slice);
+        seen.extend(selected.iter().map(|(id, _, _)| *id));
+        selected
+    };
 
     for (id, kind, ref_id) in new_tasks {
-        match kind.as_str() {
-            "push_batch" => {
+        match OutboxKind::parse(&kind) {
+            Some(OutboxKind::PushBatch) => {
                 let batch = db::fetch_batch_for_outbox(pool, ref_id).await?;
                 if batch.state != BatchState::Committed {
                     warn!(batch_id = ref_id, state = ?batch.state, "batch not committed yet; skipping dry-run payload");
                     continue;
                 }
-                let title = batch
-                    .title
-                    .as_deref()
-                    .filter(|t| !t.trim().is_empty())
-                    .unwrap_or("Untitled");
-                let body = build_main_page_request(notion_ids, title);
+                let title =
+                    outbox::batch_title(batch.title.as_deref(), default_title, &batch.created_at);
+                let cover_url = outbox::batch_cover_url(pool, ref_id).await?;
+                let body = build_main_page_request(
+                    notion_ids,
+                    &title,
+                    main_page_icon,
+                    cover_url.as_deref(),
+                );
                 println!(
                     "\n[outbox #{id}] Notion main page request (batch {ref_id})\n{}",
                     to_string_pretty(&body)?
                 );
             }
-            "push_resource" => {
+            Some(OutboxKind::PushResource) => {
                 let resource = db::fetch_resource_for_outbox(pool, ref_id).await?;
                 let parent_page = if let Some(batch_id) = resource.batch_id {
                     match resource.batch_state {
@@ -180,15 +294,35 @@ async fn print_pending_notion_payloads(
                     resource.media_name.as_deref().filter(|s| !s.is_empty()),
                     media_url.as_deref(),
                     None,
+                    &ResourceMeta {
+                        source_url: resource.source_url.clone(),
+                        tg_date: resource.tg_date,
+                        kind: Some(resource.kind),
+                        dimensions: resource.dimensions,
+                        parse_markdown,
+                    },
                 );
                 println!(
                     "\n[outbox #{id}] Notion resource request (resource {ref_id})\n{}",
                     to_string_pretty(&body)?
                 );
             }
-            other => {
+            Some(OutboxKind::UpdateResource) => {
+                let resource = db::fetch_resource_for_outbox(pool, ref_id).await?;
+                let page_id = resource.notion_page_id.as_deref().unwrap_or("?");
+                let body = build_text_properties(
+                    notion_ids,
+                    resource.text.as_deref().unwrap_or_default(),
+                    parse_markdown,
+                );
+                println!(
+                    "\n[outbox #{id}] Notion update of page {page_id} (resource {ref_id})\n{}",
+                    to_string_pretty(&body)?
+                );
+            }
+            None => {
                 warn!(
-                    task_kind = other,
+                    task_kind = %kind,
                     "unknown outbox kind for dry-run; ignoring"
                 );
             }
@@ -211,3 +345,61 @@ fn sanitize_media_url(raw: Option<&str>) -> Option<String> {
     }
     Some(url.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tasks() -> Vec<(i64, String, i64)> {
+        vec![
+            (1, "push_batch".into(), 10),
+            (2, "push_resource".into(), 20),
+            (3, "push_resource".into(), 21),
+            (4, "update_resource".into(), 20),
+            (5, "push_resource".into(), 22),
+        ]
+    }
+
+    fn ids(selected: &[(i64, String, i64)]) -> Vec<i64> {
+        selected.iter().map(|(id, _, _)| *id).collect()
+    }
+
+    #[test]
+    fn select_tasks_skips_seen_and_slices() {
+        let seen = HashSet::from([1]);
+        let all = select_tasks(tasks(), &seen, &PrintSlice::default());
+        assert_eq!(ids(&all), vec![2, 3, 4, 5]);
+
+        let slice = PrintSlice {
+            offset: 1,
+            limit: Some(2),
+            ..PrintSlice::default()
+        };
+        assert_eq!(ids(&select_tasks(tasks(), &seen, &slice)), vec![3, 4]);
+    }
+
+    #[test]
+    fn select_tasks_filters_kind_before_slicing() {
+        let slice = PrintSlice {
+            offset: 1,
+            limit: Some(1),
+            only_kind: Some(OutboxKind::PushResource),
+        };
+        let selected = select_tasks(tasks(), &HashSet::new(), &slice);
+        assert_eq!(ids(&selected), vec![3]);
+    }
+
+    #[test]
+    fn only_kind_accepts_push_kinds() {
+        assert_eq!(
+            parse_only_kind("push_batch").unwrap(),
+            OutboxKind::PushBatch
+        );
+        assert_eq!(
+            parse_only_kind("push_resource").unwrap(),
+            OutboxKind::PushResource
+        );
+        assert!(parse_only_kind("update_resource").is_err());
+        assert!(parse_only_kind("nope").is_err());
+    }
+}
@@ -3,6 +3,7 @@ use std::path::PathBuf;
 
 use anyhow::Result;
 use clap::Parser;
+use serde_json::json;
 use tg_watchbot::config::Config;
 use tg_watchbot::notion::NotionClient;
 
@@ -13,8 +14,16 @@ struct Args {
     config: PathBuf,
 
     /// Database ID to inspect
+    #[arg(long, required_unless_present = "page")]
+    db_id: Option<String>,
+
+    /// Page ID to inspect (properties and block children)
+    #[arg(long)]
+    page: Option<String>,
+
+    /// Print JSON instead of the human-readable listing
     #[arg(long)]
-    db_id: String,
+    json: bool,
 }
 
 #[tokio::main]
@@ -25,11 +34,41 @@ async fn main() -> Result<()> {
     let cfg: Config = serde_yaml::from_str(&raw)?;
     let client = NotionClient::new(cfg.notion.token.clone(), cfg.notion.version.clone());
 
-    let db = client.retrieve_database(&args.db_id).await?;
-    println!("Database ID: {}", db.id);
-    println!("Properties:");
-    for (name, prop) in db.properties {
-        println!("  {} -> {{ id: {}, type: {} }}", name, prop.id, prop.typ);
+    if let Some(db_id) = &args.db_id {
+        let db = client.retrieve_database(db_id).await?;
+        if args.json {
+            println!("{}", serde_json::to_string_pretty(&db.properties)?);
+        } else {
+            println!("Database ID: {}", db.id);
+            println!("Properties:");
+            for (name, prop) in &db.properties {
+                println!("  {} -> {{ id: {}, type: {} }}", name, prop.id, prop.typ);
+            }
+        }
+    }
+
+    if let Some(page_id) = &args.page {
+        let page = client.retrieve_page(page_id).await?;
+        let blocks = client.list_block_children(page_id).await?;
+        if args.json {
+            let out = json!({ "page": page, "blocks": blocks });
+            println!("{}", serde_json::to_string_pretty(&out)?);
+        } else {
+            println!("Page ID: {}", page.id);
+            if let Some(url) = &page.url {
+                println!("URL: {}", url);
+            }
+            println!("Properties:");
+            for (name, value) in &page.properties {
+                let typ = value.get("type").and_then(|t| t.as_str()).unwrap_or("?");
+                println!("  {} ({}): {}", name, typ, value.get(typ).unwrap_or(value));
+            }
+            println!("Blocks ({}):", blocks.len());
+            for block in &blocks {
+                let children = if block.has_children { " +children" } else { "" };
+                println!("  {} [{}]{}", block.id, block.typ, children);
+            }
+        }
     }
     Ok(())
 }
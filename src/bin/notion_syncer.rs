@@ -5,6 +5,7 @@ use tracing::{error, info, warn};
 
 use tg_watchbot::config;
 use tg_watchbot::db;
+use tg_watchbot::logging;
 use tg_watchbot::notion::NotionClient;
 use tg_watchbot::outbox;
 
@@ -26,15 +27,19 @@ struct Args {
     /// Maximum attempts for failed tasks before considering them permanently failed (default: 5)
     #[arg(long, default_value = "5")]
     max_failed_attempts: i32,
+
+    /// Ignore the cached Notion property IDs and fetch them again
+    #[arg(long)]
+    refresh_notion_ids: bool,
+
+    /// Zero the outbox cursor (highest completed task id) before syncing
+    #[arg(long)]
+    reset_cursor: bool,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .with_target(false)
-        .compact()
-        .init();
+    logging::init_tracing_from_env()?;
 
     let args = Args::parse();
     let cfg = config::load(Some(&args.config))?;
@@ -44,15 +49,28 @@ async fn main() -> Result<()> {
     let database_url = std::env::var("DATABASE_URL")
         .unwrap_or_else(|_| format!("sqlite://{}/watchbot.db", data_dir));
 
-    let pool = db::init_pool(&database_url).await?;
+    let pool = db::init_pool_with(&database_url, cfg.app.db_max_connections).await?;
     db::run_migrations(&pool).await?;
 
-    let notion_client = NotionClient::new(cfg.notion.token.clone(), cfg.notion.version.clone());
-    let notion_ids = notion_client.resolve_property_ids(&cfg).await?;
-    let max_backoff = cfg.app.max_backoff_seconds as i64;
+    let notion_client = NotionClient::new(cfg.notion.token.clone(), cfg.notion.version.clone())
+        .with_upload_retries(cfg.app.upload_retries);
+    let notion_ids = notion_client
+        .resolve_property_ids_cached(&cfg, args.refresh_notion_ids)
+        .await?;
+    let worker_opts = outbox::WorkerOptions {
+        notion_routes: notion_client
+            .resolve_route_ids_cached(&cfg, args.refresh_notion_ids)
+            .await?,
+        ..outbox::WorkerOptions::from_config(&cfg)
+    };
 
     info!("Starting Notion sync process");
 
+    if args.reset_cursor {
+        db::reset_outbox_cursor(&pool).await?;
+        info!("Outbox cursor reset to 0");
+    }
+
     // Check initial state
     let remaining = db::count_remaining_outbox_tasks(&pool).await?;
     let last_processed = db::get_last_processed_outbox_id(&pool).await?;
@@ -68,26 +86,23 @@ async fn main() -> Result<()> {
     }
 
     let mut processed_count = 0;
-    let mut last_outbox_id = last_processed;
 
     loop {
         // Get the next task to process BEFORE processing it
         if let Some((next_task_id, _, _, _, _)) = db::next_due_outbox(&pool).await? {
-            match outbox::process_next_task(&pool, &notion_client, &notion_ids, max_backoff).await {
+            match outbox::process_next_task(&pool, &notion_client, &notion_ids, &worker_opts).await
+            {
                 Ok(processed) => {
                     if processed {
                         processed_count += 1;
 
-                        // Update the last processed ID to the task we just completed
-                        last_outbox_id = next_task_id;
-                        db::update_last_processed_outbox_id(&pool, last_outbox_id).await?;
-
                         if processed_count % 10 == 0 {
+                            // The worker advances the cursor once a task is completed
                             let remaining = db::count_remaining_outbox_tasks(&pool).await?;
                             info!(
                                 processed = processed_count,
                                 remaining = remaining,
-                                last_processed_id = last_outbox_id,
+                                last_processed_id = db::get_last_processed_outbox_id(&pool).await?,
                                 "Sync progress"
                             );
                         }
@@ -111,28 +126,28 @@ async fn main() -> Result<()> {
             if remaining == 0 {
                 info!(
                     total_processed = processed_count,
-                    last_processed_id = last_outbox_id,
+                    last_processed_id = db::get_last_processed_outbox_id(&pool).await?,
                     "All outbox tasks synced successfully"
                 );
                 break;
             } else {
                 // Check if all remaining tasks are in backoff (failed tasks)
-                let failed_tasks: Vec<(i64, i32, String)> =
-                    sqlx::query_as("SELECT id, attempt, due_at FROM outbox")
+                let failed_tasks: Vec<(i64, i32, i64, Option<String>)> =
+                    sqlx::query_as("SELECT id, attempt, due_at, last_error FROM outbox")
                         .fetch_all(&pool)
                         .await?;
 
                 if !failed_tasks.is_empty() {
                     let max_attempts = failed_tasks
                         .iter()
-                        .map(|(_, attempt, _)| *attempt)
+                        .map(|(_, attempt, _, _)| *attempt)
                         .max()
                         .unwrap_or(0);
                     let min_due_time = failed_tasks
                         .iter()
-                        .map(|(_, _, due_at)| due_at.as_str())
+                        .map(|(_, _, due_at, _)| *due_at)
                         .min()
-                        .unwrap_or("unknown");
+                        .map_or_else(|| "unknown".to_string(), format_due_at);
 
                     warn!(
                         remaining = remaining,
@@ -142,6 +157,16 @@ async fn main() -> Result<()> {
                         remaining
                     );
 
+                    for (task_id, attempt, due_at, last_error) in &failed_tasks {
+                        warn!(
+                            task_id,
+                            attempt,
+                            due_at = %format_due_at(*due_at),
+                            last_error = last_error.as_deref().unwrap_or("-"),
+                            "Task in backoff"
+                        );
+                    }
+
                     // Check if any task has exceeded the max failed attempts threshold
                     if max_attempts >= args.max_failed_attempts {
                         error!(
@@ -175,3 +200,8 @@ async fn main() -> Result<()> {
     info!("Notion sync process completed successfully");
     Ok(())
 }
+
+/// `due_at` is stored as Unix seconds; show it as a UTC timestamp.
+fn format_due_at(secs: i64) -> String {
+    chrono::DateTime::from_timestamp(secs, 0).map_or_else(|| secs.to_string(), |at| at.to_rfc3339())
+}
@@ -1,12 +1,16 @@
 use anyhow::Result;
 use clap::Parser;
+use serde::Serialize;
 use std::fs;
 use std::path::PathBuf;
 use teloxide::prelude::*;
 use teloxide::types::{
-    Animation, Audio, Document, MediaKind, Message, MessageKind, Sticker, Video, VideoNote, Voice,
+    Animation, Audio, Document, FileMeta, MediaKind, Message, MessageKind, Sticker, Video,
+    VideoNote, Voice,
 };
 use tg_watchbot::config::Telegram as TelegramCfg;
+use tg_watchbot::handlers;
+use tg_watchbot::logging;
 
 #[derive(Debug, Parser)]
 #[command(
@@ -22,6 +26,10 @@ struct Args {
     /// Resolve file paths via getFile and print download URLs
     #[arg(long, default_value_t = true)]
     resolve_paths: bool,
+
+    /// Output format: human-readable `text` or JSON lines
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -29,344 +37,314 @@ struct TelegramOnlyConfig {
     telegram: TelegramCfg,
 }
 
-// Whether to resolve file_path (will call getFile)
-// true: resolve download URL for each media; false: only print metadata (saves API quota)
-pub async fn print_message_expanded(
-    bot: &Bot,
-    bot_token: &str,
-    msg: &Message,
-    resolve_file_path: bool,
-) -> Result<()> {
-    let chat_id = msg.chat.id.0;
-    let user = msg
-        .from()
-        .and_then(|u| u.username.clone())
-        .unwrap_or_default();
-    let uid = msg.from().map(|u| u.id.0 as i64).unwrap_or(0);
-    let message_id = msg.id.0;
-    println!("message_id: {}", message_id);
-
-    // Text/caption
-    if let Some(t) = msg.text() {
-        println!("[chat:{} uid:{} user:@{}] text: {}", chat_id, uid, user, t);
-    }
-    if let Some(c) = msg.caption() {
-        println!(
-            "[chat:{} uid:{} user:@{}] caption: {}",
-            chat_id, uid, user, c
-        );
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Format {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per message
+    Json,
+}
 
-    match &msg.kind {
-        MessageKind::Common(common) => {
-            match &common.media_kind {
-                MediaKind::Photo(p) => {
-                    // Multiple sizes of the same image: print each one
-                    for (i, sz) in p.photo.iter().enumerate() {
-                        let file_id = &sz.file.id;
-                        if resolve_file_path {
-                            let file = bot.get_file(file_id.clone()).await?;
-                            let url = format!(
-                                "https://api.telegram.org/file/bot{}/{}",
-                                bot_token, file.path
-                            );
-                            println!(
-                                "[chat:{} user:@{}] photo[{}]: {}x{} size={} file_id={} path={} url={}",
-                                chat_id, user, i, sz.width, sz.height, sz.file.size,
-                                file_id, file.path, url
-                            );
-                        } else {
-                            println!(
-                                "[chat:{} user:@{}] photo[{}]: {}x{} size={} file_id={}",
-                                chat_id, user, i, sz.width, sz.height, sz.file.size, file_id
-                            );
-                        }
-                    }
-                }
-                MediaKind::Video(v) => {
-                    print_video(
-                        bot,
-                        bot_token,
-                        chat_id,
-                        uid,
-                        &user,
-                        &v.video,
-                        resolve_file_path,
-                    )
-                    .await?;
-                }
-                MediaKind::Document(d) => {
-                    print_document(
-                        bot,
-                        bot_token,
-                        chat_id,
-                        uid,
-                        &user,
-                        &d.document,
-                        resolve_file_path,
-                    )
-                    .await?;
-                }
-                MediaKind::Animation(a) => {
-                    print_animation(
-                        bot,
-                        bot_token,
-                        chat_id,
-                        uid,
-                        &user,
-                        &a.animation,
-                        resolve_file_path,
-                    )
-                    .await?;
-                }
-                MediaKind::Audio(a) => {
-                    print_audio(
-                        bot,
-                        bot_token,
-                        chat_id,
-                        uid,
-                        &user,
-                        &a.audio,
-                        resolve_file_path,
-                    )
-                    .await?;
-                }
-                MediaKind::Voice(v) => {
-                    print_voice(
-                        bot,
-                        bot_token,
-                        chat_id,
-                        uid,
-                        &user,
-                        &v.voice,
-                        resolve_file_path,
-                    )
-                    .await?;
-                }
-                MediaKind::VideoNote(vn) => {
-                    print_video_note(
-                        bot,
-                        bot_token,
-                        chat_id,
-                        uid,
-                        &user,
-                        &vn.video_note,
-                        resolve_file_path,
-                    )
-                    .await?;
-                }
-                MediaKind::Sticker(s) => {
-                    print_sticker(
-                        bot,
-                        bot_token,
-                        chat_id,
-                        uid,
-                        &user,
-                        &s.sticker,
-                        resolve_file_path,
-                    )
-                    .await?;
-                }
-                other => {
-                    // Many other types (location, poll, contact...) can be added as needed
-                    println!(
-                        "[chat:{} uid:{} user:@{}] other kind: {:?}",
-                        chat_id, uid, user, other
-                    );
-                }
-            }
+/// Everything printed for one message, in either format.
+#[derive(Debug, Serialize)]
+struct MessageRecord {
+    message_id: i32,
+    chat_id: i64,
+    user_id: i64,
+    username: Option<String>,
+    /// `text`, a media kind such as `photo`, or `other`
+    kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    caption: Option<String>,
+    /// Photos list every size Telegram offers; other media have one entry
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    media: Vec<MediaRecord>,
+    /// What the bot stores for a location, contact or poll
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<String>,
+    /// Map link of a location
+    #[serde(skip_serializing_if = "Option::is_none")]
+    link: Option<String>,
+    /// Debug dump of kinds without a dedicated record (text format only)
+    #[serde(skip)]
+    unsupported: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct MediaRecord {
+    file_id: String,
+    unique_id: String,
+    size: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    height: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mime_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    performer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    is_animated: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    is_video: Option<bool>,
+    /// Set by getFile when paths are resolved
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+}
+
+impl MediaRecord {
+    fn new(file: &FileMeta) -> Self {
+        Self {
+            file_id: file.id.clone(),
+            unique_id: file.unique_id.clone(),
+            size: file.size,
+            ..Default::default()
         }
+    }
+}
+
+/// Collect the printable fields of `msg` without calling the Bot API.
+fn message_record(msg: &Message) -> MessageRecord {
+    let mut record = MessageRecord {
+        message_id: msg.id.0,
+        chat_id: msg.chat.id.0,
+        user_id: msg.from().map(|u| u.id.0 as i64).unwrap_or(0),
+        username: msg.from().and_then(|u| u.username.clone()),
+        kind: "other",
+        text: msg.text().map(str::to_string),
+        caption: msg.caption().map(str::to_string),
+        media: Vec::new(),
+        summary: None,
+        link: None,
+        unsupported: None,
+    };
+
+    let common = match &msg.kind {
+        MessageKind::Common(common) => common,
         other => {
-            println!(
-                "[chat:{} uid:{} user:@{}] non-common: {:?}",
-                chat_id, uid, user, other
-            );
+            record.unsupported = Some(format!("non-common: {:?}", other));
+            return record;
         }
+    };
+    let (kind, media) = match &common.media_kind {
+        MediaKind::Text(_) => ("text", Vec::new()),
+        // Multiple sizes of the same image: keep each one
+        MediaKind::Photo(p) => (
+            "photo",
+            p.photo
+                .iter()
+                .map(|sz| MediaRecord {
+                    width: Some(sz.width),
+                    height: Some(sz.height),
+                    ..MediaRecord::new(&sz.file)
+                })
+                .collect(),
+        ),
+        MediaKind::Video(v) => ("video", vec![video_record(&v.video)]),
+        MediaKind::Document(d) => ("document", vec![document_record(&d.document)]),
+        MediaKind::Animation(a) => ("animation", vec![animation_record(&a.animation)]),
+        MediaKind::Audio(a) => ("audio", vec![audio_record(&a.audio)]),
+        MediaKind::Voice(v) => ("voice", vec![voice_record(&v.voice)]),
+        MediaKind::VideoNote(vn) => ("video_note", vec![video_note_record(&vn.video_note)]),
+        MediaKind::Sticker(s) => ("sticker", vec![sticker_record(&s.sticker)]),
+        other => match handlers::shared_item(other) {
+            Some(item) => {
+                record.summary = Some(item.content);
+                record.link = item.media_url;
+                (item.label, Vec::new())
+            }
+            None => {
+                record.unsupported = Some(format!("other kind: {:?}", other));
+                ("other", Vec::new())
+            }
+        },
+    };
+    record.kind = kind;
+    record.media = media;
+    record
+}
+
+// One builder per media type, to keep the type-specific metadata
+
+fn video_record(v: &Video) -> MediaRecord {
+    MediaRecord {
+        width: Some(v.width),
+        height: Some(v.height),
+        duration: Some(v.duration),
+        ..MediaRecord::new(&v.file)
     }
+}
 
-    Ok(())
+fn document_record(d: &Document) -> MediaRecord {
+    MediaRecord {
+        file_name: d.file_name.clone(),
+        mime_type: d.mime_type.as_ref().map(|m| m.to_string()),
+        ..MediaRecord::new(&d.file)
+    }
 }
 
-// Print utilities for each media type
-// You could also merge into one generic function: pass file_id + tag name; separated here to print more specific metadata
+fn animation_record(a: &Animation) -> MediaRecord {
+    MediaRecord {
+        width: Some(a.width),
+        height: Some(a.height),
+        duration: Some(a.duration),
+        mime_type: a.mime_type.as_ref().map(|m| m.to_string()),
+        ..MediaRecord::new(&a.file)
+    }
+}
 
-async fn print_video(
-    bot: &Bot,
-    token: &str,
-    chat_id: i64,
-    uid: i64,
-    user: &str,
-    v: &Video,
-    resolve: bool,
-) -> Result<()> {
-    let file_id = &v.file.id;
-    if resolve {
-        let f = bot.get_file(file_id.clone()).await?;
-        let url = format!("https://api.telegram.org/file/bot{}/{}", token, f.path);
-        println!(
-            "[chat:{} uid:{} user:@{}] video: {}x{} dur={}s size={} file_id={} path={} url={}",
-            chat_id, uid, user, v.width, v.height, v.duration, v.file.size, file_id, f.path, url
-        );
-    } else {
-        println!(
-            "[chat:{} uid:{} user:@{}] video: {}x{} dur={}s size={} file_id={}",
-            chat_id, uid, user, v.width, v.height, v.duration, v.file.size, file_id
-        );
+fn audio_record(a: &Audio) -> MediaRecord {
+    MediaRecord {
+        duration: Some(a.duration),
+        performer: a.performer.clone(),
+        title: a.title.clone(),
+        mime_type: a.mime_type.as_ref().map(|m| m.to_string()),
+        ..MediaRecord::new(&a.file)
     }
-    Ok(())
 }
 
-async fn print_document(
-    bot: &Bot,
-    token: &str,
-    chat_id: i64,
-    uid: i64,
-    user: &str,
-    d: &Document,
-    resolve: bool,
-) -> Result<()> {
-    let file_id = &d.file.id;
-    if resolve {
-        let f = bot.get_file(file_id.clone()).await?;
-        let url = format!("https://api.telegram.org/file/bot{}/{}", token, f.path);
-        println!("[chat:{} uid:{} user:@{}] document: filename={:?} mime={:?} size={} file_id={} path={} url={}",
-            chat_id, uid, user, d.file_name, d.mime_type, d.file.size, file_id, f.path, url);
-    } else {
-        println!(
-            "[chat:{} uid:{} user:@{}] document: filename={:?} mime={:?} size={} file_id={}",
-            chat_id, uid, user, d.file_name, d.mime_type, d.file.size, file_id
-        );
+fn voice_record(v: &Voice) -> MediaRecord {
+    MediaRecord {
+        duration: Some(v.duration),
+        mime_type: v.mime_type.as_ref().map(|m| m.to_string()),
+        ..MediaRecord::new(&v.file)
     }
-    Ok(())
 }
 
-async fn print_animation(
-    bot: &Bot,
-    token: &str,
-    chat_id: i64,
-    uid: i64,
-    user: &str,
-    a: &Animation,
-    resolve: bool,
-) -> Result<()> {
-    let file_id = &a.file.id;
-    if resolve {
-        let f = bot.get_file(file_id.clone()).await?;
-        let url = format!("https://api.telegram.org/file/bot{}/{}", token, f.path);
-        println!("[chat:{} uid:{} user:@{}] animation: {}x{} dur={}s mime={:?} size={} file_id={} path={} url={}",
-            chat_id, uid, user, a.width, a.height, a.duration, a.mime_type, a.file.size,
-            file_id, f.path, url);
-    } else {
-        println!(
-            "[chat:{} uid:{} user:@{}] animation: {}x{} dur={}s mime={:?} size={} file_id={}",
-            chat_id, uid, user, a.width, a.height, a.duration, a.mime_type, a.file.size, file_id
-        );
+fn video_note_record(vn: &VideoNote) -> MediaRecord {
+    MediaRecord {
+        width: Some(vn.length),
+        height: Some(vn.length),
+        duration: Some(vn.duration),
+        ..MediaRecord::new(&vn.file)
     }
-    Ok(())
 }
 
-async fn print_audio(
-    bot: &Bot,
-    token: &str,
-    chat_id: i64,
-    uid: i64,
-    user: &str,
-    a: &Audio,
-    resolve: bool,
-) -> Result<()> {
-    let file_id = &a.file.id;
-    if resolve {
-        let f = bot.get_file(file_id.clone()).await?;
-        let url = format!("https://api.telegram.org/file/bot{}/{}", token, f.path);
-        println!("[chat:{} uid:{} user:@{}] audio: dur={}s performer={:?} title={:?} mime={:?} size={} file_id={} path={} url={}",
-            chat_id, uid, user, a.duration, a.performer, a.title, a.mime_type, a.file.size,
-            file_id, f.path, url);
-    } else {
-        println!("[chat:{} uid:{} user:@{}] audio: dur={}s performer={:?} title={:?} mime={:?} size={} file_id={}",
-            chat_id, uid, user, a.duration, a.performer, a.title, a.mime_type, a.file.size, file_id);
+fn sticker_record(s: &Sticker) -> MediaRecord {
+    MediaRecord {
+        width: Some(s.width.into()),
+        height: Some(s.height.into()),
+        is_animated: Some(s.is_animated()),
+        is_video: Some(s.is_video()),
+        ..MediaRecord::new(&s.file)
     }
-    Ok(())
 }
 
-async fn print_voice(
-    bot: &Bot,
-    token: &str,
-    chat_id: i64,
-    uid: i64,
-    user: &str,
-    v: &Voice,
-    resolve: bool,
-) -> Result<()> {
-    let file_id = &v.file.id;
-    if resolve {
-        let f = bot.get_file(file_id.clone()).await?;
-        let url = format!("https://api.telegram.org/file/bot{}/{}", token, f.path);
-        println!(
-            "[chat:{} uid:{} user:@{}] voice: dur={}s mime={:?} size={} file_id={} path={} url={}",
-            chat_id, uid, user, v.duration, v.mime_type, v.file.size, file_id, f.path, url
-        );
-    } else {
-        println!(
-            "[chat:{} uid:{} user:@{}] voice: dur={}s mime={:?} size={} file_id={}",
-            chat_id, uid, user, v.duration, v.mime_type, v.file.size, file_id
-        );
+/// Fill in `path` and the download `url` of every media entry via getFile.
+async fn resolve_paths(bot: &Bot, bot_token: &str, record: &mut MessageRecord) -> Result<()> {
+    for media in &mut record.media {
+        let file = bot.get_file(media.file_id.clone()).await?;
+        media.url = Some(format!(
+            "https://api.telegram.org/file/bot{}/{}",
+            bot_token, file.path
+        ));
+        media.path = Some(file.path);
     }
     Ok(())
 }
 
-async fn print_video_note(
-    bot: &Bot,
-    token: &str,
-    chat_id: i64,
-    uid: i64,
-    user: &str,
-    vn: &VideoNote,
-    resolve: bool,
-) -> Result<()> {
-    let file_id = &vn.file.id;
-    if resolve {
-        let f = bot.get_file(file_id.clone()).await?;
-        let url = format!("https://api.telegram.org/file/bot{}/{}", token, f.path);
-        println!(
-            "[chat:{} uid:{} user:@{}] video_note: len={} size={} file_id={} path={} url={}",
-            chat_id, uid, user, vn.length, vn.file.size, file_id, f.path, url
-        );
-    } else {
-        println!(
-            "[chat:{} uid:{} user:@{}] video_note: len={} size={} file_id={}",
-            chat_id, uid, user, vn.length, vn.file.size, file_id
-        );
+/// The human-readable lines for `record`.
+fn render_text(record: &MessageRecord) -> Vec<String> {
+    let prefix = format!(
+        "[chat:{} uid:{} user:@{}]",
+        record.chat_id,
+        record.user_id,
+        record.username.as_deref().unwrap_or_default()
+    );
+    let mut lines = vec![format!("message_id: {}", record.message_id)];
+    if let Some(t) = &record.text {
+        lines.push(format!("{} text: {}", prefix, t));
     }
-    Ok(())
+    if let Some(c) = &record.caption {
+        lines.push(format!("{} caption: {}", prefix, c));
+    }
+    for (i, media) in record.media.iter().enumerate() {
+        let label = if record.kind == "photo" {
+            format!("photo[{}]", i)
+        } else {
+            record.kind.to_string()
+        };
+        lines.push(format!("{} {}: {}", prefix, label, media_details(media)));
+    }
+    if let Some(summary) = &record.summary {
+        lines.push(format!("{} {}: {}", prefix, record.kind, summary));
+    }
+    if let Some(link) = &record.link {
+        lines.push(format!("{} link: {}", prefix, link));
+    }
+    if let Some(unsupported) = &record.unsupported {
+        lines.push(format!("{} {}", prefix, unsupported));
+    }
+    lines
 }
 
-async fn print_sticker(
+fn media_details(m: &MediaRecord) -> String {
+    let mut parts = Vec::new();
+    if let (Some(w), Some(h)) = (m.width, m.height) {
+        parts.push(format!("{}x{}", w, h));
+    }
+    if let Some(d) = m.duration {
+        parts.push(format!("dur={}s", d));
+    }
+    if let Some(v) = &m.file_name {
+        parts.push(format!("filename={:?}", v));
+    }
+    if let Some(v) = &m.performer {
+        parts.push(format!("performer={:?}", v));
+    }
+    if let Some(v) = &m.title {
+        parts.push(format!("title={:?}", v));
+    }
+    if let Some(v) = &m.mime_type {
+        parts.push(format!("mime={:?}", v));
+    }
+    if let Some(v) = m.is_animated {
+        parts.push(format!("is_animated={}", v));
+    }
+    if let Some(v) = m.is_video {
+        parts.push(format!("is_video={}", v));
+    }
+    parts.push(format!("size={}", m.size));
+    parts.push(format!("file_id={}", m.file_id));
+    if let Some(v) = &m.path {
+        parts.push(format!("path={}", v));
+    }
+    if let Some(v) = &m.url {
+        parts.push(format!("url={}", v));
+    }
+    parts.join(" ")
+}
+
+// Whether to resolve file_path (will call getFile)
+// true: resolve download URL for each media; false: only print metadata (saves API quota)
+async fn print_message_expanded(
     bot: &Bot,
-    token: &str,
-    chat_id: i64,
-    uid: i64,
-    user: &str,
-    s: &Sticker,
-    resolve: bool,
+    bot_token: &str,
+    msg: &Message,
+    resolve_file_path: bool,
+    format: Format,
 ) -> Result<()> {
-    let file_id = &s.file.id;
-    if resolve {
-        let f = bot.get_file(file_id.clone()).await?;
-        let url = format!("https://api.telegram.org/file/bot{}/{}", token, f.path);
-        println!("[chat:{} uid:{} user:@{}] sticker: {}x{} is_animated={} is_video={} file_id={} path={} url={}",
-            chat_id, uid, user, s.width, s.height, s.is_animated(), s.is_video(), file_id, f.path, url);
-    } else {
-        println!(
-            "[chat:{} uid:{} user:@{}] sticker: {}x{} is_animated={} is_video={} file_id={}",
-            chat_id,
-            uid,
-            user,
-            s.width,
-            s.height,
-            s.is_animated(),
-            s.is_video(),
-            file_id
-        );
+    let mut record = message_record(msg);
+    if resolve_file_path {
+        resolve_paths(bot, bot_token, &mut record).await?;
+    }
+    match format {
+        Format::Text => {
+            for line in render_text(&record) {
+                println!("{}", line);
+            }
+        }
+        Format::Json => println!("{}", serde_json::to_string(&record)?),
     }
     Ok(())
 }
@@ -374,11 +352,7 @@ async fn print_sticker(
 #[tokio::main]
 async fn main() -> Result<()> {
     // Logging setup
-    tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .with_target(false)
-        .compact()
-        .init();
+    logging::init_tracing_from_env()?;
 
     // Args + config
     let args = Args::parse();
@@ -388,6 +362,7 @@ async fn main() -> Result<()> {
     let token = cfg.telegram.bot_token.clone();
     let allowed = cfg.telegram.allowed_users.clone();
     let resolve = args.resolve_paths;
+    let format = args.format;
     let bot = Bot::new(token.clone());
 
     teloxide::repl(bot, move |bot: Bot, msg: Message| {
@@ -396,13 +371,12 @@ async fn main() -> Result<()> {
         async move {
             // filter allowed users if provided
             if let Some(from) = msg.from() {
-                let uid = from.id.0 as i64;
-                if !allowed.is_empty() && !allowed.contains(&uid) {
+                if !handlers::is_allowed(from.id.0 as i64, &allowed) {
                     return respond(());
                 }
             }
 
-            if let Err(err) = print_message_expanded(&bot, &token, &msg, resolve).await {
+            if let Err(err) = print_message_expanded(&bot, &token, &msg, resolve, format).await {
                 eprintln!("error handling message: {:#}", err);
             }
             respond(())
@@ -412,3 +386,63 @@ async fn main() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn photo_message() -> Message {
+        serde_json::from_value(json!({
+            "message_id": 5,
+            "date": 1700000000,
+            "chat": { "id": -1001, "type": "supergroup", "title": "group" },
+            "from": { "id": 42, "is_bot": false, "first_name": "A", "username": "alice" },
+            "caption": "look",
+            "photo": [
+                { "file_id": "small", "file_unique_id": "u-small", "width": 90, "height": 60, "file_size": 1000 },
+                { "file_id": "big", "file_unique_id": "u-big", "width": 1280, "height": 853, "file_size": 90000 }
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn photo_record_serializes_expected_keys() {
+        let value = serde_json::to_value(message_record(&photo_message())).unwrap();
+
+        assert_eq!(value["message_id"], 5);
+        assert_eq!(value["chat_id"], -1001);
+        assert_eq!(value["user_id"], 42);
+        assert_eq!(value["username"], "alice");
+        assert_eq!(value["kind"], "photo");
+        assert_eq!(value["caption"], "look");
+        assert!(value.get("text").is_none());
+        let media = value["media"].as_array().unwrap();
+        assert_eq!(media.len(), 2);
+        assert_eq!(
+            media[1],
+            json!({
+                "file_id": "big",
+                "unique_id": "u-big",
+                "size": 90000,
+                "width": 1280,
+                "height": 853
+            })
+        );
+    }
+
+    #[test]
+    fn text_format_lists_each_photo_size() {
+        let lines = render_text(&message_record(&photo_message()));
+        assert_eq!(
+            lines,
+            vec![
+                "message_id: 5",
+                "[chat:-1001 uid:42 user:@alice] caption: look",
+                "[chat:-1001 uid:42 user:@alice] photo[0]: 90x60 size=1000 file_id=small",
+                "[chat:-1001 uid:42 user:@alice] photo[1]: 1280x853 size=90000 file_id=big",
+            ]
+        );
+    }
+}
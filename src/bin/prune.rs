@@ -0,0 +1,61 @@
+use anyhow::Result;
+use chrono::Utc;
+use clap::Parser;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use tracing::{info, warn};
+
+use tg_watchbot::config;
+use tg_watchbot::db;
+use tg_watchbot::logging;
+
+#[derive(Debug, Parser)]
+#[command(
+    author,
+    version,
+    about = "Delete rolled-back batches, their resources and their local media"
+)]
+struct Args {
+    /// Path to YAML config file
+    #[arg(long, default_value = "config.yaml")]
+    config: PathBuf,
+
+    /// Only prune batches rolled back more than this many days ago
+    #[arg(long)]
+    days: u32,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    logging::init_tracing_from_env()?;
+
+    let args = Args::parse();
+    let cfg = config::load(Some(&args.config))?;
+
+    let data_dir = cfg.app.resolved_data_dir();
+    let database_url = std::env::var("DATABASE_URL")
+        .unwrap_or_else(|_| format!("sqlite://{}/watchbot.db", data_dir));
+    let pool = db::init_pool_with(&database_url, cfg.app.db_max_connections).await?;
+    db::run_migrations(&pool).await?;
+
+    let older_than = Utc::now() - chrono::Duration::days(args.days.into());
+    let pruned = db::prune_rolled_back_batches(&pool, older_than).await?;
+
+    // The rows are gone, so a file that fails to delete is only logged
+    let mut files = 0;
+    for path in &pruned.media_paths {
+        match tokio::fs::remove_file(path).await {
+            Ok(()) => files += 1,
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => warn!(?err, path, "failed to delete media file"),
+        }
+    }
+    info!(
+        batches = pruned.batches,
+        resources = pruned.resources,
+        outbox_tasks = pruned.outbox_tasks,
+        files,
+        "Pruned rolled-back batches"
+    );
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use clap::Parser;
+use std::path::PathBuf;
+use tracing::info;
+
+use tg_watchbot::config;
+use tg_watchbot::db;
+use tg_watchbot::logging;
+
+#[derive(Debug, Parser)]
+#[command(
+    author,
+    version,
+    about = "Re-enqueue already-synced resources so notion_syncer pushes them again"
+)]
+struct Args {
+    /// Path to YAML config file
+    #[arg(long, default_value = "config.yaml")]
+    config: PathBuf,
+
+    /// Telegram user id whose resources should be replayed
+    #[arg(long)]
+    user: i64,
+
+    /// Only replay resources created at or after this date (YYYY-MM-DD or RFC 3339)
+    #[arg(long, value_parser = parse_since)]
+    since: Option<DateTime<Utc>>,
+
+    /// Replay at most this many resources (oldest first)
+    #[arg(long)]
+    limit: Option<i64>,
+}
+
+fn parse_since(raw: &str) -> Result<DateTime<Utc>> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(raw) {
+        return Ok(dt.with_timezone(&Utc));
+    }
+    let date = NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+        .map_err(|_| anyhow!("expected YYYY-MM-DD or RFC 3339, got '{}'", raw))?;
+    Ok(date.and_hms_opt(0, 0, 0).expect("midnight").and_utc())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    logging::init_tracing_from_env()?;
+
+    let args = Args::parse();
+    let cfg = config::load(Some(&args.config))?;
+
+    let data_dir = cfg.app.resolved_data_dir();
+    let database_url = std::env::var("DATABASE_URL")
+        .unwrap_or_else(|_| format!("sqlite://{}/watchbot.db", data_dir));
+    let pool = db::init_pool_with(&database_url, cfg.app.db_max_connections).await?;
+    db::run_migrations(&pool).await?;
+
+    let user_id = db::find_user_id(&pool, args.user)
+        .await?
+        .ok_or_else(|| anyhow!("no user with Telegram id {}", args.user))?;
+    let resource_ids = db::list_synced_resource_ids(&pool, user_id, args.since, args.limit).await?;
+    if resource_ids.is_empty() {
+        info!("No synced resources match; nothing to replay");
+        return Ok(());
+    }
+
+    let (batches, resources) = db::reset_and_enqueue(&pool, &resource_ids).await?;
+    info!(
+        matched = resource_ids.len(),
+        batches, resources, "Re-enqueued outbox tasks; run notion_syncer to push them"
+    );
+    Ok(())
+}
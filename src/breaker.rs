@@ -0,0 +1,144 @@
+//! Circuit breaker guarding the outbox worker's Notion calls.
+//!
+//! After `threshold` consecutive failed tasks the circuit opens and the worker
+//! stops claiming tasks for `cooldown`. Once that passes the circuit is
+//! half-open: the worker runs a single task as a probe, which closes the
+//! circuit on success or reopens it on failure. The worker handles one task at
+//! a time, so half-open never admits more than one probe.
+
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    /// Consecutive failures that open the circuit; `0` never opens it.
+    threshold: u32,
+    cooldown: Duration,
+    failures: u32,
+    opened_at: Option<Instant>,
+    state: BreakerState,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            failures: 0,
+            opened_at: None,
+            state: BreakerState::Closed,
+        }
+    }
+
+    pub fn state(&self) -> BreakerState {
+        self.state
+    }
+
+    /// How long the caller must still wait before running a task; `None` once
+    /// it may go ahead. An open circuit whose cool-down has passed turns
+    /// half-open here.
+    pub fn wait_time(&mut self, now: Instant) -> Option<Duration> {
+        if self.state != BreakerState::Open {
+            return None;
+        }
+        let reopen_at = self.opened_at.unwrap_or(now) + self.cooldown;
+        if now < reopen_at {
+            return Some(reopen_at - now);
+        }
+        info!("notion circuit half-open; probing with one task");
+        self.state = BreakerState::HalfOpen;
+        None
+    }
+
+    pub fn record_success(&mut self) {
+        if self.state != BreakerState::Closed {
+            info!("notion circuit closed");
+        }
+        self.failures = 0;
+        self.opened_at = None;
+        self.state = BreakerState::Closed;
+    }
+
+    pub fn record_failure(&mut self, now: Instant) {
+        self.failures = self.failures.saturating_add(1);
+        let trips = match self.state {
+            BreakerState::HalfOpen => true,
+            BreakerState::Closed => self.threshold > 0 && self.failures >= self.threshold,
+            BreakerState::Open => false,
+        };
+        if trips {
+            warn!(
+                failures = self.failures,
+                cooldown_secs = self.cooldown.as_secs(),
+                "notion circuit open; pausing outbox worker"
+            );
+            self.opened_at = Some(now);
+            self.state = BreakerState::Open;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_after_threshold_and_closes_after_successful_probe() {
+        let cooldown = Duration::from_secs(30);
+        let mut breaker = CircuitBreaker::new(3, cooldown);
+        let start = Instant::now();
+
+        breaker.record_failure(start);
+        breaker.record_failure(start);
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        assert_eq!(breaker.wait_time(start), None);
+
+        breaker.record_failure(start);
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert_eq!(
+            breaker.wait_time(start + Duration::from_secs(10)),
+            Some(Duration::from_secs(20))
+        );
+
+        assert_eq!(breaker.wait_time(start + cooldown), None);
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+
+        breaker.record_success();
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        breaker.record_failure(start + cooldown);
+        assert_eq!(breaker.state(), BreakerState::Closed);
+    }
+
+    #[test]
+    fn failed_probe_reopens_circuit() {
+        let cooldown = Duration::from_secs(30);
+        let mut breaker = CircuitBreaker::new(1, cooldown);
+        let start = Instant::now();
+
+        breaker.record_failure(start);
+        let probe_at = start + cooldown;
+        assert_eq!(breaker.wait_time(probe_at), None);
+        breaker.record_failure(probe_at);
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert_eq!(breaker.wait_time(probe_at), Some(cooldown));
+    }
+
+    #[test]
+    fn zero_threshold_never_opens() {
+        let mut breaker = CircuitBreaker::new(0, Duration::from_secs(30));
+        let now = Instant::now();
+        for _ in 0..100 {
+            breaker.record_failure(now);
+        }
+        assert_eq!(breaker.state(), BreakerState::Closed);
+    }
+}
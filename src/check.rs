@@ -0,0 +1,165 @@
+//! `--check`: validate the config and reach Notion and Telegram without
+//! starting the bot, printing one PASS/FAIL line per step.
+
+use crate::config::{self, Config};
+use crate::notion::NotionClient;
+use std::fmt;
+use std::path::Path;
+use teloxide::prelude::*;
+
+/// Outcome of a single check; `detail` explains a pass or carries the error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckItem {
+    pub name: &'static str,
+    pub passed: bool,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CheckReport {
+    pub items: Vec<CheckItem>,
+}
+
+impl CheckReport {
+    pub fn passed(&self) -> bool {
+        self.items.iter().all(|item| item.passed)
+    }
+
+    fn record<T, E: fmt::Display>(
+        &mut self,
+        name: &'static str,
+        result: Result<T, E>,
+        secrets: &[&str],
+        on_pass: impl FnOnce(T) -> String,
+    ) {
+        let (passed, detail) = match result {
+            Ok(value) => (true, on_pass(value)),
+            Err(err) => (false, format!("{:#}", err)),
+        };
+        self.items.push(CheckItem {
+            name,
+            passed,
+            detail: scrub(&detail, secrets),
+        });
+    }
+}
+
+impl fmt::Display for CheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for item in &self.items {
+            let status = if item.passed { "PASS" } else { "FAIL" };
+            writeln!(f, "[{}] {}: {}", status, item.name, item.detail)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run every check against the config at `path`. Stops after a config error,
+/// since nothing else can be checked without one.
+pub async fn run(path: &Path) -> CheckReport {
+    let mut report = CheckReport::default();
+    let cfg = match config::load(Some(path)) {
+        Ok(cfg) => cfg,
+        Err(err) => {
+            report.record::<(), _>("config", Err(err), &[], |_| String::new());
+            return report;
+        }
+    };
+    report.record("config", Ok::<_, String>(()), &[], |_| {
+        format!(
+            "{} (telegram token {}, notion token {})",
+            path.display(),
+            redact(&cfg.telegram.bot_token),
+            redact(&cfg.notion.token)
+        )
+    });
+    check_notion(&cfg, &mut report).await;
+    check_telegram(&cfg, &mut report).await;
+    report
+}
+
+async fn check_notion(cfg: &Config, report: &mut CheckReport) {
+    let secrets = [cfg.notion.token.as_str()];
+    let notion = NotionClient::new(cfg.notion.token.clone(), cfg.notion.version.clone());
+    report.record(
+        "notion property ids",
+        notion.resolve_property_ids(cfg).await,
+        &secrets,
+        |ids| {
+            format!(
+                "main {} / resource {}, property types match",
+                ids.main_db, ids.resource_db
+            )
+        },
+    );
+    if !cfg.notion.routes.is_empty() {
+        report.record(
+            "notion routes",
+            notion.resolve_route_ids(cfg).await,
+            &secrets,
+            |routes| format!("{} route(s) resolved", routes.len()),
+        );
+    }
+}
+
+async fn check_telegram(cfg: &Config, report: &mut CheckReport) {
+    let bot = Bot::new(cfg.telegram.bot_token.clone());
+    report.record(
+        "telegram getMe",
+        bot.get_me().await,
+        &[cfg.telegram.bot_token.as_str()],
+        |me| match me.user.username {
+            Some(username) => format!("@{}", username),
+            None => format!("bot id {}", me.user.id.0),
+        },
+    );
+}
+
+/// Keep only enough of a token to recognise which one is configured.
+pub fn redact(secret: &str) -> String {
+    if secret.chars().count() <= 8 {
+        return "****".to_string();
+    }
+    let head: String = secret.chars().take(4).collect();
+    format!("{}****", head)
+}
+
+/// Errors can echo request URLs; the Telegram one embeds the bot token.
+fn scrub(text: &str, secrets: &[&str]) -> String {
+    secrets
+        .iter()
+        .filter(|s| !s.is_empty())
+        .fold(text.to_string(), |acc, s| acc.replace(s, &redact(s)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn empty_database_id_fails_config_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        let yaml = config::example().replace("NOTION_MAIN_DATABASE_ID", "");
+        std::fs::write(&path, yaml).unwrap();
+
+        let report = run(&path).await;
+
+        assert!(!report.passed());
+        assert_eq!(report.items.len(), 1, "no network checks without a config");
+        assert_eq!(report.items[0].name, "config");
+        assert!(report.items[0].detail.contains("main.id"));
+        assert!(report.to_string().starts_with("[FAIL] config: "));
+    }
+
+    #[test]
+    fn tokens_are_redacted() {
+        assert_eq!(redact("123456:ABCDEFGH"), "1234****");
+        assert_eq!(redact("short"), "****");
+        let msg =
+            "error sending request for url (https://api.telegram.org/bot123456:ABCDEFGH/GetMe)";
+        let scrubbed = scrub(msg, &["123456:ABCDEFGH", ""]);
+        assert!(!scrubbed.contains("ABCDEFGH"));
+        assert!(scrubbed.contains("bot1234****/GetMe"));
+    }
+}
@@ -0,0 +1,121 @@
+//! Reclaim disk by deleting local media once Notion holds the uploaded copy
+//! (`app.cleanup_after_sync`).
+
+use crate::db::{self, Pool};
+use anyhow::Result;
+use std::io::ErrorKind;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// How often the cleanup task looks for synced media.
+pub const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+const PURGE_BATCH: i64 = 100;
+
+/// Delete the media file and thumbnail of every synced, uploaded resource and
+/// mark them purged. A file that is already gone counts as deleted; any other
+/// error leaves the resource for the next run. Returns how many were purged.
+pub async fn purge_synced_media(pool: &Pool) -> Result<usize> {
+    let mut purged = 0;
+    for media in db::list_purgeable_media(pool, PURGE_BATCH).await? {
+        let mut removed = true;
+        for path in std::iter::once(media.path.as_str()).chain(media.thumb_path.as_deref()) {
+            match tokio::fs::remove_file(path).await {
+                Ok(()) => {}
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => {
+                    warn!(
+                        ?err,
+                        resource_id = media.id,
+                        path,
+                        "failed to delete synced media"
+                    );
+                    removed = false;
+                }
+            }
+        }
+        if removed {
+            db::mark_media_purged(pool, media.id).await?;
+            purged += 1;
+        }
+    }
+    Ok(purged)
+}
+
+/// Run [`purge_synced_media`] every [`CLEANUP_INTERVAL`] until the process exits.
+pub async fn run(pool: Pool) {
+    loop {
+        match purge_synced_media(&pool).await {
+            Ok(0) => {}
+            Ok(purged) => info!(purged, "deleted local media of synced resources"),
+            Err(err) => error!(?err, "media cleanup failed"),
+        }
+        tokio::time::sleep(CLEANUP_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::ResourceKind;
+
+    #[tokio::test]
+    async fn synced_upload_is_removed_and_unsynced_kept() {
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        db::run_migrations(&pool).await.unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let file = |name: &str| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, b"bytes").unwrap();
+            path.to_string_lossy().into_owned()
+        };
+        let (synced, thumb, unsynced, external) =
+            (file("1.mp4"), file("1.jpg"), file("2.jpg"), file("3.jpg"));
+        let uid = db::get_or_create_user(&pool, 19, None, None).await.unwrap();
+
+        let origin = db::ResourceOrigin {
+            thumb_path: Some(&thumb),
+            ..Default::default()
+        };
+        let synced_id =
+            db::insert_resource_from(&pool, uid, None, ResourceKind::Video, &synced, 1, &origin)
+                .await
+                .unwrap()
+                .id;
+        db::mark_resource_media_uploaded(&pool, synced_id)
+            .await
+            .unwrap();
+        db::complete_outbox_resource(&pool, 0, synced_id, "page-1")
+            .await
+            .unwrap();
+        // Uploaded but its page is not stored yet
+        let unsynced_id = db::insert_resource(&pool, uid, None, ResourceKind::Photo, &unsynced, 2)
+            .await
+            .unwrap();
+        db::mark_resource_media_uploaded(&pool, unsynced_id)
+            .await
+            .unwrap();
+        // Synced through an external URL; the local file was never uploaded
+        let external_id = db::insert_resource(&pool, uid, None, ResourceKind::Photo, &external, 3)
+            .await
+            .unwrap();
+        db::complete_outbox_resource(&pool, 0, external_id, "page-3")
+            .await
+            .unwrap();
+
+        assert_eq!(purge_synced_media(&pool).await.unwrap(), 1);
+        assert!(!std::path::Path::new(&synced).exists());
+        assert!(!std::path::Path::new(&thumb).exists());
+        assert!(std::path::Path::new(&unsynced).exists());
+        assert!(std::path::Path::new(&external).exists());
+
+        let purged: Option<String> =
+            sqlx::query_scalar("SELECT media_purged_at FROM resources WHERE id = ?")
+                .bind(synced_id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert!(purged.is_some());
+        // Already purged resources are not revisited
+        assert_eq!(purge_synced_media(&pool).await.unwrap(), 0);
+    }
+}
@@ -1,9 +1,11 @@
 //! Configuration loader and validator for the Telegram→Notion bot.
 use crate::notion::NotionIds;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::fs;
 use std::path::Path;
 use thiserror::Error;
+use tracing::warn;
 
 #[derive(Debug, Error)]
 pub enum ConfigError {
@@ -28,7 +30,178 @@ pub struct Config {
 pub struct App {
     pub data_dir: String,
     pub poll_interval_ms: u64,
+    /// How the outbox worker learns about new tasks while idle.
+    #[serde(default)]
+    pub poll_strategy: PollStrategy,
+    /// Ceiling, in seconds, for the exponential retry delay of outbox tasks.
     pub max_backoff_seconds: u64,
+    /// Failed attempts after which an outbox task is dead-lettered (0 = retry forever).
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Upper bound on open SQLite connections shared by the bot and worker.
+    #[serde(default = "default_db_max_connections")]
+    pub db_max_connections: u32,
+    /// Extra attempts for the file content POST of a Notion upload.
+    #[serde(default = "default_upload_retries")]
+    pub upload_retries: u32,
+    /// Refuse to start without ffmpeg instead of only disabling video support.
+    #[serde(default)]
+    pub require_ffmpeg: bool,
+    /// Delete local media files once they have been uploaded to Notion.
+    #[serde(default)]
+    pub cleanup_after_sync: bool,
+    /// After `/commit`, message the chat a link once the batch's Notion page exists.
+    #[serde(default)]
+    pub notify_on_sync: bool,
+    /// Port for the `/healthz` + `/metrics` HTTP endpoint; disabled when unset.
+    #[serde(default)]
+    pub health_port: Option<u16>,
+    /// How local media files are attached to Notion resource pages.
+    #[serde(default)]
+    pub media_mode: MediaMode,
+    /// Largest photo/video (bytes) the bot downloads; unset means unlimited.
+    #[serde(default)]
+    pub max_media_bytes: Option<u64>,
+    /// Most resources one batch may hold; unset means unlimited.
+    #[serde(default)]
+    pub max_batch_items: Option<usize>,
+    /// Continue resource order numbers across the user's batches instead of
+    /// restarting at 1 in each batch.
+    #[serde(default)]
+    pub global_sequence: bool,
+    /// Index resource text with SQLite FTS5 and use it for `/find`.
+    #[serde(default)]
+    pub full_text_search: bool,
+    /// Size threshold (bytes) used by `media_mode: auto`; larger files are linked externally.
+    #[serde(default = "default_media_upload_max_bytes")]
+    pub media_upload_max_bytes: u64,
+    /// Which replies the bot sends for saved items.
+    #[serde(default)]
+    pub ack_mode: AckMode,
+    /// What `/rollback` does with the items of the rolled-back batch.
+    #[serde(default)]
+    pub rollback_mode: RollbackMode,
+    /// Ask for Confirm/Cancel with inline buttons before `/commit` proceeds.
+    #[serde(default)]
+    pub confirm_commit: bool,
+    /// Files of one resource (video thumbnail + video) uploaded to Notion at once.
+    #[serde(default = "default_upload_concurrency")]
+    pub upload_concurrency: usize,
+    /// Image format of generated video thumbnails.
+    #[serde(default)]
+    pub thumbnail_format: ThumbnailFormat,
+    /// Consecutive failed outbox tasks that pause the worker; `0` disables the breaker.
+    #[serde(default = "default_breaker_threshold")]
+    pub breaker_threshold: u32,
+    /// How long the worker pauses before probing Notion with one task.
+    #[serde(default = "default_breaker_cooldown_seconds")]
+    pub breaker_cooldown_seconds: u64,
+}
+
+/// How the idle outbox worker waits for new tasks.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PollStrategy {
+    /// Check the outbox again every `poll_interval_ms`.
+    #[default]
+    Interval,
+    /// Wake as soon as the bot queues a task; `poll_interval_ms` remains the
+    /// fallback for tasks queued elsewhere (e.g. retries coming due).
+    Notify,
+}
+
+/// Strategy for attaching media that exists both locally and at an external URL.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaMode {
+    /// Link the external URL when known; upload the local file otherwise.
+    #[default]
+    External,
+    /// Always upload the local file when it exists.
+    Upload,
+    /// Upload files up to `media_upload_max_bytes`, link larger ones externally.
+    /// Files with no external URL (e.g. Telegram downloads) are still uploaded.
+    Auto,
+}
+
+/// Image format ffmpeg writes video thumbnails in.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ThumbnailFormat {
+    #[default]
+    Jpg,
+    /// Smaller files; needs an ffmpeg built with libwebp.
+    Webp,
+}
+
+impl ThumbnailFormat {
+    /// File extension of thumbnails in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            ThumbnailFormat::Jpg => "jpg",
+            ThumbnailFormat::Webp => "webp",
+        }
+    }
+}
+
+/// How the bot acknowledges saved items.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AckMode {
+    /// Reply to every saved item.
+    #[default]
+    Each,
+    /// No per-item replies; the commit reply says how many items the batch holds.
+    Summary,
+    /// No per-item replies at all. Errors and command responses are still sent.
+    Silent,
+}
+
+/// What happens to the items of a rolled-back batch.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RollbackMode {
+    /// Leave them with the rolled-back batch; they are never synced.
+    #[default]
+    Discard,
+    /// Detach unsynced items from the batch and sync each as a standalone resource.
+    Detach,
+}
+
+impl AckMode {
+    /// Whether each saved or edited item gets its own reply.
+    pub fn acks_items(self) -> bool {
+        self == AckMode::Each
+    }
+}
+
+fn default_max_attempts() -> u32 {
+    10
+}
+
+fn default_db_max_connections() -> u32 {
+    5
+}
+
+fn default_upload_retries() -> u32 {
+    2
+}
+
+fn default_upload_concurrency() -> usize {
+    1
+}
+
+fn default_breaker_threshold() -> u32 {
+    5
+}
+
+fn default_breaker_cooldown_seconds() -> u64 {
+    60
+}
+
+fn default_media_upload_max_bytes() -> u64 {
+    // Notion's single-part upload limit.
+    20 * 1024 * 1024
 }
 
 /// Telegram bot settings.
@@ -36,6 +209,12 @@ pub struct App {
 pub struct Telegram {
     pub bot_token: String,
     pub allowed_users: Vec<i64>,
+    /// Telegram user ids allowed to run `/stats`.
+    #[serde(default)]
+    pub admin_users: Vec<i64>,
+    /// Refuse an empty `allowed_users`, which would let everyone use the bot.
+    #[serde(default)]
+    pub require_allowlist: bool,
 }
 
 /// Notion API settings and database mappings.
@@ -44,6 +223,47 @@ pub struct Notion {
     pub token: String,
     pub version: String,
     pub databases: Databases,
+    /// Also append photos to the resource page body as inline `image` blocks.
+    #[serde(default)]
+    pub images_as_blocks: bool,
+    /// Per-chat destinations; chats without a route use `databases`.
+    #[serde(default)]
+    pub routes: Vec<NotionRoute>,
+    /// Convert Telegram-style markdown in text into styled Notion runs.
+    #[serde(default)]
+    pub parse_markdown: bool,
+    /// Main page title for batches committed without one; `{date}` expands to
+    /// the batch's creation date. Defaults to [`DEFAULT_BATCH_TITLE`].
+    #[serde(default)]
+    pub default_title: Option<String>,
+    /// Emoji set as the icon of every batch's main page.
+    #[serde(default)]
+    pub main_page_icon: Option<String>,
+    /// Before creating a batch's main page, reuse a page in the main database
+    /// with the same title (e.g. when a rerun lost the stored page id).
+    #[serde(default)]
+    pub dedupe_main_pages: bool,
+    /// Read each new main page back and check its title before marking the
+    /// batch synced.
+    #[serde(default)]
+    pub verify_after_create: bool,
+}
+
+/// Title of untitled batches when `notion.default_title` is not set.
+pub const DEFAULT_BATCH_TITLE: &str = "Untitled";
+
+impl Notion {
+    /// The configured `default_title`, or [`DEFAULT_BATCH_TITLE`].
+    pub fn default_title(&self) -> &str {
+        self.default_title.as_deref().unwrap_or(DEFAULT_BATCH_TITLE)
+    }
+}
+
+/// Sends everything from one Telegram chat to its own pair of databases.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NotionRoute {
+    pub chat_id: i64,
+    pub databases: Databases,
 }
 
 /// Database mapping configuration.
@@ -82,6 +302,24 @@ pub struct DbResourceFields {
     pub order: String,
     pub text: String,
     pub media: String,
+    /// Optional `url` property that receives the Telegram message permalink.
+    #[serde(default)]
+    pub source_url: Option<String>,
+    /// Optional `date` property that receives the Telegram message time.
+    #[serde(default)]
+    pub date: Option<String>,
+    /// Optional `select` property that receives the resource kind (`text`, `photo`, ...).
+    #[serde(default)]
+    pub kind: Option<String>,
+    /// Optional `number` property that receives a photo or video's width in pixels.
+    #[serde(default)]
+    pub width: Option<String>,
+    /// Optional `number` property that receives a photo or video's height in pixels.
+    #[serde(default)]
+    pub height: Option<String>,
+    /// Optional `number` property that receives a video's duration in seconds.
+    #[serde(default)]
+    pub duration: Option<String>,
 }
 
 impl Config {
@@ -96,29 +334,145 @@ impl Config {
     /// Convenience accessor that maps configuration fields into the `NotionIds`
     /// structure required by the Notion client when constructing payloads.
     #[allow(dead_code)]
+    pub fn notion_ids(&self) -> NotionIds {
+        self.notion.databases.notion_ids()
+    }
+}
+
+impl Databases {
+    /// Unresolved `NotionIds` for this database pair (fields as configured).
     pub fn notion_ids(&self) -> NotionIds {
         NotionIds {
-            main_db: self.notion.databases.main.id.clone(),
-            resource_db: self.notion.databases.resource.id.clone(),
-            f_main_title: self.notion.databases.main.fields.title.clone(),
-            f_rel_parent: self.notion.databases.resource.fields.relation.clone(),
-            f_res_order: self.notion.databases.resource.fields.order.clone(),
-            f_res_text: self.notion.databases.resource.fields.text.clone(),
-            f_res_media: self.notion.databases.resource.fields.media.clone(),
+            main_db: self.main.id.clone(),
+            resource_db: self.resource.id.clone(),
+            f_main_title: self.main.fields.title.clone(),
+            f_rel_parent: self.resource.fields.relation.clone(),
+            f_res_order: self.resource.fields.order.clone(),
+            f_res_text: self.resource.fields.text.clone(),
+            f_res_media: self.resource.fields.media.clone(),
+            f_res_source_url: self.resource.fields.source_url.clone(),
+            f_res_date: self.resource.fields.date.clone(),
+            f_res_kind: self.resource.fields.kind.clone(),
+            f_res_width: self.resource.fields.width.clone(),
+            f_res_height: self.resource.fields.height.clone(),
+            f_res_duration: self.resource.fields.duration.clone(),
         }
     }
+
+    fn has_empty_field(&self) -> bool {
+        let rf = &self.resource.fields;
+        [
+            &self.main.id,
+            &self.main.fields.title,
+            &self.main.fields.unique,
+            &self.resource.id,
+            &rf.relation,
+            &rf.order,
+            &rf.text,
+            &rf.media,
+        ]
+        .iter()
+        .any(|v| v.trim().is_empty())
+    }
+}
+
+/// `Notion-Version` values the client is known to work with, oldest first.
+/// Older versions predate request shapes the client relies on.
+pub const SUPPORTED_NOTION_VERSIONS: &[&str] = &["2022-02-22", "2022-06-28"];
+
+/// Whether `version` has the `YYYY-MM-DD` shape of a real date.
+pub fn is_valid_notion_version(version: &str) -> bool {
+    version.len() == 10
+        && version.char_indices().all(|(i, c)| {
+            if i == 4 || i == 7 {
+                c == '-'
+            } else {
+                c.is_ascii_digit()
+            }
+        })
+        && chrono::NaiveDate::parse_from_str(version, "%Y-%m-%d").is_ok()
+}
+
+/// Whether a valid `version` is older than every supported one.
+fn is_outdated_notion_version(version: &str) -> bool {
+    // ISO dates order correctly as strings
+    SUPPORTED_NOTION_VERSIONS
+        .first()
+        .is_some_and(|oldest| version < *oldest)
 }
 
 /// Load configuration from a YAML file and validate it.
 /// - If `path` is None, uses `config.yaml` in the current working directory.
+/// - Non-empty `TG_BOT_TOKEN`, `NOTION_TOKEN` and `NOTION_VERSION` environment
+///   variables replace the file's values before validation.
 pub fn load(path: Option<&Path>) -> Result<Config, ConfigError> {
+    load_with_env(path, |name| std::env::var(name).ok())
+}
+
+/// [`load`] with environment lookups answered by `env`.
+fn load_with_env(
+    path: Option<&Path>,
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<Config, ConfigError> {
     let path = path.unwrap_or_else(|| Path::new("config.yaml"));
     let content = fs::read_to_string(path)?;
-    let cfg: Config = serde_yaml::from_str(&content)?;
+    let mut cfg: Config = serde_yaml::from_str(&content)?;
+    overlay_env(&mut cfg, env);
+    let duplicates = dedup_allowed_users(&mut cfg.telegram);
+    if !duplicates.is_empty() {
+        warn!(
+            ?duplicates,
+            "telegram.allowed_users lists ids more than once"
+        );
+    }
     validate(&cfg)?;
+    let allowed = &cfg.telegram.allowed_users;
+    let locked_out: Vec<i64> = cfg
+        .telegram
+        .admin_users
+        .iter()
+        .copied()
+        .filter(|id| !allowed.is_empty() && !allowed.contains(id))
+        .collect();
+    if !locked_out.is_empty() {
+        warn!(
+            ?locked_out,
+            "telegram.admin_users not in allowed_users cannot reach the bot"
+        );
+    }
     Ok(cfg)
 }
 
+/// Drop repeated ids from `allowed_users`, keeping the first of each, and
+/// return the dropped repeats.
+fn dedup_allowed_users(telegram: &mut Telegram) -> Vec<i64> {
+    let mut seen = HashSet::new();
+    let mut duplicates = Vec::new();
+    telegram.allowed_users.retain(|id| {
+        let first = seen.insert(*id);
+        if !first {
+            duplicates.push(*id);
+        }
+        first
+    });
+    duplicates
+}
+
+/// Overlay secrets looked up with `var` onto `cfg`; unset or empty variables
+/// keep the file's value.
+fn overlay_env(cfg: &mut Config, var: impl Fn(&str) -> Option<String>) {
+    let targets = [
+        ("TG_BOT_TOKEN", &mut cfg.telegram.bot_token),
+        ("NOTION_TOKEN", &mut cfg.notion.token),
+        ("NOTION_VERSION", &mut cfg.notion.version),
+    ];
+    for (name, target) in targets {
+        if let Some(value) = var(name).filter(|v| !v.trim().is_empty()) {
+            *target = value;
+        }
+    }
+}
+
 /// Validate a configuration instance.
 fn validate(cfg: &Config) -> Result<(), ConfigError> {
     if cfg.app.data_dir.trim().is_empty() {
@@ -127,11 +481,29 @@ fn validate(cfg: &Config) -> Result<(), ConfigError> {
     if cfg.app.poll_interval_ms == 0 {
         return Err(ConfigError::Invalid("app.poll_interval_ms must be > 0"));
     }
-    // max_backoff_seconds is u64; it's inherently >= 0
+    // 0 would disable the retry ceiling altogether
+    if cfg.app.max_backoff_seconds == 0 {
+        return Err(ConfigError::Invalid("app.max_backoff_seconds must be >= 1"));
+    }
+    if cfg.app.max_batch_items == Some(0) {
+        return Err(ConfigError::Invalid("app.max_batch_items must be >= 1"));
+    }
+    if cfg.app.db_max_connections == 0 {
+        return Err(ConfigError::Invalid("app.db_max_connections must be >= 1"));
+    }
+    if cfg.app.upload_concurrency == 0 {
+        return Err(ConfigError::Invalid("app.upload_concurrency must be >= 1"));
+    }
 
     if cfg.telegram.bot_token.trim().is_empty() {
         return Err(ConfigError::Invalid("telegram.bot_token must be non-empty"));
     }
+    // An empty allowlist admits everyone
+    if cfg.telegram.require_allowlist && cfg.telegram.allowed_users.is_empty() {
+        return Err(ConfigError::Invalid(
+            "telegram.allowed_users must be non-empty when telegram.require_allowlist is set",
+        ));
+    }
 
     if cfg.notion.token.trim().is_empty() {
         return Err(ConfigError::Invalid("notion.token must be non-empty"));
@@ -139,6 +511,18 @@ fn validate(cfg: &Config) -> Result<(), ConfigError> {
     if cfg.notion.version.trim().is_empty() {
         return Err(ConfigError::Invalid("notion.version must be non-empty"));
     }
+    if !is_valid_notion_version(&cfg.notion.version) {
+        return Err(ConfigError::Invalid(
+            "notion.version must be a date in YYYY-MM-DD form",
+        ));
+    }
+    if is_outdated_notion_version(&cfg.notion.version) {
+        warn!(
+            version = %cfg.notion.version,
+            supported = ?SUPPORTED_NOTION_VERSIONS,
+            "notion.version is older than any supported Notion API version"
+        );
+    }
 
     if cfg.notion.databases.main.id.trim().is_empty() {
         return Err(ConfigError::Invalid(
@@ -183,6 +567,42 @@ fn validate(cfg: &Config) -> Result<(), ConfigError> {
         ));
     }
 
+    if cfg
+        .notion
+        .default_title
+        .as_deref()
+        .is_some_and(|t| t.trim().is_empty())
+    {
+        return Err(ConfigError::Invalid(
+            "notion.default_title must be non-empty when set",
+        ));
+    }
+
+    if cfg
+        .notion
+        .main_page_icon
+        .as_deref()
+        .is_some_and(|icon| icon.trim().is_empty())
+    {
+        return Err(ConfigError::Invalid(
+            "notion.main_page_icon must be non-empty when set",
+        ));
+    }
+
+    let mut route_chats = HashSet::new();
+    for route in &cfg.notion.routes {
+        if !route_chats.insert(route.chat_id) {
+            return Err(ConfigError::Invalid(
+                "notion.routes must not repeat a chat_id",
+            ));
+        }
+        if route.databases.has_empty_field() {
+            return Err(ConfigError::Invalid(
+                "notion.routes[].databases ids and fields must be non-empty",
+            ));
+        }
+    }
+
     Ok(())
 }
 
@@ -240,6 +660,7 @@ notion:
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashMap;
     use tempfile::tempdir;
 
     #[test]
@@ -305,6 +726,41 @@ mod tests {
         assert!(matches!(validate(&cfg), Err(ConfigError::Invalid(_))));
     }
 
+    #[test]
+    fn env_overrides_placeholder_tokens() {
+        let td = tempdir().unwrap();
+        let p = td.path().join("config.yaml");
+        let yaml = example()
+            .replace("YOUR_TELEGRAM_BOT_TOKEN", "")
+            .replace("YOUR_NOTION_INTEGRATION_TOKEN", "${NOTION_TOKEN}");
+        fs::write(&p, yaml).unwrap();
+
+        let env = HashMap::from([
+            ("TG_BOT_TOKEN", "123:env-bot"),
+            ("NOTION_TOKEN", "secret_env"),
+            ("NOTION_VERSION", ""),
+        ]);
+        let cfg = load_with_env(Some(&p), |name| env.get(name).map(|v| v.to_string())).unwrap();
+        assert_eq!(cfg.telegram.bot_token, "123:env-bot");
+        assert_eq!(cfg.notion.token, "secret_env");
+        // An empty variable keeps the file's value
+        let file: Config = serde_yaml::from_str(example()).unwrap();
+        assert_eq!(cfg.notion.version, file.notion.version);
+    }
+
+    #[test]
+    fn overlay_env_ignores_unset_variables() {
+        let mut cfg: Config = serde_yaml::from_str(example()).unwrap();
+        let before = cfg.clone();
+        overlay_env(&mut cfg, |_| None);
+        assert_eq!(cfg, before);
+        overlay_env(&mut cfg, |name| {
+            (name == "NOTION_VERSION").then(|| "2099-01-01".to_string())
+        });
+        assert_eq!(cfg.notion.version, "2099-01-01");
+        assert_eq!(cfg.notion.token, before.notion.token);
+    }
+
     #[test]
     fn ensure_dirs_creates_data_dir() {
         let td = tempdir().unwrap();
@@ -323,4 +779,261 @@ mod tests {
         let cfg = load(Some(&p)).unwrap();
         assert_eq!(cfg.telegram.allowed_users, vec![123456789]);
     }
+
+    #[test]
+    fn media_mode_defaults_and_parses() {
+        let cfg: Config = serde_yaml::from_str(example()).unwrap();
+        assert_eq!(cfg.app.media_mode, MediaMode::External);
+        assert_eq!(cfg.app.media_upload_max_bytes, 20 * 1024 * 1024);
+
+        let yaml = example().replace(
+            "max_backoff_seconds: 60",
+            "max_backoff_seconds: 60\n  media_mode: auto\n  media_upload_max_bytes: 1024",
+        );
+        let cfg: Config = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(cfg.app.media_mode, MediaMode::Auto);
+        assert_eq!(cfg.app.media_upload_max_bytes, 1024);
+    }
+
+    #[test]
+    fn poll_strategy_defaults_to_interval() {
+        let cfg: Config = serde_yaml::from_str(example()).unwrap();
+        assert_eq!(cfg.app.poll_strategy, PollStrategy::Interval);
+        let yaml = example().replace(
+            "max_backoff_seconds: 60",
+            "max_backoff_seconds: 60\n  poll_strategy: notify",
+        );
+        let cfg: Config = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(cfg.app.poll_strategy, PollStrategy::Notify);
+    }
+
+    #[test]
+    fn rollback_mode_defaults_to_discard() {
+        let cfg: Config = serde_yaml::from_str(example()).unwrap();
+        assert_eq!(cfg.app.rollback_mode, RollbackMode::Discard);
+        let yaml = example().replace(
+            "max_backoff_seconds: 60",
+            "max_backoff_seconds: 60\n  rollback_mode: detach",
+        );
+        let cfg: Config = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(cfg.app.rollback_mode, RollbackMode::Detach);
+    }
+
+    #[test]
+    fn ack_mode_defaults_to_each_and_parses() {
+        let cfg: Config = serde_yaml::from_str(example()).unwrap();
+        assert_eq!(cfg.app.ack_mode, AckMode::Each);
+        assert!(cfg.app.ack_mode.acks_items());
+
+        for (name, mode) in [("summary", AckMode::Summary), ("silent", AckMode::Silent)] {
+            let yaml = example().replace(
+                "max_backoff_seconds: 60",
+                &format!("max_backoff_seconds: 60\n  ack_mode: {}", name),
+            );
+            let cfg: Config = serde_yaml::from_str(&yaml).unwrap();
+            assert_eq!(cfg.app.ack_mode, mode);
+            assert!(!mode.acks_items());
+        }
+    }
+
+    #[test]
+    fn max_batch_items_is_optional_and_must_be_positive() {
+        let mut cfg: Config = serde_yaml::from_str(example()).unwrap();
+        assert_eq!(cfg.app.max_batch_items, None);
+        cfg.app.max_batch_items = Some(0);
+        match validate(&cfg).unwrap_err() {
+            ConfigError::Invalid(msg) => assert!(msg.contains("max_batch_items")),
+            _ => panic!("wrong error"),
+        }
+        cfg.app.max_batch_items = Some(500);
+        assert!(validate(&cfg).is_ok());
+    }
+
+    #[test]
+    fn notion_version_must_be_a_date() {
+        let mut cfg: Config = serde_yaml::from_str(example()).unwrap();
+        assert!(is_valid_notion_version("2022-06-28"));
+        assert!(validate(&cfg).is_ok());
+        for bad in [
+            "2022-6-28",
+            "2022-13-01",
+            "2022-02-30",
+            "20220628",
+            "latest",
+        ] {
+            assert!(!is_valid_notion_version(bad), "{}", bad);
+            cfg.notion.version = bad.to_string();
+            match validate(&cfg).unwrap_err() {
+                ConfigError::Invalid(msg) => assert!(msg.contains("notion.version")),
+                _ => panic!("wrong error"),
+            }
+        }
+    }
+
+    #[test]
+    fn ancient_notion_version_only_warns() {
+        let mut cfg: Config = serde_yaml::from_str(example()).unwrap();
+        cfg.notion.version = "2021-05-13".to_string();
+        assert!(is_outdated_notion_version(&cfg.notion.version));
+        assert!(validate(&cfg).is_ok());
+        assert!(!is_outdated_notion_version("2022-06-28"));
+        assert!(!is_outdated_notion_version("2099-01-01"));
+    }
+
+    #[test]
+    fn thumbnail_format_defaults_to_jpg() {
+        let cfg: Config = serde_yaml::from_str(example()).unwrap();
+        assert_eq!(cfg.app.thumbnail_format, ThumbnailFormat::Jpg);
+        let cfg: Config = serde_yaml::from_str(&example().replace(
+            "max_backoff_seconds: 60",
+            "max_backoff_seconds: 60\n  thumbnail_format: webp",
+        ))
+        .unwrap();
+        assert_eq!(cfg.app.thumbnail_format, ThumbnailFormat::Webp);
+        assert_eq!(cfg.app.thumbnail_format.extension(), "webp");
+    }
+
+    #[test]
+    fn max_backoff_seconds_must_be_positive() {
+        let mut cfg: Config = serde_yaml::from_str(example()).unwrap();
+        cfg.app.max_backoff_seconds = 0;
+        match validate(&cfg).unwrap_err() {
+            ConfigError::Invalid(msg) => assert!(msg.contains("max_backoff_seconds")),
+            _ => panic!("wrong error"),
+        }
+    }
+
+    #[test]
+    fn db_max_connections_defaults_and_must_be_positive() {
+        let mut cfg: Config = serde_yaml::from_str(example()).unwrap();
+        assert_eq!(cfg.app.db_max_connections, 5);
+        cfg.app.db_max_connections = 0;
+        let err = validate(&cfg).unwrap_err();
+        match err {
+            ConfigError::Invalid(msg) => assert!(msg.contains("db_max_connections")),
+            _ => panic!("wrong error"),
+        }
+    }
+
+    #[test]
+    fn require_allowlist_rejects_empty_allowed_users() {
+        let mut cfg: Config = serde_yaml::from_str(example()).unwrap();
+        assert!(!cfg.telegram.require_allowlist);
+        cfg.telegram.allowed_users.clear();
+        validate(&cfg).unwrap();
+        cfg.telegram.require_allowlist = true;
+        match validate(&cfg).unwrap_err() {
+            ConfigError::Invalid(msg) => assert!(msg.contains("allowed_users")),
+            _ => panic!("wrong error"),
+        }
+        cfg.telegram.allowed_users = vec![1];
+        validate(&cfg).unwrap();
+    }
+
+    #[test]
+    fn allowed_users_are_deduplicated_in_order() {
+        let mut cfg: Config = serde_yaml::from_str(example()).unwrap();
+        cfg.telegram.allowed_users = vec![3, 1, 3, 2, 1, 3];
+        assert_eq!(dedup_allowed_users(&mut cfg.telegram), vec![3, 1, 3]);
+        assert_eq!(cfg.telegram.allowed_users, vec![3, 1, 2]);
+        assert!(dedup_allowed_users(&mut cfg.telegram).is_empty());
+    }
+
+    #[test]
+    fn upload_concurrency_defaults_and_must_be_positive() {
+        let mut cfg: Config = serde_yaml::from_str(example()).unwrap();
+        assert_eq!(cfg.app.upload_concurrency, 1);
+        cfg.app.upload_concurrency = 0;
+        match validate(&cfg).unwrap_err() {
+            ConfigError::Invalid(msg) => assert!(msg.contains("upload_concurrency")),
+            _ => panic!("wrong error"),
+        }
+    }
+
+    #[test]
+    fn default_title_falls_back_and_rejects_blank() {
+        let mut cfg: Config = serde_yaml::from_str(example()).unwrap();
+        assert_eq!(cfg.notion.default_title(), "Untitled");
+        cfg.notion.default_title = Some("Batch {date}".into());
+        assert_eq!(cfg.notion.default_title(), "Batch {date}");
+        validate(&cfg).unwrap();
+        cfg.notion.default_title = Some(" ".into());
+        match validate(&cfg).unwrap_err() {
+            ConfigError::Invalid(msg) => assert!(msg.contains("default_title")),
+            _ => panic!("wrong error"),
+        }
+    }
+
+    #[test]
+    fn main_page_icon_rejects_blank() {
+        let mut cfg: Config = serde_yaml::from_str(example()).unwrap();
+        assert_eq!(cfg.notion.main_page_icon, None);
+        cfg.notion.main_page_icon = Some("📦".into());
+        validate(&cfg).unwrap();
+        cfg.notion.main_page_icon = Some("".into());
+        match validate(&cfg).unwrap_err() {
+            ConfigError::Invalid(msg) => assert!(msg.contains("main_page_icon")),
+            _ => panic!("wrong error"),
+        }
+    }
+
+    #[test]
+    fn parses_multiple_routes() {
+        let cfg: Config = serde_yaml::from_str(example()).unwrap();
+        assert!(cfg.notion.routes.is_empty());
+
+        let yaml = format!(
+            "{}  routes:\n{}{}",
+            example(),
+            route_yaml(-1001, "A"),
+            route_yaml(-1002, "B")
+        );
+        let cfg: Config = serde_yaml::from_str(&yaml).unwrap();
+        validate(&cfg).unwrap();
+        assert_eq!(cfg.notion.routes.len(), 2);
+        assert_eq!(cfg.notion.routes[0].chat_id, -1001);
+        assert_eq!(
+            cfg.notion.routes[0].databases.notion_ids().main_db,
+            "MAIN_A"
+        );
+        assert_eq!(
+            cfg.notion.routes[1].databases.notion_ids().resource_db,
+            "RES_B"
+        );
+        // The default destination is unchanged
+        assert_eq!(cfg.notion_ids().main_db, "NOTION_MAIN_DATABASE_ID");
+
+        let yaml = format!(
+            "{}  routes:\n{}{}",
+            example(),
+            route_yaml(-1001, "A"),
+            route_yaml(-1001, "B")
+        );
+        let cfg: Config = serde_yaml::from_str(&yaml).unwrap();
+        let err = validate(&cfg).unwrap_err();
+        match err {
+            ConfigError::Invalid(msg) => assert!(msg.contains("chat_id")),
+            _ => panic!("wrong error"),
+        }
+    }
+
+    fn route_yaml(chat_id: i64, suffix: &str) -> String {
+        format!(
+            r#"    - chat_id: {chat_id}
+      databases:
+        main:
+          id: "MAIN_{suffix}"
+          fields:
+            title: "title"
+            unique: "unique"
+        resource:
+          id: "RES_{suffix}"
+          fields:
+            relation: "rel-parent"
+            order: "res-order"
+            text: "res-text"
+            media: "res-media"
+"#
+        )
+    }
 }
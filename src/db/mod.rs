@@ -14,4 +14,7 @@ pub mod repo;
 pub use repo::*;
 
 // Surface view models used by callers (e.g., outbox worker).
-pub use model::{BatchForOutbox, ResourceForOutbox};
+pub use model::{
+    BatchForOutbox, BatchSummary, InsertedResource, Notification, ResourceDetail,
+    ResourceForOutbox, ResourceMatch, ResourceOrigin, Stats,
+};
@@ -3,7 +3,8 @@
 //! Keep these structs focused on the data returned by queries. Business logic
 //! should live in higher layers.
 
-use crate::model::BatchState;
+use crate::model::{BatchState, MediaDimensions, ResourceKind};
+use chrono::{DateTime, Utc};
 
 /// Batch slice used by the outbox worker to decide how to sync a batch.
 #[derive(Debug, Clone)]
@@ -11,6 +12,15 @@ pub struct BatchForOutbox {
     pub state: BatchState,
     pub title: Option<String>,
     pub notion_page_id: Option<String>,
+    /// Main page created by an earlier attempt that failed its read-back
+    /// check; checked again on retry instead of creating another page.
+    pub pending_notion_page_id: Option<String>,
+    /// Routed chat id selecting the Notion databases; `None` uses the default.
+    pub notion_route: Option<i64>,
+    /// Chat the batch was opened in; `None` for rows from before it was recorded.
+    pub chat_id: Option<i64>,
+    /// As stored by SQLite, e.g. `2024-01-31 12:00:00` (UTC).
+    pub created_at: String,
 }
 
 /// Resource slice used by the outbox worker when pushing an item.
@@ -18,12 +28,142 @@ pub struct BatchForOutbox {
 pub struct ResourceForOutbox {
     pub batch_id: Option<i64>,
     pub sequence: i64,
-    pub kind: String,
+    pub kind: ResourceKind,
     pub content: String,
     pub text: Option<String>,
     pub media_name: Option<String>,
     pub media_url: Option<String>,
+    pub source_url: Option<String>,
+    pub tg_date: Option<DateTime<Utc>>,
+    /// Thumbnail generated for a video when it was saved.
+    pub thumb_path: Option<String>,
     pub notion_page_id: Option<String>,
     pub batch_state: Option<BatchState>,
     pub batch_notion_page_id: Option<String>,
+    /// Routed chat id selecting the Notion databases; `None` uses the default.
+    pub notion_route: Option<i64>,
+    /// Chat the resource was sent from; `None` for rows from before it was recorded.
+    pub chat_id: Option<i64>,
+    /// Size and play time of a photo or video; empty for other kinds.
+    pub dimensions: MediaDimensions,
+}
+
+/// One row of a user's batch history.
+#[derive(Debug, Clone)]
+pub struct BatchSummary {
+    pub id: i64,
+    pub title: Option<String>,
+    pub state: BatchState,
+    pub created_at: String,
+    pub resource_count: i64,
+}
+
+/// Details recorded with a new resource besides its content.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResourceOrigin<'a> {
+    /// Permalink to the original message, when the chat has one.
+    pub source_url: Option<&'a str>,
+    /// File `unique_id` for media, or a chat+message key for text.
+    pub unique_id: Option<&'a str>,
+    /// When the user sent the message.
+    pub tg_date: Option<DateTime<Utc>>,
+    /// Chat the message was sent in.
+    pub chat_id: Option<i64>,
+    /// Routed chat id for standalone resources; batched ones follow their batch.
+    pub notion_route: Option<i64>,
+    /// Local thumbnail generated for a video.
+    pub thumb_path: Option<&'a str>,
+    /// Link stored up front, e.g. the map of a shared location.
+    pub media_url: Option<&'a str>,
+    /// Caption sent with a photo or video, stored as its text.
+    pub text: Option<&'a str>,
+    /// Size and play time Telegram reported for a photo or video.
+    pub dimensions: MediaDimensions,
+    /// Number a batched resource after the user's highest sequence in any
+    /// batch rather than within its own batch (`app.global_sequence`).
+    pub global_sequence: bool,
+}
+
+/// Outcome of inserting a resource; `fresh` is false when it already existed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InsertedResource {
+    pub id: i64,
+    pub fresh: bool,
+}
+
+/// Local files of a synced resource whose media now lives in Notion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PurgeableMedia {
+    pub id: i64,
+    pub path: String,
+    pub thumb_path: Option<String>,
+}
+
+/// What [`prune_rolled_back_batches`](super::repo::prune_rolled_back_batches) deleted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrunedBatches {
+    pub batches: u64,
+    pub resources: u64,
+    /// Pending and dead-lettered outbox tasks of the pruned rows.
+    pub outbox_tasks: u64,
+    /// Local media and thumbnail files of the pruned resources that no
+    /// remaining resource uses; the caller deletes them.
+    pub media_paths: Vec<String>,
+}
+
+/// A resource whose text matched a `/find` query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceMatch {
+    pub id: i64,
+    /// Title of the batch holding it; `None` for standalone resources.
+    pub batch_title: Option<String>,
+    pub sequence: i64,
+    pub text: String,
+}
+
+/// One resource as shown by `/get`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceDetail {
+    pub id: i64,
+    pub kind: ResourceKind,
+    /// Message text or caption; the content itself for text resources.
+    pub text: Option<String>,
+    pub media_name: Option<String>,
+    pub notion_page_id: Option<String>,
+    /// Its push task was moved to `failed_outbox`.
+    pub dead_lettered: bool,
+}
+
+/// A synced batch whose chat should get a link to its Notion page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub id: i64,
+    pub chat_id: i64,
+    pub batch_id: i64,
+    pub batch_title: Option<String>,
+    pub page_id: String,
+}
+
+/// Bot-wide counts for `/stats`. The per-state and per-kind lists are sorted
+/// by name and leave out zero counts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub users: i64,
+    pub batches_by_state: Vec<(String, i64)>,
+    pub resources_by_kind: Vec<(String, i64)>,
+    pub pending_outbox: i64,
+    pub dead_letters: i64,
+}
+
+/// Outbox task that exhausted its attempts and was moved to `failed_outbox`.
+#[derive(Debug, Clone)]
+pub struct DeadLetter {
+    pub id: i64,
+    pub outbox_id: i64,
+    pub user_id: i64,
+    pub kind: String,
+    pub ref_id: i64,
+    pub attempt: i32,
+    pub last_error: Option<String>,
+    pub failed_at: String,
 }
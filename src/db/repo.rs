@@ -1,23 +1,44 @@
-use super::model::{BatchForOutbox, ResourceForOutbox};
-use crate::model::{BatchState, OutboxKind};
+use super::model::{
+    BatchForOutbox, BatchSummary, DeadLetter, InsertedResource, Notification, PrunedBatches,
+    PurgeableMedia, ResourceDetail, ResourceForOutbox, ResourceMatch, ResourceOrigin, Stats,
+};
+use crate::backoff::{jittered_backoff_secs, Jitter, RandomJitter};
+use crate::model::{BatchState, MediaDimensions, OutboxKind, ResourceKind};
 use anyhow::{anyhow, Context, Result};
 use chrono::{DateTime, Utc};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous};
 use sqlx::{Row, Transaction};
 use sqlx::{Sqlite, SqlitePool};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 use tracing::instrument;
 
 pub type Pool = SqlitePool;
 type OutboxItem = (i64, i64, String, i64, i32);
 
+pub const DEFAULT_MAX_CONNECTIONS: u32 = 5;
+const BUSY_TIMEOUT: Duration = Duration::from_millis(5000);
+
 pub async fn init_pool(database_url: &str) -> Result<Pool> {
+    init_pool_with(database_url, DEFAULT_MAX_CONNECTIONS).await
+}
+
+/// Open a pool of at most `max_connections`. Every connection uses WAL,
+/// `synchronous=FULL` and a 5s busy timeout so concurrent writers wait
+/// instead of failing with `database is locked`.
+pub async fn init_pool_with(database_url: &str, max_connections: u32) -> Result<Pool> {
+    if max_connections == 0 {
+        return Err(anyhow!("max_connections must be >= 1"));
+    }
     let normalized = prepare_sqlite_url(database_url);
-    let pool = SqlitePool::connect(&normalized).await?;
-    // Enable WAL and stricter durability.
-    sqlx::query("PRAGMA journal_mode=WAL;")
-        .execute(&pool)
-        .await?;
-    sqlx::query("PRAGMA synchronous=FULL;")
-        .execute(&pool)
+    let connect = SqliteConnectOptions::from_str(&normalized)?
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Wal)
+        .synchronous(SqliteSynchronous::Full)
+        .busy_timeout(BUSY_TIMEOUT);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(max_connections)
+        .connect_with(connect)
         .await?;
     Ok(pool)
 }
@@ -87,6 +108,28 @@ pub async fn run_migrations(pool: &Pool) -> Result<()> {
     Ok(())
 }
 
+/// Create the FTS5 index behind [`fts_search`] and the triggers keeping it in
+/// sync, indexing existing resources the first time. Fails when SQLite was
+/// built without FTS5.
+pub async fn enable_fts(pool: &Pool) -> Result<()> {
+    let exists: Option<String> =
+        sqlx::query_scalar("SELECT name FROM sqlite_master WHERE name = 'resources_fts'")
+            .fetch_optional(pool)
+            .await?;
+    sqlx::Executor::execute(
+        pool,
+        include_str!("../../migrations_fts/0001_resources_fts.sql"),
+    )
+    .await
+    .context("failed to create the FTS5 index (is FTS5 compiled into SQLite?)")?;
+    if exists.is_none() {
+        sqlx::query("INSERT INTO resources_fts (resources_fts) VALUES ('rebuild')")
+            .execute(pool)
+            .await?;
+    }
+    Ok(())
+}
+
 #[instrument(skip_all)]
 pub async fn get_or_create_user(
     pool: &Pool,
@@ -133,8 +176,267 @@ pub async fn current_batch_state(pool: &Pool, user_id: i64) -> Result<Option<Bat
     Ok(state.and_then(|s| BatchState::parse_state(&s)))
 }
 
+/// A user's batches, newest first, with their resource counts.
+#[allow(dead_code)]
+pub async fn list_batches(
+    pool: &Pool,
+    user_id: i64,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<BatchSummary>> {
+    list_batches_in_state(pool, user_id, None, limit, offset).await
+}
+
+/// Like [`list_batches`], optionally restricted to one state.
+pub async fn list_batches_in_state(
+    pool: &Pool,
+    user_id: i64,
+    state: Option<BatchState>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<BatchSummary>> {
+    let rows = sqlx::query(
+        "SELECT b.id, b.title, b.state, b.created_at, COUNT(r.id) AS resource_count \
+         FROM batches b LEFT JOIN resources r ON r.batch_id = b.id \
+         WHERE b.user_id = ? AND (? IS NULL OR b.state = ?) \
+         GROUP BY b.id \
+         ORDER BY datetime(b.created_at) DESC, b.id DESC \
+         LIMIT ? OFFSET ?",
+    )
+    .bind(user_id)
+    .bind(state.map(|s| s.as_str()))
+    .bind(state.map(|s| s.as_str()))
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            let id: i64 = row.get("id");
+            let state_str: String = row.get("state");
+            let state = BatchState::parse_state(&state_str)
+                .ok_or_else(|| anyhow!("batch {} has unknown state {}", id, state_str))?;
+            Ok(BatchSummary {
+                id,
+                title: row.try_get("title").ok().flatten(),
+                state,
+                created_at: row.get("created_at"),
+                resource_count: row.get("resource_count"),
+            })
+        })
+        .collect()
+}
+
+/// `(title, notion_page_id)` of the user's most recently committed batch that
+/// already has its Notion page.
+#[instrument(skip_all)]
+pub async fn latest_synced_batch(
+    pool: &Pool,
+    user_id: i64,
+) -> Result<Option<(Option<String>, String)>> {
+    let row = sqlx::query_as(
+        "SELECT title, notion_page_id FROM batches \
+         WHERE user_id = ? AND state = 'COMMITTED' AND notion_page_id IS NOT NULL \
+         ORDER BY id DESC LIMIT 1",
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row)
+}
+
+/// Up to `limit` of the user's resources whose text contains `query`
+/// (case-insensitive for ASCII), newest first. `%` and `_` match literally.
+#[instrument(skip_all)]
+pub async fn search_resources(
+    pool: &Pool,
+    user_id: i64,
+    query: &str,
+    limit: i64,
+) -> Result<Vec<ResourceMatch>> {
+    let pattern = format!("%{}%", escape_like(query));
+    let rows = sqlx::query(
+        "SELECT r.id, r.sequence, r.text, b.title AS batch_title \
+         FROM resources r LEFT JOIN batches b ON r.batch_id = b.id \
+         WHERE r.user_id = ? AND r.text LIKE ? ESCAPE '\\' \
+         ORDER BY r.id DESC \
+         LIMIT ?",
+    )
+    .bind(user_id)
+    .bind(pattern)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(resource_match).collect())
+}
+
+/// Like [`search_resources`] but through the FTS5 index from [`enable_fts`]:
+/// every word of `query` must appear, matched by token and stem (`running`
+/// finds `run`), best matches first.
+#[instrument(skip_all)]
+pub async fn fts_search(
+    pool: &Pool,
+    user_id: i64,
+    query: &str,
+    limit: i64,
+) -> Result<Vec<ResourceMatch>> {
+    let Some(expr) = fts_query(query) else {
+        return Ok(Vec::new());
+    };
+    let rows = sqlx::query(
+        "SELECT r.id, r.sequence, r.text, b.title AS batch_title \
+         FROM resources_fts \
+         JOIN resources r ON r.id = resources_fts.rowid \
+         LEFT JOIN batches b ON r.batch_id = b.id \
+         WHERE resources_fts MATCH ? AND r.user_id = ? \
+         ORDER BY resources_fts.rank, r.id DESC \
+         LIMIT ?",
+    )
+    .bind(expr)
+    .bind(user_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(resource_match).collect())
+}
+
+/// Quote each word so FTS5 operators and punctuation in user input are
+/// searched as plain text. `None` when there is nothing to search for.
+fn fts_query(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// Columns read by [`resource_detail`].
+const RESOURCE_DETAIL_COLUMNS: &str =
+    "r.id, r.kind, r.content, r.text, r.media_name, r.notion_page_id, \
+     EXISTS(SELECT 1 FROM failed_outbox f \
+            WHERE f.kind = 'push_resource' AND f.ref_id = r.id) AS dead_lettered";
+
+/// The resource at `sequence` in one of the user's batches; `None` when the
+/// batch belongs to someone else or has no such resource.
+#[instrument(skip_all)]
+pub async fn get_resource(
+    pool: &Pool,
+    user_id: i64,
+    batch_id: i64,
+    sequence: i64,
+) -> Result<Option<ResourceDetail>> {
+    let row = sqlx::query(&format!(
+        "SELECT {} FROM resources r \
+         WHERE r.user_id = ? AND r.batch_id = ? AND r.sequence = ?",
+        RESOURCE_DETAIL_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(batch_id)
+    .bind(sequence)
+    .fetch_optional(pool)
+    .await?;
+    row.map(resource_detail).transpose()
+}
+
+/// The user's resource saved from Telegram message `tg_message_id` in
+/// `chat_id`, found through the `(user_id, chat_id, tg_message_id)` unique
+/// index. Message ids are only unique within a chat.
+#[allow(dead_code)]
+#[instrument(skip_all)]
+pub async fn find_resource_by_message(
+    pool: &Pool,
+    user_id: i64,
+    chat_id: i64,
+    tg_message_id: i32,
+) -> Result<Option<ResourceDetail>> {
+    let row = sqlx::query(&format!(
+        "SELECT {} FROM resources r WHERE r.user_id = ? AND r.chat_id = ? AND r.tg_message_id = ?",
+        RESOURCE_DETAIL_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(chat_id)
+    .bind(tg_message_id)
+    .fetch_optional(pool)
+    .await?;
+    row.map(resource_detail).transpose()
+}
+
+fn resource_detail(row: sqlx::sqlite::SqliteRow) -> Result<ResourceDetail> {
+    let id: i64 = row.get("id");
+    let kind_str: String = row.get("kind");
+    let kind = ResourceKind::parse(&kind_str)
+        .ok_or_else(|| anyhow!("resource {} has unknown kind {}", id, kind_str))?;
+    let text = row
+        .get::<Option<String>, _>("text")
+        .filter(|s| !s.is_empty())
+        .or_else(|| (kind == ResourceKind::Text).then(|| row.get("content")));
+    Ok(ResourceDetail {
+        id,
+        kind,
+        text,
+        media_name: row.get("media_name"),
+        notion_page_id: row.get("notion_page_id"),
+        dead_lettered: row.get("dead_lettered"),
+    })
+}
+
+fn resource_match(row: sqlx::sqlite::SqliteRow) -> ResourceMatch {
+    ResourceMatch {
+        id: row.get("id"),
+        batch_title: row.get("batch_title"),
+        sequence: row.get::<Option<i64>, _>("sequence").unwrap_or(1),
+        text: row.get("text"),
+    }
+}
+
+/// Escape `\`, `%` and `_` for a `LIKE ... ESCAPE '\'` pattern.
+fn escape_like(query: &str) -> String {
+    let mut escaped = String::with_capacity(query.len());
+    for c in query.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Title stored on the user's current batch, if any (e.g. from `/begin <name>`).
+pub async fn current_batch_title(pool: &Pool, user_id: i64) -> Result<Option<String>> {
+    let title: Option<Option<String>> = sqlx::query_scalar(
+        "SELECT b.title FROM batches b JOIN current_batch c ON c.batch_id = b.id WHERE c.user_id = ?",
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(title.flatten().filter(|t| !t.trim().is_empty()))
+}
+
 #[instrument(skip_all)]
 pub async fn open_batch(pool: &Pool, user_id: i64) -> Result<i64> {
+    open_batch_routed(pool, user_id, None, None, None).await
+}
+
+/// Open a batch whose title is already known; a later `commit_batch` with no
+/// title keeps it.
+#[allow(dead_code)]
+#[instrument(skip_all)]
+pub async fn open_batch_with_title(pool: &Pool, user_id: i64, title: &str) -> Result<i64> {
+    open_batch_routed(pool, user_id, None, None, Some(title)).await
+}
+
+/// Open a batch that syncs to the Notion databases of `notion_route`
+/// (a routed chat id; `None` for the default databases), optionally titled.
+/// `chat_id` records the chat it was opened in.
+#[instrument(skip_all)]
+pub async fn open_batch_routed(
+    pool: &Pool,
+    user_id: i64,
+    notion_route: Option<i64>,
+    chat_id: Option<i64>,
+    title: Option<&str>,
+) -> Result<i64> {
     let mut tx = pool.begin().await?;
     let existing =
         sqlx::query_scalar::<_, i64>("SELECT batch_id FROM current_batch WHERE user_id = ?")
@@ -144,13 +446,17 @@ pub async fn open_batch(pool: &Pool, user_id: i64) -> Result<i64> {
     if existing.is_some() {
         return Err(anyhow!("batch already open"));
     }
-    let batch_id: i64 =
-        sqlx::query("INSERT INTO batches (user_id, state) VALUES (?, ?) RETURNING id")
-            .bind(user_id)
-            .bind(BatchState::Open.as_str())
-            .fetch_one(&mut *tx)
-            .await?
-            .get("id");
+    let batch_id: i64 = sqlx::query(
+        "INSERT INTO batches (user_id, state, notion_route, chat_id, title) VALUES (?, ?, ?, ?, ?) RETURNING id",
+    )
+    .bind(user_id)
+    .bind(BatchState::Open.as_str())
+    .bind(notion_route)
+    .bind(chat_id)
+    .bind(title)
+    .fetch_one(&mut *tx)
+    .await?
+    .get("id");
     sqlx::query("INSERT INTO current_batch (user_id, batch_id) VALUES (?, ?)")
         .bind(user_id)
         .bind(batch_id)
@@ -162,6 +468,18 @@ pub async fn open_batch(pool: &Pool, user_id: i64) -> Result<i64> {
 
 #[instrument(skip_all)]
 pub async fn rollback_batch(pool: &Pool, user_id: i64) -> Result<()> {
+    rollback_open_batch(pool, user_id, false).await.map(|_| ())
+}
+
+/// Roll back the open batch like [`rollback_batch`], but keep its unsynced
+/// resources as standalone items: each leaves the batch and is queued for its
+/// own push. Returns how many were detached.
+#[instrument(skip_all)]
+pub async fn rollback_batch_detaching(pool: &Pool, user_id: i64) -> Result<usize> {
+    rollback_open_batch(pool, user_id, true).await
+}
+
+async fn rollback_open_batch(pool: &Pool, user_id: i64, detach: bool) -> Result<usize> {
     let mut tx = pool.begin().await?;
     let batch_id =
         sqlx::query_scalar::<_, i64>("SELECT batch_id FROM current_batch WHERE user_id = ?")
@@ -177,16 +495,44 @@ pub async fn rollback_batch(pool: &Pool, user_id: i64) -> Result<()> {
     .bind(batch_id)
     .execute(&mut *tx)
     .await?;
+    let mut detached = 0;
+    if detach {
+        // Each keeps the sequence it had in the batch; a reopened batch keeps
+        // its synced ones
+        let res_ids: Vec<i64> = sqlx::query_scalar(
+            "UPDATE resources SET batch_id = NULL \
+             WHERE batch_id = ? AND notion_page_id IS NULL RETURNING id",
+        )
+        .bind(batch_id)
+        .fetch_all(&mut *tx)
+        .await?;
+        for &rid in &res_ids {
+            enqueue_outbox_tx(&mut tx, user_id, OutboxKind::PushResource, rid, Utc::now()).await?;
+        }
+        detached = res_ids.len();
+    }
     sqlx::query("DELETE FROM current_batch WHERE user_id = ?")
         .bind(user_id)
         .execute(&mut *tx)
         .await?;
     tx.commit().await?;
-    Ok(())
+    Ok(detached)
 }
 
 #[instrument(skip_all)]
 pub async fn commit_batch(pool: &Pool, user_id: i64, title: Option<&str>) -> Result<i64> {
+    commit_batch_notify(pool, user_id, title, None).await
+}
+
+/// [`commit_batch`] that also records `notify_chat_id`: once the batch's
+/// Notion page is created, a [`Notification`] for that chat is queued.
+#[instrument(skip_all)]
+pub async fn commit_batch_notify(
+    pool: &Pool,
+    user_id: i64,
+    title: Option<&str>,
+    notify_chat_id: Option<i64>,
+) -> Result<i64> {
     let mut tx = pool.begin().await?;
     let batch_id =
         sqlx::query_scalar::<_, i64>("SELECT batch_id FROM current_batch WHERE user_id = ?")
@@ -196,26 +542,46 @@ pub async fn commit_batch(pool: &Pool, user_id: i64, title: Option<&str>) -> Res
     let Some(batch_id) = batch_id else {
         return Err(anyhow!("no open batch"));
     };
-    sqlx::query("UPDATE batches SET state = 'COMMITTED', committed_at = CURRENT_TIMESTAMP, title = COALESCE(?, title) WHERE id = ?")
+    let prev = sqlx::query("SELECT title, notion_page_id FROM batches WHERE id = ?")
+        .bind(batch_id)
+        .fetch_one(&mut *tx)
+        .await?;
+    let synced = prev
+        .get::<Option<String>, _>("notion_page_id")
+        .is_some_and(|id| !id.trim().is_empty());
+    let retitled =
+        title.is_some_and(|t| prev.get::<Option<String>, _>("title").as_deref() != Some(t));
+    sqlx::query("UPDATE batches SET state = 'COMMITTED', committed_at = CURRENT_TIMESTAMP, title = COALESCE(?, title), notify_chat_id = ? WHERE id = ?")
         .bind(title)
+        .bind(notify_chat_id)
         .bind(batch_id)
         .execute(&mut *tx)
         .await?;
-    // enqueue push for batch
-    enqueue_outbox_tx(
-        &mut tx,
-        user_id,
-        OutboxKind::PushBatch,
-        batch_id,
-        Utc::now(),
+    // enqueue push for batch; a reopened batch whose main page exists only
+    // needs it to rename the page
+    if (!synced || retitled)
+        && !outbox_task_exists_tx(&mut tx, OutboxKind::PushBatch, batch_id).await?
+    {
+        enqueue_outbox_tx(
+            &mut tx,
+            user_id,
+            OutboxKind::PushBatch,
+            batch_id,
+            Utc::now(),
+        )
+        .await?;
+    }
+
+    // enqueue the batch's resources; a reopened batch skips those already
+    // synced or still queued
+    let res_ids: Vec<i64> = sqlx::query_scalar(
+        "SELECT id FROM resources WHERE batch_id = ? AND notion_page_id IS NULL \
+         AND id NOT IN (SELECT ref_id FROM outbox WHERE kind = ?)",
     )
+    .bind(batch_id)
+    .bind(OutboxKind::PushResource.as_str())
+    .fetch_all(&mut *tx)
     .await?;
-
-    // enqueue all resources in batch
-    let res_ids: Vec<i64> = sqlx::query_scalar("SELECT id FROM resources WHERE batch_id = ?")
-        .bind(batch_id)
-        .fetch_all(&mut *tx)
-        .await?;
     for rid in res_ids {
         enqueue_outbox_tx(&mut tx, user_id, OutboxKind::PushResource, rid, Utc::now()).await?;
     }
@@ -247,66 +613,256 @@ pub async fn mark_current_batch_waiting_title(pool: &Pool, user_id: i64) -> Resu
     Ok(())
 }
 
+/// Move the user's current batch from `WAITING_TITLE` back to `OPEN`,
+/// keeping its resources.
+#[instrument(skip_all)]
+pub async fn mark_current_batch_open(pool: &Pool, user_id: i64) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let row = sqlx::query(
+        "SELECT b.id, b.state FROM batches b JOIN current_batch c ON c.batch_id = b.id WHERE c.user_id = ?",
+    )
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(row) = row else {
+        return Err(anyhow!("no open batch"));
+    };
+    let batch_id: i64 = row.get("id");
+    let state: String = row.get("state");
+    let state = BatchState::parse_state(&state)
+        .ok_or_else(|| anyhow!("batch {} has unknown state {}", batch_id, state))?;
+    if !state.can_transition_to(BatchState::Open) {
+        return Err(anyhow!("batch {} cannot reopen from {:?}", batch_id, state));
+    }
+    sqlx::query("UPDATE batches SET state = ? WHERE id = ?")
+        .bind(BatchState::Open.as_str())
+        .bind(batch_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Make the user's committed batch `batch_id` their current batch again, so
+/// new items continue its sequence. Fails if another batch is open. Its
+/// queued push tasks are dropped, since they cannot run while the batch is
+/// open. A main page already pushed to Notion stays; the next commit enqueues
+/// only the resources not synced yet, and retitles the page if the title changed.
+#[allow(dead_code)]
+#[instrument(skip_all)]
+pub async fn reopen_batch(pool: &Pool, user_id: i64, batch_id: i64) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let existing =
+        sqlx::query_scalar::<_, i64>("SELECT batch_id FROM current_batch WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+    if existing.is_some() {
+        return Err(anyhow!("batch already open"));
+    }
+    let state: Option<String> =
+        sqlx::query_scalar("SELECT state FROM batches WHERE id = ? AND user_id = ?")
+            .bind(batch_id)
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+    let Some(state) = state else {
+        return Err(anyhow!("batch {} not found", batch_id));
+    };
+    let state = BatchState::parse_state(&state)
+        .ok_or_else(|| anyhow!("batch {} has unknown state {}", batch_id, state))?;
+    if state != BatchState::Committed || !state.can_transition_to(BatchState::Open) {
+        return Err(anyhow!("batch {} cannot reopen from {:?}", batch_id, state));
+    }
+    sqlx::query(
+        "DELETE FROM outbox WHERE (kind = ? AND ref_id = ?) \
+         OR (kind = ? AND ref_id IN (SELECT id FROM resources WHERE batch_id = ?))",
+    )
+    .bind(OutboxKind::PushBatch.as_str())
+    .bind(batch_id)
+    .bind(OutboxKind::PushResource.as_str())
+    .bind(batch_id)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("UPDATE batches SET state = ? WHERE id = ?")
+        .bind(BatchState::Open.as_str())
+        .bind(batch_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("INSERT INTO current_batch (user_id, batch_id) VALUES (?, ?)")
+        .bind(user_id)
+        .bind(batch_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Insert a resource and return its id. A repeated message returns the id of
+/// the existing row; see [`insert_resource_from`].
+#[allow(dead_code)]
 #[instrument(skip_all)]
 pub async fn insert_resource(
     pool: &Pool,
     user_id: i64,
     batch_id: Option<i64>,
-    kind: &str,
+    kind: ResourceKind,
     content: &str,
     tg_message_id: i32,
 ) -> Result<i64> {
+    let origin = ResourceOrigin::default();
+    let inserted = insert_resource_from(
+        pool,
+        user_id,
+        batch_id,
+        kind,
+        content,
+        tg_message_id,
+        &origin,
+    )
+    .await?;
+    Ok(inserted.id)
+}
+
+/// Id of the resource already saved from this file in this message of
+/// `chat_id`, so a re-delivered update can be ignored before its media is
+/// downloaded again.
+#[instrument(skip_all)]
+pub async fn find_redelivered_resource(
+    pool: &Pool,
+    user_id: i64,
+    chat_id: i64,
+    tg_message_id: i32,
+    unique_id: &str,
+) -> Result<Option<i64>> {
+    let id = sqlx::query_scalar(
+        "SELECT id FROM resources \
+         WHERE user_id = ? AND chat_id = ? AND tg_message_id = ? AND unique_id = ?",
+    )
+    .bind(user_id)
+    .bind(chat_id)
+    .bind(tg_message_id)
+    .bind(unique_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(id)
+}
+
+/// Insert a resource with its Telegram origin. If the user already has a
+/// resource for the same message in the same chat, that row is returned with
+/// `fresh: false` and nothing is enqueued, so re-delivered updates are
+/// harmless. Without `origin.chat_id` every insert is fresh. The permalink is stored in the same transaction so
+/// the worker never sees the row without it.
+#[instrument(skip_all)]
+pub async fn insert_resource_from(
+    pool: &Pool,
+    user_id: i64,
+    batch_id: Option<i64>,
+    kind: ResourceKind,
+    content: &str,
+    tg_message_id: i32,
+    origin: &ResourceOrigin<'_>,
+) -> Result<InsertedResource> {
     let mut tx = pool.begin().await?;
 
-    // Calculate sequence for items in a batch (1..N). Standalone items use 1.
-    let sequence_opt: Option<i64> = if let Some(batch_id) = batch_id {
-        let max_seq: Option<i64> =
-            sqlx::query_scalar("SELECT MAX(sequence) FROM resources WHERE batch_id = ?")
-                .bind(batch_id)
-                .fetch_optional(&mut *tx)
-                .await?;
-        Some(max_seq.unwrap_or(0) + 1)
-    } else {
-        Some(1)
-    };
-    let text_value = if kind == "text" {
-        Some(content.to_string())
-    } else {
-        None
+    // Locations keep their coordinates readable on the Notion page
+    let text_value = match kind {
+        ResourceKind::Text | ResourceKind::Location => Some(content.to_string()),
+        ResourceKind::Photo | ResourceKind::Video => origin
+            .text
+            .filter(|t| !t.trim().is_empty())
+            .map(str::to_string),
     };
+    // Write first: a deferred transaction that reads before writing cannot
+    // take the write lock once another connection has committed meanwhile.
+    // The message index turns a re-delivered message into a no-op.
     let rec = sqlx::query(
-        "INSERT INTO resources (user_id, batch_id, kind, content, tg_message_id, sequence, text, media_name, media_url) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING id",
+        "INSERT INTO resources (user_id, batch_id, kind, content, tg_message_id, text, media_name, media_url, source_url, unique_id, notion_route, tg_date, thumb_path, chat_id, width, height, duration) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) ON CONFLICT DO NOTHING RETURNING id",
     )
     .bind(user_id)
     .bind(batch_id)
-    .bind(kind)
+    .bind(kind.as_str())
     .bind(content)
     .bind(tg_message_id)
-    .bind(sequence_opt)
     .bind(text_value)
     .bind::<Option<String>>(None)
-    .bind::<Option<String>>(None)
-    .fetch_one(&mut *tx)
+    .bind(origin.media_url)
+    .bind(origin.source_url)
+    .bind(origin.unique_id)
+    .bind(origin.notion_route)
+    .bind(origin.tg_date)
+    .bind(origin.thumb_path)
+    .bind(origin.chat_id)
+    .bind(origin.dimensions.width)
+    .bind(origin.dimensions.height)
+    .bind(origin.dimensions.duration)
+    .fetch_optional(&mut *tx)
     .await?;
+    let Some(rec) = rec else {
+        let id: i64 = sqlx::query_scalar(
+            "SELECT id FROM resources WHERE user_id = ? AND chat_id = ? AND tg_message_id = ?",
+        )
+        .bind(user_id)
+        .bind(origin.chat_id)
+        .bind(tg_message_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        return Ok(InsertedResource { id, fresh: false });
+    };
     let id: i64 = rec.get("id");
 
-    // If standalone (no batch), enqueue push task
-    if batch_id.is_none() {
-        enqueue_outbox_tx(&mut tx, user_id, OutboxKind::PushResource, id, Utc::now()).await?;
-    }
+    // Resources in a batch must land in the batch's resource database. Only an
+    // open batch takes new items: commit enqueues the members it has then.
+    let notion_route: Option<i64> = match batch_id {
+        Some(batch_id) => {
+            let row = sqlx::query("SELECT state, notion_route FROM batches WHERE id = ?")
+                .bind(batch_id)
+                .fetch_optional(&mut *tx)
+                .await?
+                .ok_or_else(|| anyhow!("batch {} not found", batch_id))?;
+            let state: String = row.get("state");
+            if BatchState::parse_state(&state) != Some(BatchState::Open) {
+                return Err(anyhow!(
+                    "batch {} is {}; cannot add resources",
+                    batch_id,
+                    state
+                ));
+            }
+            row.get("notion_route")
+        }
+        None => origin.notion_route,
+    };
 
-    tx.commit().await?;
-    Ok(id)
+    let sequence = if origin.global_sequence && batch_id.is_some() {
+        next_global_sequence_in(&mut tx, user_id).await?
+    } else {
+        next_resource_sequence_in(&mut tx, batch_id).await?
+    };
+    sqlx::query("UPDATE resources SET sequence = ?, notion_route = ? WHERE id = ?")
+        .bind(sequence)
+        .bind(notion_route)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
+    // If standalone (no batch), enqueue push task
+    if batch_id.is_none() {
+        enqueue_outbox_tx(&mut tx, user_id, OutboxKind::PushResource, id, Utc::now()).await?;
+    }
+
+    tx.commit().await?;
+    Ok(InsertedResource { id, fresh: true })
 }
 
 // View models are declared in `model.rs` to keep repository focused on SQL.
 
 pub async fn fetch_batch_for_outbox(pool: &Pool, batch_id: i64) -> Result<BatchForOutbox> {
-    let row =
-        sqlx::query("SELECT id, user_id, state, title, notion_page_id FROM batches WHERE id = ?")
-            .bind(batch_id)
-            .fetch_optional(pool)
-            .await?;
+    let row = sqlx::query(
+        "SELECT id, user_id, state, title, notion_page_id, pending_notion_page_id, notion_route, chat_id, created_at FROM batches WHERE id = ?",
+    )
+    .bind(batch_id)
+    .fetch_optional(pool)
+    .await?;
 
     let Some(row) = row else {
         return Err(anyhow!("batch {} not found", batch_id));
@@ -323,13 +879,33 @@ pub async fn fetch_batch_for_outbox(pool: &Pool, batch_id: i64) -> Result<BatchF
             .try_get::<String, _>("notion_page_id")
             .ok()
             .filter(|s| !s.trim().is_empty()),
+        pending_notion_page_id: row
+            .try_get::<Option<String>, _>("pending_notion_page_id")
+            .ok()
+            .flatten(),
+        notion_route: row.try_get::<Option<i64>, _>("notion_route").ok().flatten(),
+        chat_id: row.try_get::<Option<i64>, _>("chat_id").ok().flatten(),
+        created_at: row.get("created_at"),
     })
 }
 
+/// Remember a main page created for `batch_id` before it is verified, so a
+/// retry checks that page again instead of creating a second one.
+#[instrument(skip_all)]
+pub async fn set_batch_pending_page(pool: &Pool, batch_id: i64, page_id: &str) -> Result<()> {
+    sqlx::query("UPDATE batches SET pending_notion_page_id = ? WHERE id = ?")
+        .bind(page_id)
+        .bind(batch_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
 pub async fn fetch_resource_for_outbox(pool: &Pool, resource_id: i64) -> Result<ResourceForOutbox> {
     let row = sqlx::query(
-        "SELECT r.id, r.user_id, r.batch_id, r.sequence, r.text, r.media_name, r.media_url, \
-                r.notion_page_id, r.kind, r.content, r.tg_message_id, \
+        "SELECT r.id, r.user_id, r.batch_id, r.sequence, r.text, r.media_name, r.media_url, r.source_url, r.tg_date, r.thumb_path, \
+                r.notion_page_id, r.kind, r.content, r.tg_message_id, r.notion_route, r.chat_id, \
+                r.width, r.height, r.duration, \
                 b.state AS batch_state, b.notion_page_id AS batch_notion_page_id \
          FROM resources r \
          LEFT JOIN batches b ON r.batch_id = b.id \
@@ -351,20 +927,16 @@ pub async fn fetch_resource_for_outbox(pool: &Pool, resource_id: i64) -> Result<
         .flatten()
         .unwrap_or(1);
 
-    let kind: String = row.get("kind");
+    let kind_str: String = row.get("kind");
+    let kind = ResourceKind::parse(&kind_str)
+        .ok_or_else(|| anyhow!("resource {} has unknown kind {}", resource_id, kind_str))?;
     let content: String = row.get("content");
     let text: Option<String> = row
         .try_get::<Option<String>, _>("text")
         .ok()
         .flatten()
         .filter(|s| !s.is_empty());
-    let text = text.or_else(|| {
-        if kind == "text" {
-            Some(content.clone())
-        } else {
-            None
-        }
-    });
+    let text = text.or_else(|| (kind == ResourceKind::Text).then(|| content.clone()));
 
     let batch_state = row
         .try_get::<Option<String>, _>("batch_state")
@@ -383,6 +955,18 @@ pub async fn fetch_resource_for_outbox(pool: &Pool, resource_id: i64) -> Result<
             .ok()
             .flatten(),
         media_url: row.try_get::<Option<String>, _>("media_url").ok().flatten(),
+        source_url: row
+            .try_get::<Option<String>, _>("source_url")
+            .ok()
+            .flatten(),
+        tg_date: row
+            .try_get::<Option<DateTime<Utc>>, _>("tg_date")
+            .ok()
+            .flatten(),
+        thumb_path: row
+            .try_get::<Option<String>, _>("thumb_path")
+            .ok()
+            .flatten(),
         notion_page_id: row
             .try_get::<Option<String>, _>("notion_page_id")
             .ok()
@@ -392,33 +976,349 @@ pub async fn fetch_resource_for_outbox(pool: &Pool, resource_id: i64) -> Result<
             .try_get::<Option<String>, _>("batch_notion_page_id")
             .ok()
             .flatten(),
+        notion_route: row.try_get::<Option<i64>, _>("notion_route").ok().flatten(),
+        chat_id: row.try_get::<Option<i64>, _>("chat_id").ok().flatten(),
+        dimensions: MediaDimensions {
+            width: row.try_get::<Option<u32>, _>("width").ok().flatten(),
+            height: row.try_get::<Option<u32>, _>("height").ok().flatten(),
+            duration: row.try_get::<Option<u32>, _>("duration").ok().flatten(),
+        },
     })
 }
 
-pub async fn mark_batch_notion_page_id(pool: &Pool, batch_id: i64, page_id: &str) -> Result<()> {
-    sqlx::query("UPDATE batches SET notion_page_id = ? WHERE id = ?")
-        .bind(page_id)
-        .bind(batch_id)
+/// All resources attached to a batch, in sequence order, as the worker would see them.
+pub async fn fetch_batch_resources_for_outbox(
+    pool: &Pool,
+    batch_id: i64,
+) -> Result<Vec<ResourceForOutbox>> {
+    let ids: Vec<i64> =
+        sqlx::query_scalar("SELECT id FROM resources WHERE batch_id = ? ORDER BY sequence, id")
+            .bind(batch_id)
+            .fetch_all(pool)
+            .await?;
+    let mut resources = Vec::with_capacity(ids.len());
+    for id in ids {
+        resources.push(fetch_resource_for_outbox(pool, id).await?);
+    }
+    Ok(resources)
+}
+
+/// Record that a resource's local file was uploaded to Notion, which makes it
+/// eligible for [`list_purgeable_media`] once the page id is stored.
+#[instrument(skip_all)]
+pub async fn mark_resource_media_uploaded(pool: &Pool, resource_id: i64) -> Result<()> {
+    sqlx::query("UPDATE resources SET media_uploaded = 1 WHERE id = ?")
+        .bind(resource_id)
         .execute(pool)
         .await
-        .context("failed to persist batch notion page")?;
+        .context("failed to mark resource media uploaded")?;
+    Ok(())
+}
+
+/// Synced resources whose uploaded media still has local files, oldest first.
+/// Externally linked media is never listed.
+#[instrument(skip_all)]
+pub async fn list_purgeable_media(pool: &Pool, limit: i64) -> Result<Vec<PurgeableMedia>> {
+    let rows: Vec<(i64, String, Option<String>)> = sqlx::query_as(
+        "SELECT id, content, thumb_path FROM resources \
+         WHERE notion_page_id IS NOT NULL AND media_uploaded = 1 AND media_purged_at IS NULL \
+         ORDER BY id LIMIT ?",
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(id, path, thumb_path)| PurgeableMedia {
+            id,
+            path,
+            thumb_path,
+        })
+        .collect())
+}
+
+/// Record that a resource's local files were deleted; its media is only in Notion now.
+#[instrument(skip_all)]
+pub async fn mark_media_purged(pool: &Pool, resource_id: i64) -> Result<()> {
+    sqlx::query("UPDATE resources SET media_purged_at = CURRENT_TIMESTAMP WHERE id = ?")
+        .bind(resource_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Delete `ROLLED_BACK` batches rolled back before `older_than`, with their
+/// resources and outbox tasks (pending and dead-lettered), in one transaction.
+/// Files are left on disk: their paths are returned for the caller to remove.
+#[allow(dead_code)]
+#[instrument(skip_all)]
+pub async fn prune_rolled_back_batches(
+    pool: &Pool,
+    older_than: DateTime<Utc>,
+) -> Result<PrunedBatches> {
+    let cutoff = older_than.format("%Y-%m-%d %H:%M:%S").to_string();
+    let mut tx = pool.begin().await?;
+    let batch_ids: Vec<i64> = sqlx::query_scalar(
+        "SELECT id FROM batches WHERE state = ? \
+         AND datetime(COALESCE(rolled_back_at, created_at)) < datetime(?) ORDER BY id",
+    )
+    .bind(BatchState::RolledBack.as_str())
+    .bind(&cutoff)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut pruned = PrunedBatches::default();
+    for batch_id in batch_ids {
+        let files: Vec<(String, String, Option<String>)> =
+            sqlx::query_as("SELECT kind, content, thumb_path FROM resources WHERE batch_id = ?")
+                .bind(batch_id)
+                .fetch_all(&mut *tx)
+                .await?;
+        for (kind, content, thumb_path) in files {
+            // Text and locations keep their content in the row, not in a file
+            if matches!(
+                ResourceKind::parse(&kind),
+                Some(ResourceKind::Photo | ResourceKind::Video)
+            ) {
+                pruned.media_paths.push(content);
+            }
+            pruned.media_paths.extend(thumb_path);
+        }
+        for table in ["outbox", "failed_outbox"] {
+            pruned.outbox_tasks += sqlx::query(&format!(
+                "DELETE FROM {} WHERE (kind = ? AND ref_id = ?) \
+                 OR (kind IN (?, ?) AND ref_id IN (SELECT id FROM resources WHERE batch_id = ?))",
+                table
+            ))
+            .bind(OutboxKind::PushBatch.as_str())
+            .bind(batch_id)
+            .bind(OutboxKind::PushResource.as_str())
+            .bind(OutboxKind::UpdateResource.as_str())
+            .bind(batch_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        }
+        pruned.resources += sqlx::query("DELETE FROM resources WHERE batch_id = ?")
+            .bind(batch_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        pruned.batches += sqlx::query("DELETE FROM batches WHERE id = ?")
+            .bind(batch_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+    }
+
+    // A file re-saved into another batch stays with the resource still using it
+    pruned.media_paths.sort();
+    pruned.media_paths.dedup();
+    let mut unused = Vec::with_capacity(pruned.media_paths.len());
+    for path in pruned.media_paths {
+        let in_use: bool = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM resources WHERE content = ? OR thumb_path = ?)",
+        )
+        .bind(&path)
+        .bind(&path)
+        .fetch_one(&mut *tx)
+        .await?;
+        if !in_use {
+            unused.push(path);
+        }
+    }
+    pruned.media_paths = unused;
+    tx.commit().await?;
+    Ok(pruned)
+}
+
+/// Replace the text of the user's text resource from `tg_message_id` in
+/// `chat_id`, or the caption of a photo or video, after the Telegram message
+/// was edited. A
+/// resource already synced to Notion also gets an `update_resource` task; an
+/// unsynced one is picked up by its pending push. Returns the resource id, or
+/// `None` when no such resource matches.
+#[instrument(skip_all)]
+pub async fn update_resource_text(
+    pool: &Pool,
+    user_id: i64,
+    chat_id: i64,
+    tg_message_id: i32,
+    text: &str,
+) -> Result<Option<i64>> {
+    let mut tx = pool.begin().await?;
+    let row: Option<(i64, Option<String>)> = sqlx::query_as(
+        "SELECT id, notion_page_id FROM resources \
+         WHERE user_id = ? AND chat_id = ? AND tg_message_id = ? \
+         AND kind IN ('text', 'photo', 'video')",
+    )
+    .bind(user_id)
+    .bind(chat_id)
+    .bind(tg_message_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some((resource_id, notion_page_id)) = row else {
+        return Ok(None);
+    };
+
+    // Media keeps its file path in `content`
+    sqlx::query(
+        "UPDATE resources SET content = CASE WHEN kind = 'text' THEN ? ELSE content END, \
+         text = ? WHERE id = ?",
+    )
+    .bind(text)
+    .bind(text)
+    .bind(resource_id)
+    .execute(&mut *tx)
+    .await
+    .context("failed to update resource text")?;
+    if notion_page_id.is_some()
+        && !outbox_task_exists_tx(&mut tx, OutboxKind::UpdateResource, resource_id).await?
+    {
+        enqueue_outbox_tx(
+            &mut tx,
+            user_id,
+            OutboxKind::UpdateResource,
+            resource_id,
+            Utc::now(),
+        )
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(Some(resource_id))
+}
+
+/// Persist the batch's Notion page id and delete its outbox row in one transaction,
+/// so a crash can never leave a synced batch queued for another push. A batch
+/// committed with a notify chat queues its [`Notification`] here, once.
+#[instrument(skip_all)]
+pub async fn complete_outbox_batch(
+    pool: &Pool,
+    outbox_id: i64,
+    batch_id: i64,
+    page_id: &str,
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "UPDATE batches SET notion_page_id = ?, pending_notion_page_id = NULL WHERE id = ?",
+    )
+    .bind(page_id)
+    .bind(batch_id)
+    .execute(&mut *tx)
+    .await
+    .context("failed to persist batch notion page")?;
+    sqlx::query(
+        "INSERT INTO notifications (chat_id, batch_id, page_id) \
+         SELECT notify_chat_id, id, ? FROM batches WHERE id = ? AND notify_chat_id IS NOT NULL",
+    )
+    .bind(page_id)
+    .bind(batch_id)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("UPDATE batches SET notify_chat_id = NULL WHERE id = ?")
+        .bind(batch_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM outbox WHERE id = ?")
+        .bind(outbox_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Queued notifications, oldest first.
+#[instrument(skip_all)]
+pub async fn list_notifications(pool: &Pool, limit: i64) -> Result<Vec<Notification>> {
+    let rows: Vec<(i64, i64, i64, Option<String>, String)> = sqlx::query_as(
+        "SELECT n.id, n.chat_id, n.batch_id, b.title, n.page_id \
+         FROM notifications n JOIN batches b ON b.id = n.batch_id \
+         ORDER BY n.id LIMIT ?",
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(
+            |(id, chat_id, batch_id, batch_title, page_id)| Notification {
+                id,
+                chat_id,
+                batch_id,
+                batch_title,
+                page_id,
+            },
+        )
+        .collect())
+}
+
+#[instrument(skip_all)]
+pub async fn delete_notification(pool: &Pool, id: i64) -> Result<()> {
+    sqlx::query("DELETE FROM notifications WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
     Ok(())
 }
 
-pub async fn mark_resource_notion_page_id(
+/// Resource counterpart of [`complete_outbox_batch`].
+#[instrument(skip_all)]
+pub async fn complete_outbox_resource(
     pool: &Pool,
+    outbox_id: i64,
     resource_id: i64,
     page_id: &str,
 ) -> Result<()> {
+    let mut tx = pool.begin().await?;
     sqlx::query("UPDATE resources SET notion_page_id = ? WHERE id = ?")
         .bind(page_id)
         .bind(resource_id)
-        .execute(pool)
+        .execute(&mut *tx)
         .await
         .context("failed to persist resource notion page")?;
+    sqlx::query("DELETE FROM outbox WHERE id = ?")
+        .bind(outbox_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
     Ok(())
 }
 
+/// Sequence for the next resource of `batch_id`: `MAX(sequence) + 1`, so
+/// deleting a row never leads to a reused number. Standalone items use 1.
+#[allow(dead_code)]
+pub async fn next_resource_sequence(pool: &Pool, batch_id: Option<i64>) -> Result<i64> {
+    let mut conn = pool.acquire().await?;
+    next_resource_sequence_in(&mut conn, batch_id).await
+}
+
+async fn next_resource_sequence_in(
+    conn: &mut sqlx::SqliteConnection,
+    batch_id: Option<i64>,
+) -> Result<i64> {
+    let Some(batch_id) = batch_id else {
+        return Ok(1);
+    };
+    let max_seq: Option<i64> =
+        sqlx::query_scalar("SELECT MAX(sequence) FROM resources WHERE batch_id = ?")
+            .bind(batch_id)
+            .fetch_one(&mut *conn)
+            .await?;
+    Ok(max_seq.unwrap_or(0) + 1)
+}
+
+/// Next sequence after the user's highest one in any batch; standalone
+/// resources (always 1) do not count.
+async fn next_global_sequence_in(conn: &mut sqlx::SqliteConnection, user_id: i64) -> Result<i64> {
+    let max_seq: Option<i64> = sqlx::query_scalar(
+        "SELECT MAX(sequence) FROM resources WHERE user_id = ? AND batch_id IS NOT NULL",
+    )
+    .bind(user_id)
+    .fetch_one(&mut *conn)
+    .await?;
+    Ok(max_seq.unwrap_or(0) + 1)
+}
+
 async fn enqueue_outbox_tx(
     tx: &mut Transaction<'_, Sqlite>,
     user_id: i64,
@@ -432,17 +1332,186 @@ async fn enqueue_outbox_tx(
     .bind(user_id)
     .bind(kind.as_str())
     .bind(ref_id)
-    .bind(due_at)
+    .bind(due_at.timestamp())
     .fetch_one(&mut **tx)
     .await?;
     Ok(rec.get("id"))
 }
 
+/// Enqueue a push for every unsynced resource of a committed batch that has
+/// no outbox task and was never dead-lettered, e.g. rows attached after the
+/// commit. Returns how many were enqueued; other batch states enqueue nothing.
+#[allow(dead_code)]
+#[instrument(skip_all)]
+pub async fn enqueue_missing_resources(pool: &Pool, batch_id: i64) -> Result<usize> {
+    let mut tx = pool.begin().await?;
+    let missing: Vec<(i64, i64)> = sqlx::query_as(
+        "SELECT r.id, r.user_id FROM resources r JOIN batches b ON b.id = r.batch_id \
+         WHERE r.batch_id = ? AND b.state = 'COMMITTED' AND r.notion_page_id IS NULL \
+         AND NOT EXISTS (SELECT 1 FROM outbox o WHERE o.kind = 'push_resource' AND o.ref_id = r.id) \
+         AND NOT EXISTS (SELECT 1 FROM failed_outbox f WHERE f.kind = 'push_resource' AND f.ref_id = r.id) \
+         ORDER BY r.sequence, r.id",
+    )
+    .bind(batch_id)
+    .fetch_all(&mut *tx)
+    .await?;
+    let now = Utc::now();
+    for &(rid, user_id) in &missing {
+        enqueue_outbox_tx(&mut tx, user_id, OutboxKind::PushResource, rid, now).await?;
+    }
+    tx.commit().await?;
+    Ok(missing.len())
+}
+
+#[allow(dead_code)]
+#[instrument(skip_all)]
+pub async fn find_user_id(pool: &Pool, tg_user_id: i64) -> Result<Option<i64>> {
+    let id = sqlx::query_scalar::<_, i64>("SELECT id FROM users WHERE tg_user_id = ?")
+        .bind(tg_user_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(id)
+}
+
+/// Already-synced resources of a user, oldest first, optionally limited to
+/// those created at or after `since`.
+#[allow(dead_code)]
+#[instrument(skip_all)]
+pub async fn list_synced_resource_ids(
+    pool: &Pool,
+    user_id: i64,
+    since: Option<DateTime<Utc>>,
+    limit: Option<i64>,
+) -> Result<Vec<i64>> {
+    let since = since.map(|d| d.format("%Y-%m-%d %H:%M:%S").to_string());
+    let ids = sqlx::query_scalar::<_, i64>(
+        "SELECT id FROM resources \
+         WHERE user_id = ? AND notion_page_id IS NOT NULL \
+           AND (? IS NULL OR datetime(created_at) >= datetime(?)) \
+         ORDER BY datetime(created_at), id \
+         LIMIT ?",
+    )
+    .bind(user_id)
+    .bind(&since)
+    .bind(&since)
+    .bind(limit.unwrap_or(-1))
+    .fetch_all(pool)
+    .await?;
+    Ok(ids)
+}
+
+/// Clear stored Notion page ids and enqueue fresh push tasks so the given
+/// resources are synced again. A resource in a batch re-pushes its whole
+/// batch: the batch page is recreated first, then every resource in it.
+/// Rows that already have an outbox task are not enqueued twice.
+/// Returns the number of `(batches, resources)` enqueued.
+#[allow(dead_code)]
+#[instrument(skip_all)]
+pub async fn reset_and_enqueue(pool: &Pool, resource_ids: &[i64]) -> Result<(usize, usize)> {
+    let mut tx = pool.begin().await?;
+    let now = Utc::now();
+
+    let mut batch_ids: Vec<i64> = Vec::new();
+    let mut standalone: Vec<i64> = Vec::new();
+    for &rid in resource_ids {
+        let batch_id: Option<i64> =
+            sqlx::query_scalar("SELECT batch_id FROM resources WHERE id = ?")
+                .bind(rid)
+                .fetch_optional(&mut *tx)
+                .await?
+                .ok_or_else(|| anyhow!("resource {} not found", rid))?;
+        match batch_id {
+            Some(bid) if !batch_ids.contains(&bid) => batch_ids.push(bid),
+            Some(_) => {}
+            None => standalone.push(rid),
+        }
+    }
+
+    let mut batches = 0;
+    let mut resources = 0;
+    let mut queued_resources: Vec<i64> = Vec::new();
+    for bid in batch_ids {
+        let row = sqlx::query("SELECT user_id, state FROM batches WHERE id = ?")
+            .bind(bid)
+            .fetch_one(&mut *tx)
+            .await?;
+        let state: String = row.get("state");
+        if BatchState::parse_state(&state) != Some(BatchState::Committed) {
+            continue;
+        }
+        let user_id: i64 = row.get("user_id");
+        sqlx::query("UPDATE batches SET notion_page_id = NULL WHERE id = ?")
+            .bind(bid)
+            .execute(&mut *tx)
+            .await?;
+        if !outbox_task_exists_tx(&mut tx, OutboxKind::PushBatch, bid).await? {
+            enqueue_outbox_tx(&mut tx, user_id, OutboxKind::PushBatch, bid, now).await?;
+            batches += 1;
+        }
+        let members: Vec<i64> =
+            sqlx::query_scalar("SELECT id FROM resources WHERE batch_id = ? ORDER BY sequence, id")
+                .bind(bid)
+                .fetch_all(&mut *tx)
+                .await?;
+        queued_resources.extend(members);
+    }
+    queued_resources.extend(standalone);
+
+    for rid in queued_resources {
+        let user_id: i64 = sqlx::query_scalar("SELECT user_id FROM resources WHERE id = ?")
+            .bind(rid)
+            .fetch_one(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE resources SET notion_page_id = NULL WHERE id = ?")
+            .bind(rid)
+            .execute(&mut *tx)
+            .await?;
+        if !outbox_task_exists_tx(&mut tx, OutboxKind::PushResource, rid).await? {
+            enqueue_outbox_tx(&mut tx, user_id, OutboxKind::PushResource, rid, now).await?;
+            resources += 1;
+        }
+    }
+
+    tx.commit().await?;
+    Ok((batches, resources))
+}
+
+fn truncate_error(error: &str) -> String {
+    if error.chars().count() <= MAX_ERROR_CHARS {
+        return error.to_string();
+    }
+    let mut out: String = error.chars().take(MAX_ERROR_CHARS).collect();
+    out.push('…');
+    out
+}
+
+async fn outbox_task_exists_tx(
+    tx: &mut Transaction<'_, Sqlite>,
+    kind: OutboxKind,
+    ref_id: i64,
+) -> Result<bool> {
+    let count: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM outbox WHERE kind = ? AND ref_id = ?")
+            .bind(kind.as_str())
+            .bind(ref_id)
+            .fetch_one(&mut **tx)
+            .await?;
+    Ok(count > 0)
+}
+
 #[instrument(skip_all)]
 pub async fn next_due_outbox(pool: &Pool) -> Result<Option<OutboxItem>> {
+    next_due_outbox_at(pool, Utc::now()).await
+}
+
+/// The task [`next_due_outbox`] would return if the time were `now`.
+/// `due_at` is stored as Unix seconds.
+#[instrument(skip_all)]
+pub async fn next_due_outbox_at(pool: &Pool, now: DateTime<Utc>) -> Result<Option<OutboxItem>> {
     let row = sqlx::query(
-        "SELECT id, user_id, kind, ref_id, attempt FROM outbox WHERE datetime(due_at) <= CURRENT_TIMESTAMP ORDER BY (CASE WHEN kind = 'push_batch' THEN 0 ELSE 1 END), datetime(due_at) ASC LIMIT 1",
+        "SELECT id, user_id, kind, ref_id, attempt FROM outbox WHERE due_at <= ? ORDER BY (CASE WHEN kind = 'push_batch' THEN 0 ELSE 1 END), due_at ASC, id LIMIT 1",
     )
+    .bind(now.timestamp())
     .fetch_optional(pool)
     .await?;
     if let Some(row) = row {
@@ -457,11 +1526,49 @@ pub async fn next_due_outbox(pool: &Pool) -> Result<Option<OutboxItem>> {
     }
 }
 
+/// How long a claimed outbox task stays hidden from other workers. A worker
+/// that dies mid-task releases it when the lease runs out.
+pub const OUTBOX_LEASE_SECS: i64 = 300;
+
+/// Claim the task [`next_due_outbox`] would return. The same statement moves
+/// its `due_at` [`OUTBOX_LEASE_SECS`] ahead, so a concurrent worker skips it,
+/// and clears the previous attempt's `last_error`. Completing or
+/// dead-lettering the task deletes the row; a failure releases it through the
+/// backoff, which records the new error.
+#[instrument(skip_all)]
+pub async fn claim_next_due_outbox(pool: &Pool) -> Result<Option<OutboxItem>> {
+    let now = Utc::now().timestamp();
+    let row = sqlx::query(
+        "UPDATE outbox SET due_at = ?, last_error = NULL \
+         WHERE id = ( \
+             SELECT id FROM outbox WHERE due_at <= ? \
+             ORDER BY (CASE WHEN kind = 'push_batch' THEN 0 ELSE 1 END), due_at ASC, id \
+             LIMIT 1 \
+         ) \
+         RETURNING id, user_id, kind, ref_id, attempt",
+    )
+    .bind(now + OUTBOX_LEASE_SECS)
+    .bind(now)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|row| {
+        (
+            row.get("id"),
+            row.get("user_id"),
+            row.get("kind"),
+            row.get("ref_id"),
+            row.get("attempt"),
+        )
+    }))
+}
+
+/// Due tasks as `(id, kind, ref_id, last_error)`.
 #[allow(dead_code)]
-pub async fn list_due_outbox(pool: &Pool) -> Result<Vec<(i64, String, i64)>> {
+pub async fn list_due_outbox(pool: &Pool) -> Result<Vec<(i64, String, i64, Option<String>)>> {
     let rows = sqlx::query(
-        "SELECT id, kind, ref_id FROM outbox WHERE datetime(due_at) <= CURRENT_TIMESTAMP ORDER BY datetime(due_at) ASC",
+        "SELECT id, kind, ref_id, last_error FROM outbox WHERE due_at <= ? ORDER BY due_at ASC, id",
     )
+    .bind(Utc::now().timestamp())
     .fetch_all(pool)
     .await?;
 
@@ -471,7 +1578,8 @@ pub async fn list_due_outbox(pool: &Pool) -> Result<Vec<(i64, String, i64)>> {
             let id: i64 = row.get("id");
             let kind: String = row.get("kind");
             let ref_id: i64 = row.get("ref_id");
-            (id, kind, ref_id)
+            let last_error: Option<String> = row.get("last_error");
+            (id, kind, ref_id, last_error)
         })
         .collect();
     Ok(tasks)
@@ -486,31 +1594,101 @@ pub async fn delete_outbox(pool: &Pool, id: i64) -> Result<()> {
     Ok(())
 }
 
+/// Longest failure reason kept on an outbox row.
+pub const MAX_ERROR_CHARS: usize = 300;
+
 #[instrument(skip_all)]
 pub async fn backoff_outbox_with_cap(
     pool: &Pool,
     id: i64,
     attempt: i32,
     max_cap_secs: i64,
+    error: Option<&str>,
 ) -> Result<()> {
-    let secs = (5_i64) * (1_i64 << attempt.min(10));
-    let cap = if max_cap_secs <= 0 {
-        secs
-    } else {
-        max_cap_secs
-    };
-    let secs = secs.min(cap);
-    sqlx::query(
-        "UPDATE outbox SET attempt = ?, due_at = datetime('now', ? || ' seconds') WHERE id = ?",
+    backoff_outbox_with_jitter(pool, id, attempt, max_cap_secs, error, &RandomJitter).await
+}
+
+/// Exponential backoff (see [`crate::backoff::compute_backoff_secs`]) with
+/// jitter applied before capping.
+/// `error` replaces the row's `last_error` (truncated); `None` clears it.
+#[instrument(skip_all)]
+pub async fn backoff_outbox_with_jitter(
+    pool: &Pool,
+    id: i64,
+    attempt: i32,
+    max_cap_secs: i64,
+    error: Option<&str>,
+    jitter: &dyn Jitter,
+) -> Result<()> {
+    let secs = jittered_backoff_secs(attempt, max_cap_secs, jitter);
+    sqlx::query("UPDATE outbox SET attempt = ?, due_at = ?, last_error = ? WHERE id = ?")
+        .bind(attempt + 1)
+        .bind(Utc::now().timestamp() + secs)
+        .bind(error.map(truncate_error))
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Move an outbox task to `failed_outbox` with its final error, removing it from the queue.
+/// The recorded attempt count includes the failure that triggered the move.
+#[instrument(skip_all)]
+pub async fn move_to_dead_letter(pool: &Pool, outbox_id: i64, last_error: &str) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let moved = sqlx::query(
+        "INSERT INTO failed_outbox (outbox_id, user_id, kind, ref_id, attempt, last_error) \
+         SELECT id, user_id, kind, ref_id, attempt + 1, ? FROM outbox WHERE id = ?",
     )
-    .bind(attempt + 1)
-    .bind(secs)
-    .bind(id)
-    .execute(pool)
-    .await?;
+    .bind(last_error)
+    .bind(outbox_id)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if moved == 0 {
+        return Err(anyhow!("outbox task {} not found", outbox_id));
+    }
+    sqlx::query("DELETE FROM outbox WHERE id = ?")
+        .bind(outbox_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
     Ok(())
 }
 
+#[allow(dead_code)]
+#[instrument(skip_all)]
+pub async fn list_dead_letters(pool: &Pool) -> Result<Vec<DeadLetter>> {
+    let rows = sqlx::query(
+        "SELECT id, outbox_id, user_id, kind, ref_id, attempt, last_error, \
+                CAST(failed_at AS TEXT) AS failed_at \
+         FROM failed_outbox ORDER BY id",
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| DeadLetter {
+            id: row.get("id"),
+            outbox_id: row.get("outbox_id"),
+            user_id: row.get("user_id"),
+            kind: row.get("kind"),
+            ref_id: row.get("ref_id"),
+            attempt: row.get("attempt"),
+            last_error: row.get("last_error"),
+            failed_at: row.get("failed_at"),
+        })
+        .collect())
+}
+
+#[instrument(skip_all)]
+pub async fn count_dead_letters(pool: &Pool) -> Result<i64> {
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM failed_outbox")
+        .fetch_one(pool)
+        .await?;
+    Ok(count)
+}
+
 #[allow(dead_code)]
 #[instrument(skip_all)]
 pub async fn get_last_processed_outbox_id(pool: &Pool) -> Result<i64> {
@@ -520,11 +1698,13 @@ pub async fn get_last_processed_outbox_id(pool: &Pool) -> Result<i64> {
     Ok(id)
 }
 
-#[allow(dead_code)]
+/// Record that outbox task `outbox_id` completed. The cursor is monotonic: it
+/// holds the highest completed id, so finishing an older retried task never
+/// moves it back.
 #[instrument(skip_all)]
 pub async fn update_last_processed_outbox_id(pool: &Pool, outbox_id: i64) -> Result<()> {
     sqlx::query(
-        "UPDATE outbox_cursor SET last_sent_outbox_id = ?, updated_at = CURRENT_TIMESTAMP WHERE id = 1",
+        "UPDATE outbox_cursor SET last_sent_outbox_id = MAX(last_sent_outbox_id, ?), updated_at = CURRENT_TIMESTAMP WHERE id = 1",
     )
     .bind(outbox_id)
     .execute(pool)
@@ -532,6 +1712,50 @@ pub async fn update_last_processed_outbox_id(pool: &Pool, outbox_id: i64) -> Res
     Ok(())
 }
 
+/// Zero the outbox cursor, the only way to move it backwards.
+#[allow(dead_code)]
+#[instrument(skip_all)]
+pub async fn reset_outbox_cursor(pool: &Pool) -> Result<()> {
+    sqlx::query(
+        "UPDATE outbox_cursor SET last_sent_outbox_id = 0, updated_at = CURRENT_TIMESTAMP WHERE id = 1",
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Counts of users, batches by state, resources by kind, queued outbox
+/// tasks and dead letters.
+#[instrument(skip_all)]
+pub async fn stats(pool: &Pool) -> Result<Stats> {
+    let users: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+        .fetch_one(pool)
+        .await?;
+    let batches_by_state: Vec<(String, i64)> =
+        sqlx::query_as("SELECT state, COUNT(*) FROM batches GROUP BY state ORDER BY state")
+            .fetch_all(pool)
+            .await?;
+    let resources_by_kind: Vec<(String, i64)> =
+        sqlx::query_as("SELECT kind, COUNT(*) FROM resources GROUP BY kind ORDER BY kind")
+            .fetch_all(pool)
+            .await?;
+    Ok(Stats {
+        users,
+        batches_by_state,
+        resources_by_kind,
+        pending_outbox: count_remaining_outbox_tasks(pool).await?,
+        dead_letters: count_dead_letters(pool).await?,
+    })
+}
+
+/// Round-trip time of a trivial query, as a cheap liveness check.
+#[instrument(skip_all)]
+pub async fn ping(pool: &Pool) -> Result<Duration> {
+    let started = Instant::now();
+    sqlx::query("SELECT 1").execute(pool).await?;
+    Ok(started.elapsed())
+}
+
 #[allow(dead_code)]
 #[instrument(skip_all)]
 pub async fn count_remaining_outbox_tasks(pool: &Pool) -> Result<i64> {
@@ -541,6 +1765,81 @@ pub async fn count_remaining_outbox_tasks(pool: &Pool) -> Result<i64> {
     Ok(count)
 }
 
+#[instrument(skip_all)]
+pub async fn count_remaining_outbox_tasks_for_user(pool: &Pool, user_id: i64) -> Result<i64> {
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM outbox WHERE user_id = ?")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+    Ok(count)
+}
+
+#[instrument(skip_all)]
+pub async fn count_batch_resources(pool: &Pool, batch_id: i64) -> Result<i64> {
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM resources WHERE batch_id = ?")
+        .bind(batch_id)
+        .fetch_one(pool)
+        .await?;
+    Ok(count)
+}
+
+/// Move the resource at sequence `from` of `batch_id` to `to`, shifting the
+/// resources in between by one, in one transaction. Returns `false` (and
+/// changes nothing) unless both `from` and `to` are sequences in the batch.
+#[instrument(skip_all)]
+pub async fn reorder_resource(pool: &Pool, batch_id: i64, from: i64, to: i64) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+    let moved: Option<i64> =
+        sqlx::query_scalar("SELECT id FROM resources WHERE batch_id = ? AND sequence = ?")
+            .bind(batch_id)
+            .bind(from)
+            .fetch_optional(&mut *tx)
+            .await?;
+    let target_exists: bool = sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM resources WHERE batch_id = ? AND sequence = ?)",
+    )
+    .bind(batch_id)
+    .bind(to)
+    .fetch_one(&mut *tx)
+    .await?;
+    let Some(moved) = moved.filter(|_| target_exists) else {
+        return Ok(false);
+    };
+    // Close the gap left at `from` and open one at `to`
+    let shift = if from < to {
+        "UPDATE resources SET sequence = sequence - 1 \
+         WHERE batch_id = ? AND sequence > ? AND sequence <= ?"
+    } else {
+        "UPDATE resources SET sequence = sequence + 1 \
+         WHERE batch_id = ? AND sequence >= ? AND sequence < ?"
+    };
+    sqlx::query(shift)
+        .bind(batch_id)
+        .bind(from.min(to))
+        .bind(from.max(to))
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE resources SET sequence = ? WHERE id = ?")
+        .bind(to)
+        .bind(moved)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// The batch's lowest-sequence resource, if it has any.
+#[instrument(skip_all)]
+pub async fn first_batch_resource_id(pool: &Pool, batch_id: i64) -> Result<Option<i64>> {
+    let id = sqlx::query_scalar(
+        "SELECT id FROM resources WHERE batch_id = ? ORDER BY sequence, id LIMIT 1",
+    )
+    .bind(batch_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(id)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -555,55 +1854,1501 @@ mod tests {
         pool
     }
 
+    /// Origin of a message sent in `chat_id`, the key edits are matched on.
+    fn in_chat(chat_id: i64) -> ResourceOrigin<'static> {
+        ResourceOrigin {
+            chat_id: Some(chat_id),
+            ..Default::default()
+        }
+    }
+
     #[tokio::test]
-    async fn test_open_commit_rollback() {
+    async fn test_pool_handles_concurrent_writers() {
+        let td = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}/watchbot.db", td.path().display());
+        let pool = init_pool_with(&url, 3).await.unwrap();
+        assert_eq!(pool.options().get_max_connections(), 3);
+        run_migrations(&pool).await.unwrap();
+        let uid = get_or_create_user(&pool, 7, None, None).await.unwrap();
+
+        let tasks: Vec<_> = (0..6)
+            .map(|i| {
+                let pool = pool.clone();
+                tokio::spawn(async move {
+                    insert_resource(&pool, uid, None, ResourceKind::Text, &format!("m{}", i), i)
+                        .await
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+        assert_eq!(count_remaining_outbox_tasks(&pool).await.unwrap(), 6);
+        assert!(init_pool_with(&url, 0).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_waiting_title_reopens_batch() {
         let pool = setup_pool().await;
-        let uid = get_or_create_user(&pool, 123, Some("alice"), Some("Alice"))
+        let uid = get_or_create_user(&pool, 42, None, None).await.unwrap();
+        let bid = open_batch(&pool, uid).await.unwrap();
+        insert_resource(&pool, uid, Some(bid), ResourceKind::Text, "one", 1)
+            .await
+            .unwrap();
+        insert_resource(&pool, uid, Some(bid), ResourceKind::Text, "two", 2)
             .await
             .unwrap();
 
-        assert!(current_open_batch_id(&pool, uid).await.unwrap().is_none());
-        let bid = open_batch(&pool, uid).await.unwrap();
+        // Only a batch waiting for its title can be reopened
+        assert!(mark_current_batch_open(&pool, uid).await.is_err());
+
+        mark_current_batch_waiting_title(&pool, uid).await.unwrap();
+        mark_current_batch_open(&pool, uid).await.unwrap();
+        assert_eq!(
+            current_batch_state(&pool, uid).await.unwrap(),
+            Some(BatchState::Open)
+        );
         assert_eq!(current_open_batch_id(&pool, uid).await.unwrap(), Some(bid));
+        assert_eq!(count_batch_resources(&pool, bid).await.unwrap(), 2);
+        assert_eq!(count_remaining_outbox_tasks(&pool).await.unwrap(), 0);
+    }
 
-        // insert resource in batch
-        let _rid = insert_resource(&pool, uid, Some(bid), "text", "hello", 1)
+    #[tokio::test]
+    async fn test_reset_and_enqueue_orders_batches_first() {
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 5, None, None).await.unwrap();
+        let bid = open_batch(&pool, uid).await.unwrap();
+        let r1 = insert_resource(&pool, uid, Some(bid), ResourceKind::Text, "a", 1)
             .await
             .unwrap();
-        // standalone should enqueue outbox
-        let _rid2 = insert_resource(&pool, uid, None, "text", "single", 2)
+        let r2 = insert_resource(&pool, uid, Some(bid), ResourceKind::Text, "b", 2)
+            .await
+            .unwrap();
+        commit_batch(&pool, uid, Some("Trip")).await.unwrap();
+        let solo = insert_resource(&pool, uid, None, ResourceKind::Text, "solo", 3)
             .await
             .unwrap();
 
-        // commit should enqueue tasks
-        commit_batch(&pool, uid, Some("My Title")).await.unwrap();
-        assert!(current_open_batch_id(&pool, uid).await.unwrap().is_none());
+        // Sync everything
+        while let Some((oid, _, kind, ref_id, _)) = next_due_outbox(&pool).await.unwrap() {
+            let page = format!("page-{}-{}", kind, ref_id);
+            if kind == "push_batch" {
+                complete_outbox_batch(&pool, oid, ref_id, &page)
+                    .await
+                    .unwrap();
+            } else {
+                complete_outbox_resource(&pool, oid, ref_id, &page)
+                    .await
+                    .unwrap();
+            }
+        }
+        let synced = list_synced_resource_ids(&pool, uid, None, None)
+            .await
+            .unwrap();
+        assert_eq!(synced, vec![r1, r2, solo]);
+        assert_eq!(
+            list_synced_resource_ids(&pool, uid, None, Some(1))
+                .await
+                .unwrap(),
+            vec![r1]
+        );
 
-        // Expect at least 2 tasks: one batch, one resource (rid) plus standalone already queued
-        let cnt: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM outbox")
+        // Replaying one batch member re-pushes the batch and all its members
+        let (batches, resources) = reset_and_enqueue(&pool, &[r2, solo]).await.unwrap();
+        assert_eq!((batches, resources), (1, 3));
+        let rows: Vec<(String, i64)> =
+            sqlx::query_as("SELECT kind, ref_id FROM outbox ORDER BY id")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                ("push_batch".to_string(), bid),
+                ("push_resource".to_string(), r1),
+                ("push_resource".to_string(), r2),
+                ("push_resource".to_string(), solo),
+            ]
+        );
+        let batch = fetch_batch_for_outbox(&pool, bid).await.unwrap();
+        assert!(batch.notion_page_id.is_none());
+        for rid in [r1, r2, solo] {
+            let r = fetch_resource_for_outbox(&pool, rid).await.unwrap();
+            assert!(r.notion_page_id.is_none());
+        }
+
+        // A second replay does not double-enqueue
+        assert_eq!(reset_and_enqueue(&pool, &[r1, solo]).await.unwrap(), (0, 0));
+        assert_eq!(count_remaining_outbox_tasks(&pool).await.unwrap(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_ping_succeeds_on_live_pool() {
+        let pool = setup_pool().await;
+        assert!(ping(&pool).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_backoff_records_and_clears_last_error() {
+        struct NoJitter;
+        impl Jitter for NoJitter {
+            fn sample(&self) -> f64 {
+                0.0
+            }
+        }
+
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 8, None, None).await.unwrap();
+        insert_resource(&pool, uid, None, ResourceKind::Text, "x", 1)
+            .await
+            .unwrap();
+        let (oid, ..) = next_due_outbox(&pool).await.unwrap().unwrap();
+        let last_error = || async {
+            sqlx::query_scalar::<_, Option<String>>("SELECT last_error FROM outbox WHERE id = ?")
+                .bind(oid)
+                .fetch_one(&pool)
+                .await
+                .unwrap()
+        };
+
+        backoff_outbox_with_jitter(&pool, oid, 0, 60, Some("notion error 502"), &NoJitter)
+            .await
+            .unwrap();
+        assert_eq!(last_error().await.as_deref(), Some("notion error 502"));
+
+        let long = "e".repeat(MAX_ERROR_CHARS + 50);
+        backoff_outbox_with_jitter(&pool, oid, 1, 60, Some(&long), &NoJitter)
+            .await
+            .unwrap();
+        let stored = last_error().await.unwrap();
+        assert_eq!(stored.chars().count(), MAX_ERROR_CHARS + 1);
+        assert!(stored.ends_with('…'));
+
+        backoff_outbox_with_jitter(&pool, oid, 2, 60, None, &NoJitter)
+            .await
+            .unwrap();
+        assert_eq!(last_error().await, None);
+
+        // A retry starts without the previous attempt's error
+        backoff_outbox_with_jitter(&pool, oid, 3, 60, Some("notion error 503"), &NoJitter)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE outbox SET due_at = 0")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let (claimed, ..) = claim_next_due_outbox(&pool).await.unwrap().unwrap();
+        assert_eq!(claimed, oid);
+        assert_eq!(last_error().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_backoff_never_exceeds_cap() {
+        struct MaxJitter;
+        impl Jitter for MaxJitter {
+            fn sample(&self) -> f64 {
+                1.0
+            }
+        }
+
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 83, None, None).await.unwrap();
+        insert_resource(&pool, uid, None, ResourceKind::Text, "x", 1)
+            .await
+            .unwrap();
+        let (oid, ..) = next_due_outbox(&pool).await.unwrap().unwrap();
+
+        // 5s * 2^10 would be well over an hour without the cap
+        backoff_outbox_with_jitter(&pool, oid, 20, 30, None, &MaxJitter)
+            .await
+            .unwrap();
+        let due_at: i64 = sqlx::query_scalar("SELECT due_at FROM outbox WHERE id = ?")
+            .bind(oid)
             .fetch_one(&pool)
             .await
             .unwrap();
-        assert!(cnt >= 2);
+        let delay = due_at - Utc::now().timestamp();
+        assert!(delay <= 30, "scheduled {delay}s out");
+        assert!(delay >= 25, "cap should be reached, got {delay}s");
+    }
 
-        // Backoff and delete flow
-        if let Some((oid, _u, _k, _r, attempt)) = next_due_outbox(&pool).await.unwrap() {
-            backoff_outbox(&pool, oid, attempt).await.unwrap();
+    #[tokio::test]
+    async fn test_unknown_resource_kind_is_an_error() {
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 84, None, None).await.unwrap();
+        let rid = insert_resource(&pool, uid, None, ResourceKind::Photo, "/tmp/a.pdf", 1)
+            .await
+            .unwrap();
+        let res = fetch_resource_for_outbox(&pool, rid).await.unwrap();
+        assert_eq!(res.kind, ResourceKind::Photo);
+
+        // A kind written by a newer build must not be pushed as if it were media
+        sqlx::query("UPDATE resources SET kind = 'document' WHERE id = ?")
+            .bind(rid)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let err = fetch_resource_for_outbox(&pool, rid).await.unwrap_err();
+        assert!(err.to_string().contains("unknown kind document"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_next_due_outbox_compares_against_given_time() {
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 85, None, None).await.unwrap();
+        insert_resource(&pool, uid, None, ResourceKind::Text, "x", 1)
+            .await
+            .unwrap();
+        let due = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        sqlx::query("UPDATE outbox SET due_at = ?")
+            .bind(due.timestamp())
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let before = due - chrono::Duration::seconds(1);
+        assert!(next_due_outbox_at(&pool, before).await.unwrap().is_none());
+        assert!(next_due_outbox_at(&pool, due).await.unwrap().is_some());
+        let after = due + chrono::Duration::seconds(1);
+        assert!(next_due_outbox_at(&pool, after).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_get_resource_by_batch_and_sequence() {
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 84, None, None).await.unwrap();
+        let other = get_or_create_user(&pool, 85, None, None).await.unwrap();
+        let batch_id = open_batch_with_title(&pool, uid, "Trip").await.unwrap();
+        insert_resource(&pool, uid, Some(batch_id), ResourceKind::Text, "first", 1)
+            .await
+            .unwrap();
+        let second = insert_resource(&pool, uid, Some(batch_id), ResourceKind::Text, "second", 2)
+            .await
+            .unwrap();
+
+        let found = get_resource(&pool, uid, batch_id, 2)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.id, second);
+        assert_eq!(found.kind, ResourceKind::Text);
+        assert_eq!(found.text.as_deref(), Some("second"));
+        assert_eq!(found.notion_page_id, None);
+        assert!(!found.dead_lettered);
+
+        assert!(get_resource(&pool, uid, batch_id, 3)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(get_resource(&pool, other, batch_id, 2)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_detach_rollback_enqueues_each_resource_standalone() {
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 87, None, None).await.unwrap();
+        let batch_id = open_batch(&pool, uid).await.unwrap();
+        let mut ids = Vec::new();
+        for (msg, content) in [(1, "a"), (2, "b"), (3, "c")] {
+            let id = insert_resource(&pool, uid, Some(batch_id), ResourceKind::Text, content, msg)
+                .await
+                .unwrap();
+            ids.push(id);
+        }
+        assert_eq!(count_remaining_outbox_tasks(&pool).await.unwrap(), 0);
+
+        assert_eq!(rollback_batch_detaching(&pool, uid).await.unwrap(), 3);
+
+        assert_eq!(current_open_batch_id(&pool, uid).await.unwrap(), None);
+        let state: String = sqlx::query_scalar("SELECT state FROM batches WHERE id = ?")
+            .bind(batch_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(state, "ROLLED_BACK");
+        let attached: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM resources WHERE batch_id IS NOT NULL")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(attached, 0);
+        let pushed: Vec<i64> = sqlx::query_scalar(
+            "SELECT ref_id FROM outbox WHERE kind = 'push_resource' ORDER BY ref_id",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(pushed, ids);
+        assert_eq!(count_remaining_outbox_tasks(&pool).await.unwrap(), 3);
+        let sequences: Vec<i64> =
+            sqlx::query_scalar("SELECT sequence FROM resources WHERE user_id = ? ORDER BY id")
+                .bind(uid)
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(sequences, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_discard_rollback_leaves_resources_attached() {
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 88, None, None).await.unwrap();
+        let batch_id = open_batch(&pool, uid).await.unwrap();
+        insert_resource(&pool, uid, Some(batch_id), ResourceKind::Text, "a", 1)
+            .await
+            .unwrap();
+
+        rollback_batch(&pool, uid).await.unwrap();
+
+        let attached: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM resources WHERE batch_id = ?")
+            .bind(batch_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(attached, 1);
+        assert_eq!(count_remaining_outbox_tasks(&pool).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_reorder_resource_shifts_affected_range() {
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 86, None, None).await.unwrap();
+        let batch_id = open_batch(&pool, uid).await.unwrap();
+        let mut ids = Vec::new();
+        for (msg, content) in [(1, "a"), (2, "b"), (3, "c"), (4, "d")] {
+            let id = insert_resource(&pool, uid, Some(batch_id), ResourceKind::Text, content, msg)
+                .await
+                .unwrap();
+            ids.push(id);
         }
+        let order = || async {
+            sqlx::query_as::<_, (i64, i64)>(
+                "SELECT id, sequence FROM resources WHERE batch_id = ? ORDER BY sequence",
+            )
+            .bind(batch_id)
+            .fetch_all(&pool)
+            .await
+            .unwrap()
+        };
+
+        assert!(reorder_resource(&pool, batch_id, 3, 1).await.unwrap());
+        assert_eq!(
+            order().await,
+            vec![(ids[2], 1), (ids[0], 2), (ids[1], 3), (ids[3], 4)]
+        );
+
+        // And back down: #1 to #3 restores the original order
+        assert!(reorder_resource(&pool, batch_id, 1, 3).await.unwrap());
+        assert_eq!(
+            order().await,
+            vec![(ids[0], 1), (ids[1], 2), (ids[2], 3), (ids[3], 4)]
+        );
+
+        // Out of bounds leaves everything in place
+        assert!(!reorder_resource(&pool, batch_id, 2, 5).await.unwrap());
+        assert!(!reorder_resource(&pool, batch_id, 0, 1).await.unwrap());
+        assert_eq!(order().await[1], (ids[1], 2));
     }
 
-    pub async fn backoff_outbox(pool: &Pool, id: i64, attempt: i32) -> Result<()> {
-        // Exponential backoff: 5s * 2^attempt, capped at 3600s
-        let secs = (5_i64) * (1_i64 << attempt.min(10));
-        let secs = secs.min(3600);
-        sqlx::query(
-            "UPDATE outbox SET attempt = ?, due_at = datetime('now', ? || ' seconds') WHERE id = ?",
+    #[tokio::test]
+    async fn test_find_resource_by_message_and_reject_duplicate() {
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 82, None, None).await.unwrap();
+        let other = get_or_create_user(&pool, 83, None, None).await.unwrap();
+        let id = insert_resource_from(
+            &pool,
+            uid,
+            None,
+            ResourceKind::Text,
+            "hello",
+            41,
+            &in_chat(-100),
         )
-        .bind(attempt + 1)
-        .bind(secs)
-        .bind(id)
-        .execute(pool)
-        .await?;
-        Ok(())
+        .await
+        .unwrap()
+        .id;
+        insert_resource_from(
+            &pool,
+            other,
+            None,
+            ResourceKind::Text,
+            "same id",
+            41,
+            &in_chat(-100),
+        )
+        .await
+        .unwrap();
+
+        let found = find_resource_by_message(&pool, uid, -100, 41)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.id, id);
+        assert_eq!(found.text.as_deref(), Some("hello"));
+        assert!(find_resource_by_message(&pool, uid, -100, 42)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(find_resource_by_message(&pool, uid, -200, 41)
+            .await
+            .unwrap()
+            .is_none());
+
+        // The index rejects a second row; a re-delivery through the repo is a
+        // no-op, even for text, which has no file to match on
+        let raw = sqlx::query(
+            "INSERT INTO resources (user_id, kind, content, tg_message_id, chat_id) VALUES (?, 'text', 'hello', 41, -100)",
+        )
+        .bind(uid)
+        .execute(&pool)
+        .await;
+        assert!(raw.is_err());
+        let again = insert_resource_from(
+            &pool,
+            uid,
+            None,
+            ResourceKind::Text,
+            "hello",
+            41,
+            &in_chat(-100),
+        )
+        .await
+        .unwrap();
+        assert_eq!(again, InsertedResource { id, fresh: false });
+
+        // Message ids repeat across chats: the same id and text elsewhere is new
+        let elsewhere = insert_resource_from(
+            &pool,
+            uid,
+            None,
+            ResourceKind::Text,
+            "hello",
+            41,
+            &in_chat(-200),
+        )
+        .await
+        .unwrap();
+        assert!(elsewhere.fresh);
+        assert_eq!(
+            find_resource_by_message(&pool, uid, -200, 41)
+                .await
+                .unwrap()
+                .map(|r| r.id),
+            Some(elsewhere.id)
+        );
+        let (rows, tasks): (i64, i64) = sqlx::query_as(
+            "SELECT (SELECT COUNT(*) FROM resources WHERE user_id = ?), \
+             (SELECT COUNT(*) FROM outbox WHERE kind = 'push_resource')",
+        )
+        .bind(uid)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!((rows, tasks), (2, 3));
+    }
+
+    #[tokio::test]
+    async fn test_message_index_migration_keeps_existing_rows() {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        let all = sqlx::migrate!("./migrations");
+        let before_index = sqlx::migrate::Migrator {
+            migrations: all
+                .migrations
+                .iter()
+                .filter(|m| m.version < 13)
+                .cloned()
+                .collect::<Vec<_>>()
+                .into(),
+            ..sqlx::migrate!("./migrations")
+        };
+        before_index.run(&pool).await.unwrap();
+        let uid = get_or_create_user(&pool, 84, None, None).await.unwrap();
+        // Older builds saved a caption as its own row for the same message,
+        // and had no chat to tell message ids from different chats apart
+        let insert = |kind: &'static str, content: &'static str, page: Option<&'static str>| {
+            let pool = pool.clone();
+            async move {
+                sqlx::query_scalar::<_, i64>(
+                    "INSERT INTO resources (user_id, kind, content, tg_message_id, notion_page_id) \
+                     VALUES (?, ?, ?, 51, ?) RETURNING id",
+                )
+                .bind(uid)
+                .bind(kind)
+                .bind(content)
+                .bind(page)
+                .fetch_one(&pool)
+                .await
+                .unwrap()
+            }
+        };
+        let photo = insert("photo", "/p.jpg", Some("page-1")).await;
+        let caption = insert("text", "sunset", None).await;
+        sqlx::query(
+            "INSERT INTO outbox (user_id, kind, ref_id, due_at) VALUES (?, 'push_resource', ?, 0)",
+        )
+        .bind(uid)
+        .bind(caption)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        all.run(&pool).await.unwrap();
+
+        let rows: Vec<(i64, String, Option<String>)> =
+            sqlx::query_as("SELECT id, kind, notion_page_id FROM resources ORDER BY id")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                (photo, "photo".into(), Some("page-1".into())),
+                (caption, "text".into(), None),
+            ]
+        );
+        let queued: Vec<i64> = sqlx::query_scalar("SELECT ref_id FROM outbox")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(queued, vec![caption]);
+        // New rows continue after the old ids
+        let next = insert_resource(&pool, uid, None, ResourceKind::Text, "next", 52)
+            .await
+            .unwrap();
+        assert!(next > caption);
+    }
+
+    #[tokio::test]
+    async fn test_search_resources_matches_text_newest_first() {
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 86, None, None).await.unwrap();
+        let other = get_or_create_user(&pool, 87, None, None).await.unwrap();
+        let batch_id = open_batch_with_title(&pool, uid, "Groceries")
+            .await
+            .unwrap();
+        let seed = [
+            (Some(batch_id), ResourceKind::Text, "Buy oat milk", 1),
+            (Some(batch_id), ResourceKind::Text, "MILK again", 2),
+            (None, ResourceKind::Text, "bread", 3),
+            (None, ResourceKind::Photo, "/data/milk.jpg", 4),
+            (None, ResourceKind::Text, "100% milk_chocolate", 5),
+        ];
+        for (batch, kind, content, msg_id) in seed {
+            insert_resource(&pool, uid, batch, kind, content, msg_id)
+                .await
+                .unwrap();
+        }
+        insert_resource(&pool, other, None, ResourceKind::Text, "milk", 1)
+            .await
+            .unwrap();
+
+        let found = search_resources(&pool, uid, "milk", 10).await.unwrap();
+        let texts: Vec<&str> = found.iter().map(|m| m.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec!["100% milk_chocolate", "MILK again", "Buy oat milk"]
+        );
+        assert_eq!(found[1].batch_title.as_deref(), Some("Groceries"));
+        assert_eq!(found[1].sequence, 2);
+        assert_eq!(found[0].batch_title, None);
+
+        let limited = search_resources(&pool, uid, "milk", 2).await.unwrap();
+        assert_eq!(limited.len(), 2);
+
+        // Wildcards in the query are literal
+        let percent = search_resources(&pool, uid, "0%", 10).await.unwrap();
+        assert_eq!(percent.len(), 1);
+        let underscore = search_resources(&pool, uid, "k_c", 10).await.unwrap();
+        assert_eq!(underscore.len(), 1);
+        assert!(search_resources(&pool, uid, "o_t", 10)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_fts_search_matches_tokens_and_stems() {
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 88, None, None).await.unwrap();
+        let other = get_or_create_user(&pool, 89, None, None).await.unwrap();
+        // Indexed by the rebuild when FTS is first enabled
+        let old = insert_resource(&pool, uid, None, ResourceKind::Text, "Running shoes", 1)
+            .await
+            .unwrap();
+        if let Err(err) = enable_fts(&pool).await {
+            eprintln!("skipping: SQLite without FTS5 ({:#})", err);
+            return;
+        }
+        enable_fts(&pool).await.unwrap();
+        let batch_id = open_batch_with_title(&pool, uid, "Training").await.unwrap();
+        let new = insert_resource_from(
+            &pool,
+            uid,
+            Some(batch_id),
+            ResourceKind::Text,
+            "I run daily",
+            2,
+            &in_chat(-100),
+        )
+        .await
+        .unwrap()
+        .id;
+        insert_resource(&pool, uid, None, ResourceKind::Text, "rerun later", 3)
+            .await
+            .unwrap();
+        insert_resource(&pool, other, None, ResourceKind::Text, "run", 1)
+            .await
+            .unwrap();
+
+        let found = fts_search(&pool, uid, "runs", 10).await.unwrap();
+        let mut ids: Vec<i64> = found.iter().map(|m| m.id).collect();
+        ids.sort();
+        assert_eq!(
+            ids,
+            vec![old, new],
+            "stemmed tokens match, substrings do not"
+        );
+        let batched = found.iter().find(|m| m.id == new).unwrap();
+        assert_eq!(batched.batch_title.as_deref(), Some("Training"));
+
+        // Edits are re-indexed
+        update_resource_text(&pool, uid, -100, 2, "I swim daily")
+            .await
+            .unwrap();
+        let found = fts_search(&pool, uid, "swimming", 10).await.unwrap();
+        assert_eq!(found.iter().map(|m| m.id).collect::<Vec<_>>(), vec![new]);
+
+        // Operators are searched as plain words, so `OR` must appear too
+        assert!(fts_search(&pool, uid, "shoes OR nothing", 10)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(fts_search(&pool, uid, "  ", 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_batch_push_queues_notification_for_commit_chat() {
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 90, None, None).await.unwrap();
+
+        open_batch(&pool, uid).await.unwrap();
+        let quiet = commit_batch(&pool, uid, Some("Quiet")).await.unwrap();
+        open_batch(&pool, uid).await.unwrap();
+        let loud = commit_batch_notify(&pool, uid, Some("Loud"), Some(-100123))
+            .await
+            .unwrap();
+        while let Some((oid, _, kind, ref_id, _)) = claim_next_due_outbox(&pool).await.unwrap() {
+            assert_eq!(kind, "push_batch");
+            complete_outbox_batch(&pool, oid, ref_id, &format!("page-{}", ref_id))
+                .await
+                .unwrap();
+        }
+
+        let queued = list_notifications(&pool, 10).await.unwrap();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(
+            queued,
+            vec![Notification {
+                id: queued[0].id,
+                chat_id: -100123,
+                batch_id: loud,
+                batch_title: Some("Loud".into()),
+                page_id: format!("page-{}", loud),
+            }]
+        );
+        assert_ne!(queued[0].batch_id, quiet);
+
+        // A later re-push (e.g. replay) does not notify again
+        complete_outbox_batch(&pool, 0, loud, "page-again")
+            .await
+            .unwrap();
+        assert_eq!(list_notifications(&pool, 10).await.unwrap().len(), 1);
+
+        delete_notification(&pool, queued[0].id).await.unwrap();
+        assert!(list_notifications(&pool, 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_committed_batch_refuses_resources_and_backfills_missing() {
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 93, None, None).await.unwrap();
+        let bid = open_batch(&pool, uid).await.unwrap();
+        let queued = insert_resource(&pool, uid, Some(bid), ResourceKind::Text, "a", 1)
+            .await
+            .unwrap();
+        commit_batch(&pool, uid, None).await.unwrap();
+
+        let err = insert_resource(&pool, uid, Some(bid), ResourceKind::Text, "late", 2)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("cannot add resources"), "{err}");
+        assert_eq!(count_batch_resources(&pool, bid).await.unwrap(), 1);
+        assert!(
+            insert_resource(&pool, uid, Some(9999), ResourceKind::Text, "x", 3)
+                .await
+                .is_err()
+        );
+
+        // Rows attached behind the guard's back: one unsynced, one synced
+        let attach = |content: &'static str, seq: i64, page: Option<&'static str>| {
+            let pool = pool.clone();
+            async move {
+                sqlx::query_scalar::<_, i64>(
+                    "INSERT INTO resources (user_id, batch_id, kind, content, tg_message_id, sequence, notion_page_id) \
+                     VALUES (?, ?, 'text', ?, ?, ?, ?) RETURNING id",
+                )
+                .bind(uid)
+                .bind(bid)
+                .bind(content)
+                .bind(seq as i32 + 10)
+                .bind(seq)
+                .bind(page)
+                .fetch_one(&pool)
+                .await
+                .unwrap()
+            }
+        };
+        let missing = attach("missing", 2, None).await;
+        attach("synced", 3, Some("page-3")).await;
+
+        assert_eq!(enqueue_missing_resources(&pool, bid).await.unwrap(), 1);
+        let rows: Vec<(String, i64)> =
+            sqlx::query_as("SELECT kind, ref_id FROM outbox ORDER BY id")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                ("push_batch".to_string(), bid),
+                ("push_resource".to_string(), queued),
+                ("push_resource".to_string(), missing),
+            ]
+        );
+        assert_eq!(enqueue_missing_resources(&pool, bid).await.unwrap(), 0);
+
+        // An open batch is enqueued by its commit, not by the backfill
+        let open = open_batch(&pool, uid).await.unwrap();
+        insert_resource(&pool, uid, Some(open), ResourceKind::Text, "b", 4)
+            .await
+            .unwrap();
+        assert_eq!(enqueue_missing_resources(&pool, open).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_latest_synced_batch_skips_unsynced() {
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 94, None, None).await.unwrap();
+        assert_eq!(latest_synced_batch(&pool, uid).await.unwrap(), None);
+
+        open_batch(&pool, uid).await.unwrap();
+        let synced = commit_batch(&pool, uid, Some("Synced")).await.unwrap();
+        open_batch(&pool, uid).await.unwrap();
+        commit_batch(&pool, uid, Some("Pending")).await.unwrap();
+        // Another user's newer synced batch is not ours
+        let other = get_or_create_user(&pool, 95, None, None).await.unwrap();
+        open_batch(&pool, other).await.unwrap();
+        let theirs = commit_batch(&pool, other, Some("Theirs")).await.unwrap();
+        complete_outbox_batch(&pool, 0, synced, "page-synced")
+            .await
+            .unwrap();
+        complete_outbox_batch(&pool, 0, theirs, "page-theirs")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            latest_synced_batch(&pool, uid).await.unwrap(),
+            Some((Some("Synced".to_string()), "page-synced".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_global_sequence_continues_across_batches() {
+        let pool = setup_pool().await;
+        // Two batches per user, two items each; `global` decides the numbering
+        async fn sequences(pool: &Pool, tg_user_id: i64, global: bool) -> Vec<i64> {
+            let uid = get_or_create_user(pool, tg_user_id, None, None)
+                .await
+                .unwrap();
+            let origin = ResourceOrigin {
+                global_sequence: global,
+                ..Default::default()
+            };
+            for (msg, content) in [(1, "a"), (2, "b"), (3, "c"), (4, "d")] {
+                if msg % 2 == 1 {
+                    open_batch(pool, uid).await.unwrap();
+                }
+                let bid = current_open_batch_id(pool, uid).await.unwrap();
+                insert_resource_from(pool, uid, bid, ResourceKind::Text, content, msg, &origin)
+                    .await
+                    .unwrap();
+                if msg % 2 == 0 {
+                    commit_batch(pool, uid, None).await.unwrap();
+                }
+            }
+            // Standalone items neither advance nor use the global counter
+            insert_resource_from(pool, uid, None, ResourceKind::Text, "solo", 5, &origin)
+                .await
+                .unwrap();
+            sqlx::query_scalar("SELECT sequence FROM resources WHERE user_id = ? ORDER BY id")
+                .bind(uid)
+                .fetch_all(pool)
+                .await
+                .unwrap()
+        }
+
+        assert_eq!(sequences(&pool, 96, false).await, vec![1, 2, 1, 2, 1]);
+        assert_eq!(sequences(&pool, 97, true).await, vec![1, 2, 3, 4, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_insert_resource_ignores_redelivered_unique_id() {
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 9, None, None).await.unwrap();
+        let origin = ResourceOrigin {
+            source_url: None,
+            unique_id: Some("AgADBAADb6cxG"),
+            chat_id: Some(-100),
+            ..Default::default()
+        };
+
+        let first =
+            insert_resource_from(&pool, uid, None, ResourceKind::Photo, "p.jpg", 7, &origin)
+                .await
+                .unwrap();
+        assert!(first.fresh);
+        // Re-delivery may carry a different local path; unique_id still matches.
+        let second =
+            insert_resource_from(&pool, uid, None, ResourceKind::Photo, "p2.jpg", 7, &origin)
+                .await
+                .unwrap();
+        assert_eq!(
+            second,
+            InsertedResource {
+                id: first.id,
+                fresh: false
+            }
+        );
+
+        let resources: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM resources")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        let tasks: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM outbox")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(resources, 1);
+        assert_eq!(tasks, 1);
+
+        // The same file forwarded in a later message is saved again
+        let later =
+            insert_resource_from(&pool, uid, None, ResourceKind::Photo, "p.jpg", 8, &origin)
+                .await
+                .unwrap();
+        assert!(later.fresh);
+        assert_ne!(later.id, first.id);
+    }
+
+    #[tokio::test]
+    async fn test_sequence_not_reused_after_delete() {
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 10, None, None).await.unwrap();
+        let bid = open_batch(&pool, uid).await.unwrap();
+        assert_eq!(next_resource_sequence(&pool, Some(bid)).await.unwrap(), 1);
+        assert_eq!(next_resource_sequence(&pool, None).await.unwrap(), 1);
+
+        insert_resource(&pool, uid, Some(bid), ResourceKind::Text, "a", 1)
+            .await
+            .unwrap();
+        insert_resource(&pool, uid, Some(bid), ResourceKind::Text, "b", 2)
+            .await
+            .unwrap();
+        let last = insert_resource(&pool, uid, Some(bid), ResourceKind::Text, "c", 3)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM resources WHERE id = ?")
+            .bind(last)
+            .execute(&pool)
+            .await
+            .unwrap();
+        // Delete a middle row too: COUNT(*)+1 would now collide with "b"
+        sqlx::query("DELETE FROM resources WHERE batch_id = ? AND sequence = 1")
+            .bind(bid)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert_eq!(next_resource_sequence(&pool, Some(bid)).await.unwrap(), 3);
+        insert_resource(&pool, uid, Some(bid), ResourceKind::Text, "d", 4)
+            .await
+            .unwrap();
+        let sequences: Vec<i64> = sqlx::query_scalar(
+            "SELECT sequence FROM resources WHERE batch_id = ? ORDER BY sequence",
+        )
+        .bind(bid)
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(sequences, vec![2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_open_with_title_commits_without_prompt() {
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 11, None, None).await.unwrap();
+        let bid = open_batch_with_title(&pool, uid, "Trip").await.unwrap();
+        assert_eq!(
+            current_batch_title(&pool, uid).await.unwrap().as_deref(),
+            Some("Trip")
+        );
+        assert_eq!(
+            current_batch_state(&pool, uid).await.unwrap(),
+            Some(BatchState::Open)
+        );
+
+        // Plain commit keeps the pre-set title
+        assert_eq!(commit_batch(&pool, uid, None).await.unwrap(), bid);
+        let batch = fetch_batch_for_outbox(&pool, bid).await.unwrap();
+        assert_eq!(batch.state, BatchState::Committed);
+        assert_eq!(batch.title.as_deref(), Some("Trip"));
+        assert_eq!(current_batch_title(&pool, uid).await.unwrap(), None);
+
+        // Untitled batches still report no title
+        open_batch(&pool, uid).await.unwrap();
+        assert_eq!(current_batch_title(&pool, uid).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_list_batches_counts_and_orders_newest_first() {
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 12, None, None).await.unwrap();
+        let other = get_or_create_user(&pool, 13, None, None).await.unwrap();
+
+        let committed = open_batch(&pool, uid).await.unwrap();
+        insert_resource(&pool, uid, Some(committed), ResourceKind::Text, "a", 1)
+            .await
+            .unwrap();
+        insert_resource(&pool, uid, Some(committed), ResourceKind::Text, "b", 2)
+            .await
+            .unwrap();
+        commit_batch(&pool, uid, Some("First")).await.unwrap();
+
+        let rolled_back = open_batch(&pool, uid).await.unwrap();
+        insert_resource(&pool, uid, Some(rolled_back), ResourceKind::Text, "c", 3)
+            .await
+            .unwrap();
+        rollback_batch(&pool, uid).await.unwrap();
+
+        let open = open_batch(&pool, uid).await.unwrap();
+        open_batch(&pool, other).await.unwrap();
+
+        let all = list_batches(&pool, uid, 10, 0).await.unwrap();
+        let ids: Vec<i64> = all.iter().map(|b| b.id).collect();
+        assert_eq!(ids, vec![open, rolled_back, committed]);
+        let states: Vec<BatchState> = all.iter().map(|b| b.state).collect();
+        assert_eq!(
+            states,
+            vec![
+                BatchState::Open,
+                BatchState::RolledBack,
+                BatchState::Committed
+            ]
+        );
+        let counts: Vec<i64> = all.iter().map(|b| b.resource_count).collect();
+        assert_eq!(counts, vec![0, 1, 2]);
+        assert_eq!(all[2].title.as_deref(), Some("First"));
+
+        let page = list_batches(&pool, uid, 1, 1).await.unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].id, rolled_back);
+
+        let committed_only = list_batches_in_state(&pool, uid, Some(BatchState::Committed), 10, 0)
+            .await
+            .unwrap();
+        assert_eq!(committed_only.len(), 1);
+        assert_eq!(committed_only[0].id, committed);
+    }
+
+    #[tokio::test]
+    async fn test_edit_of_synced_text_enqueues_update() {
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 16, None, None).await.unwrap();
+        let rid = insert_resource_from(
+            &pool,
+            uid,
+            None,
+            ResourceKind::Text,
+            "typo",
+            3,
+            &in_chat(-100),
+        )
+        .await
+        .unwrap()
+        .id;
+        let (push_id, ..) = next_due_outbox(&pool).await.unwrap().unwrap();
+        complete_outbox_resource(&pool, push_id, rid, "page-1")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            update_resource_text(&pool, uid, -100, 3, "fixed")
+                .await
+                .unwrap(),
+            Some(rid)
+        );
+        // A second edit before the worker runs reuses the pending task
+        update_resource_text(&pool, uid, -100, 3, "fixed again")
+            .await
+            .unwrap();
+
+        let tasks: Vec<(String, i64)> = sqlx::query_as("SELECT kind, ref_id FROM outbox")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(tasks, vec![("update_resource".to_string(), rid)]);
+        let resource = fetch_resource_for_outbox(&pool, rid).await.unwrap();
+        assert_eq!(resource.text.as_deref(), Some("fixed again"));
+        assert_eq!(resource.notion_page_id.as_deref(), Some("page-1"));
+
+        assert_eq!(
+            update_resource_text(&pool, uid, -100, 99, "nothing")
+                .await
+                .unwrap(),
+            None
+        );
+        // The same message id in another chat is a different message
+        assert_eq!(
+            update_resource_text(&pool, uid, -200, 3, "nothing")
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_edit_of_unsynced_text_only_updates_row() {
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 17, None, None).await.unwrap();
+        let rid = insert_resource_from(
+            &pool,
+            uid,
+            None,
+            ResourceKind::Text,
+            "draft",
+            4,
+            &in_chat(-100),
+        )
+        .await
+        .unwrap()
+        .id;
+
+        update_resource_text(&pool, uid, -100, 4, "final")
+            .await
+            .unwrap();
+
+        let kinds: Vec<String> = sqlx::query_scalar("SELECT kind FROM outbox")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(kinds, vec!["push_resource".to_string()]);
+        let resource = fetch_resource_for_outbox(&pool, rid).await.unwrap();
+        assert_eq!(resource.text.as_deref(), Some("final"));
+    }
+
+    #[tokio::test]
+    async fn test_thumb_path_is_stored_and_fetched() {
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 18, None, None).await.unwrap();
+        let origin = ResourceOrigin {
+            thumb_path: Some("/data/media/thumbs/3_abc.jpg"),
+            ..Default::default()
+        };
+        let video =
+            insert_resource_from(&pool, uid, None, ResourceKind::Video, "/v.mp4", 3, &origin)
+                .await
+                .unwrap();
+        let photo = insert_resource(&pool, uid, None, ResourceKind::Photo, "/p.jpg", 4)
+            .await
+            .unwrap();
+
+        let fetched = fetch_resource_for_outbox(&pool, video.id).await.unwrap();
+        assert_eq!(
+            fetched.thumb_path.as_deref(),
+            Some("/data/media/thumbs/3_abc.jpg")
+        );
+        let fetched = fetch_resource_for_outbox(&pool, photo).await.unwrap();
+        assert_eq!(fetched.thumb_path, None);
+    }
+
+    #[tokio::test]
+    async fn test_chat_id_is_stored_and_fetched() {
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 21, None, None).await.unwrap();
+        let batch_id = open_batch_routed(&pool, uid, None, Some(-100777), None)
+            .await
+            .unwrap();
+        let origin = ResourceOrigin {
+            chat_id: Some(-100888),
+            ..Default::default()
+        };
+        let batched = insert_resource_from(
+            &pool,
+            uid,
+            Some(batch_id),
+            ResourceKind::Text,
+            "a",
+            1,
+            &origin,
+        )
+        .await
+        .unwrap();
+        let standalone = insert_resource(&pool, uid, None, ResourceKind::Text, "b", 2)
+            .await
+            .unwrap();
+
+        let fetched = fetch_resource_for_outbox(&pool, batched.id).await.unwrap();
+        assert_eq!(fetched.chat_id, Some(-100888));
+        let fetched = fetch_resource_for_outbox(&pool, standalone).await.unwrap();
+        assert_eq!(fetched.chat_id, None);
+        let batch = fetch_batch_for_outbox(&pool, batch_id).await.unwrap();
+        assert_eq!(batch.chat_id, Some(-100777));
+    }
+
+    #[tokio::test]
+    async fn test_prune_removes_only_old_rolled_back_batches() {
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 22, None, None).await.unwrap();
+        let add_video = |batch_id: i64, path: &'static str, message_id: i32| {
+            let pool = pool.clone();
+            async move {
+                let origin = ResourceOrigin {
+                    thumb_path: Some("/m/thumb.jpg"),
+                    ..Default::default()
+                };
+                insert_resource_from(
+                    &pool,
+                    uid,
+                    Some(batch_id),
+                    ResourceKind::Video,
+                    path,
+                    message_id,
+                    &origin,
+                )
+                .await
+                .unwrap()
+                .id
+            }
+        };
+
+        let old = open_batch(&pool, uid).await.unwrap();
+        let old_video = add_video(old, "/m/old.mp4", 1).await;
+        insert_resource(&pool, uid, Some(old), ResourceKind::Text, "note", 2)
+            .await
+            .unwrap();
+        rollback_batch(&pool, uid).await.unwrap();
+        sqlx::query("UPDATE batches SET rolled_back_at = '2020-01-01 00:00:00' WHERE id = ?")
+            .bind(old)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO outbox (user_id, kind, ref_id, due_at) VALUES (?, ?, ?, ?)")
+            .bind(uid)
+            .bind(OutboxKind::UpdateResource.as_str())
+            .bind(old_video)
+            .bind(Utc::now())
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let recent = open_batch(&pool, uid).await.unwrap();
+        add_video(recent, "/m/new.mp4", 3).await;
+        rollback_batch(&pool, uid).await.unwrap();
+
+        let cutoff = Utc::now() - chrono::Duration::days(30);
+        let pruned = prune_rolled_back_batches(&pool, cutoff).await.unwrap();
+        assert_eq!(
+            pruned,
+            PrunedBatches {
+                batches: 1,
+                resources: 2,
+                outbox_tasks: 1,
+                // The thumbnail path is still used by the recent batch
+                media_paths: vec!["/m/old.mp4".to_string()],
+            }
+        );
+
+        let batches: Vec<i64> = sqlx::query_scalar("SELECT id FROM batches ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(batches, vec![recent]);
+        let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM resources")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(remaining, 1);
+        assert_eq!(count_remaining_outbox_tasks(&pool).await.unwrap(), 0);
+        assert_eq!(
+            prune_rolled_back_batches(&pool, cutoff).await.unwrap(),
+            PrunedBatches::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_open_commit_rollback() {
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 123, Some("alice"), Some("Alice"))
+            .await
+            .unwrap();
+
+        assert!(current_open_batch_id(&pool, uid).await.unwrap().is_none());
+        let bid = open_batch(&pool, uid).await.unwrap();
+        assert_eq!(current_open_batch_id(&pool, uid).await.unwrap(), Some(bid));
+
+        // insert resource in batch
+        let _rid = insert_resource(&pool, uid, Some(bid), ResourceKind::Text, "hello", 1)
+            .await
+            .unwrap();
+        // standalone should enqueue outbox
+        let _rid2 = insert_resource(&pool, uid, None, ResourceKind::Text, "single", 2)
+            .await
+            .unwrap();
+
+        // commit should enqueue tasks
+        commit_batch(&pool, uid, Some("My Title")).await.unwrap();
+        assert!(current_open_batch_id(&pool, uid).await.unwrap().is_none());
+
+        // Expect at least 2 tasks: one batch, one resource (rid) plus standalone already queued
+        let cnt: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM outbox")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert!(cnt >= 2);
+
+        // Backoff and delete flow
+        if let Some((oid, _u, _k, _r, attempt)) = next_due_outbox(&pool).await.unwrap() {
+            backoff_outbox_with_cap(&pool, oid, attempt, 60, None)
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_count_outbox_tasks_for_user() {
+        let pool = setup_pool().await;
+        let alice = get_or_create_user(&pool, 1, Some("alice"), None)
+            .await
+            .unwrap();
+        let bob = get_or_create_user(&pool, 2, Some("bob"), None)
+            .await
+            .unwrap();
+
+        insert_resource(&pool, alice, None, ResourceKind::Text, "a1", 1)
+            .await
+            .unwrap();
+        insert_resource(&pool, alice, None, ResourceKind::Text, "a2", 2)
+            .await
+            .unwrap();
+        insert_resource(&pool, bob, None, ResourceKind::Text, "b1", 3)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            count_remaining_outbox_tasks_for_user(&pool, alice)
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            count_remaining_outbox_tasks_for_user(&pool, bob)
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(count_remaining_outbox_tasks(&pool).await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_complete_outbox_sets_page_and_deletes_row() {
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 7, None, None).await.unwrap();
+        let bid = open_batch(&pool, uid).await.unwrap();
+        let rid = insert_resource(&pool, uid, Some(bid), ResourceKind::Text, "x", 1)
+            .await
+            .unwrap();
+        commit_batch(&pool, uid, Some("T")).await.unwrap();
+
+        let batch_task: i64 =
+            sqlx::query_scalar("SELECT id FROM outbox WHERE kind = 'push_batch' AND ref_id = ?")
+                .bind(bid)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        complete_outbox_batch(&pool, batch_task, bid, "page-b")
+            .await
+            .unwrap();
+
+        let res_task: i64 =
+            sqlx::query_scalar("SELECT id FROM outbox WHERE kind = 'push_resource' AND ref_id = ?")
+                .bind(rid)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        complete_outbox_resource(&pool, res_task, rid, "page-r")
+            .await
+            .unwrap();
+
+        let batch_page: Option<String> =
+            sqlx::query_scalar("SELECT notion_page_id FROM batches WHERE id = ?")
+                .bind(bid)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        let res_page: Option<String> =
+            sqlx::query_scalar("SELECT notion_page_id FROM resources WHERE id = ?")
+                .bind(rid)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(batch_page.as_deref(), Some("page-b"));
+        assert_eq!(res_page.as_deref(), Some("page-r"));
+        assert_eq!(count_remaining_outbox_tasks(&pool).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_reopen_batch_appends_and_recommits() {
+        let pool = setup_pool().await;
+        let uid = get_or_create_user(&pool, 101, None, None).await.unwrap();
+        let other = get_or_create_user(&pool, 102, None, None).await.unwrap();
+        let bid = open_batch(&pool, uid).await.unwrap();
+        let first = insert_resource(&pool, uid, Some(bid), ResourceKind::Text, "a", 1)
+            .await
+            .unwrap();
+        let queued = insert_resource(&pool, uid, Some(bid), ResourceKind::Text, "q", 2)
+            .await
+            .unwrap();
+        commit_batch(&pool, uid, Some("Trip")).await.unwrap();
+        // The worker already pushed the main page and the first resource
+        sqlx::query("UPDATE batches SET notion_page_id = 'main' WHERE id = ?")
+            .bind(bid)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE resources SET notion_page_id = 'res-a' WHERE id = ?")
+            .bind(first)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM outbox WHERE kind = 'push_batch' OR ref_id = ?")
+            .bind(first)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let outbox = || async {
+            sqlx::query_as::<_, (String, i64)>("SELECT kind, ref_id FROM outbox ORDER BY id")
+                .fetch_all(&pool)
+                .await
+                .unwrap()
+        };
+
+        assert!(reopen_batch(&pool, other, bid).await.is_err());
+        assert!(reopen_batch(&pool, uid, 9999).await.is_err());
+        let open = open_batch(&pool, uid).await.unwrap();
+        let err = reopen_batch(&pool, uid, bid).await.unwrap_err();
+        assert!(err.to_string().contains("already open"), "{err}");
+        rollback_batch(&pool, uid).await.unwrap();
+        assert!(reopen_batch(&pool, uid, open).await.is_err());
+
+        reopen_batch(&pool, uid, bid).await.unwrap();
+        assert_eq!(current_open_batch_id(&pool, uid).await.unwrap(), Some(bid));
+        assert_eq!(
+            current_batch_state(&pool, uid).await.unwrap(),
+            Some(BatchState::Open)
+        );
+        // The queued push would fail while the batch is open
+        assert!(outbox().await.is_empty());
+        let late = insert_resource(&pool, uid, Some(bid), ResourceKind::Text, "b", 3)
+            .await
+            .unwrap();
+        let seq: i64 = sqlx::query_scalar("SELECT sequence FROM resources WHERE id = ?")
+            .bind(late)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(seq, 3);
+
+        assert_eq!(commit_batch(&pool, uid, None).await.unwrap(), bid);
+        assert_eq!(current_open_batch_id(&pool, uid).await.unwrap(), None);
+        let title: Option<String> = sqlx::query_scalar("SELECT title FROM batches WHERE id = ?")
+            .bind(bid)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(title.as_deref(), Some("Trip"));
+        // Only unsynced resources are queued; the main page keeps its title
+        assert_eq!(
+            outbox().await,
+            vec![
+                ("push_resource".to_string(), queued),
+                ("push_resource".to_string(), late),
+            ]
+        );
+
+        // A new title queues one push_batch to rename the page
+        reopen_batch(&pool, uid, bid).await.unwrap();
+        commit_batch(&pool, uid, Some("Trip, day 2")).await.unwrap();
+        assert_eq!(
+            outbox().await,
+            vec![
+                ("push_batch".to_string(), bid),
+                ("push_resource".to_string(), queued),
+                ("push_resource".to_string(), late),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stats_counts_users_batches_resources_and_outbox() {
+        let pool = setup_pool().await;
+        assert_eq!(stats(&pool).await.unwrap(), Stats::default());
+
+        let a = get_or_create_user(&pool, 104, None, None).await.unwrap();
+        let b = get_or_create_user(&pool, 105, None, None).await.unwrap();
+        let bid = open_batch(&pool, a).await.unwrap();
+        insert_resource(&pool, a, Some(bid), ResourceKind::Text, "t1", 1)
+            .await
+            .unwrap();
+        insert_resource(&pool, a, Some(bid), ResourceKind::Text, "t2", 2)
+            .await
+            .unwrap();
+        insert_resource(&pool, a, Some(bid), ResourceKind::Photo, "p.jpg", 3)
+            .await
+            .unwrap();
+        commit_batch(&pool, a, None).await.unwrap();
+        open_batch(&pool, b).await.unwrap();
+        rollback_batch(&pool, b).await.unwrap();
+        insert_resource(&pool, b, None, ResourceKind::Text, "loose", 4)
+            .await
+            .unwrap();
+        let oid: i64 = sqlx::query_scalar("SELECT MIN(id) FROM outbox")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        move_to_dead_letter(&pool, oid, "boom").await.unwrap();
+
+        assert_eq!(
+            stats(&pool).await.unwrap(),
+            Stats {
+                users: 2,
+                batches_by_state: vec![("COMMITTED".into(), 1), ("ROLLED_BACK".into(), 1)],
+                resources_by_kind: vec![("photo".into(), 1), ("text".into(), 3)],
+                pending_outbox: 4,
+                dead_letters: 1,
+            }
+        );
     }
 }
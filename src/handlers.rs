@@ -1,18 +1,287 @@
+use crate::config::{AckMode, RollbackMode, ThumbnailFormat};
 use crate::db;
+use crate::model::{BatchState, MediaDimensions, ResourceKind};
+use crate::notion::{page_url, NotionIds};
+use crate::outbox::OutboxSignal;
+use crate::thumbnail::FfmpegStatus;
+use crate::validate;
 use anyhow::Result;
+use async_trait::async_trait;
 use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use teloxide::net::Download;
 use teloxide::prelude::*;
-use teloxide::types::{MediaKind, MessageKind};
+use teloxide::types::{
+    CallbackQuery, Contact, FileMeta, InlineKeyboardButton, InlineKeyboardMarkup, MediaKind,
+    MessageId, MessageKind, Poll,
+};
+use teloxide::{ApiError, DownloadError, RequestError};
 use tracing::{info, instrument, warn};
 
+/// Telegram no longer serves a file, typically because its `file_id` is used
+/// long after the message arrived (e.g. a replay). Only a fresh upload helps.
+#[derive(Debug, thiserror::Error)]
+#[error("telegram file {file_id} has expired")]
+pub struct MediaExpired {
+    pub file_id: String,
+}
+
+/// Whether `getFile` failed because Telegram no longer knows the file id.
+fn is_expired_file(err: &RequestError) -> bool {
+    match err {
+        RequestError::Api(
+            ApiError::WrongFileId | ApiError::FileIdInvalid | ApiError::WrongFileIdOrUrl,
+        ) => true,
+        RequestError::Api(ApiError::Unknown(message)) => {
+            let message = message.to_ascii_lowercase();
+            message.contains("file not found")
+                || (message.contains("file") && message.contains("expired"))
+        }
+        _ => false,
+    }
+}
+
+/// Whether a download failed because the file path from `getFile` is gone.
+fn is_expired_download(err: &DownloadError) -> bool {
+    matches!(err, DownloadError::Network(err) if err.status() == Some(reqwest::StatusCode::NOT_FOUND))
+}
+
+/// The Telegram side of handling a message: replies and file downloads.
+/// Implemented for [`Bot`]; tests record the replies instead.
+#[async_trait]
+pub trait Responder: Send + Sync {
+    /// Send `text` to `chat_id`.
+    async fn send_text<T: Into<String> + Send>(&self, chat_id: ChatId, text: T) -> Result<()>;
+
+    /// Send `text` with one inline button per `(label, callback data)` pair.
+    async fn send_choice(
+        &self,
+        chat_id: ChatId,
+        text: &str,
+        buttons: &[(&str, String)],
+    ) -> Result<()>;
+
+    /// Replace the text of an earlier message, dropping its inline buttons.
+    async fn edit_text(&self, chat_id: ChatId, message_id: MessageId, text: &str) -> Result<()>;
+
+    /// Acknowledge a button press so the client stops showing progress.
+    async fn answer_callback(&self, callback_id: &str) -> Result<()>;
+
+    /// Download Telegram file `file_id` into `dir` as `file_name`, or as
+    /// `{msg_id}_{unique id}.{ext}` when `None`, and return its path. Fails
+    /// with [`MediaExpired`] when Telegram no longer has the file.
+    async fn fetch_file(
+        &self,
+        file_id: &str,
+        dir: &str,
+        msg_id: i32,
+        file_name: Option<&str>,
+    ) -> Result<String>;
+}
+
+#[async_trait]
+impl Responder for Bot {
+    async fn send_text<T: Into<String> + Send>(&self, chat_id: ChatId, text: T) -> Result<()> {
+        self.send_message(chat_id, text).await?;
+        Ok(())
+    }
+
+    async fn send_choice(
+        &self,
+        chat_id: ChatId,
+        text: &str,
+        buttons: &[(&str, String)],
+    ) -> Result<()> {
+        let row = buttons
+            .iter()
+            .map(|(label, data)| InlineKeyboardButton::callback(*label, data.clone()))
+            .collect::<Vec<_>>();
+        self.send_message(chat_id, text)
+            .reply_markup(InlineKeyboardMarkup::new(vec![row]))
+            .await?;
+        Ok(())
+    }
+
+    async fn edit_text(&self, chat_id: ChatId, message_id: MessageId, text: &str) -> Result<()> {
+        self.edit_message_text(chat_id, message_id, text).await?;
+        Ok(())
+    }
+
+    async fn answer_callback(&self, callback_id: &str) -> Result<()> {
+        self.answer_callback_query(callback_id).await?;
+        Ok(())
+    }
+
+    async fn fetch_file(
+        &self,
+        file_id: &str,
+        dir: &str,
+        msg_id: i32,
+        file_name: Option<&str>,
+    ) -> Result<String> {
+        // Resolve file path from Telegram API, then download to local storage
+        let file = self.get_file(file_id).await.map_err(|err| {
+            if is_expired_file(&err) {
+                anyhow::Error::new(MediaExpired {
+                    file_id: file_id.to_string(),
+                })
+            } else {
+                err.into()
+            }
+        })?;
+        tokio::fs::create_dir_all(dir).await.ok();
+        let path = match file_name {
+            Some(name) => format!("{}{}", dir, name),
+            None => {
+                // Try to preserve the original file extension from Telegram's file path
+                let ext = std::path::Path::new(&file.path)
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .unwrap_or("bin");
+                format!("{}{}_{}.{}", dir, msg_id, file.meta.unique_id, ext)
+            }
+        };
+        // Write under a temporary name so a finished path is always a complete
+        // file, which later deliveries reuse instead of downloading again.
+        let part = format!("{}.part", path);
+        let mut dst = tokio::fs::File::create(&part).await?;
+        if let Err(err) = self.download_file(&file.path, &mut dst).await {
+            drop(dst);
+            let _ = tokio::fs::remove_file(&part).await;
+            if is_expired_download(&err) {
+                return Err(MediaExpired {
+                    file_id: file_id.to_string(),
+                }
+                .into());
+            }
+            return Err(err.into());
+        }
+        tokio::fs::rename(&part, &path).await?;
+        Ok(path)
+    }
+}
+
+/// Settings the handlers need beyond the database pool.
+#[derive(Debug, Clone, Default)]
+pub struct HandlerOptions {
+    pub data_dir: String,
+    /// Resolved Notion mapping, used by `/validate` to check property resolution.
+    pub notion_ids: Option<NotionIds>,
+    /// Videos are rejected up front when thumbnails cannot be generated.
+    pub ffmpeg: FfmpegStatus,
+    /// Format of generated video thumbnails (`app.thumbnail_format`).
+    pub thumbnail_format: ThumbnailFormat,
+    /// Media larger than this many bytes is rejected before downloading.
+    pub max_media_bytes: Option<u64>,
+    /// Items an open batch may hold before further ones are refused.
+    pub max_batch_items: Option<usize>,
+    /// Batch items continue the user's order numbers across batches.
+    pub global_sequence: bool,
+    /// `/find` searches the FTS5 index instead of a plain substring match.
+    pub full_text_search: bool,
+    /// Committed batches queue a link to their Notion page for the committing chat.
+    pub notify_on_sync: bool,
+    /// Which saved-item replies are sent (`app.ack_mode`).
+    pub ack_mode: AckMode,
+    /// Telegram user ids that may run `/stats`.
+    pub admin_users: Vec<i64>,
+    /// `/commit` without a title asks for confirmation with inline buttons.
+    pub confirm_commit: bool,
+    /// What `/rollback` does with the batch's items (`app.rollback_mode`).
+    pub rollback_mode: RollbackMode,
+    /// Per-chat mappings from `notion.routes`, keyed by chat id.
+    pub notion_routes: HashMap<i64, NotionIds>,
+    /// Serializes message handling per user; shared by every clone.
+    pub user_locks: UserLocks,
+    /// Woken whenever a handler queues outbox tasks.
+    pub outbox_signal: OutboxSignal,
+}
+
+/// One async mutex per user, so rapid-fire messages from the same user
+/// cannot interleave their read-then-write sequences on the current batch.
+#[derive(Debug, Clone, Default)]
+pub struct UserLocks {
+    locks: Arc<Mutex<HashMap<i64, Arc<tokio::sync::Mutex<()>>>>>,
+}
+
+impl UserLocks {
+    /// Wait for `user_id`'s lock. It is released when the guard is dropped.
+    pub async fn lock(&self, user_id: i64) -> tokio::sync::OwnedMutexGuard<()> {
+        let lock = self
+            .locks
+            .lock()
+            .expect("user lock map poisoned")
+            .entry(user_id)
+            .or_default()
+            .clone();
+        lock.lock_owned().await
+    }
+}
+
+impl HandlerOptions {
+    /// Route key persisted with batches/resources from `chat_id`; `None` when
+    /// the chat syncs to the default databases.
+    pub fn notion_route(&self, chat_id: i64) -> Option<i64> {
+        self.notion_routes.contains_key(&chat_id).then_some(chat_id)
+    }
+
+    /// Wake the worker for a freshly saved resource; only standalone ones
+    /// queue a push right away.
+    fn wake_outbox_for(&self, batch_id: Option<i64>) {
+        if batch_id.is_none() {
+            self.outbox_signal.notify();
+        }
+    }
+
+    /// Chat to notify once a batch committed from `chat_id` is synced.
+    fn notify_chat(&self, chat_id: i64) -> Option<i64> {
+        self.notify_on_sync.then_some(chat_id)
+    }
+
+    fn route_ids(&self, notion_route: Option<i64>) -> Option<&NotionIds> {
+        match notion_route {
+            Some(chat_id) => self.notion_routes.get(&chat_id),
+            None => self.notion_ids.as_ref(),
+        }
+    }
+}
+
+/// Whether a file of `size` bytes is over `limit`; `None` means unlimited.
+pub fn exceeds_limit(size: u64, limit: Option<u64>) -> bool {
+    limit.is_some_and(|limit| size > limit)
+}
+
+/// Whether a Telegram user may talk to the bot. An empty allowlist allows everyone.
+pub fn is_allowed(uid: i64, allowed: &[i64]) -> bool {
+    allowed.is_empty() || allowed.contains(&uid)
+}
+
+/// Answer `/whoami` with the sender's Telegram id. Call it before the
+/// allowlist gate so operators can look up ids to add to
+/// `telegram.allowed_users`. Returns whether the message was `/whoami`.
+pub async fn reply_whoami(bot: &impl Responder, msg: &Message, allowed: &[i64]) -> Result<bool> {
+    let Some(user) = msg.from() else {
+        return Ok(false);
+    };
+    if msg.text().map(str::trim) != Some("/whoami") {
+        return Ok(false);
+    }
+    let uid = user.id.0 as i64;
+    let reply = format_whoami(uid, user.username.as_deref(), is_allowed(uid, allowed));
+    bot.send_text(msg.chat.id, reply).await?;
+    Ok(true)
+}
+
 #[instrument(skip_all)]
 pub async fn handle_update(
-    bot: &Bot,
+    bot: &impl Responder,
     pool: &SqlitePool,
-    data_dir: &str,
+    opts: &HandlerOptions,
     msg: &Message,
 ) -> Result<()> {
+    let data_dir = opts.data_dir.as_str();
     let user = match msg.from() {
         Some(u) => u,
         None => return Ok(()),
@@ -25,7 +294,26 @@ pub async fn handle_update(
         user.first_name,
         user.last_name.clone().unwrap_or_default()
     );
+
+    // Ping health check: answered before any other query so that a failing
+    // database still gets a reply instead of silence
+    if msg.text().map(str::trim) == Some("/ping") {
+        let checked: Result<String> = async {
+            let latency = db::ping(pool).await?;
+            let pending = db::count_remaining_outbox_tasks(pool).await?;
+            Ok(format_ping(latency, pending))
+        }
+        .await;
+        let reply = checked.unwrap_or_else(|err| {
+            warn!(?err, "ping failed");
+            "PING FAILED: database unavailable".to_string()
+        });
+        let _ = bot.send_text(msg.chat.id, reply).await;
+        return Ok(());
+    }
     let user_id = db::get_or_create_user(pool, tg_user_id, username, Some(&full_name)).await?;
+    // Held until this function returns, including early returns and `?`.
+    let _user_guard = opts.user_locks.lock(user_id).await;
 
     let message_id = msg.id.0;
 
@@ -35,17 +323,28 @@ pub async fn handle_update(
             if let Some(text) = msg.text() {
                 let trimmed = text.trim();
                 if trimmed.eq_ignore_ascii_case("/rollback") {
-                    if let Err(err) = db::rollback_batch(pool, user_id).await {
-                        warn!(?err, "failed to rollback batch");
+                    match rollback_current_batch(pool, opts, user_id).await {
+                        Ok(reply) => {
+                            let _ = bot.send_text(msg.chat.id, reply).await;
+                        }
+                        Err(err) => warn!(?err, "failed to rollback batch"),
+                    }
+                    return Ok(());
+                }
+                if trimmed.eq_ignore_ascii_case("/cancel") {
+                    if let Err(err) = db::mark_current_batch_open(pool, user_id).await {
+                        warn!(?err, "failed to cancel commit");
                     } else {
-                        let _ = bot.send_message(msg.chat.id, "Rolled back.").await;
+                        let _ = bot
+                            .send_text(msg.chat.id, "Commit cancelled, batch still open.")
+                            .await;
                     }
                     return Ok(());
                 }
                 // Disallow commands as titles while waiting for title
                 if trimmed.starts_with('/') {
                     let _ = bot
-                        .send_message(
+                        .send_text(
                             msg.chat.id,
                             "Invalid input: title must be a text message. Please send text.",
                         )
@@ -55,7 +354,7 @@ pub async fn handle_update(
 
                 if trimmed.is_empty() {
                     let _ = bot
-                        .send_message(
+                        .send_text(
                             msg.chat.id,
                             "Invalid input: title must be a non-empty text message. Please send text.",
                         )
@@ -64,21 +363,26 @@ pub async fn handle_update(
                 }
 
                 // Use this text as the title and commit
-                if let Err(err) = db::commit_batch(pool, user_id, Some(trimmed)).await {
-                    warn!(?err, "failed to commit batch with provided title");
-                } else {
-                    let _ = bot
-                        .send_message(
-                            msg.chat.id,
-                            format!("Committed batch with title: {}", trimmed),
-                        )
-                        .await;
+                match db::commit_batch_notify(
+                    pool,
+                    user_id,
+                    Some(trimmed),
+                    opts.notify_chat(msg.chat.id.0),
+                )
+                .await
+                {
+                    Err(err) => warn!(?err, "failed to commit batch with provided title"),
+                    Ok(batch_id) => {
+                        opts.outbox_signal.notify();
+                        let reply = commit_reply(pool, opts, batch_id, trimmed).await?;
+                        let _ = bot.send_text(msg.chat.id, reply).await;
+                    }
                 }
                 return Ok(());
             } else {
                 // Non-text input while waiting for title
                 let _ = bot
-                    .send_message(
+                    .send_text(
                         msg.chat.id,
                         "Invalid input: title must be a text message. Please send text.",
                     )
@@ -92,83 +396,202 @@ pub async fn handle_update(
         let caption = msg.caption().map(str::to_owned);
 
         if let Some(text) = text_content.as_deref() {
-            handle_text_content(bot, msg, pool, user_id, message_id, text, true).await?;
+            handle_text_content(bot, msg, pool, opts, user_id, message_id, text, true).await?;
             return Ok(());
         }
 
-        if let Some(caption) = caption.as_deref() {
-            handle_text_content(bot, msg, pool, user_id, message_id, caption, false).await?;
+        if let Some(count) = full_batch_count(pool, opts, user_id).await? {
+            reject_batch_full(bot, msg, count).await;
+            return Ok(());
         }
 
         match &common.media_kind {
             MediaKind::Text(_) => {}
             MediaKind::Photo(photo) => {
                 if let Some(size) = photo.photo.last() {
-                    let path =
-                        download_file(bot, data_dir, tg_user_id, message_id, size.file.id.as_ref())
-                            .await?;
+                    if exceeds_limit(size.file.size as u64, opts.max_media_bytes) {
+                        reject_too_large(bot, msg, opts).await;
+                        return Ok(());
+                    }
+                    if let Some(id) = db::find_redelivered_resource(
+                        pool,
+                        user_id,
+                        msg.chat.id.0,
+                        message_id,
+                        &size.file.unique_id,
+                    )
+                    .await?
+                    {
+                        info!(resource_id = id, "ignoring re-delivered photo");
+                        return Ok(());
+                    }
+                    let Some(path) =
+                        download_media(bot, msg, data_dir, tg_user_id, &size.file, None).await?
+                    else {
+                        return Ok(());
+                    };
                     let batch_id = db::current_open_batch_id(pool, user_id).await?;
-                    let _rid =
-                        db::insert_resource(pool, user_id, batch_id, "photo", &path, message_id)
-                            .await?;
+                    let source_url = message_permalink(msg);
+                    let origin = db::ResourceOrigin {
+                        source_url: source_url.as_deref(),
+                        unique_id: Some(size.file.unique_id.as_ref()),
+                        notion_route: opts.notion_route(msg.chat.id.0),
+                        global_sequence: opts.global_sequence,
+                        tg_date: Some(msg.date),
+                        chat_id: Some(msg.chat.id.0),
+                        thumb_path: None,
+                        media_url: None,
+                        text: caption.as_deref(),
+                        dimensions: MediaDimensions {
+                            width: Some(size.width),
+                            height: Some(size.height),
+                            duration: None,
+                        },
+                    };
+                    let inserted = db::insert_resource_from(
+                        pool,
+                        user_id,
+                        batch_id,
+                        ResourceKind::Photo,
+                        &path,
+                        message_id,
+                        &origin,
+                    )
+                    .await?;
+                    if !inserted.fresh {
+                        info!(resource_id = inserted.id, "ignoring re-delivered photo");
+                        return Ok(());
+                    }
+                    opts.wake_outbox_for(batch_id);
                     let ack = if batch_id.is_some() {
                         "Saved photo (in batch)."
                     } else {
                         "Saved photo."
                     };
-                    let _ = bot.send_message(msg.chat.id, ack).await;
+                    ack_item(bot, msg, opts, ack).await;
                 }
             }
             MediaKind::Video(video) => {
-                let path = download_file(
+                save_video(
                     bot,
-                    data_dir,
+                    pool,
+                    opts,
+                    msg,
+                    user_id,
                     tg_user_id,
-                    message_id,
-                    video.video.file.id.as_ref(),
+                    &video.video.file,
+                    None,
+                    MediaDimensions {
+                        width: Some(video.video.width),
+                        height: Some(video.video.height),
+                        duration: Some(video.video.duration),
+                    },
+                    "video",
                 )
                 .await?;
-                // Generate thumbnail before persisting; treat failure as overall failure
-                match crate::thumbnail::generate_thumbnail(&path, data_dir).await {
-                    Ok(thumb_path) => {
-                        info!(video=%path, thumb=%thumb_path.display(), "generated thumbnail");
-                    }
-                    Err(err) => {
-                        warn!(?err, video=%path, "failed to generate thumbnail; aborting save");
-                        let _ = bot
-                            .send_message(
-                                msg.chat.id,
-                                "Failed to save video (thumbnail generation error).",
-                            )
-                            .await;
+            }
+            MediaKind::VideoNote(note) => {
+                let file = &note.video_note.file;
+                // Round videos have no file name; give them one with an extension
+                let name = video_note_file_name(message_id, &file.unique_id);
+                save_video(
+                    bot,
+                    pool,
+                    opts,
+                    msg,
+                    user_id,
+                    tg_user_id,
+                    file,
+                    Some(&name),
+                    // Round videos are square, `length` pixels across
+                    MediaDimensions {
+                        width: Some(note.video_note.length),
+                        height: Some(note.video_note.length),
+                        duration: Some(note.video_note.duration),
+                    },
+                    "video note",
+                )
+                .await?;
+            }
+            other => match shared_item(other) {
+                Some(item) => {
+                    let (inserted, batch_id) =
+                        insert_shared_item(pool, opts, user_id, msg, &item).await?;
+                    if !inserted.fresh {
+                        info!(
+                            resource_id = inserted.id,
+                            kind = item.label,
+                            "ignoring re-delivered item"
+                        );
                         return Ok(());
                     }
+                    let ack = if batch_id.is_some() {
+                        format!("Saved {} (in batch).", item.label)
+                    } else {
+                        format!("Saved {}.", item.label)
+                    };
+                    ack_item(bot, msg, opts, ack).await;
                 }
-                let batch_id = db::current_open_batch_id(pool, user_id).await?;
-                let _rid = db::insert_resource(pool, user_id, batch_id, "video", &path, message_id)
-                    .await?;
-                let ack = if batch_id.is_some() {
-                    "Saved video (in batch)."
-                } else {
-                    "Saved video."
-                };
-                let _ = bot.send_message(msg.chat.id, ack).await;
-            }
-            _ => {
-                let _ = bot
-                    .send_message(msg.chat.id, "Unsupported message type.")
-                    .await;
-            }
+                None => {
+                    // Keep the caption of media we cannot store
+                    if let Some(caption) = caption.as_deref() {
+                        handle_text_content(
+                            bot, msg, pool, opts, user_id, message_id, caption, false,
+                        )
+                        .await?;
+                    }
+                    let _ = bot
+                        .send_text(msg.chat.id, "Unsupported message type.")
+                        .await;
+                }
+            },
         }
     }
 
     Ok(())
 }
 
+/// Apply an edited Telegram message to the text resource stored from it, or
+/// an edited caption to its photo or video. Edits of unknown messages,
+/// commands and other content are ignored.
+#[instrument(skip_all)]
+pub async fn handle_edit(
+    bot: &impl Responder,
+    pool: &SqlitePool,
+    opts: &HandlerOptions,
+    msg: &Message,
+) -> Result<()> {
+    let Some(user) = msg.from() else {
+        return Ok(());
+    };
+    let Some(user_id) = db::find_user_id(pool, user.id.0 as i64).await? else {
+        return Ok(());
+    };
+    let Some(text) = msg.text().or_else(|| msg.caption()) else {
+        return Ok(());
+    };
+    let _user_guard = opts.user_locks.lock(user_id).await;
+
+    match db::update_resource_text(pool, user_id, msg.chat.id.0, msg.id.0, text).await? {
+        Some(resource_id) => {
+            info!(resource_id, "updated text from edited message");
+            opts.outbox_signal.notify();
+            ack_item(bot, msg, opts, "Updated.").await;
+        }
+        None => info!(
+            message_id = msg.id.0,
+            "edited message has no stored text; ignoring"
+        ),
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn handle_text_content(
-    bot: &Bot,
+    bot: &impl Responder,
     msg: &Message,
     pool: &SqlitePool,
+    opts: &HandlerOptions,
     user_id: i64,
     message_id: i32,
     text_content: &str,
@@ -181,86 +604,1909 @@ async fn handle_text_content(
         return Ok(());
     }
 
-    // Ping health check
-    if allow_commands && (trimmed == "/ping") {
-        let _ = bot.send_message(msg.chat.id, "PONG").await;
-        return Ok(());
-    }
-    if allow_commands && trimmed == "/begin" {
-        if let Err(err) = db::open_batch(pool, user_id).await {
+    if let Some(title) = parse_command_arg(trimmed, "/begin").filter(|_| allow_commands) {
+        let notion_route = opts.notion_route(msg.chat.id.0);
+        if let Err(err) =
+            db::open_batch_routed(pool, user_id, notion_route, Some(msg.chat.id.0), title).await
+        {
             warn!(?err, "failed to open batch");
         } else {
             info!(user_id, "opened batch");
-            let _ = bot.send_message(msg.chat.id, "Opened batch.").await;
+            let reply = match title {
+                Some(title) => format!("Opened batch: {}", title),
+                None => "Opened batch.".to_string(),
+            };
+            let _ = bot.send_text(msg.chat.id, reply).await;
         }
         return Ok(());
     }
 
-    if allow_commands && trimmed == "/commit" {
-        match db::current_open_batch_id(pool, user_id).await? {
-            None => {
-                let _ = bot
-                    .send_message(msg.chat.id, "No open batch to commit.")
-                    .await;
+    if let Some(inline_title) = parse_command_arg(trimmed, "/commit").filter(|_| allow_commands) {
+        // A title given to `/begin <name>` saves the prompt
+        let title = match inline_title {
+            Some(title) => Some(title.to_string()),
+            None => db::current_batch_title(pool, user_id).await?,
+        };
+        let batch_id = db::current_open_batch_id(pool, user_id).await?;
+        if let (Some(batch_id), None, true) = (batch_id, inline_title, opts.confirm_commit) {
+            let count = db::count_batch_resources(pool, batch_id).await?;
+            let buttons = [
+                (
+                    "Confirm",
+                    commit_callback_data(CommitChoice::Confirm, batch_id),
+                ),
+                (
+                    "Cancel",
+                    commit_callback_data(CommitChoice::Cancel, batch_id),
+                ),
+            ];
+            let _ = bot
+                .send_choice(msg.chat.id, &format_commit_question(count), &buttons)
+                .await;
+            return Ok(());
+        }
+        match (batch_id, title) {
+            (None, _) => {
+                let _ = bot.send_text(msg.chat.id, "No open batch to commit.").await;
+            }
+            (Some(_), Some(title)) => {
+                match db::commit_batch_notify(
+                    pool,
+                    user_id,
+                    Some(&title),
+                    opts.notify_chat(msg.chat.id.0),
+                )
+                .await
+                {
+                    Err(err) => warn!(?err, "failed to commit batch with known title"),
+                    Ok(batch_id) => {
+                        info!(user_id, "committed batch with known title");
+                        opts.outbox_signal.notify();
+                        let reply = commit_reply(pool, opts, batch_id, &title).await?;
+                        let _ = bot.send_text(msg.chat.id, reply).await;
+                    }
+                }
             }
-            Some(_) => {
+            (Some(_), None) => {
                 if let Err(err) = db::mark_current_batch_waiting_title(pool, user_id).await {
                     warn!(?err, "failed to mark batch waiting title");
                 } else {
-                    let _ = bot.send_message(msg.chat.id, "Please input title:").await;
+                    let _ = bot
+                        .send_text(
+                            msg.chat.id,
+                            "Please input title (or /cancel to keep editing):",
+                        )
+                        .await;
                 }
             }
         }
         return Ok(());
     }
 
-    if allow_commands && trimmed == "/rollback" {
-        if let Err(err) = db::rollback_batch(pool, user_id).await {
-            warn!(?err, "failed to rollback batch");
+    if allow_commands && trimmed == "/status" {
+        let batch = match db::current_batch_state(pool, user_id).await? {
+            Some(state) => match db::current_open_batch_id(pool, user_id).await? {
+                Some(batch_id) => Some((state, db::count_batch_resources(pool, batch_id).await?)),
+                None => None,
+            },
+            None => None,
+        };
+        let pending = db::count_remaining_outbox_tasks_for_user(pool, user_id).await?;
+        let _ = bot
+            .send_text(msg.chat.id, format_status(batch, pending))
+            .await;
+        return Ok(());
+    }
+
+    if allow_commands && trimmed == "/history" {
+        let batches =
+            db::list_batches_in_state(pool, user_id, Some(BatchState::Committed), HISTORY_LIMIT, 0)
+                .await?;
+        let _ = bot.send_text(msg.chat.id, format_history(&batches)).await;
+        return Ok(());
+    }
+
+    if allow_commands && trimmed == "/stats" {
+        let is_admin = msg
+            .from()
+            .is_some_and(|u| opts.admin_users.contains(&(u.id.0 as i64)));
+        let reply = if is_admin {
+            format_stats(&db::stats(pool).await?)
         } else {
-            info!(user_id, "rolled back batch");
-            let _ = bot.send_message(msg.chat.id, "Rolled back.").await;
+            "Only admins can use /stats.".to_string()
+        };
+        let _ = bot.send_text(msg.chat.id, reply).await;
+        return Ok(());
+    }
+
+    if allow_commands && trimmed == "/resend" {
+        let reply = match db::latest_synced_batch(pool, user_id).await? {
+            Some((title, page_id)) => format!(
+                "{}: {}",
+                title.as_deref().unwrap_or("Untitled"),
+                page_url(&page_id)
+            ),
+            None => {
+                let committed =
+                    db::list_batches_in_state(pool, user_id, Some(BatchState::Committed), 1, 0)
+                        .await?;
+                if committed.is_empty() {
+                    "No committed batches yet.".to_string()
+                } else {
+                    "Latest batch not synced yet.".to_string()
+                }
+            }
+        };
+        let _ = bot.send_text(msg.chat.id, reply).await;
+        return Ok(());
+    }
+
+    if let Some(query) = parse_command_arg(trimmed, "/find").filter(|_| allow_commands) {
+        let reply = match query {
+            None => "Usage: /find <text>".to_string(),
+            Some(query) => {
+                let matches = if opts.full_text_search {
+                    db::fts_search(pool, user_id, query, FIND_LIMIT).await?
+                } else {
+                    db::search_resources(pool, user_id, query, FIND_LIMIT).await?
+                };
+                format_find(query, &matches)
+            }
+        };
+        let _ = bot.send_text(msg.chat.id, reply).await;
+        return Ok(());
+    }
+
+    if let Some(arg) = parse_command_arg(trimmed, "/get").filter(|_| allow_commands) {
+        let reply = match arg.and_then(parse_number_pair) {
+            None => "Usage: /get <batch> <seq>".to_string(),
+            Some((batch_id, sequence)) => {
+                match db::get_resource(pool, user_id, batch_id, sequence).await? {
+                    Some(resource) => format_resource_detail(batch_id, sequence, &resource),
+                    None => format!("No resource #{} in batch {}.", sequence, batch_id),
+                }
+            }
+        };
+        let _ = bot.send_text(msg.chat.id, reply).await;
+        return Ok(());
+    }
+
+    if let Some(arg) = parse_command_arg(trimmed, "/move").filter(|_| allow_commands) {
+        let reply = match arg.and_then(parse_number_pair) {
+            None => "Usage: /move <from> <to>".to_string(),
+            Some((from, to)) => match db::current_open_batch_id(pool, user_id).await? {
+                None => "No open batch to reorder.".to_string(),
+                Some(batch_id) => {
+                    if db::reorder_resource(pool, batch_id, from, to).await? {
+                        let resources =
+                            db::fetch_batch_resources_for_outbox(pool, batch_id).await?;
+                        format_order(from, to, &resources)
+                    } else {
+                        format!("No resource #{} or #{} in the open batch.", from, to)
+                    }
+                }
+            },
+        };
+        let _ = bot.send_text(msg.chat.id, reply).await;
+        return Ok(());
+    }
+
+    if allow_commands && trimmed == "/validate" {
+        let reply = match db::current_open_batch_id(pool, user_id).await? {
+            None => "No open batch to validate.".to_string(),
+            Some(batch_id) => {
+                let batch = db::fetch_batch_for_outbox(pool, batch_id).await?;
+                let resources = db::fetch_batch_resources_for_outbox(pool, batch_id).await?;
+                let problems = validate::validate_batch(
+                    batch.title.as_deref(),
+                    &resources,
+                    opts.route_ids(batch.notion_route),
+                );
+                format_validation_report(resources.len(), &problems)
+            }
+        };
+        let _ = bot.send_text(msg.chat.id, reply).await;
+        return Ok(());
+    }
+
+    if allow_commands && trimmed == "/rollback" {
+        match rollback_current_batch(pool, opts, user_id).await {
+            Ok(reply) => {
+                info!(user_id, "rolled back batch");
+                let _ = bot.send_text(msg.chat.id, reply).await;
+            }
+            Err(err) => warn!(?err, "failed to rollback batch"),
         }
         return Ok(());
     }
 
     // Unknown slash command: reply and do not persist
     if allow_commands && trimmed.starts_with('/') {
-        let _ = bot.send_message(msg.chat.id, "Unknown command.").await;
+        let _ = bot.send_text(msg.chat.id, "Unknown command.").await;
         return Ok(());
     }
 
+    // Captions were checked together with their media
+    if allow_commands {
+        if let Some(count) = full_batch_count(pool, opts, user_id).await? {
+            reject_batch_full(bot, msg, count).await;
+            return Ok(());
+        }
+    }
+
     let batch_id = db::current_open_batch_id(pool, user_id).await?;
-    let _rid =
-        db::insert_resource(pool, user_id, batch_id, "text", text_content, message_id).await?;
+    let source_url = message_permalink(msg);
+    let unique_id = message_unique_id("text", msg.chat.id.0, message_id);
+    let origin = db::ResourceOrigin {
+        source_url: source_url.as_deref(),
+        unique_id: Some(&unique_id),
+        notion_route: opts.notion_route(msg.chat.id.0),
+        global_sequence: opts.global_sequence,
+        tg_date: Some(msg.date),
+        chat_id: Some(msg.chat.id.0),
+        thumb_path: None,
+        media_url: None,
+        text: None,
+        dimensions: MediaDimensions::default(),
+    };
+    let inserted = db::insert_resource_from(
+        pool,
+        user_id,
+        batch_id,
+        ResourceKind::Text,
+        text_content,
+        message_id,
+        &origin,
+    )
+    .await?;
+    if !inserted.fresh {
+        info!(resource_id = inserted.id, "ignoring re-delivered text");
+        return Ok(());
+    }
+    opts.wake_outbox_for(batch_id);
     let ack = if batch_id.is_some() {
         "Saved (in batch)."
     } else {
         "Saved."
     };
-    let _ = bot.send_message(msg.chat.id, ack).await;
+    ack_item(bot, msg, opts, ack).await;
     Ok(())
 }
 
-async fn download_file(
-    bot: &Bot,
-    data_dir: &str,
+/// Download a video or video note, generate its thumbnail and store both as
+/// a `video` resource. `label` names it in replies.
+#[allow(clippy::too_many_arguments)]
+async fn save_video(
+    bot: &impl Responder,
+    pool: &SqlitePool,
+    opts: &HandlerOptions,
+    msg: &Message,
+    user_id: i64,
     tg_user_id: i64,
-    msg_id: i32,
-    file_id: &str,
+    file: &FileMeta,
+    file_name: Option<&str>,
+    dimensions: MediaDimensions,
+    label: &str,
+) -> Result<()> {
+    let data_dir = opts.data_dir.as_str();
+    let message_id = msg.id.0;
+    if let Some(reason) = video_unsupported_reason(opts.ffmpeg) {
+        let _ = bot.send_text(msg.chat.id, reason).await;
+        return Ok(());
+    }
+    if exceeds_limit(file.size as u64, opts.max_media_bytes) {
+        reject_too_large(bot, msg, opts).await;
+        return Ok(());
+    }
+    if let Some(id) =
+        db::find_redelivered_resource(pool, user_id, msg.chat.id.0, message_id, &file.unique_id)
+            .await?
+    {
+        info!(resource_id = id, label, "ignoring re-delivered video");
+        return Ok(());
+    }
+    let Some(path) = download_media(bot, msg, data_dir, tg_user_id, file, file_name).await? else {
+        return Ok(());
+    };
+    // Generate thumbnail before persisting; treat failure as overall failure
+    let thumb_path =
+        match crate::thumbnail::generate_thumbnail(&path, data_dir, opts.thumbnail_format).await {
+            Ok(thumb_path) => {
+                info!(video=%path, thumb=%thumb_path.display(), "generated thumbnail");
+                thumb_path
+            }
+            Err(err) => {
+                warn!(?err, video=%path, "failed to generate thumbnail; aborting save");
+                let _ = bot
+                    .send_text(
+                        msg.chat.id,
+                        format!("Failed to save {} (thumbnail generation error).", label),
+                    )
+                    .await;
+                return Ok(());
+            }
+        };
+    let thumb_path = thumb_path.to_string_lossy();
+    let batch_id = db::current_open_batch_id(pool, user_id).await?;
+    let source_url = message_permalink(msg);
+    let origin = db::ResourceOrigin {
+        source_url: source_url.as_deref(),
+        unique_id: Some(file.unique_id.as_ref()),
+        notion_route: opts.notion_route(msg.chat.id.0),
+        global_sequence: opts.global_sequence,
+        tg_date: Some(msg.date),
+        chat_id: Some(msg.chat.id.0),
+        thumb_path: Some(&thumb_path),
+        media_url: None,
+        text: msg.caption(),
+        dimensions,
+    };
+    let inserted = db::insert_resource_from(
+        pool,
+        user_id,
+        batch_id,
+        ResourceKind::Video,
+        &path,
+        message_id,
+        &origin,
+    )
+    .await?;
+    if !inserted.fresh {
+        info!(
+            resource_id = inserted.id,
+            label, "ignoring re-delivered video"
+        );
+        return Ok(());
+    }
+    opts.wake_outbox_for(batch_id);
+    let ack = if batch_id.is_some() {
+        format!("Saved {} (in batch).", label)
+    } else {
+        format!("Saved {}.", label)
+    };
+    ack_item(bot, msg, opts, ack).await;
+    Ok(())
+}
+
+/// Local file name for a round video. Telegram gives video notes no name, and
+/// the unique id keeps notes with the same message id in other chats apart.
+pub fn video_note_file_name(message_id: i32, unique_id: &str) -> String {
+    format!("video_note_{}_{}.mp4", message_id, unique_id)
+}
+
+/// Button pressed under a `/commit` confirmation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitChoice {
+    Confirm,
+    Cancel,
+}
+
+/// Callback data for `choice` on batch `batch_id`: `commit:confirm:<id>` or `commit:cancel:<id>`.
+pub fn commit_callback_data(choice: CommitChoice, batch_id: i64) -> String {
+    let choice = match choice {
+        CommitChoice::Confirm => "confirm",
+        CommitChoice::Cancel => "cancel",
+    };
+    format!("commit:{}:{}", choice, batch_id)
+}
+
+/// Inverse of [`commit_callback_data`]; `None` for any other data.
+pub fn parse_commit_callback(data: &str) -> Option<(CommitChoice, i64)> {
+    let rest = data.strip_prefix("commit:")?;
+    let (choice, batch_id) = rest.split_once(':')?;
+    let choice = match choice {
+        "confirm" => CommitChoice::Confirm,
+        "cancel" => CommitChoice::Cancel,
+        _ => return None,
+    };
+    Some((choice, batch_id.parse().ok()?))
+}
+
+fn format_commit_question(count: i64) -> String {
+    match count {
+        1 => "Commit this batch of 1 item?".to_string(),
+        n => format!("Commit this batch of {} items?", n),
+    }
+}
+
+/// Handle a Confirm/Cancel press under a `/commit` confirmation. Confirm
+/// commits a batch whose title is known and otherwise asks for one (like
+/// `/commit`); Cancel leaves the batch open. Buttons of a batch that is no
+/// longer the user's open batch do nothing. The prompt is edited to the outcome.
+#[instrument(skip_all)]
+pub async fn handle_callback(
+    bot: &impl Responder,
+    pool: &SqlitePool,
+    opts: &HandlerOptions,
+    query: &CallbackQuery,
+) -> Result<()> {
+    let _ = bot.answer_callback(&query.id).await;
+    let Some((choice, batch_id)) = query.data.as_deref().and_then(parse_commit_callback) else {
+        return Ok(());
+    };
+    let Some(user_id) = db::find_user_id(pool, query.from.id.0 as i64).await? else {
+        return Ok(());
+    };
+    let _user_guard = opts.user_locks.lock(user_id).await;
+    let chat_id = match &query.message {
+        Some(message) => message.chat.id,
+        None => ChatId(query.from.id.0 as i64),
+    };
+
+    let open = db::current_open_batch_id(pool, user_id).await? == Some(batch_id)
+        && db::current_batch_state(pool, user_id).await? == Some(BatchState::Open);
+    let reply = match (open, choice) {
+        (false, _) => "This batch is no longer open.".to_string(),
+        (true, CommitChoice::Cancel) => "Commit cancelled, batch still open.".to_string(),
+        (true, CommitChoice::Confirm) => match db::current_batch_title(pool, user_id).await? {
+            Some(title) => {
+                let committed = db::commit_batch_notify(
+                    pool,
+                    user_id,
+                    Some(&title),
+                    opts.notify_chat(chat_id.0),
+                )
+                .await?;
+                info!(user_id, "committed batch after confirmation");
+                opts.outbox_signal.notify();
+                commit_reply(pool, opts, committed, &title).await?
+            }
+            None => {
+                db::mark_current_batch_waiting_title(pool, user_id).await?;
+                "Please input title (or /cancel to keep editing):".to_string()
+            }
+        },
+    };
+    match &query.message {
+        Some(message) => {
+            let _ = bot.edit_text(chat_id, message.id, &reply).await;
+        }
+        None => {
+            let _ = bot.send_text(chat_id, reply).await;
+        }
+    }
+    Ok(())
+}
+
+/// Acknowledge a saved or edited item, unless `app.ack_mode` suppresses it.
+async fn ack_item(
+    bot: &impl Responder,
+    msg: &Message,
+    opts: &HandlerOptions,
+    ack: impl Into<String> + Send,
+) {
+    if opts.ack_mode.acks_items() {
+        let _ = bot.send_text(msg.chat.id, ack).await;
+    }
+}
+
+/// Reply to a commit; in `summary` mode it also counts the batch's items.
+async fn commit_reply(
+    pool: &SqlitePool,
+    opts: &HandlerOptions,
+    batch_id: i64,
+    title: &str,
+) -> Result<String> {
+    let items = match opts.ack_mode {
+        AckMode::Summary => Some(db::count_batch_resources(pool, batch_id).await?),
+        AckMode::Each | AckMode::Silent => None,
+    };
+    Ok(format_commit_reply(title, items))
+}
+
+/// `Committed batch with title: ...`, followed by the item count when given.
+pub fn format_commit_reply(title: &str, items: Option<i64>) -> String {
+    match items {
+        Some(1) => format!("Committed batch with title: {} (1 item)", title),
+        Some(n) => format!("Committed batch with title: {} ({} items)", title, n),
+        None => format!("Committed batch with title: {}", title),
+    }
+}
+
+/// Text, locations, contacts and polls have no Telegram file id, so a
+/// chat+message key identifies them across re-deliveries.
+fn message_unique_id(kind: &str, chat_id: i64, message_id: i32) -> String {
+    format!("{}:{}:{}", kind, chat_id, message_id)
+}
+
+/// A location, contact or poll stored as a resource without any download.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharedItem {
+    /// `Location`, or `Text` for contact and poll summaries.
+    pub kind: ResourceKind,
+    pub content: String,
+    pub media_url: Option<String>,
+    /// Name used in replies and in the unique id.
+    pub label: &'static str,
+}
+
+/// Resource for the media kinds that carry their data inline; `None` for others.
+pub fn shared_item(media_kind: &MediaKind) -> Option<SharedItem> {
+    match media_kind {
+        MediaKind::Location(l) => {
+            let (lat, lon) = (l.location.latitude, l.location.longitude);
+            Some(SharedItem {
+                kind: ResourceKind::Location,
+                content: format!("{},{}", lat, lon),
+                media_url: Some(location_url(lat, lon)),
+                label: "location",
+            })
+        }
+        MediaKind::Contact(c) => Some(SharedItem {
+            kind: ResourceKind::Text,
+            content: contact_summary(&c.contact),
+            media_url: None,
+            label: "contact",
+        }),
+        MediaKind::Poll(p) => Some(SharedItem {
+            kind: ResourceKind::Text,
+            content: poll_summary(&p.poll),
+            media_url: None,
+            label: "poll",
+        }),
+        _ => None,
+    }
+}
+
+/// OpenStreetMap link centred on the coordinates.
+pub fn location_url(lat: f64, lon: f64) -> String {
+    format!(
+        "https://www.openstreetmap.org/?mlat={lat}&mlon={lon}#map=16/{lat}/{lon}",
+        lat = lat,
+        lon = lon
+    )
+}
+
+fn contact_summary(contact: &Contact) -> String {
+    let name = match &contact.last_name {
+        Some(last) => format!("{} {}", contact.first_name, last),
+        None => contact.first_name.clone(),
+    };
+    format!("Contact: {} ({})", name, contact.phone_number)
+}
+
+fn poll_summary(poll: &Poll) -> String {
+    let mut summary = format!("Poll: {}", poll.question);
+    for option in &poll.options {
+        summary.push_str("\n- ");
+        summary.push_str(&option.text);
+    }
+    summary
+}
+
+/// Store `item` for `msg` the way text is stored: in the open batch if any,
+/// keyed by chat+message so re-deliveries are ignored.
+async fn insert_shared_item(
+    pool: &SqlitePool,
+    opts: &HandlerOptions,
+    user_id: i64,
+    msg: &Message,
+    item: &SharedItem,
+) -> Result<(db::InsertedResource, Option<i64>)> {
+    let batch_id = db::current_open_batch_id(pool, user_id).await?;
+    let source_url = message_permalink(msg);
+    let unique_id = message_unique_id(item.label, msg.chat.id.0, msg.id.0);
+    let origin = db::ResourceOrigin {
+        source_url: source_url.as_deref(),
+        unique_id: Some(&unique_id),
+        notion_route: opts.notion_route(msg.chat.id.0),
+        global_sequence: opts.global_sequence,
+        tg_date: Some(msg.date),
+        chat_id: Some(msg.chat.id.0),
+        thumb_path: None,
+        media_url: item.media_url.as_deref(),
+        text: None,
+        dimensions: MediaDimensions::default(),
+    };
+    let inserted = db::insert_resource_from(
+        pool,
+        user_id,
+        batch_id,
+        item.kind,
+        &item.content,
+        msg.id.0,
+        &origin,
+    )
+    .await?;
+    if inserted.fresh {
+        opts.wake_outbox_for(batch_id);
+    }
+    Ok((inserted, batch_id))
+}
+
+/// `https://t.me/{username}/{id}` for messages in public groups/channels;
+/// private chats have no shareable link.
+fn message_permalink(msg: &Message) -> Option<String> {
+    if msg.chat.is_private() {
+        return None;
+    }
+    permalink(msg.chat.username(), msg.id.0)
+}
+
+fn permalink(chat_username: Option<&str>, message_id: i32) -> Option<String> {
+    let username = chat_username.filter(|u| !u.is_empty())?;
+    Some(format!("https://t.me/{}/{}", username, message_id))
+}
+
+/// Roll back the open batch per `app.rollback_mode`; returns the reply.
+async fn rollback_current_batch(
+    pool: &SqlitePool,
+    opts: &HandlerOptions,
+    user_id: i64,
 ) -> Result<String> {
-    // Resolve file path from Telegram API, then download to local storage
-    let file = bot.get_file(file_id).await?;
+    match opts.rollback_mode {
+        RollbackMode::Discard => {
+            db::rollback_batch(pool, user_id).await?;
+            Ok("Rolled back.".to_string())
+        }
+        RollbackMode::Detach => {
+            let detached = db::rollback_batch_detaching(pool, user_id).await?;
+            if detached > 0 {
+                opts.outbox_signal.notify();
+            }
+            Ok(format!(
+                "Rolled back; {} item(s) kept as standalone.",
+                detached
+            ))
+        }
+    }
+}
+
+/// Split `/command [arg]` (e.g. `/commit <title>`). Returns `None` for other
+/// input, `Some(None)` for a bare command (or one followed only by
+/// whitespace), else the trimmed argument.
+fn parse_command_arg<'a>(text: &'a str, command: &str) -> Option<Option<&'a str>> {
+    let rest = text.trim().strip_prefix(command)?;
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let title = rest.trim();
+    Some((!title.is_empty()).then_some(title))
+}
+
+/// Reply for video messages that cannot be handled, if any.
+fn video_unsupported_reason(ffmpeg: FfmpegStatus) -> Option<&'static str> {
+    match ffmpeg {
+        FfmpegStatus::Available => None,
+        FfmpegStatus::Missing => Some("video support unavailable (ffmpeg not installed)"),
+    }
+}
+
+async fn reject_too_large(bot: &impl Responder, msg: &Message, opts: &HandlerOptions) {
+    let limit_mb = opts.max_media_bytes.unwrap_or_default() as f64 / (1024.0 * 1024.0);
+    let reply = format!(
+        "File too large (limit: {} MB)",
+        format!("{:.1}", limit_mb).trim_end_matches(".0")
+    );
+    let _ = bot.send_text(msg.chat.id, reply).await;
+}
+
+/// Item count of the user's open batch once it has reached
+/// `opts.max_batch_items`; `None` while there is room or no batch is open.
+async fn full_batch_count(
+    pool: &SqlitePool,
+    opts: &HandlerOptions,
+    user_id: i64,
+) -> Result<Option<i64>> {
+    let Some(limit) = opts.max_batch_items else {
+        return Ok(None);
+    };
+    let Some(batch_id) = db::current_open_batch_id(pool, user_id).await? else {
+        return Ok(None);
+    };
+    let count = db::count_batch_resources(pool, batch_id).await?;
+    Ok((count >= limit as i64).then_some(count))
+}
+
+async fn reject_batch_full(bot: &impl Responder, msg: &Message, count: i64) {
+    let reply = format!("Batch full ({} items). Please /commit.", count);
+    let _ = bot.send_text(msg.chat.id, reply).await;
+}
+
+fn format_stats(stats: &db::Stats) -> String {
+    let counts = |pairs: &[(String, i64)]| {
+        if pairs.is_empty() {
+            return "none".to_string();
+        }
+        pairs
+            .iter()
+            .map(|(name, count)| format!("{} {}", name, count))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    format!(
+        "Users: {}\nBatches: {}\nResources: {}\nPending sync: {}\nDead letters: {}",
+        stats.users,
+        counts(&stats.batches_by_state),
+        counts(&stats.resources_by_kind),
+        stats.pending_outbox,
+        stats.dead_letters
+    )
+}
+
+/// How many committed batches `/history` lists.
+const HISTORY_LIMIT: i64 = 10;
+
+fn format_history(batches: &[db::BatchSummary]) -> String {
+    if batches.is_empty() {
+        return "No committed batches yet.".to_string();
+    }
+    let mut lines = vec!["Recent batches:".to_string()];
+    for batch in batches {
+        let date = batch.created_at.get(..10).unwrap_or(&batch.created_at);
+        lines.push(format!(
+            "{} {} ({} item(s))",
+            date,
+            batch.title.as_deref().unwrap_or("Untitled"),
+            batch.resource_count
+        ));
+    }
+    lines.join("\n")
+}
+
+/// How many matches `/find` lists.
+const FIND_LIMIT: i64 = 10;
+/// Characters of matched text shown per `/find` result.
+const FIND_SNIPPET_CHARS: usize = 60;
+
+fn format_find(query: &str, matches: &[db::ResourceMatch]) -> String {
+    if matches.is_empty() {
+        return format!("No resources match \"{}\".", query);
+    }
+    let mut lines = vec![format!("Matches for \"{}\":", query)];
+    for m in matches {
+        let snippet = snippet(&m.text, FIND_SNIPPET_CHARS);
+        let place = match &m.batch_title {
+            Some(title) => format!("{} #{}", title, m.sequence),
+            None => "(no batch)".to_string(),
+        };
+        lines.push(format!("{}: {}", place, snippet));
+    }
+    lines.join("\n")
+}
+
+/// Characters of text shown per resource in the `/move` reply.
+const ORDER_SNIPPET_CHARS: usize = 40;
+
+fn format_order(from: i64, to: i64, resources: &[db::ResourceForOutbox]) -> String {
+    let mut lines = vec![format!("Moved #{} to #{}. New order:", from, to)];
+    for r in resources {
+        let label = match &r.text {
+            Some(text) => snippet(text, ORDER_SNIPPET_CHARS),
+            None => std::path::Path::new(&r.content)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        };
+        lines.push(format!("#{} {}: {}", r.sequence, r.kind.as_str(), label));
+    }
+    lines.join("\n")
+}
+
+/// `text` on one line, cut to `max_chars` with an ellipsis.
+fn snippet(text: &str, max_chars: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text,
+    }
+}
+
+/// Parse a two-number argument: `<batch> <seq>` of `/get`, `<from> <to>` of `/move`.
+fn parse_number_pair(arg: &str) -> Option<(i64, i64)> {
+    let mut parts = arg.split_whitespace();
+    let first = parts.next()?.parse().ok()?;
+    let second = parts.next()?.parse().ok()?;
+    parts.next().is_none().then_some((first, second))
+}
+
+fn format_resource_detail(batch_id: i64, sequence: i64, resource: &db::ResourceDetail) -> String {
+    let mut lines = vec![format!(
+        "Batch {} #{} ({})",
+        batch_id,
+        sequence,
+        resource.kind.as_str()
+    )];
+    if let Some(text) = &resource.text {
+        lines.push(text.clone());
+    } else if let Some(name) = &resource.media_name {
+        lines.push(name.clone());
+    }
+    let status = match (&resource.notion_page_id, resource.dead_lettered) {
+        (Some(page_id), _) => format!("synced: {}", page_url(page_id)),
+        (None, true) => "failed".to_string(),
+        (None, false) => "pending".to_string(),
+    };
+    lines.push(format!("Status: {}", status));
+    lines.join("\n")
+}
+
+fn format_whoami(tg_user_id: i64, username: Option<&str>, allowed: bool) -> String {
+    let username = match username {
+        Some(name) => format!("@{}", name),
+        None => "(none)".to_string(),
+    };
+    let access = if allowed { "allowed" } else { "not allowed" };
+    format!(
+        "Telegram user id: {}\nUsername: {}\nAccess: {}",
+        tg_user_id, username, access
+    )
+}
+
+fn format_ping(latency: Duration, pending: i64) -> String {
+    format!("PONG (db {}ms, {} pending)", latency.as_millis(), pending)
+}
+
+fn format_status(batch: Option<(BatchState, i64)>, pending: i64) -> String {
+    let pending_line = format!("Pending sync: {} task(s)", pending);
+    match batch {
+        Some((state, items)) => format!(
+            "Batch: {} ({} item(s))\n{}",
+            state.as_str(),
+            items,
+            pending_line
+        ),
+        None => pending_line,
+    }
+}
+
+fn format_validation_report(item_count: usize, problems: &[validate::Problem]) -> String {
+    if problems.is_empty() {
+        return format!("Batch OK: {} item(s) ready to commit.", item_count);
+    }
+    let mut out = format!("Found {} problem(s):", problems.len());
+    for p in problems {
+        out.push_str(&format!("\n- {}", p));
+    }
+    out
+}
+
+/// Local path of the media of `msg`, downloading it unless an earlier
+/// delivery already did. `None` means Telegram expired the file and the user
+/// was asked to resend it.
+async fn download_media(
+    bot: &impl Responder,
+    msg: &Message,
+    data_dir: &str,
+    tg_user_id: i64,
+    file: &FileMeta,
+    file_name: Option<&str>,
+) -> Result<Option<String>> {
     let dir = format!("{}/media/{}/", data_dir, tg_user_id);
-    tokio::fs::create_dir_all(&dir).await.ok();
-    // Try to preserve the original file extension from Telegram's file path
-    let ext = std::path::Path::new(&file.path)
-        .extension()
-        .and_then(|e| e.to_str())
-        .unwrap_or("bin");
-    let path = format!("{}{}_{}.{}", dir, msg_id, file.meta.unique_id, ext);
-    let mut dst = tokio::fs::File::create(&path).await?;
-    bot.download_file(&file.path, &mut dst).await?;
-    Ok(path)
+    if let Some(path) = local_copy(&dir, msg.id.0, &file.unique_id, file_name).await {
+        info!(path = %path, "reusing downloaded media");
+        return Ok(Some(path));
+    }
+    match bot
+        .fetch_file(file.id.as_ref(), &dir, msg.id.0, file_name)
+        .await
+    {
+        Ok(path) => Ok(Some(path)),
+        Err(err) if err.downcast_ref::<MediaExpired>().is_some() => {
+            warn!(?err, "telegram file expired");
+            let _ = bot
+                .send_text(msg.chat.id, "Media expired, please resend.")
+                .await;
+            Ok(None)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// A finished download of this file in `dir`, named as [`Responder::fetch_file`] names it.
+async fn local_copy(
+    dir: &str,
+    msg_id: i32,
+    unique_id: &str,
+    file_name: Option<&str>,
+) -> Option<String> {
+    if let Some(name) = file_name {
+        let path = format!("{}{}", dir, name);
+        return tokio::fs::try_exists(&path)
+            .await
+            .unwrap_or(false)
+            .then_some(path);
+    }
+    let prefix = format!("{}_{}.", msg_id, unique_id);
+    let mut entries = tokio::fs::read_dir(dir).await.ok()?;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with(&prefix) && !name.ends_with(".part") {
+            return Some(format!("{}{}", dir, name));
+        }
+    }
+    None
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn user_lock_serializes_same_user_only() {
+        let locks = UserLocks::default();
+        let guard = locks.lock(1).await;
+        let same = tokio::time::timeout(Duration::from_millis(50), locks.lock(1)).await;
+        assert!(same.is_err(), "second lock for the same user must wait");
+        let other = tokio::time::timeout(Duration::from_millis(50), locks.lock(2)).await;
+        assert!(other.is_ok(), "other users are not blocked");
+        drop(guard);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), locks.clone().lock(1))
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn concurrent_inserts_for_one_user_get_contiguous_sequences() {
+        let td = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}/watchbot.db", td.path().display());
+        let pool = db::init_pool_with(&url, 3).await.unwrap();
+        db::run_migrations(&pool).await.unwrap();
+        let uid = db::get_or_create_user(&pool, 15, None, None).await.unwrap();
+        db::open_batch(&pool, uid).await.unwrap();
+        let locks = UserLocks::default();
+
+        let tasks: Vec<_> = (0..2)
+            .map(|i| {
+                let pool = pool.clone();
+                let locks = locks.clone();
+                tokio::spawn(async move {
+                    let _guard = locks.lock(uid).await;
+                    let batch_id = db::current_open_batch_id(&pool, uid).await.unwrap();
+                    tokio::task::yield_now().await;
+                    db::insert_resource(
+                        &pool,
+                        uid,
+                        batch_id,
+                        ResourceKind::Text,
+                        &format!("m{}", i),
+                        i,
+                    )
+                    .await
+                    .unwrap()
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let mut sequences: Vec<i64> =
+            sqlx::query_scalar("SELECT sequence FROM resources WHERE user_id = ?")
+                .bind(uid)
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        sequences.sort();
+        assert_eq!(sequences, vec![1, 2]);
+    }
+
+    #[test]
+    fn stats_lists_counts_by_state_and_kind() {
+        let stats = db::Stats {
+            users: 2,
+            batches_by_state: vec![("COMMITTED".into(), 3), ("OPEN".into(), 1)],
+            resources_by_kind: Vec::new(),
+            pending_outbox: 5,
+            dead_letters: 0,
+        };
+        assert_eq!(
+            format_stats(&stats),
+            "Users: 2\nBatches: COMMITTED 3, OPEN 1\nResources: none\nPending sync: 5\nDead letters: 0"
+        );
+    }
+
+    #[tokio::test]
+    async fn summary_mode_counts_items_in_commit_reply() {
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        db::run_migrations(&pool).await.unwrap();
+        let uid = db::get_or_create_user(&pool, 103, None, None)
+            .await
+            .unwrap();
+        let batch_id = db::open_batch(&pool, uid).await.unwrap();
+        for i in 1..=3 {
+            db::insert_resource(&pool, uid, Some(batch_id), ResourceKind::Text, "m", i)
+                .await
+                .unwrap();
+        }
+        db::commit_batch(&pool, uid, None).await.unwrap();
+
+        let mut opts = HandlerOptions {
+            ack_mode: AckMode::Summary,
+            ..HandlerOptions::default()
+        };
+        assert_eq!(
+            commit_reply(&pool, &opts, batch_id, "Trip").await.unwrap(),
+            "Committed batch with title: Trip (3 items)"
+        );
+        for mode in [AckMode::Each, AckMode::Silent] {
+            opts.ack_mode = mode;
+            assert_eq!(
+                commit_reply(&pool, &opts, batch_id, "Trip").await.unwrap(),
+                "Committed batch with title: Trip"
+            );
+        }
+        assert_eq!(
+            format_commit_reply("One", Some(1)),
+            "Committed batch with title: One (1 item)"
+        );
+    }
+
+    #[tokio::test]
+    async fn full_batch_refuses_the_next_item_and_stays_open() {
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        db::run_migrations(&pool).await.unwrap();
+        let uid = db::get_or_create_user(&pool, 21, None, None).await.unwrap();
+        let opts = HandlerOptions {
+            max_batch_items: Some(2),
+            ..HandlerOptions::default()
+        };
+        assert_eq!(full_batch_count(&pool, &opts, uid).await.unwrap(), None);
+
+        let batch_id = db::open_batch(&pool, uid).await.unwrap();
+        for i in 1..=2 {
+            assert_eq!(full_batch_count(&pool, &opts, uid).await.unwrap(), None);
+            db::insert_resource(&pool, uid, Some(batch_id), ResourceKind::Text, "m", i)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(full_batch_count(&pool, &opts, uid).await.unwrap(), Some(2));
+        assert_eq!(
+            db::current_open_batch_id(&pool, uid).await.unwrap(),
+            Some(batch_id)
+        );
+        let unlimited = HandlerOptions {
+            max_batch_items: None,
+            ..opts
+        };
+        assert_eq!(
+            full_batch_count(&pool, &unlimited, uid).await.unwrap(),
+            None
+        );
+    }
+
+    fn shared_message(fields: serde_json::Value) -> Message {
+        let mut value = serde_json::json!({
+            "message_id": 8,
+            "date": 1700000000,
+            "chat": { "id": 77, "type": "private", "first_name": "A" },
+            "from": { "id": 77, "is_bot": false, "first_name": "A" }
+        });
+        value
+            .as_object_mut()
+            .unwrap()
+            .extend(fields.as_object().unwrap().clone());
+        serde_json::from_value(value).unwrap()
+    }
+
+    /// Records replies instead of sending them; downloads fail unless
+    /// `download_path` is set.
+    #[derive(Default)]
+    struct RecordingResponder {
+        sent: Mutex<Vec<(i64, String)>>,
+        /// Path every download pretends to have written.
+        download_path: Option<String>,
+        /// Fail every download as if Telegram had expired the file.
+        expired_files: bool,
+        /// Number of downloads attempted.
+        fetches: Mutex<usize>,
+        /// Text and callback data of each message sent with buttons.
+        choices: Mutex<Vec<(String, Vec<String>)>>,
+        edits: Mutex<Vec<(i32, String)>>,
+        answered: Mutex<Vec<String>>,
+    }
+
+    impl RecordingResponder {
+        /// Replies recorded since the last call.
+        fn take(&self) -> Vec<String> {
+            let sent = std::mem::take(&mut *self.sent.lock().unwrap());
+            sent.into_iter().map(|(_, text)| text).collect()
+        }
+    }
+
+    #[async_trait]
+    impl Responder for RecordingResponder {
+        async fn send_text<T: Into<String> + Send>(&self, chat_id: ChatId, text: T) -> Result<()> {
+            self.sent.lock().unwrap().push((chat_id.0, text.into()));
+            Ok(())
+        }
+
+        async fn send_choice(
+            &self,
+            _chat_id: ChatId,
+            text: &str,
+            buttons: &[(&str, String)],
+        ) -> Result<()> {
+            let data = buttons.iter().map(|(_, data)| data.clone()).collect();
+            self.choices.lock().unwrap().push((text.to_string(), data));
+            Ok(())
+        }
+
+        async fn edit_text(
+            &self,
+            _chat_id: ChatId,
+            message_id: MessageId,
+            text: &str,
+        ) -> Result<()> {
+            self.edits
+                .lock()
+                .unwrap()
+                .push((message_id.0, text.to_string()));
+            Ok(())
+        }
+
+        async fn answer_callback(&self, callback_id: &str) -> Result<()> {
+            self.answered.lock().unwrap().push(callback_id.to_string());
+            Ok(())
+        }
+
+        async fn fetch_file(
+            &self,
+            file_id: &str,
+            _dir: &str,
+            _msg_id: i32,
+            _file_name: Option<&str>,
+        ) -> Result<String> {
+            *self.fetches.lock().unwrap() += 1;
+            if self.expired_files {
+                return Err(MediaExpired {
+                    file_id: file_id.to_string(),
+                }
+                .into());
+            }
+            self.download_path
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("downloads are not available in tests"))
+        }
+    }
+
+    fn text_message(message_id: i32, text: &str) -> Message {
+        shared_message(serde_json::json!({ "message_id": message_id, "text": text }))
+    }
+
+    #[tokio::test]
+    async fn captioned_photo_is_one_resource_with_text_and_media() {
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        db::run_migrations(&pool).await.unwrap();
+        let opts = HandlerOptions::default();
+        let bot = RecordingResponder {
+            download_path: Some("/data/media/77/9_ph.jpg".into()),
+            ..RecordingResponder::default()
+        };
+        let photo = serde_json::json!([{
+            "file_id": "f", "file_unique_id": "ph", "file_size": 10, "width": 1, "height": 1
+        }]);
+        let msg = shared_message(serde_json::json!({
+            "message_id": 9, "caption": "Sunset", "photo": photo.clone()
+        }));
+        handle_update(&bot, &pool, &opts, &msg).await.unwrap();
+        assert_eq!(bot.take(), vec!["Saved photo."]);
+
+        let edited = shared_message(serde_json::json!({
+            "message_id": 9, "caption": "Sunset at the pier", "photo": photo,
+            "edit_date": 1700000100
+        }));
+        handle_edit(&bot, &pool, &opts, &edited).await.unwrap();
+
+        let rows: Vec<(String, String, Option<String>)> =
+            sqlx::query_as("SELECT kind, content, text FROM resources")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(
+            rows,
+            vec![(
+                "photo".to_string(),
+                "/data/media/77/9_ph.jpg".to_string(),
+                Some("Sunset at the pier".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn classifies_stale_file_errors_as_expired() {
+        assert!(is_expired_file(&RequestError::Api(ApiError::FileIdInvalid)));
+        assert!(is_expired_file(&RequestError::Api(ApiError::WrongFileId)));
+        assert!(is_expired_file(&RequestError::Api(ApiError::Unknown(
+            "Bad Request: file not found".into()
+        ))));
+        assert!(is_expired_file(&RequestError::Api(ApiError::Unknown(
+            "Bad Request: FILE_REFERENCE_EXPIRED".into()
+        ))));
+        assert!(!is_expired_file(&RequestError::Api(ApiError::Unknown(
+            "Bad Request: file is too big".into()
+        ))));
+        assert!(!is_expired_file(&RequestError::RetryAfter(
+            Duration::from_secs(3)
+        )));
+    }
+
+    #[tokio::test]
+    async fn expired_media_asks_to_resend_and_saves_nothing() {
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        db::run_migrations(&pool).await.unwrap();
+        let td = tempfile::tempdir().unwrap();
+        let opts = HandlerOptions {
+            data_dir: td.path().display().to_string(),
+            ..HandlerOptions::default()
+        };
+        let bot = RecordingResponder {
+            expired_files: true,
+            ..RecordingResponder::default()
+        };
+        let msg = shared_message(serde_json::json!({
+            "message_id": 4, "photo": [{
+                "file_id": "old", "file_unique_id": "ph", "file_size": 10, "width": 1, "height": 1
+            }]
+        }));
+
+        handle_update(&bot, &pool, &opts, &msg).await.unwrap();
+        assert_eq!(bot.take(), vec!["Media expired, please resend."]);
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM resources")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[tokio::test]
+    async fn downloaded_media_is_reused_instead_of_fetched_again() {
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        db::run_migrations(&pool).await.unwrap();
+        let td = tempfile::tempdir().unwrap();
+        let data_dir = td.path().display().to_string();
+        let media_dir = format!("{}/media/77/", data_dir);
+        std::fs::create_dir_all(&media_dir).unwrap();
+        std::fs::write(format!("{}4_ph.part", media_dir), b"partial").unwrap();
+        std::fs::write(format!("{}4_ph.jpg", media_dir), b"jpeg").unwrap();
+        let opts = HandlerOptions {
+            data_dir,
+            ..HandlerOptions::default()
+        };
+        // The file id has expired, but the earlier download is still on disk
+        let bot = RecordingResponder {
+            expired_files: true,
+            ..RecordingResponder::default()
+        };
+        let msg = shared_message(serde_json::json!({
+            "message_id": 4, "photo": [{
+                "file_id": "old", "file_unique_id": "ph", "file_size": 10, "width": 1, "height": 1
+            }]
+        }));
+
+        handle_update(&bot, &pool, &opts, &msg).await.unwrap();
+        assert_eq!(bot.take(), vec!["Saved photo."]);
+        assert_eq!(*bot.fetches.lock().unwrap(), 0);
+        let content: String = sqlx::query_scalar("SELECT content FROM resources")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(content, format!("{}4_ph.jpg", media_dir));
+    }
+
+    #[tokio::test]
+    async fn same_message_id_in_another_chat_is_saved() {
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        db::run_migrations(&pool).await.unwrap();
+        let opts = HandlerOptions::default();
+        let bot = RecordingResponder::default();
+        let in_group = shared_message(serde_json::json!({
+            "message_id": 5, "text": "hi",
+            "chat": { "id": -100, "type": "group", "title": "G" }
+        }));
+
+        handle_update(&bot, &pool, &opts, &text_message(5, "hi"))
+            .await
+            .unwrap();
+        handle_update(&bot, &pool, &opts, &in_group).await.unwrap();
+        // Only a re-delivery in the same chat is ignored
+        handle_update(&bot, &pool, &opts, &in_group).await.unwrap();
+        assert_eq!(bot.take(), vec!["Saved.", "Saved."]);
+        let chats: Vec<i64> = sqlx::query_scalar("SELECT chat_id FROM resources ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(chats, vec![77, -100]);
+    }
+
+    #[tokio::test]
+    async fn redelivered_photo_is_ignored_before_download() {
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        db::run_migrations(&pool).await.unwrap();
+        let opts = HandlerOptions::default();
+        let bot = RecordingResponder {
+            download_path: Some("/data/media/77/5_ph.jpg".into()),
+            ..RecordingResponder::default()
+        };
+        let photo = |message_id: i32| {
+            shared_message(serde_json::json!({
+                "message_id": message_id, "photo": [{
+                    "file_id": "f", "file_unique_id": "ph", "file_size": 10, "width": 1, "height": 1
+                }]
+            }))
+        };
+
+        handle_update(&bot, &pool, &opts, &photo(5)).await.unwrap();
+        handle_update(&bot, &pool, &opts, &photo(5)).await.unwrap();
+        assert_eq!(bot.take(), vec!["Saved photo."]);
+        assert_eq!(*bot.fetches.lock().unwrap(), 1);
+
+        // The same file sent again in a later message is a new resource
+        handle_update(&bot, &pool, &opts, &photo(6)).await.unwrap();
+        assert_eq!(bot.take(), vec!["Saved photo."]);
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM resources")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[tokio::test]
+    async fn commands_reply_and_drive_the_batch_lifecycle() {
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        db::run_migrations(&pool).await.unwrap();
+        let opts = HandlerOptions::default();
+        let bot = RecordingResponder::default();
+        let mut next_id = 0;
+        let mut send = |text: &str| {
+            next_id += 1;
+            text_message(next_id, text)
+        };
+
+        handle_update(&bot, &pool, &opts, &send("/ping"))
+            .await
+            .unwrap();
+        handle_update(&bot, &pool, &opts, &send("/nope"))
+            .await
+            .unwrap();
+        handle_update(&bot, &pool, &opts, &send("/commit"))
+            .await
+            .unwrap();
+        let replies = bot.take();
+        assert!(replies[0].starts_with("PONG (db "), "{}", replies[0]);
+        assert!(replies[0].ends_with("ms, 0 pending)"), "{}", replies[0]);
+        assert_eq!(
+            replies[1..],
+            ["Unknown command.", "No open batch to commit."]
+        );
+        let uid = db::find_user_id(&pool, 77).await.unwrap().unwrap();
+        assert_eq!(db::current_open_batch_id(&pool, uid).await.unwrap(), None);
+
+        // Known title: /commit commits straight away
+        handle_update(&bot, &pool, &opts, &send("/begin Trip"))
+            .await
+            .unwrap();
+        let trip = db::current_open_batch_id(&pool, uid)
+            .await
+            .unwrap()
+            .unwrap();
+        handle_update(&bot, &pool, &opts, &send("hello"))
+            .await
+            .unwrap();
+        handle_update(&bot, &pool, &opts, &send("/commit"))
+            .await
+            .unwrap();
+        assert_eq!(
+            bot.take(),
+            vec![
+                "Opened batch: Trip",
+                "Saved (in batch).",
+                "Committed batch with title: Trip"
+            ]
+        );
+        assert_eq!(db::current_open_batch_id(&pool, uid).await.unwrap(), None);
+        assert_eq!(db::count_batch_resources(&pool, trip).await.unwrap(), 1);
+        let state: String = sqlx::query_scalar("SELECT state FROM batches WHERE id = ?")
+            .bind(trip)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(state, "COMMITTED");
+
+        // No title yet: /commit asks for one and the next text is used
+        handle_update(&bot, &pool, &opts, &send("/begin"))
+            .await
+            .unwrap();
+        handle_update(&bot, &pool, &opts, &send("/commit"))
+            .await
+            .unwrap();
+        assert_eq!(
+            db::current_batch_state(&pool, uid).await.unwrap(),
+            Some(BatchState::WaitingTitle)
+        );
+        handle_update(&bot, &pool, &opts, &send("Later"))
+            .await
+            .unwrap();
+        assert_eq!(
+            bot.take(),
+            vec![
+                "Opened batch.",
+                "Please input title (or /cancel to keep editing):",
+                "Committed batch with title: Later"
+            ]
+        );
+
+        handle_update(&bot, &pool, &opts, &send("/begin"))
+            .await
+            .unwrap();
+        let dropped = db::current_open_batch_id(&pool, uid)
+            .await
+            .unwrap()
+            .unwrap();
+        handle_update(&bot, &pool, &opts, &send("/rollback"))
+            .await
+            .unwrap();
+        assert_eq!(bot.take(), vec!["Opened batch.", "Rolled back."]);
+        assert_eq!(db::current_open_batch_id(&pool, uid).await.unwrap(), None);
+        let state: String = sqlx::query_scalar("SELECT state FROM batches WHERE id = ?")
+            .bind(dropped)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(state, "ROLLED_BACK");
+
+        // Commands were not stored as resources
+        let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM resources")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(stored, 1);
+        assert!(bot.sent.lock().unwrap().iter().all(|(chat, _)| *chat == 77));
+    }
+
+    #[tokio::test]
+    async fn ping_replies_when_the_database_is_down() {
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        db::run_migrations(&pool).await.unwrap();
+        pool.close().await;
+        let bot = RecordingResponder::default();
+
+        handle_update(
+            &bot,
+            &pool,
+            &HandlerOptions::default(),
+            &text_message(1, "/ping"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(bot.take(), vec!["PING FAILED: database unavailable"]);
+    }
+
+    fn callback(id: &str, data: &str) -> CallbackQuery {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "from": { "id": 77, "is_bot": false, "first_name": "A" },
+            "chat_instance": "ci",
+            "data": data,
+            "message": shared_message(serde_json::json!({
+                "message_id": 50,
+                "text": "Commit this batch?"
+            })),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn commit_callback_data_round_trips() {
+        for choice in [CommitChoice::Confirm, CommitChoice::Cancel] {
+            let data = commit_callback_data(choice, 12);
+            assert_eq!(parse_commit_callback(&data), Some((choice, 12)));
+        }
+        assert_eq!(parse_commit_callback("commit:maybe:12"), None);
+        assert_eq!(parse_commit_callback("commit:confirm:x"), None);
+        assert_eq!(parse_commit_callback("other"), None);
+    }
+
+    #[tokio::test]
+    async fn confirm_commit_asks_first_and_routes_the_buttons() {
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        db::run_migrations(&pool).await.unwrap();
+        let opts = HandlerOptions {
+            confirm_commit: true,
+            ..HandlerOptions::default()
+        };
+        let bot = RecordingResponder::default();
+        handle_update(&bot, &pool, &opts, &text_message(1, "/begin"))
+            .await
+            .unwrap();
+        handle_update(&bot, &pool, &opts, &text_message(2, "item"))
+            .await
+            .unwrap();
+        let uid = db::find_user_id(&pool, 77).await.unwrap().unwrap();
+        let batch_id = db::current_open_batch_id(&pool, uid)
+            .await
+            .unwrap()
+            .unwrap();
+
+        handle_update(&bot, &pool, &opts, &text_message(3, "/commit"))
+            .await
+            .unwrap();
+        let confirm = commit_callback_data(CommitChoice::Confirm, batch_id);
+        let cancel = commit_callback_data(CommitChoice::Cancel, batch_id);
+        assert_eq!(
+            bot.choices.lock().unwrap().clone(),
+            vec![(
+                "Commit this batch of 1 item?".to_string(),
+                vec![confirm.clone(), cancel.clone()]
+            )]
+        );
+        assert_eq!(
+            db::current_batch_state(&pool, uid).await.unwrap(),
+            Some(BatchState::Open)
+        );
+
+        // Cancel keeps the batch open
+        handle_callback(&bot, &pool, &opts, &callback("cb1", &cancel))
+            .await
+            .unwrap();
+        assert_eq!(
+            db::current_batch_state(&pool, uid).await.unwrap(),
+            Some(BatchState::Open)
+        );
+
+        // Confirm moves on to asking for the title
+        handle_callback(&bot, &pool, &opts, &callback("cb2", &confirm))
+            .await
+            .unwrap();
+        assert_eq!(
+            db::current_batch_state(&pool, uid).await.unwrap(),
+            Some(BatchState::WaitingTitle)
+        );
+
+        // A second press on the old prompt changes nothing
+        handle_callback(&bot, &pool, &opts, &callback("cb3", &confirm))
+            .await
+            .unwrap();
+        assert_eq!(
+            db::current_batch_state(&pool, uid).await.unwrap(),
+            Some(BatchState::WaitingTitle)
+        );
+
+        assert_eq!(
+            bot.edits.lock().unwrap().clone(),
+            vec![
+                (50, "Commit cancelled, batch still open.".to_string()),
+                (
+                    50,
+                    "Please input title (or /cancel to keep editing):".to_string()
+                ),
+                (50, "This batch is no longer open.".to_string()),
+            ]
+        );
+        assert_eq!(
+            bot.answered.lock().unwrap().clone(),
+            vec!["cb1", "cb2", "cb3"]
+        );
+        // Only the item ack went out as a plain message besides /begin
+        assert_eq!(bot.take(), vec!["Opened batch.", "Saved (in batch)."]);
+    }
+
+    #[tokio::test]
+    async fn standalone_item_wakes_the_outbox_worker() {
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        db::run_migrations(&pool).await.unwrap();
+        let opts = HandlerOptions::default();
+        let bot = RecordingResponder::default();
+
+        handle_update(&bot, &pool, &opts, &text_message(1, "loose note"))
+            .await
+            .unwrap();
+        tokio::time::timeout(
+            Duration::from_secs(1),
+            opts.outbox_signal.wait(Duration::from_secs(30)),
+        )
+        .await
+        .expect("saving a standalone item did not wake the worker");
+    }
+
+    #[tokio::test]
+    async fn move_reorders_the_open_batch() {
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        db::run_migrations(&pool).await.unwrap();
+        let opts = HandlerOptions::default();
+        let bot = RecordingResponder::default();
+
+        handle_update(&bot, &pool, &opts, &text_message(1, "/move 2 1"))
+            .await
+            .unwrap();
+        for (id, text) in [(2, "/begin"), (3, "alpha"), (4, "beta"), (5, "gamma")] {
+            handle_update(&bot, &pool, &opts, &text_message(id, text))
+                .await
+                .unwrap();
+        }
+        bot.take();
+        for (id, text) in [(6, "/move 3 1"), (7, "/move 1 9"), (8, "/move 1")] {
+            handle_update(&bot, &pool, &opts, &text_message(id, text))
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            bot.take(),
+            vec![
+                "Moved #3 to #1. New order:\n#1 text: gamma\n#2 text: alpha\n#3 text: beta",
+                "No resource #1 or #9 in the open batch.",
+                "Usage: /move <from> <to>",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn silent_mode_sends_no_item_replies() {
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        db::run_migrations(&pool).await.unwrap();
+        let opts = HandlerOptions {
+            ack_mode: AckMode::Silent,
+            ..HandlerOptions::default()
+        };
+        let bot = RecordingResponder::default();
+
+        handle_update(&bot, &pool, &opts, &text_message(1, "loose note"))
+            .await
+            .unwrap();
+        handle_update(&bot, &pool, &opts, &text_message(2, "/ping"))
+            .await
+            .unwrap();
+        let replies = bot.take();
+        assert_eq!(replies.len(), 1);
+        assert!(replies[0].starts_with("PONG"));
+        let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM resources")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(stored, 1);
+    }
+
+    fn media_kind(msg: &Message) -> &MediaKind {
+        match &msg.kind {
+            MessageKind::Common(common) => &common.media_kind,
+            other => panic!("unexpected message kind {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn location_is_saved_with_coordinates_and_map_link() {
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        db::run_migrations(&pool).await.unwrap();
+        let uid = db::get_or_create_user(&pool, 20, None, None).await.unwrap();
+        let opts = HandlerOptions::default();
+        let msg = shared_message(serde_json::json!({
+            "location": { "latitude": 48.8584, "longitude": 2.2945 }
+        }));
+
+        let item = shared_item(media_kind(&msg)).unwrap();
+        let (inserted, batch_id) = insert_shared_item(&pool, &opts, uid, &msg, &item)
+            .await
+            .unwrap();
+        assert!(inserted.fresh);
+        assert_eq!(batch_id, None);
+
+        let res = db::fetch_resource_for_outbox(&pool, inserted.id)
+            .await
+            .unwrap();
+        assert_eq!(res.kind, ResourceKind::Location);
+        assert_eq!(res.content, "48.8584,2.2945");
+        assert_eq!(res.text.as_deref(), Some("48.8584,2.2945"));
+        let url = res.media_url.unwrap();
+        assert!(url.starts_with("https://www.openstreetmap.org/?mlat=48.8584&mlon=2.2945"));
+
+        let again = insert_shared_item(&pool, &opts, uid, &msg, &item)
+            .await
+            .unwrap();
+        assert!(!again.0.fresh, "re-delivery is ignored");
+    }
+
+    #[test]
+    fn poll_and_contact_become_readable_text() {
+        let poll = shared_message(serde_json::json!({
+            "poll": {
+                "id": "p1",
+                "question": "Lunch?",
+                "options": [
+                    { "text": "Pizza", "voter_count": 0 },
+                    { "text": "Sushi", "voter_count": 0 }
+                ],
+                "total_voter_count": 0,
+                "is_closed": false,
+                "is_anonymous": true,
+                "type": "regular",
+                "allows_multiple_answers": false
+            }
+        }));
+        let item = shared_item(media_kind(&poll)).unwrap();
+        assert_eq!(item.kind, ResourceKind::Text);
+        assert_eq!(item.content, "Poll: Lunch?\n- Pizza\n- Sushi");
+
+        let contact = shared_message(serde_json::json!({
+            "contact": { "phone_number": "+15550100", "first_name": "Ann", "last_name": "Lee" }
+        }));
+        let item = shared_item(media_kind(&contact)).unwrap();
+        assert_eq!(item.content, "Contact: Ann Lee (+15550100)");
+        assert_eq!(item.media_url, None);
+    }
+
+    #[test]
+    fn exceeds_limit_is_strict_and_none_is_unlimited() {
+        assert!(!exceeds_limit(1024, Some(1024)));
+        assert!(exceeds_limit(1025, Some(1024)));
+        assert!(!exceeds_limit(0, Some(0)));
+        assert!(!exceeds_limit(u64::MAX, None));
+    }
+
+    #[test]
+    fn videos_rejected_only_when_ffmpeg_missing() {
+        assert_eq!(video_unsupported_reason(FfmpegStatus::Available), None);
+        assert_eq!(
+            video_unsupported_reason(FfmpegStatus::Missing),
+            Some("video support unavailable (ffmpeg not installed)")
+        );
+        assert_eq!(HandlerOptions::default().ffmpeg, FfmpegStatus::Available);
+    }
+
+    #[test]
+    fn empty_allowlist_allows_everyone() {
+        assert!(is_allowed(1, &[]));
+        assert!(is_allowed(-42, &[]));
+    }
+
+    #[test]
+    fn populated_allowlist_filters_users() {
+        let allowed = vec![123456789, 42];
+        assert!(is_allowed(42, &allowed));
+        assert!(is_allowed(123456789, &allowed));
+        assert!(!is_allowed(7, &allowed));
+    }
+
+    #[test]
+    fn permalink_needs_public_username() {
+        assert_eq!(
+            permalink(Some("somechannel"), 42).as_deref(),
+            Some("https://t.me/somechannel/42")
+        );
+        assert_eq!(permalink(None, 42), None);
+        assert_eq!(permalink(Some(""), 42), None);
+    }
+
+    #[test]
+    fn commit_accepts_inline_title() {
+        assert_eq!(
+            parse_command_arg("/commit My Title", "/commit"),
+            Some(Some("My Title"))
+        );
+        assert_eq!(
+            parse_command_arg("/commit   Trip  ", "/commit"),
+            Some(Some("Trip"))
+        );
+        assert_eq!(parse_command_arg("/commit", "/commit"), Some(None));
+        assert_eq!(parse_command_arg("/commit    ", "/commit"), Some(None));
+        assert_eq!(parse_command_arg("/committed", "/commit"), None);
+        assert_eq!(parse_command_arg("hello", "/commit"), None);
+    }
+
+    #[test]
+    fn begin_accepts_optional_name() {
+        assert_eq!(
+            parse_command_arg("/begin Summer trip", "/begin"),
+            Some(Some("Summer trip"))
+        );
+        assert_eq!(parse_command_arg("/begin", "/begin"), Some(None));
+        assert_eq!(parse_command_arg("/beginning", "/begin"), None);
+    }
+
+    #[test]
+    fn whoami_reports_id_username_and_access() {
+        assert_eq!(
+            format_whoami(123, Some("alice"), true),
+            "Telegram user id: 123\nUsername: @alice\nAccess: allowed"
+        );
+        assert_eq!(
+            format_whoami(456, None, false),
+            "Telegram user id: 456\nUsername: (none)\nAccess: not allowed"
+        );
+    }
+
+    #[test]
+    fn history_lists_titles_and_counts() {
+        assert_eq!(format_history(&[]), "No committed batches yet.");
+        let batches = vec![
+            db::BatchSummary {
+                id: 2,
+                title: Some("Trip".into()),
+                state: BatchState::Committed,
+                created_at: "2024-02-01 10:00:00".into(),
+                resource_count: 3,
+            },
+            db::BatchSummary {
+                id: 1,
+                title: None,
+                state: BatchState::Committed,
+                created_at: "2024-01-31 09:00:00".into(),
+                resource_count: 1,
+            },
+        ];
+        assert_eq!(
+            format_history(&batches),
+            "Recent batches:\n2024-02-01 Trip (3 item(s))\n2024-01-31 Untitled (1 item(s))"
+        );
+    }
+
+    #[test]
+    fn get_parses_batch_and_sequence() {
+        assert_eq!(parse_number_pair("12 3"), Some((12, 3)));
+        assert_eq!(parse_number_pair("  12   3 "), Some((12, 3)));
+        assert_eq!(parse_number_pair("12"), None);
+        assert_eq!(parse_number_pair("12 x"), None);
+        assert_eq!(parse_number_pair("12 3 4"), None);
+        let resource = db::ResourceDetail {
+            id: 7,
+            kind: ResourceKind::Photo,
+            text: None,
+            media_name: Some("cat.jpg".into()),
+            notion_page_id: Some("abcd".into()),
+            dead_lettered: false,
+        };
+        assert_eq!(
+            format_resource_detail(12, 3, &resource),
+            "Batch 12 #3 (photo)\ncat.jpg\nStatus: synced: https://www.notion.so/abcd"
+        );
+    }
+
+    #[test]
+    fn find_lists_batch_sequence_and_snippet() {
+        assert_eq!(format_find("cat", &[]), "No resources match \"cat\".");
+        let matches = vec![
+            db::ResourceMatch {
+                id: 9,
+                batch_title: Some("Trip".into()),
+                sequence: 4,
+                text: "the cat\nsat".into(),
+            },
+            db::ResourceMatch {
+                id: 3,
+                batch_title: None,
+                sequence: 1,
+                text: format!("cat {}", "x".repeat(80)),
+            },
+        ];
+        assert_eq!(
+            format_find("cat", &matches),
+            format!(
+                "Matches for \"cat\":\nTrip #4: the cat sat\n(no batch): cat {}…",
+                "x".repeat(56)
+            )
+        );
+    }
+
+    #[test]
+    fn ping_reports_latency_and_pending() {
+        assert_eq!(
+            format_ping(Duration::from_micros(2_400), 3),
+            "PONG (db 2ms, 3 pending)"
+        );
+    }
+
+    #[test]
+    fn status_reports_batch_and_pending() {
+        assert_eq!(
+            format_status(Some((BatchState::Open, 3)), 2),
+            "Batch: OPEN (3 item(s))\nPending sync: 2 task(s)"
+        );
+        assert_eq!(format_status(None, 0), "Pending sync: 0 task(s)");
+    }
+
+    #[test]
+    fn validation_report_lists_problems() {
+        assert_eq!(
+            format_validation_report(3, &[]),
+            "Batch OK: 3 item(s) ready to commit."
+        );
+        let report = format_validation_report(1, &[validate::Problem::TitleTooLong { len: 2001 }]);
+        assert_eq!(
+            report,
+            "Found 1 problem(s):\n- title is 2001 characters (limit 2000)"
+        );
+    }
+}
@@ -0,0 +1,173 @@
+//! Optional HTTP endpoint exposing liveness and outbox metrics.
+//!
+//! - `GET /healthz`: 200 when the database answers, 503 otherwise.
+//! - `GET /metrics`: JSON snapshot of the outbox queue and, when the worker
+//!   runs in this process, its Notion circuit breaker.
+
+use crate::breaker::{BreakerState, CircuitBreaker};
+use crate::db::{self, Pool};
+use anyhow::Result;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde::Serialize;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct Metrics {
+    pub remaining_outbox_tasks: i64,
+    pub last_processed_outbox_id: i64,
+    pub dead_letter_count: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notion_circuit: Option<BreakerState>,
+}
+
+/// Breaker shared between the outbox worker and the health server.
+pub type SharedBreaker = Arc<Mutex<CircuitBreaker>>;
+
+pub async fn collect_metrics(pool: &Pool, breaker: Option<&SharedBreaker>) -> Result<Metrics> {
+    Ok(Metrics {
+        remaining_outbox_tasks: db::count_remaining_outbox_tasks(pool).await?,
+        last_processed_outbox_id: db::get_last_processed_outbox_id(pool).await?,
+        dead_letter_count: db::count_dead_letters(pool).await?,
+        notion_circuit: breaker.map(|b| b.lock().expect("breaker lock").state()),
+    })
+}
+
+/// Route a request to the matching endpoint.
+pub async fn handle(
+    pool: &Pool,
+    breaker: Option<&SharedBreaker>,
+    method: &Method,
+    path: &str,
+) -> Response<Body> {
+    match (method, path) {
+        (&Method::GET, "/healthz") => match sqlx::query("SELECT 1").execute(pool).await {
+            Ok(_) => text(StatusCode::OK, "ok"),
+            Err(err) => {
+                warn!(?err, "health check failed");
+                text(StatusCode::SERVICE_UNAVAILABLE, "database unavailable")
+            }
+        },
+        (&Method::GET, "/metrics") => match collect_metrics(pool, breaker).await {
+            Ok(metrics) => Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-Type", "application/json")
+                .body(Body::from(
+                    serde_json::to_string(&metrics).unwrap_or_default(),
+                ))
+                .expect("valid response"),
+            Err(err) => {
+                warn!(?err, "failed to collect metrics");
+                text(StatusCode::SERVICE_UNAVAILABLE, "metrics unavailable")
+            }
+        },
+        _ => text(StatusCode::NOT_FOUND, "not found"),
+    }
+}
+
+/// Serve the endpoints on `0.0.0.0:{port}` until the process exits.
+pub async fn serve(pool: Pool, breaker: Option<SharedBreaker>, port: u16) -> Result<()> {
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let make_svc = make_service_fn(move |_conn| {
+        let pool = pool.clone();
+        let breaker = breaker.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let pool = pool.clone();
+                let breaker = breaker.clone();
+                async move {
+                    Ok::<_, Infallible>(
+                        handle(&pool, breaker.as_ref(), req.method(), req.uri().path()).await,
+                    )
+                }
+            }))
+        }
+    });
+    info!(%addr, "starting health server");
+    Server::try_bind(&addr)?.serve(make_svc).await?;
+    Ok(())
+}
+
+fn text(status: StatusCode, body: &'static str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "text/plain")
+        .body(Body::from(body))
+        .expect("valid response")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::ResourceKind;
+
+    async fn setup_pool() -> Pool {
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        pool
+    }
+
+    async fn body_string(res: Response<Body>) -> String {
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn healthz_ok_with_live_pool() {
+        let pool = setup_pool().await;
+        let res = handle(&pool, None, &Method::GET, "/healthz").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(body_string(res).await, "ok");
+    }
+
+    #[tokio::test]
+    async fn metrics_reports_outbox_counts() {
+        let pool = setup_pool().await;
+        let uid = db::get_or_create_user(&pool, 1, None, None).await.unwrap();
+        db::insert_resource(&pool, uid, None, ResourceKind::Text, "a", 1)
+            .await
+            .unwrap();
+        db::insert_resource(&pool, uid, None, ResourceKind::Text, "b", 2)
+            .await
+            .unwrap();
+        let (oid, ..) = db::next_due_outbox(&pool).await.unwrap().unwrap();
+        db::move_to_dead_letter(&pool, oid, "gave up")
+            .await
+            .unwrap();
+
+        let res = handle(&pool, None, &Method::GET, "/metrics").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body_string(res).await).unwrap();
+        assert_eq!(json["remaining_outbox_tasks"], 1);
+        assert_eq!(json["last_processed_outbox_id"], 0);
+        assert_eq!(json["dead_letter_count"], 1);
+        assert!(json.get("notion_circuit").is_none());
+    }
+
+    #[tokio::test]
+    async fn metrics_reports_breaker_state() {
+        let pool = setup_pool().await;
+        let breaker: SharedBreaker = Arc::new(Mutex::new(CircuitBreaker::new(
+            1,
+            std::time::Duration::from_secs(60),
+        )));
+        breaker
+            .lock()
+            .unwrap()
+            .record_failure(std::time::Instant::now());
+
+        let res = handle(&pool, Some(&breaker), &Method::GET, "/metrics").await;
+        let json: serde_json::Value = serde_json::from_str(&body_string(res).await).unwrap();
+        assert_eq!(json["notion_circuit"], "open");
+    }
+
+    #[tokio::test]
+    async fn unknown_path_is_not_found() {
+        let pool = setup_pool().await;
+        let res = handle(&pool, None, &Method::GET, "/nope").await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -1,7 +1,15 @@
+pub mod backoff;
+pub mod breaker;
+pub mod check;
+pub mod cleanup;
 pub mod config;
 pub mod db;
 pub mod handlers;
+pub mod health;
+pub mod logging;
 pub mod model;
+pub mod notify;
 pub mod notion;
 pub mod outbox;
 pub mod thumbnail;
+pub mod validate;
@@ -0,0 +1,81 @@
+//! Tracing subscriber setup shared by the bot and its binaries.
+
+use anyhow::{anyhow, Result};
+use std::str::FromStr;
+use tracing_subscriber::EnvFilter;
+
+/// Environment variable selecting the log output format.
+pub const LOG_FORMAT_VAR: &str = "LOG_FORMAT";
+
+/// How log lines are written to stderr.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// One human-readable line per event.
+    #[default]
+    Compact,
+    /// One JSON object per event, for log collectors such as Loki or ELK.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "compact" => Ok(LogFormat::Compact),
+            "json" => Ok(LogFormat::Json),
+            other => Err(anyhow!(
+                "unknown log format {:?} (expected \"compact\" or \"json\")",
+                other
+            )),
+        }
+    }
+}
+
+impl LogFormat {
+    /// Format named by `LOG_FORMAT`; compact when unset or empty.
+    pub fn from_env() -> Result<Self> {
+        match std::env::var(LOG_FORMAT_VAR) {
+            Ok(value) if !value.trim().is_empty() => value.parse(),
+            _ => Ok(LogFormat::default()),
+        }
+    }
+}
+
+/// Install the global subscriber, filtered by `RUST_LOG`. Later calls keep
+/// the subscriber installed first.
+pub fn init_tracing(format: LogFormat) {
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_target(false);
+    let _ = match format {
+        LogFormat::Compact => builder.compact().try_init(),
+        LogFormat::Json => builder.json().try_init(),
+    };
+}
+
+/// [`init_tracing`] with the format from `LOG_FORMAT`.
+pub fn init_tracing_from_env() -> Result<()> {
+    init_tracing(LogFormat::from_env()?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_known_formats_and_rejects_others() {
+        assert_eq!("compact".parse::<LogFormat>().unwrap(), LogFormat::Compact);
+        assert_eq!(" JSON ".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        let err = "pretty".parse::<LogFormat>().unwrap_err();
+        assert!(err.to_string().contains("pretty"));
+    }
+
+    #[test]
+    fn init_tracing_does_not_panic_for_any_format() {
+        init_tracing(LogFormat::Json);
+        init_tracing(LogFormat::Compact);
+        tracing::info!("logged after init");
+    }
+}
@@ -1,20 +1,17 @@
 use anyhow::Result;
 use clap::Parser;
 use std::path::PathBuf;
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use teloxide::{
     prelude::*,
     types::{BotCommand, KeyboardButton, KeyboardMarkup, MenuButton},
 };
-use tracing::{error, info};
-
-mod config;
-mod db;
-mod handlers;
-mod model;
-mod notion;
-mod outbox;
-mod thumbnail;
+use tg_watchbot::{
+    breaker, check, cleanup, config, db, handlers, health, logging, notify, notion, outbox,
+    thumbnail,
+};
+use tracing::{error, info, warn};
 
 #[derive(Debug, Parser)]
 #[command(author, version, about)]
@@ -22,17 +19,33 @@ struct Args {
     /// Path to YAML config file
     #[arg(long, default_value = "config.yaml")]
     config: PathBuf,
+
+    /// Ignore the cached Notion property IDs and fetch them again
+    #[arg(long)]
+    refresh_notion_ids: bool,
+
+    /// Check the config, Notion and Telegram access, then exit without starting the bot
+    #[arg(long)]
+    check: bool,
+
+    /// Do not reply to saved items; overrides `app.ack_mode` with `silent`
+    #[arg(long)]
+    quiet: bool,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .with_target(false)
-        .compact()
-        .init();
+    logging::init_tracing_from_env()?;
 
     let args = Args::parse();
+    if args.check {
+        let report = check::run(&args.config).await;
+        print!("{}", report);
+        if !report.passed() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
     let cfg = config::load(Some(&args.config))?;
     cfg.ensure_dirs()?;
 
@@ -40,32 +53,78 @@ async fn main() -> Result<()> {
     let database_url = std::env::var("DATABASE_URL")
         .unwrap_or_else(|_| format!("sqlite://{}/watchbot.db", data_dir));
 
-    let pool = db::init_pool(&database_url).await?;
+    let pool = db::init_pool_with(&database_url, cfg.app.db_max_connections).await?;
     db::run_migrations(&pool).await?;
+    if cfg.app.full_text_search {
+        db::enable_fts(&pool).await?;
+    }
 
-    // Preflight dependency check
-    thumbnail::ensure_ffmpeg_available().await?;
+    // Preflight dependency check; without ffmpeg only video support is lost
+    let ffmpeg = match thumbnail::ensure_ffmpeg_available().await {
+        Ok(()) => thumbnail::FfmpegStatus::Available,
+        Err(err) if cfg.app.require_ffmpeg => return Err(err),
+        Err(err) => {
+            warn!(?err, "ffmpeg missing; video messages will be rejected");
+            thumbnail::FfmpegStatus::Missing
+        }
+    };
 
     // Spawn outbox worker (single-threaded)
     let notion_client =
-        notion::NotionClient::new(cfg.notion.token.clone(), cfg.notion.version.clone());
+        notion::NotionClient::new(cfg.notion.token.clone(), cfg.notion.version.clone())
+            .with_upload_retries(cfg.app.upload_retries);
     // Resolve Notion property IDs at startup; builders will use property IDs as keys.
-    let notion_ids = notion_client.resolve_property_ids(&cfg).await?;
+    let notion_ids = notion_client
+        .resolve_property_ids_cached(&cfg, args.refresh_notion_ids)
+        .await?;
+    let notion_routes = notion_client
+        .resolve_route_ids_cached(&cfg, args.refresh_notion_ids)
+        .await?;
     let worker_pool = pool.clone();
     let poll_sleep = Duration::from_millis(cfg.app.poll_interval_ms);
-    let max_backoff = cfg.app.max_backoff_seconds as i64;
+    let worker_opts = outbox::WorkerOptions {
+        notion_routes: notion_routes.clone(),
+        ..outbox::WorkerOptions::from_config(&cfg)
+    };
     let worker_client = notion_client.clone();
     let worker_ids = notion_ids.clone();
+    let breaker: health::SharedBreaker = Arc::new(Mutex::new(breaker::CircuitBreaker::new(
+        cfg.app.breaker_threshold,
+        Duration::from_secs(cfg.app.breaker_cooldown_seconds),
+    )));
+    let worker_breaker = breaker.clone();
+    let outbox_signal = outbox::OutboxSignal::default();
+    let worker_signal = outbox_signal.clone();
+    let poll_strategy = cfg.app.poll_strategy;
     tokio::spawn(async move {
         loop {
-            match outbox::process_next_task(&worker_pool, &worker_client, &worker_ids, max_backoff)
+            let wait = worker_breaker
+                .lock()
+                .expect("breaker lock")
+                .wait_time(Instant::now());
+            if let Some(wait) = wait {
+                tokio::time::sleep(wait).await;
+                continue;
+            }
+            match outbox::run_next_task(&worker_pool, &worker_client, &worker_ids, &worker_opts)
                 .await
             {
-                Ok(processed) => {
-                    if !processed {
-                        tokio::time::sleep(poll_sleep).await;
-                    }
-                }
+                Ok(outbox::TaskOutcome::Idle) => match poll_strategy {
+                    config::PollStrategy::Interval => tokio::time::sleep(poll_sleep).await,
+                    config::PollStrategy::Notify => worker_signal.wait(poll_sleep).await,
+                },
+                Ok(outbox::TaskOutcome::Succeeded) => worker_breaker
+                    .lock()
+                    .expect("breaker lock")
+                    .record_success(),
+                // A dead-lettered task says nothing about whether Notion is up.
+                Ok(outbox::TaskOutcome::Rejected) => {}
+                Ok(outbox::TaskOutcome::Failed) => worker_breaker
+                    .lock()
+                    .expect("breaker lock")
+                    .record_failure(Instant::now()),
+                // Nor does a local failure such as a missing file.
+                Ok(outbox::TaskOutcome::FailedLocally) => {}
                 Err(err) => {
                     error!(?err, "outbox worker error");
                     tokio::time::sleep(Duration::from_secs(1)).await;
@@ -74,51 +133,161 @@ async fn main() -> Result<()> {
         }
     });
 
+    if cfg.app.cleanup_after_sync {
+        tokio::spawn(cleanup::run(pool.clone()));
+    }
+
+    if let Some(port) = cfg.app.health_port {
+        let health_pool = pool.clone();
+        tokio::spawn(async move {
+            if let Err(err) = health::serve(health_pool, Some(breaker), port).await {
+                error!(?err, "health server stopped");
+            }
+        });
+    }
+
     let bot = Bot::new(cfg.telegram.bot_token.clone());
+    if cfg.app.notify_on_sync {
+        tokio::spawn(notify::run(bot.clone(), pool.clone()));
+    }
+    let allowed = cfg.telegram.allowed_users.clone();
+    let handler_opts = Arc::new(handlers::HandlerOptions {
+        data_dir,
+        notion_ids: Some(notion_ids),
+        ffmpeg,
+        thumbnail_format: cfg.app.thumbnail_format,
+        max_media_bytes: cfg.app.max_media_bytes,
+        max_batch_items: cfg.app.max_batch_items,
+        global_sequence: cfg.app.global_sequence,
+        full_text_search: cfg.app.full_text_search,
+        notify_on_sync: cfg.app.notify_on_sync,
+        admin_users: cfg.telegram.admin_users.clone(),
+        ack_mode: if args.quiet {
+            config::AckMode::Silent
+        } else {
+            cfg.app.ack_mode
+        },
+        confirm_commit: cfg.app.confirm_commit,
+        rollback_mode: cfg.app.rollback_mode,
+        notion_routes,
+        user_locks: handlers::UserLocks::default(),
+        outbox_signal,
+    });
 
     info!("starting telegram bot");
-    teloxide::repl(bot, move |bot: Bot, msg: Message| {
-        let pool = pool.clone();
-        let data_dir = data_dir.clone();
-        async move {
-            // Show keyboard and register commands only on /start to avoid spamming every message
-            if let Some(text) = msg.text() {
-                if text == "/start" {
-                    bot.set_chat_menu_button()
-                        .chat_id(msg.chat.id)
-                        .menu_button(MenuButton::Default)
-                        .await?;
-
-                    bot.set_my_commands(vec![
-                        BotCommand::new("begin", "Open a new batch"),
-                        BotCommand::new("commit", "Commit current batch (will ask for title)"),
-                        BotCommand::new("rollback", "Rollback current batch"),
-                        BotCommand::new("ping", "Health check"),
-                    ])
-                    .await?;
-
-                    bot.send_message(msg.chat.id, "Please select an action:")
-                        .reply_markup(KeyboardMarkup::new(vec![
-                            vec![
-                                KeyboardButton::new("/begin"),
-                                KeyboardButton::new("/commit"),
-                            ],
-                            vec![
-                                KeyboardButton::new("/ping"),
-                                KeyboardButton::new("/rollback"),
-                            ],
-                        ]))
-                        .await?;
-                }
-            }
+    let handler = dptree::entry()
+        .branch(Update::filter_message().endpoint(on_message))
+        .branch(Update::filter_edited_message().endpoint(on_edited_message))
+        .branch(Update::filter_callback_query().endpoint(on_callback_query));
+    Dispatcher::builder(bot, handler)
+        .dependencies(dptree::deps![pool, handler_opts, Arc::new(allowed)])
+        .default_handler(|_| async {})
+        .enable_ctrlc_handler()
+        .build()
+        .dispatch()
+        .await;
 
-            if let Err(err) = handlers::handle_update(&bot, &pool, &data_dir, &msg).await {
-                error!(?err, "failed to handle update");
-            }
-            respond(())
+    Ok(())
+}
+
+async fn on_message(
+    bot: Bot,
+    msg: Message,
+    pool: db::Pool,
+    handler_opts: Arc<handlers::HandlerOptions>,
+    allowed: Arc<Vec<i64>>,
+) -> ResponseResult<()> {
+    match handlers::reply_whoami(&bot, &msg, &allowed).await {
+        Ok(true) => return respond(()),
+        Ok(false) => {}
+        Err(err) => error!(?err, "failed to answer /whoami"),
+    }
+    if let Some(from) = msg.from() {
+        if !handlers::is_allowed(from.id.0 as i64, &allowed) {
+            return respond(());
         }
-    })
-    .await;
+    }
 
-    Ok(())
+    // Show keyboard and register commands only on /start to avoid spamming every message
+    if let Some(text) = msg.text() {
+        if text == "/start" {
+            bot.set_chat_menu_button()
+                .chat_id(msg.chat.id)
+                .menu_button(MenuButton::Default)
+                .await?;
+
+            bot.set_my_commands(vec![
+                BotCommand::new("begin", "Open a new batch: /begin [title]"),
+                BotCommand::new(
+                    "commit",
+                    "Commit current batch: /commit <title>, or asks for title",
+                ),
+                BotCommand::new("rollback", "Rollback current batch"),
+                BotCommand::new("history", "List recently committed batches"),
+                BotCommand::new("find", "Search your saved text: /find <text>"),
+                BotCommand::new("get", "Show one resource: /get <batch> <seq>"),
+                BotCommand::new("move", "Reorder the open batch: /move <from> <to>"),
+                BotCommand::new("resend", "Send the Notion link of your latest synced batch"),
+                BotCommand::new("cancel", "Cancel a pending commit, keep the batch open"),
+                BotCommand::new("validate", "Check current batch before committing"),
+                BotCommand::new("status", "Show batch state and pending sync count"),
+                BotCommand::new("stats", "Bot-wide counts (admins only)"),
+                BotCommand::new("ping", "Health check"),
+                BotCommand::new("whoami", "Show your Telegram user id"),
+            ])
+            .await?;
+
+            bot.send_message(msg.chat.id, "Please select an action:")
+                .reply_markup(KeyboardMarkup::new(vec![
+                    vec![
+                        KeyboardButton::new("/begin"),
+                        KeyboardButton::new("/commit"),
+                    ],
+                    vec![
+                        KeyboardButton::new("/ping"),
+                        KeyboardButton::new("/rollback"),
+                    ],
+                ]))
+                .await?;
+        }
+    }
+
+    if let Err(err) = handlers::handle_update(&bot, &pool, &handler_opts, &msg).await {
+        error!(?err, "failed to handle update");
+    }
+    respond(())
+}
+
+async fn on_edited_message(
+    bot: Bot,
+    msg: Message,
+    pool: db::Pool,
+    handler_opts: Arc<handlers::HandlerOptions>,
+    allowed: Arc<Vec<i64>>,
+) -> ResponseResult<()> {
+    if let Some(from) = msg.from() {
+        if !handlers::is_allowed(from.id.0 as i64, &allowed) {
+            return respond(());
+        }
+    }
+    if let Err(err) = handlers::handle_edit(&bot, &pool, &handler_opts, &msg).await {
+        error!(?err, "failed to handle edited message");
+    }
+    respond(())
+}
+
+async fn on_callback_query(
+    bot: Bot,
+    query: CallbackQuery,
+    pool: db::Pool,
+    handler_opts: Arc<handlers::HandlerOptions>,
+    allowed: Arc<Vec<i64>>,
+) -> ResponseResult<()> {
+    if !handlers::is_allowed(query.from.id.0 as i64, &allowed) {
+        return respond(());
+    }
+    if let Err(err) = handlers::handle_callback(&bot, &pool, &handler_opts, &query).await {
+        error!(?err, "failed to handle callback query");
+    }
+    respond(())
 }
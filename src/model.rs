@@ -1,6 +1,15 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+/// Size and play time Telegram reports for a photo or video.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MediaDimensions {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Seconds; photos have none.
+    pub duration: Option<u32>,
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub enum BatchState {
     Open,
@@ -28,12 +37,29 @@ impl BatchState {
             _ => None,
         }
     }
+
+    /// Allowed batch lifecycle moves. `WaitingTitle -> Open` backs out of `/commit`;
+    /// `Committed -> Open` is [`crate::db::reopen_batch`].
+    pub fn can_transition_to(&self, next: BatchState) -> bool {
+        matches!(
+            (self, next),
+            (BatchState::Open, BatchState::WaitingTitle)
+                | (BatchState::Open, BatchState::Committed)
+                | (BatchState::Open, BatchState::RolledBack)
+                | (BatchState::WaitingTitle, BatchState::Open)
+                | (BatchState::WaitingTitle, BatchState::Committed)
+                | (BatchState::WaitingTitle, BatchState::RolledBack)
+                | (BatchState::Committed, BatchState::Open)
+        )
+    }
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub enum OutboxKind {
     PushBatch,
     PushResource,
+    /// Rewrite the text of an already-synced resource page.
+    UpdateResource,
 }
 
 impl OutboxKind {
@@ -41,6 +67,48 @@ impl OutboxKind {
         match self {
             OutboxKind::PushBatch => "push_batch",
             OutboxKind::PushResource => "push_resource",
+            OutboxKind::UpdateResource => "update_resource",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "push_batch" => Some(OutboxKind::PushBatch),
+            "push_resource" => Some(OutboxKind::PushResource),
+            "update_resource" => Some(OutboxKind::UpdateResource),
+            _ => None,
+        }
+    }
+}
+
+/// What a stored resource holds; decides how the worker pushes it to Notion.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ResourceKind {
+    /// Message text, or a contact/poll summary.
+    Text,
+    Photo,
+    Video,
+    /// Coordinates as `lat,lon`, with a map link as the media URL.
+    Location,
+}
+
+impl ResourceKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ResourceKind::Text => "text",
+            ResourceKind::Photo => "photo",
+            ResourceKind::Video => "video",
+            ResourceKind::Location => "location",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "text" => Some(ResourceKind::Text),
+            "photo" => Some(ResourceKind::Photo),
+            "video" => Some(ResourceKind::Video),
+            "location" => Some(ResourceKind::Location),
+            _ => None,
         }
     }
 }
@@ -71,7 +139,7 @@ pub struct Resource {
     pub id: i64,
     pub user_id: i64,
     pub batch_id: Option<i64>,
-    pub kind: String,
+    pub kind: ResourceKind,
     pub content: String,
     pub tg_message_id: i32,
     pub created_at: DateTime<Utc>,
@@ -86,3 +154,55 @@ pub struct OutboxTask {
     pub attempt: i32,
     pub due_at: DateTime<Utc>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outbox_kind_round_trips() {
+        for kind in [
+            OutboxKind::PushBatch,
+            OutboxKind::PushResource,
+            OutboxKind::UpdateResource,
+        ] {
+            assert_eq!(OutboxKind::parse(kind.as_str()), Some(kind));
+        }
+        assert_eq!(OutboxKind::parse("push_batch"), Some(OutboxKind::PushBatch));
+        assert_eq!(
+            OutboxKind::parse("push_resource"),
+            Some(OutboxKind::PushResource)
+        );
+        assert_eq!(
+            OutboxKind::parse("update_resource"),
+            Some(OutboxKind::UpdateResource)
+        );
+    }
+
+    #[test]
+    fn outbox_kind_rejects_unknown() {
+        assert_eq!(OutboxKind::parse("push_delete"), None);
+        assert_eq!(OutboxKind::parse("PUSH_BATCH"), None);
+        assert_eq!(OutboxKind::parse(""), None);
+    }
+
+    #[test]
+    fn resource_kind_round_trips() {
+        for kind in [
+            ResourceKind::Text,
+            ResourceKind::Photo,
+            ResourceKind::Video,
+            ResourceKind::Location,
+        ] {
+            assert_eq!(ResourceKind::parse(kind.as_str()), Some(kind));
+        }
+        assert_eq!(ResourceKind::parse("photo"), Some(ResourceKind::Photo));
+    }
+
+    #[test]
+    fn resource_kind_rejects_unknown() {
+        assert_eq!(ResourceKind::parse("document"), None);
+        assert_eq!(ResourceKind::parse("Text"), None);
+        assert_eq!(ResourceKind::parse(""), None);
+    }
+}
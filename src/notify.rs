@@ -0,0 +1,89 @@
+//! Tell the committing chat where its batch landed in Notion
+//! (`app.notify_on_sync`). The outbox worker queues a notification when it
+//! creates a batch page; this task sends them.
+
+use crate::db::{self, Notification, Pool};
+use crate::notion::page_url;
+use anyhow::Result;
+use std::time::Duration;
+use teloxide::prelude::*;
+use teloxide::RequestError;
+use tracing::{error, info, warn};
+
+/// How often queued notifications are sent.
+pub const NOTIFY_INTERVAL: Duration = Duration::from_secs(5);
+const NOTIFY_BATCH: i64 = 20;
+
+/// Reply text for a synced batch.
+pub fn notification_text(notification: &Notification) -> String {
+    let url = page_url(&notification.page_id);
+    match notification.batch_title.as_deref() {
+        Some(title) => format!("Synced \"{}\" to Notion: {}", title, url),
+        None => format!("Synced batch to Notion: {}", url),
+    }
+}
+
+/// Send every queued notification and delete it. Telegram rejecting a
+/// message (e.g. the bot was blocked) drops it; a network error leaves it for
+/// the next run. Returns how many were sent.
+pub async fn send_pending(bot: &Bot, pool: &Pool) -> Result<usize> {
+    let mut sent = 0;
+    for notification in db::list_notifications(pool, NOTIFY_BATCH).await? {
+        match bot
+            .send_message(
+                ChatId(notification.chat_id),
+                notification_text(&notification),
+            )
+            .await
+        {
+            Ok(_) => sent += 1,
+            Err(RequestError::Api(err)) => {
+                warn!(
+                    ?err,
+                    batch_id = notification.batch_id,
+                    "dropping sync notification rejected by telegram"
+                );
+            }
+            Err(err) => return Err(err.into()),
+        }
+        db::delete_notification(pool, notification.id).await?;
+    }
+    Ok(sent)
+}
+
+/// Run [`send_pending`] every [`NOTIFY_INTERVAL`] until the process exits.
+pub async fn run(bot: Bot, pool: Pool) {
+    loop {
+        match send_pending(&bot, &pool).await {
+            Ok(0) => {}
+            Ok(sent) => info!(sent, "sent sync notifications"),
+            Err(err) => error!(?err, "sending sync notifications failed"),
+        }
+        tokio::time::sleep(NOTIFY_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notification_text_links_page_and_names_batch() {
+        let mut notification = Notification {
+            id: 1,
+            chat_id: 42,
+            batch_id: 3,
+            batch_title: Some("Trip".into()),
+            page_id: "ab-cd".into(),
+        };
+        assert_eq!(
+            notification_text(&notification),
+            "Synced \"Trip\" to Notion: https://www.notion.so/abcd"
+        );
+        notification.batch_title = None;
+        assert_eq!(
+            notification_text(&notification),
+            "Synced batch to Notion: https://www.notion.so/abcd"
+        );
+    }
+}
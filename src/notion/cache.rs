@@ -0,0 +1,147 @@
+//! On-disk caches for resolved Notion property IDs.
+//!
+//! The default databases are cached at `{data_dir}/notion_ids.json`, the
+//! routes at `{data_dir}/notion_route_ids.json` and the property names
+//! `export_html` queries by at `{data_dir}/notion_export_props.json`. Each file
+//! is keyed by the configured database ids plus a hash of the configured field
+//! names, so any mapping change in `config.yaml` invalidates it.
+
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, Databases};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheFile<T> {
+    key: String,
+    ids: T,
+}
+
+pub fn cache_path(data_dir: &str) -> PathBuf {
+    Path::new(data_dir).join("notion_ids.json")
+}
+
+pub fn routes_cache_path(data_dir: &str) -> PathBuf {
+    Path::new(data_dir).join("notion_route_ids.json")
+}
+
+pub fn export_cache_path(data_dir: &str) -> PathBuf {
+    Path::new(data_dir).join("notion_export_props.json")
+}
+
+/// Cache key for the default databases; see [`databases_key`].
+pub fn cache_key(cfg: &Config) -> String {
+    databases_key(&cfg.notion.databases)
+}
+
+/// Cache key for `notion.routes`: `{chat_id}={databases_key}` per route, in
+/// config order.
+pub fn routes_key(cfg: &Config) -> String {
+    cfg.notion
+        .routes
+        .iter()
+        .map(|route| format!("{}={}", route.chat_id, databases_key(&route.databases)))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// Cache key: `{main_db}:{resource_db}:{hash of configured field names}`.
+pub fn databases_key(dbs: &Databases) -> String {
+    let main = &dbs.main;
+    let res = &dbs.resource;
+    let fields = [
+        main.fields.title.as_str(),
+        main.fields.unique.as_str(),
+        res.fields.relation.as_str(),
+        res.fields.order.as_str(),
+        res.fields.text.as_str(),
+        res.fields.media.as_str(),
+        res.fields.source_url.as_deref().unwrap_or(""),
+        res.fields.date.as_deref().unwrap_or(""),
+        res.fields.kind.as_deref().unwrap_or(""),
+        res.fields.width.as_deref().unwrap_or(""),
+        res.fields.height.as_deref().unwrap_or(""),
+        res.fields.duration.as_deref().unwrap_or(""),
+    ]
+    .join("\u{1f}");
+    format!("{}:{}:{:016x}", main.id, res.id, fnv1a64(fields.as_bytes()))
+}
+
+/// Return the cached ids if the file exists and was written for `key`.
+pub fn load<T: DeserializeOwned>(path: &Path, key: &str) -> Option<T> {
+    let raw = fs::read_to_string(path).ok()?;
+    let cached: CacheFile<T> = serde_json::from_str(&raw).ok()?;
+    (cached.key == key).then_some(cached.ids)
+}
+
+/// Write the cache atomically (temp file + rename).
+pub fn store<T: Serialize>(path: &Path, key: &str, ids: &T) -> Result<()> {
+    let body = serde_json::to_string_pretty(&CacheFile {
+        key: key.to_string(),
+        ids,
+    })?;
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, body).with_context(|| format!("failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("failed to replace {}", path.display()))?;
+    Ok(())
+}
+
+// Stable across Rust versions, unlike `DefaultHasher`.
+fn fnv1a64(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in bytes {
+        hash ^= u64::from(*b);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{self, NotionRoute};
+    use crate::notion::NotionIds;
+    use tempfile::tempdir;
+
+    #[test]
+    fn round_trip_and_invalidation() {
+        let td = tempdir().unwrap();
+        let path = cache_path(&td.path().to_string_lossy());
+        let mut cfg: Config = serde_yaml::from_str(config::example()).unwrap();
+        let key = cache_key(&cfg);
+        let ids = cfg.notion_ids();
+
+        assert!(load::<NotionIds>(&path, &key).is_none());
+        store(&path, &key, &ids).unwrap();
+        assert_eq!(load(&path, &key), Some(ids));
+        assert!(!path.with_extension("json.tmp").exists());
+
+        cfg.notion.databases.resource.fields.text = "Body".into();
+        let changed = cache_key(&cfg);
+        assert_ne!(changed, key);
+        assert!(load::<NotionIds>(&path, &changed).is_none());
+    }
+
+    #[test]
+    fn routes_key_tracks_route_mappings() {
+        let mut cfg: Config = serde_yaml::from_str(config::example()).unwrap();
+        assert_eq!(routes_key(&cfg), "");
+        cfg.notion.routes.push(NotionRoute {
+            chat_id: -1001,
+            databases: cfg.notion.databases.clone(),
+        });
+        let key = routes_key(&cfg);
+        assert!(key.starts_with("-1001=NOTION_MAIN_DATABASE_ID:"));
+
+        cfg.notion.routes[0].databases.resource.fields.media = "Files".into();
+        assert_ne!(routes_key(&cfg), key);
+        cfg.notion.routes[0] = NotionRoute {
+            chat_id: -1002,
+            databases: cfg.notion.databases.clone(),
+        };
+        assert_ne!(routes_key(&cfg), key);
+    }
+}
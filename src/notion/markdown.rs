@@ -0,0 +1,242 @@
+//! Telegram-style inline markdown (`*bold*`, `_italic_`, `` `code` ``,
+//! `[text](url)`) to Notion rich_text runs. Markers without a partner are
+//! kept as literal text, and `\` escapes the next character.
+
+use serde_json::{json, Map, Value};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Style {
+    bold: bool,
+    italic: bool,
+    code: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Run {
+    text: String,
+    style: Style,
+    link: Option<String>,
+}
+
+/// Split `text` into rich_text runs carrying annotations and links.
+pub fn markdown_to_rich_text(text: &str) -> Vec<Value> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut runs = Vec::new();
+    parse(&chars, Style::default(), None, &mut runs);
+    runs.into_iter().map(run_json).collect()
+}
+
+fn parse(chars: &[char], style: Style, link: Option<&str>, runs: &mut Vec<Run>) {
+    let mut plain = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\\' if i + 1 < chars.len() => {
+                plain.push(chars[i + 1]);
+                i += 2;
+                continue;
+            }
+            '*' | '_' => {
+                if let Some(end) = closing(chars, i + 1, c) {
+                    push(runs, &mut plain, style, link);
+                    let mut inner = style;
+                    if c == '*' {
+                        inner.bold = true;
+                    } else {
+                        inner.italic = true;
+                    }
+                    parse(&chars[i + 1..end], inner, link, runs);
+                    i = end + 1;
+                    continue;
+                }
+            }
+            '`' => {
+                if let Some(end) = closing(chars, i + 1, '`') {
+                    push(runs, &mut plain, style, link);
+                    // Code spans are verbatim: no nested markers or escapes
+                    let mut code: String = chars[i + 1..end].iter().collect();
+                    push(
+                        runs,
+                        &mut code,
+                        Style {
+                            code: true,
+                            ..style
+                        },
+                        link,
+                    );
+                    i = end + 1;
+                    continue;
+                }
+            }
+            '[' if link.is_none() => {
+                if let Some((text_end, url_end)) = link_bounds(chars, i) {
+                    push(runs, &mut plain, style, link);
+                    let url: String = chars[text_end + 2..url_end].iter().collect();
+                    parse(&chars[i + 1..text_end], style, Some(&url), runs);
+                    i = url_end + 1;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        plain.push(c);
+        i += 1;
+    }
+    push(runs, &mut plain, style, link);
+}
+
+/// Index of the next unescaped `marker` after `from`, if it closes a
+/// non-empty span.
+fn closing(chars: &[char], from: usize, marker: char) -> Option<usize> {
+    let mut i = from;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if marker != '`' => i += 2,
+            c if c == marker => return (i > from).then_some(i),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// For `[text](url)` starting at `open`, the indexes of `]` and `)`.
+fn link_bounds(chars: &[char], open: usize) -> Option<(usize, usize)> {
+    let text_end = (open + 1..chars.len()).find(|&i| chars[i] == ']')?;
+    if text_end == open + 1 || chars.get(text_end + 1) != Some(&'(') {
+        return None;
+    }
+    let url_end = (text_end + 2..chars.len()).find(|&i| chars[i] == ')')?;
+    (url_end > text_end + 2).then_some((text_end, url_end))
+}
+
+/// Move `text` into a run, merging with the previous one when it looks the same.
+fn push(runs: &mut Vec<Run>, text: &mut String, style: Style, link: Option<&str>) {
+    if text.is_empty() {
+        return;
+    }
+    let text = std::mem::take(text);
+    match runs.last_mut() {
+        Some(last) if last.style == style && last.link.as_deref() == link => {
+            last.text.push_str(&text)
+        }
+        _ => runs.push(Run {
+            text,
+            style,
+            link: link.map(str::to_string),
+        }),
+    }
+}
+
+fn run_json(run: Run) -> Value {
+    let mut text = Map::new();
+    text.insert("content".into(), Value::String(run.text));
+    if let Some(url) = run.link {
+        text.insert("link".into(), json!({ "url": url }));
+    }
+    let mut value = Map::new();
+    value.insert("text".into(), Value::Object(text));
+    if run.style != Style::default() {
+        value.insert(
+            "annotations".into(),
+            json!({
+                "bold": run.style.bold,
+                "italic": run.style.italic,
+                "code": run.style.code,
+            }),
+        );
+    }
+    Value::Object(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_text_is_a_single_unstyled_run() {
+        assert_eq!(
+            markdown_to_rich_text("just text"),
+            vec![json!({ "text": { "content": "just text" } })]
+        );
+        assert!(markdown_to_rich_text("").is_empty());
+    }
+
+    #[test]
+    fn bold_italic_and_code_set_annotations() {
+        let runs = markdown_to_rich_text("a *b* _c_ `d`");
+        assert_eq!(
+            runs,
+            vec![
+                json!({ "text": { "content": "a " } }),
+                json!({ "text": { "content": "b" }, "annotations": { "bold": true, "italic": false, "code": false } }),
+                json!({ "text": { "content": " " } }),
+                json!({ "text": { "content": "c" }, "annotations": { "bold": false, "italic": true, "code": false } }),
+                json!({ "text": { "content": " " } }),
+                json!({ "text": { "content": "d" }, "annotations": { "bold": false, "italic": false, "code": true } }),
+            ]
+        );
+    }
+
+    #[test]
+    fn links_carry_their_url() {
+        let runs = markdown_to_rich_text("see [the docs](https://example.com/a_b) now");
+        assert_eq!(
+            runs,
+            vec![
+                json!({ "text": { "content": "see " } }),
+                json!({ "text": { "content": "the docs", "link": { "url": "https://example.com/a_b" } } }),
+                json!({ "text": { "content": " now" } }),
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_styles_combine() {
+        let runs = markdown_to_rich_text("*bold _both_*");
+        assert_eq!(
+            runs,
+            vec![
+                json!({ "text": { "content": "bold " }, "annotations": { "bold": true, "italic": false, "code": false } }),
+                json!({ "text": { "content": "both" }, "annotations": { "bold": true, "italic": true, "code": false } }),
+            ]
+        );
+        let runs = markdown_to_rich_text("[*bold link*](https://x.y)");
+        assert_eq!(
+            runs,
+            vec![json!({
+                "text": { "content": "bold link", "link": { "url": "https://x.y" } },
+                "annotations": { "bold": true, "italic": false, "code": false }
+            })]
+        );
+    }
+
+    #[test]
+    fn unbalanced_and_escaped_markers_stay_literal() {
+        let literal = |s: &str| vec![json!({ "text": { "content": s } })];
+        assert_eq!(markdown_to_rich_text("2 * 3 = 6"), literal("2 * 3 = 6"));
+        assert_eq!(markdown_to_rich_text("snake_case"), literal("snake_case"));
+        assert_eq!(markdown_to_rich_text("**"), literal("**"));
+        assert_eq!(markdown_to_rich_text("[no url]"), literal("[no url]"));
+        assert_eq!(
+            markdown_to_rich_text(r"\*not bold\*"),
+            literal("*not bold*")
+        );
+        // Crossed markers: the inner `_` has no partner inside the bold span
+        let runs = markdown_to_rich_text("*a _b* c_");
+        assert_eq!(
+            runs,
+            vec![
+                json!({ "text": { "content": "a _b" }, "annotations": { "bold": true, "italic": false, "code": false } }),
+                json!({ "text": { "content": " c_" } }),
+            ]
+        );
+        // Markers inside code are verbatim
+        assert_eq!(
+            markdown_to_rich_text("`*x*`"),
+            vec![
+                json!({ "text": { "content": "*x*" }, "annotations": { "bold": false, "italic": false, "code": true } })
+            ]
+        );
+    }
+}
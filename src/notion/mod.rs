@@ -1,20 +1,123 @@
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
 use reqwest::{Client, StatusCode, Url};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Map, Value};
-use std::any::Any;
+use std::collections::HashMap;
 use std::fmt;
+use std::ops::Range;
 use std::path::Path;
+use std::time::Duration;
 use tokio::fs;
-use tracing::{info, warn};
+use tracing::{info, instrument, warn};
 
-use crate::config::Config;
-use crate::notion::model::RetrieveDatabaseResp;
+use crate::config::{Config, Databases};
+use crate::model::{MediaDimensions, ResourceKind};
+use crate::notion::model::{
+    Block, BlockChildrenResp, QueryResponse, RetrieveDatabaseResp, RetrievePageResp,
+};
 
+pub mod cache;
+pub mod markdown;
 pub mod model;
 
+pub use markdown::markdown_to_rich_text;
+
 const NOTION_API_BASE: &str = "https://api.notion.com/";
+const DEFAULT_UPLOAD_RETRIES: u32 = 2;
+const UPLOAD_RETRY_DELAY: Duration = Duration::from_millis(250);
+/// Notion rejects `single_part` uploads above 20 MB.
+const SINGLE_PART_MAX_BYTES: u64 = 20 * 1024 * 1024;
+const UPLOAD_PART_BYTES: u64 = 10 * 1024 * 1024;
+
+/// A failed Notion API call, classified so callers can tell a request worth
+/// retrying from one that can never succeed. Methods still return
+/// `anyhow::Result`; downcast (or search the error chain) to inspect it.
+#[derive(Debug, thiserror::Error)]
+pub enum NotionError {
+    #[error("rate limited by Notion (retry after {retry_after:?})")]
+    RateLimited { retry_after: Option<Duration> },
+    #[error("notion object not found")]
+    NotFound,
+    #[error("notion rejected the token or lacks access")]
+    Unauthorized,
+    /// 409 `conflict_error`: the object changed while Notion handled the
+    /// request; sending it again usually works. Holds the response body.
+    #[error("notion conflict: {0}")]
+    Conflict(String),
+    /// 400 `validation_error`: Notion refused this request's content, e.g. a
+    /// property value it does not accept. Holds the status and response body.
+    #[error("notion error {0}")]
+    Invalid(String),
+    /// Any other 4xx; holds the status and response body.
+    #[error("notion error {0}")]
+    BadRequest(String),
+    #[error("notion error {0}")]
+    Server(StatusCode),
+    #[error("failed to reach Notion: {0}")]
+    Transport(#[from] reqwest::Error),
+}
+
+impl NotionError {
+    /// Classify a non-2xx response. 403 is reported as [`NotionError::Unauthorized`]
+    /// since it means the integration was not given access to the object.
+    pub fn from_status(status: StatusCode, body: &str, retry_after: Option<Duration>) -> Self {
+        match status {
+            StatusCode::TOO_MANY_REQUESTS => NotionError::RateLimited { retry_after },
+            StatusCode::NOT_FOUND => NotionError::NotFound,
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => NotionError::Unauthorized,
+            StatusCode::CONFLICT => NotionError::Conflict(body.to_string()),
+            StatusCode::BAD_REQUEST if error_code(body).as_deref() == Some("validation_error") => {
+                NotionError::Invalid(format!("{}: {}", status, body))
+            }
+            s if s.is_client_error() => NotionError::BadRequest(format!("{}: {}", s, body)),
+            s => NotionError::Server(s),
+        }
+    }
+
+    /// Consume a non-2xx response, reading `Retry-After` (in seconds) and the body.
+    async fn from_response(res: reqwest::Response) -> Self {
+        let status = res.status();
+        let retry_after = res
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+        let body = res.text().await.unwrap_or_default();
+        warn!("Notion API error - Status: {}, Body: {}", status, body);
+        Self::from_status(status, &body, retry_after)
+    }
+
+    /// Whether sending the same request again right away may succeed.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            NotionError::RateLimited { .. }
+                | NotionError::Conflict(_)
+                | NotionError::Server(_)
+                | NotionError::Transport(_)
+        )
+    }
+
+    /// Whether Notion refused this request itself, so it can never succeed
+    /// while other requests still do. A rejected token, a database that is no
+    /// longer shared or a missing object affect every request until someone
+    /// fixes them, so they are not.
+    pub fn rejects_request(&self) -> bool {
+        matches!(self, NotionError::Invalid(_))
+    }
+}
+
+/// The `code` of a Notion error body such as `{"object": "error", "code": ...}`.
+fn error_code(body: &str) -> Option<String> {
+    #[derive(Deserialize)]
+    struct ErrorBody {
+        code: Option<String>,
+    }
+    serde_json::from_str::<ErrorBody>(body).ok()?.code
+}
 
 #[derive(Clone)]
 pub struct NotionClient {
@@ -22,9 +125,10 @@ pub struct NotionClient {
     base_url: Url,
     token: String,
     version: String,
+    upload_retries: u32,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct NotionIds {
     pub main_db: String,
     pub resource_db: String,
@@ -33,6 +137,33 @@ pub struct NotionIds {
     pub f_res_order: String,
     pub f_res_text: String,
     pub f_res_media: String,
+    #[serde(default)]
+    pub f_res_source_url: Option<String>,
+    #[serde(default)]
+    pub f_res_date: Option<String>,
+    #[serde(default)]
+    pub f_res_kind: Option<String>,
+    #[serde(default)]
+    pub f_res_width: Option<String>,
+    #[serde(default)]
+    pub f_res_height: Option<String>,
+    #[serde(default)]
+    pub f_res_duration: Option<String>,
+}
+
+/// Per-resource values written to optional Notion properties.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResourceMeta {
+    /// Link back to the original Telegram message.
+    pub source_url: Option<String>,
+    /// When the Telegram message was sent.
+    pub tg_date: Option<DateTime<Utc>>,
+    /// Kind of the resource, written as a `select` option.
+    pub kind: Option<ResourceKind>,
+    /// Size and play time of a photo or video, written as numbers.
+    pub dimensions: MediaDimensions,
+    /// Style the text from its markdown (`notion.parse_markdown`).
+    pub parse_markdown: bool,
 }
 
 impl fmt::Debug for NotionClient {
@@ -44,9 +175,22 @@ impl fmt::Debug for NotionClient {
 }
 
 #[async_trait]
-pub trait NotionService: Send + Sync + Any {
-    async fn create_main_page(&self, ids: &NotionIds, title: &str) -> Result<String>;
+pub trait NotionService: Send + Sync {
+    /// See [`build_main_page_request`] for the optional icon and cover.
+    async fn create_main_page(
+        &self,
+        ids: &NotionIds,
+        title: &str,
+        icon_emoji: Option<&str>,
+        cover_url: Option<&str>,
+    ) -> Result<String>;
+
+    /// Id of a page in the main database titled exactly `title`, if any.
+    async fn find_main_page(&self, _ids: &NotionIds, _title: &str) -> Result<Option<String>> {
+        Err(anyhow!("querying databases not supported"))
+    }
 
+    #[allow(clippy::too_many_arguments)]
     async fn create_resource_page(
         &self,
         ids: &NotionIds,
@@ -55,7 +199,59 @@ pub trait NotionService: Send + Sync + Any {
         text: Option<&str>,
         media_name: Option<&str>,
         media_url: Option<&str>,
+        meta: &ResourceMeta,
     ) -> Result<String>;
+
+    /// Upload a local file and return its Notion file upload id.
+    async fn upload_file(&self, _file_path: &Path) -> Result<String> {
+        Err(anyhow!("upload not supported"))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn create_resource_page_with_file_upload(
+        &self,
+        _ids: &NotionIds,
+        _parent_main_page_id: Option<&str>,
+        _order: i64,
+        _text: Option<&str>,
+        _media_name: Option<&str>,
+        _file_upload_id: Option<&str>,
+        _meta: &ResourceMeta,
+    ) -> Result<String> {
+        Err(anyhow!("upload not supported"))
+    }
+
+    /// `files` are `(name, file_upload_id)` pairs, attached in order.
+    async fn create_resource_page_with_file_uploads(
+        &self,
+        _ids: &NotionIds,
+        _parent_main_page_id: Option<&str>,
+        _order: i64,
+        _text: Option<&str>,
+        _files: &[(String, String)],
+        _meta: &ResourceMeta,
+    ) -> Result<String> {
+        Err(anyhow!("upload not supported"))
+    }
+
+    async fn retrieve_page(&self, _page_id: &str) -> Result<RetrievePageResp> {
+        Err(anyhow!("retrieving pages not supported"))
+    }
+
+    /// Append blocks (see [`build_image_block`]) to the body of a page.
+    async fn append_block_children(&self, _block_id: &str, _children: &[Value]) -> Result<()> {
+        Err(anyhow!("appending blocks not supported"))
+    }
+
+    /// Overwrite properties (see [`build_text_properties`]) of an existing page.
+    async fn update_page_properties(&self, _page_id: &str, _properties: &Value) -> Result<()> {
+        Err(anyhow!("updating pages not supported"))
+    }
+
+    /// Replace the cover image of an existing page.
+    async fn update_page_cover(&self, _page_id: &str, _cover: ImageSource<'_>) -> Result<()> {
+        Err(anyhow!("updating pages not supported"))
+    }
 }
 
 impl NotionClient {
@@ -70,45 +266,112 @@ impl NotionClient {
             .no_proxy()
             .build()
             .expect("reqwest client");
+        Self::with_client(http, token, version, base_url)
+    }
+
+    /// Use a caller-built `reqwest::Client`, e.g. one with test timeouts.
+    pub fn with_client(http: Client, token: String, version: String, base_url: Url) -> Self {
         Self {
             http,
             base_url,
             token,
             version,
+            upload_retries: DEFAULT_UPLOAD_RETRIES,
         }
     }
 
+    /// Extra attempts for the file content POST in [`NotionClient::upload_file`].
+    pub fn with_upload_retries(mut self, retries: u32) -> Self {
+        self.upload_retries = retries;
+        self
+    }
+
     /// Resolve property IDs for the configured databases by fetching their
     /// schemas and mapping display names -> property IDs. Returns `NotionIds`
     /// whose `f_*` fields are property IDs (not display names).
     pub async fn resolve_property_ids(&self, cfg: &Config) -> Result<NotionIds> {
-        let _main_db = self
-            .retrieve_database(&cfg.notion.databases.main.id)
+        self.resolve_database_ids(&cfg.notion.databases).await
+    }
+
+    /// Check one configured database pair against Notion and return its ids.
+    /// The fetched schemas are also checked for property types; see
+    /// [`check_schema`].
+    pub async fn resolve_database_ids(&self, dbs: &Databases) -> Result<NotionIds> {
+        let main_db = self
+            .retrieve_database(&dbs.main.id)
             .await
             .context("failed to retrieve main database schema")?;
-        let _res_db = self
-            .retrieve_database(&cfg.notion.databases.resource.id)
+        let res_db = self
+            .retrieve_database(&dbs.resource.id)
             .await
             .context("failed to retrieve resource database schema")?;
+        check_schema(dbs, &main_db, &res_db)?;
 
-        let _lookup = |db: &RetrieveDatabaseResp, name: &str| -> Result<String> {
-            db.properties
-                .get(name)
-                .map(|p| p.id.clone())
-                .ok_or_else(|| {
-                    anyhow!("property '{}' not found in Notion database {}", name, db.id)
-                })
-        };
+        resolve_schema_ids(dbs, &main_db, &res_db)
+    }
+
+    /// Resolve every `notion.routes` entry, keyed by chat id. Each route costs
+    /// two schema requests; see [`NotionClient::resolve_route_ids_cached`].
+    pub async fn resolve_route_ids(&self, cfg: &Config) -> Result<HashMap<i64, NotionIds>> {
+        let mut routes = HashMap::new();
+        for route in &cfg.notion.routes {
+            let ids = self
+                .resolve_database_ids(&route.databases)
+                .await
+                .with_context(|| format!("failed to resolve route for chat {}", route.chat_id))?;
+            routes.insert(route.chat_id, ids);
+        }
+        Ok(routes)
+    }
+
+    /// Like `resolve_property_ids`, but served from `{data_dir}/notion_ids.json`
+    /// when the cache matches the configured databases and field names. A cache
+    /// hit skips the schema type check, since the key pins the field names.
+    /// `refresh` forces a re-fetch.
+    pub async fn resolve_property_ids_cached(
+        &self,
+        cfg: &Config,
+        refresh: bool,
+    ) -> Result<NotionIds> {
+        let path = cache::cache_path(&cfg.app.resolved_data_dir());
+        let key = cache::cache_key(cfg);
+        if !refresh {
+            if let Some(ids) = cache::load(&path, &key) {
+                info!(path=%path.display(), "using cached Notion property IDs");
+                return Ok(ids);
+            }
+        }
+        let ids = self.resolve_property_ids(cfg).await?;
+        if let Err(err) = cache::store(&path, &key, &ids) {
+            warn!(?err, "failed to cache Notion property IDs");
+        }
+        Ok(ids)
+    }
 
-        Ok(NotionIds {
-            main_db: cfg.notion.databases.main.id.clone(),
-            resource_db: cfg.notion.databases.resource.id.clone(),
-            f_main_title: cfg.notion.databases.main.fields.title.clone(),
-            f_rel_parent: cfg.notion.databases.resource.fields.relation.clone(),
-            f_res_order: cfg.notion.databases.resource.fields.order.clone(),
-            f_res_text: cfg.notion.databases.resource.fields.text.clone(),
-            f_res_media: cfg.notion.databases.resource.fields.media.clone(),
-        })
+    /// Like `resolve_route_ids`, but served from
+    /// `{data_dir}/notion_route_ids.json` when the cache matches every
+    /// configured route. `refresh` forces a re-fetch.
+    pub async fn resolve_route_ids_cached(
+        &self,
+        cfg: &Config,
+        refresh: bool,
+    ) -> Result<HashMap<i64, NotionIds>> {
+        if cfg.notion.routes.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let path = cache::routes_cache_path(&cfg.app.resolved_data_dir());
+        let key = cache::routes_key(cfg);
+        if !refresh {
+            if let Some(routes) = cache::load(&path, &key) {
+                info!(path=%path.display(), "using cached Notion route IDs");
+                return Ok(routes);
+            }
+        }
+        let routes = self.resolve_route_ids(cfg).await?;
+        if let Err(err) = cache::store(&path, &key, &routes) {
+            warn!(?err, "failed to cache Notion route IDs");
+        }
+        Ok(routes)
     }
 
     pub fn build_request(&self, body: &Value) -> Result<reqwest::Request> {
@@ -126,6 +389,7 @@ impl NotionClient {
             .context("failed to build Notion request")
     }
 
+    #[instrument(skip_all)]
     async fn execute_create(&self, body: Value) -> Result<String> {
         let request = self.build_request(&body)?;
         info!(url=%request.url(), "=== NOTION API REQUEST ===");
@@ -146,7 +410,7 @@ impl NotionClient {
             .http
             .execute(request)
             .await
-            .context("failed to reach Notion")?;
+            .map_err(NotionError::Transport)?;
 
         info!("=== NOTION API RESPONSE ===");
         info!("Response Status: {}", res.status());
@@ -155,16 +419,8 @@ impl NotionClient {
             info!("  {}: {}", name, value.to_str().unwrap_or("[invalid]"));
         }
 
-        if res.status() == StatusCode::TOO_MANY_REQUESTS {
-            let body = res.text().await.unwrap_or_default();
-            warn!("Rate limited by Notion: {}", body);
-            return Err(anyhow!("received 429 from Notion: {}", body));
-        }
         if !res.status().is_success() {
-            let status = res.status();
-            let body = res.text().await.unwrap_or_default();
-            warn!("Notion API error - Status: {}, Body: {}", status, body);
-            return Err(anyhow!("notion error {}: {}", status, body));
+            return Err(NotionError::from_response(res).await.into());
         }
 
         let response_body = res.text().await.context("failed to read Notion response")?;
@@ -176,11 +432,29 @@ impl NotionClient {
         Ok(payload.id)
     }
 
-    pub async fn create_main_page(&self, ids: &NotionIds, title: &str) -> Result<String> {
-        let body = build_main_page_request(ids, title);
+    pub async fn create_main_page(
+        &self,
+        ids: &NotionIds,
+        title: &str,
+        icon_emoji: Option<&str>,
+        cover_url: Option<&str>,
+    ) -> Result<String> {
+        let body = build_main_page_request(ids, title, icon_emoji, cover_url);
         self.execute_create(body).await
     }
 
+    /// First page of the main database whose title equals `title`.
+    pub async fn find_main_page(&self, ids: &NotionIds, title: &str) -> Result<Option<String>> {
+        let filter = main_title_filter(ids, title);
+        let resp = self
+            .query_database(&ids.main_db, Some(filter), None, None)
+            .await?;
+        Ok(resp
+            .results
+            .iter()
+            .find_map(|page| page.get("id")?.as_str().map(str::to_string)))
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub async fn create_resource_page(
         &self,
@@ -190,6 +464,7 @@ impl NotionClient {
         text: Option<&str>,
         media_name: Option<&str>,
         media_url: Option<&str>,
+        meta: &ResourceMeta,
     ) -> Result<String> {
         let body = build_resource_page_request(
             ids,
@@ -199,6 +474,7 @@ impl NotionClient {
             media_name,
             media_url,
             None,
+            meta,
         );
         self.execute_create(body).await
     }
@@ -212,6 +488,7 @@ impl NotionClient {
         text: Option<&str>,
         media_name: Option<&str>,
         file_upload_id: Option<&str>,
+        meta: &ResourceMeta,
     ) -> Result<String> {
         let body = build_resource_page_request(
             ids,
@@ -221,6 +498,7 @@ impl NotionClient {
             media_name,
             None,
             file_upload_id,
+            meta,
         );
         self.execute_create(body).await
     }
@@ -233,9 +511,16 @@ impl NotionClient {
         order: i64,
         text: Option<&str>,
         files: &[(String, String)], // (name, file_upload_id)
+        meta: &ResourceMeta,
     ) -> Result<String> {
-        let body =
-            build_resource_page_request_with_uploads(ids, parent_main_page_id, order, text, files);
+        let body = build_resource_page_request_with_uploads(
+            ids,
+            parent_main_page_id,
+            order,
+            text,
+            files,
+            meta,
+        );
         self.execute_create(body).await
     }
 
@@ -252,18 +537,157 @@ impl NotionClient {
             .header("Authorization", format!("Bearer {}", self.token))
             .header("Notion-Version", &self.version)
             .send()
-            .await?;
+            .await
+            .map_err(NotionError::Transport)?;
         if !res.status().is_success() {
-            return Err(anyhow::anyhow!(
-                "notion retrieve db error {}: {}",
-                res.status(),
-                res.text().await.unwrap_or_default()
-            ));
+            return Err(anyhow::Error::new(NotionError::from_response(res).await)
+                .context(format!("notion retrieve db {} failed", database_id)));
         }
         Ok(res.json::<RetrieveDatabaseResp>().await?)
     }
 
+    /// One page of a database query. Pass the previous response's
+    /// `next_cursor` as `start_cursor` to continue while `has_more`.
+    #[allow(dead_code)]
+    pub async fn query_database(
+        &self,
+        database_id: &str,
+        filter: Option<Value>,
+        sorts: Option<Value>,
+        start_cursor: Option<&str>,
+    ) -> Result<QueryResponse> {
+        let url = self
+            .base_url
+            .join(&format!("v1/databases/{}/query", database_id))?;
+        let mut body = Map::new();
+        if let Some(filter) = filter {
+            body.insert("filter".into(), filter);
+        }
+        if let Some(sorts) = sorts {
+            body.insert("sorts".into(), sorts);
+        }
+        if let Some(cursor) = start_cursor {
+            body.insert("start_cursor".into(), json!(cursor));
+        }
+        let res = self
+            .http
+            .post(url)
+            .header("Authorization", format!("Bearer {}", self.token))
+            .header("Notion-Version", &self.version)
+            .json(&Value::Object(body))
+            .send()
+            .await
+            .map_err(NotionError::Transport)?;
+        if !res.status().is_success() {
+            return Err(anyhow::Error::new(NotionError::from_response(res).await)
+                .context(format!("notion query database {} failed", database_id)));
+        }
+        Ok(res.json::<QueryResponse>().await?)
+    }
+
+    #[allow(dead_code)]
+    pub async fn retrieve_page(&self, page_id: &str) -> Result<RetrievePageResp> {
+        let url = self.base_url.join(&format!("v1/pages/{}", page_id))?;
+        let res = self
+            .http
+            .get(url)
+            .header("Authorization", format!("Bearer {}", self.token))
+            .header("Notion-Version", &self.version)
+            .send()
+            .await
+            .map_err(NotionError::Transport)?;
+        if !res.status().is_success() {
+            return Err(anyhow::Error::new(NotionError::from_response(res).await)
+                .context(format!("notion retrieve page {} failed", page_id)));
+        }
+        Ok(res.json::<RetrievePageResp>().await?)
+    }
+
+    /// All direct children of a block (or page), following pagination.
+    #[allow(dead_code)]
+    pub async fn list_block_children(&self, block_id: &str) -> Result<Vec<Block>> {
+        let mut blocks = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut url = self
+                .base_url
+                .join(&format!("v1/blocks/{}/children", block_id))?;
+            url.query_pairs_mut().append_pair("page_size", "100");
+            if let Some(c) = &cursor {
+                url.query_pairs_mut().append_pair("start_cursor", c);
+            }
+            let res = self
+                .http
+                .get(url)
+                .header("Authorization", format!("Bearer {}", self.token))
+                .header("Notion-Version", &self.version)
+                .send()
+                .await
+                .map_err(NotionError::Transport)?;
+            if !res.status().is_success() {
+                return Err(anyhow::Error::new(NotionError::from_response(res).await)
+                    .context(format!("notion list block children {} failed", block_id)));
+            }
+            let page = res.json::<BlockChildrenResp>().await?;
+            blocks.extend(page.results);
+            match page.next_cursor.filter(|_| page.has_more) {
+                Some(next) => cursor = Some(next),
+                None => return Ok(blocks),
+            }
+        }
+    }
+
+    pub async fn append_block_children(&self, block_id: &str, children: &[Value]) -> Result<()> {
+        let url = self
+            .base_url
+            .join(&format!("v1/blocks/{}/children", block_id))?;
+        let res = self
+            .http
+            .patch(url)
+            .header("Authorization", format!("Bearer {}", self.token))
+            .header("Notion-Version", &self.version)
+            .json(&json!({ "children": children }))
+            .send()
+            .await
+            .map_err(NotionError::Transport)?;
+        if !res.status().is_success() {
+            return Err(anyhow::Error::new(NotionError::from_response(res).await)
+                .context(format!("notion append block children {} failed", block_id)));
+        }
+        Ok(())
+    }
+
+    pub async fn update_page_properties(&self, page_id: &str, properties: &Value) -> Result<()> {
+        self.update_page(page_id, &json!({ "properties": properties }))
+            .await
+    }
+
+    pub async fn update_page_cover(&self, page_id: &str, cover: ImageSource<'_>) -> Result<()> {
+        self.update_page(page_id, &json!({ "cover": build_file_object(cover) }))
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn update_page(&self, page_id: &str, body: &Value) -> Result<()> {
+        let url = self.base_url.join(&format!("v1/pages/{}", page_id))?;
+        let res = self
+            .http
+            .patch(url)
+            .header("Authorization", format!("Bearer {}", self.token))
+            .header("Notion-Version", &self.version)
+            .json(body)
+            .send()
+            .await
+            .map_err(NotionError::Transport)?;
+        if !res.status().is_success() {
+            return Err(anyhow::Error::new(NotionError::from_response(res).await)
+                .context(format!("notion update page {} failed", page_id)));
+        }
+        Ok(())
+    }
+
     /// Upload a file to Notion using the 3-step process and return the file URL
+    #[instrument(skip_all)]
     pub async fn upload_file<P: AsRef<Path>>(&self, file_path: P) -> Result<String> {
         let file_path = file_path.as_ref();
         let file_name = file_path
@@ -276,13 +700,25 @@ impl NotionClient {
             .await
             .with_context(|| format!("failed to read file: {}", file_path.display()))?;
 
-        // Step 1: Create file upload object
+        // Step 1: Create file upload object; large files go through multi-part mode
+        let content_type = self.get_content_type(file_path);
+        let parts = upload_parts(file_content.len() as u64, UPLOAD_PART_BYTES);
+        let multi_part = file_content.len() as u64 > SINGLE_PART_MAX_BYTES;
         let create_upload_url = self.base_url.join("v1/file_uploads")?;
-        let create_body = json!({
-            "name": file_name,
-            "content_type": self.get_content_type(file_path),
-            "mode": "single_part"
-        });
+        let create_body = if multi_part {
+            json!({
+                "name": file_name,
+                "content_type": content_type,
+                "mode": "multi_part",
+                "number_of_parts": parts.len(),
+            })
+        } else {
+            json!({
+                "name": file_name,
+                "content_type": content_type,
+                "mode": "single_part"
+            })
+        };
 
         let create_res = self
             .http
@@ -293,12 +729,14 @@ impl NotionClient {
             .json(&create_body)
             .send()
             .await
+            .map_err(NotionError::Transport)
             .context("failed to create file upload")?;
 
         if !create_res.status().is_success() {
-            let status = create_res.status();
-            let body = create_res.text().await.unwrap_or_default();
-            return Err(anyhow!("create file upload failed {}: {}", status, body));
+            return Err(
+                anyhow::Error::new(NotionError::from_response(create_res).await)
+                    .context("create file upload failed"),
+            );
         }
 
         let create_response: CreateFileUploadResponse = create_res
@@ -306,63 +744,185 @@ impl NotionClient {
             .await
             .context("failed to parse create upload response")?;
 
-        // Step 2: Send file content
-        let content_type = self.get_content_type(file_path);
-        let form = reqwest::multipart::Form::new().part(
+        // Step 2: Send file content (one POST per part), retrying transient failures
+        // against the same upload id
+        if multi_part {
+            for (idx, range) in parts.iter().enumerate() {
+                let chunk = &file_content[range.start as usize..range.end as usize];
+                self.send_with_retries(
+                    &create_response.upload_url,
+                    file_name,
+                    content_type,
+                    chunk,
+                    Some(idx + 1),
+                )
+                .await?;
+            }
+
+            // Step 3: Multi-part uploads must be completed explicitly
+            let complete_url = self
+                .base_url
+                .join(&format!("v1/file_uploads/{}/complete", create_response.id))?;
+            let complete_res = self
+                .http
+                .post(complete_url)
+                .header("Authorization", format!("Bearer {}", self.token))
+                .header("Notion-Version", &self.version)
+                .send()
+                .await
+                .map_err(NotionError::Transport)
+                .context("failed to complete file upload")?;
+            if !complete_res.status().is_success() {
+                return Err(
+                    anyhow::Error::new(NotionError::from_response(complete_res).await)
+                        .context("complete file upload failed"),
+                );
+            }
+        } else {
+            self.send_with_retries(
+                &create_response.upload_url,
+                file_name,
+                content_type,
+                &file_content,
+                None,
+            )
+            .await?;
+        }
+
+        // The file is now uploaded and ready to be used
+        // Return the file upload ID which can be referenced in page properties
+        info!(
+            "Successfully uploaded file: {} with ID: {}",
+            file_name, create_response.id
+        );
+        Ok(create_response.id)
+    }
+
+    async fn send_with_retries(
+        &self,
+        upload_url: &str,
+        file_name: &str,
+        content_type: &str,
+        content: &[u8],
+        part_number: Option<usize>,
+    ) -> Result<()> {
+        let attempts = self.upload_retries + 1;
+        let mut attempt = 1;
+        loop {
+            match self
+                .send_file_content(upload_url, file_name, content_type, content, part_number)
+                .await
+            {
+                Ok(()) => return Ok(()),
+                Err(SendError::Transient(err)) if attempt < attempts => {
+                    warn!(
+                        ?err,
+                        attempt,
+                        file_name,
+                        ?part_number,
+                        "file content upload failed; retrying"
+                    );
+                    tokio::time::sleep(UPLOAD_RETRY_DELAY * attempt).await;
+                    attempt += 1;
+                }
+                Err(SendError::Transient(err)) | Err(SendError::Fatal(err)) => {
+                    return Err(err.context(format!(
+                        "failed to upload {} after {} attempt(s)",
+                        file_name, attempt
+                    )));
+                }
+            }
+        }
+    }
+
+    async fn send_file_content(
+        &self,
+        upload_url: &str,
+        file_name: &str,
+        content_type: &str,
+        content: &[u8],
+        part_number: Option<usize>,
+    ) -> std::result::Result<(), SendError> {
+        let mut form = reqwest::multipart::Form::new().part(
             "file",
-            reqwest::multipart::Part::bytes(file_content)
+            reqwest::multipart::Part::bytes(content.to_vec())
                 .file_name(file_name.to_string())
-                .mime_str(content_type)?,
+                .mime_str(content_type)
+                .map_err(|e| SendError::Fatal(e.into()))?,
         );
+        if let Some(part_number) = part_number {
+            form = form.text("part_number", part_number.to_string());
+        }
 
         let send_res = self
             .http
-            .post(&create_response.upload_url)
+            .post(upload_url)
             .header("Authorization", format!("Bearer {}", self.token))
             .header("Notion-Version", &self.version)
             .multipart(form)
             .send()
             .await
-            .context("failed to send file content")?;
+            .map_err(|e| {
+                SendError::Transient(
+                    anyhow::Error::new(NotionError::Transport(e))
+                        .context("failed to send file content"),
+                )
+            })?;
 
         if !send_res.status().is_success() {
-            let status = send_res.status();
-            let body = send_res.text().await.unwrap_or_default();
-            return Err(anyhow!("send file failed {}: {}", status, body));
+            let err = NotionError::from_response(send_res).await;
+            let retryable = err.is_retryable();
+            let err = anyhow::Error::new(err).context("send file failed");
+            if retryable {
+                return Err(SendError::Transient(err));
+            }
+            return Err(SendError::Fatal(err));
         }
-
-        // The file is now uploaded and ready to be used
-        // Return the file upload ID which can be referenced in page properties
-        info!(
-            "Successfully uploaded file: {} with ID: {}",
-            file_name, create_response.id
-        );
-        Ok(create_response.id)
+        Ok(())
     }
 
     fn get_content_type(&self, file_path: &Path) -> &'static str {
-        match file_path
-            .extension()
-            .and_then(|ext| ext.to_str())
-            .map(|s| s.to_ascii_lowercase())
-        {
-            Some(ext) if ext == "jpg" || ext == "jpeg" => "image/jpeg",
-            Some(ext) if ext == "png" => "image/png",
-            Some(ext) if ext == "gif" => "image/gif",
-            Some(ext) if ext == "mp4" => "video/mp4",
-            Some(ext) if ext == "mov" => "video/quicktime",
-            Some(ext) if ext == "avi" => "video/x-msvideo",
-            _ => "application/octet-stream",
-        }
+        content_type_for_path(file_path)
+    }
+}
+
+/// Map a file extension to the MIME type sent with Notion uploads.
+pub fn content_type_for_path(file_path: &Path) -> &'static str {
+    match file_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|s| s.to_ascii_lowercase())
+    {
+        Some(ext) if ext == "jpg" || ext == "jpeg" => "image/jpeg",
+        Some(ext) if ext == "png" => "image/png",
+        Some(ext) if ext == "gif" => "image/gif",
+        Some(ext) if ext == "webp" => "image/webp",
+        Some(ext) if ext == "mp4" => "video/mp4",
+        Some(ext) if ext == "mov" => "video/quicktime",
+        Some(ext) if ext == "avi" => "video/x-msvideo",
+        _ => "application/octet-stream",
     }
 }
 
 #[async_trait]
 impl NotionService for NotionClient {
-    async fn create_main_page(&self, ids: &NotionIds, title: &str) -> Result<String> {
-        NotionClient::create_main_page(self, ids, title).await
+    #[instrument(skip_all)]
+    async fn create_main_page(
+        &self,
+        ids: &NotionIds,
+        title: &str,
+        icon_emoji: Option<&str>,
+        cover_url: Option<&str>,
+    ) -> Result<String> {
+        NotionClient::create_main_page(self, ids, title, icon_emoji, cover_url).await
+    }
+
+    #[instrument(skip_all)]
+    async fn find_main_page(&self, ids: &NotionIds, title: &str) -> Result<Option<String>> {
+        NotionClient::find_main_page(self, ids, title).await
     }
 
+    #[instrument(skip_all)]
     async fn create_resource_page(
         &self,
         ids: &NotionIds,
@@ -371,6 +931,7 @@ impl NotionService for NotionClient {
         text: Option<&str>,
         media_name: Option<&str>,
         media_url: Option<&str>,
+        meta: &ResourceMeta,
     ) -> Result<String> {
         NotionClient::create_resource_page(
             self,
@@ -380,12 +941,106 @@ impl NotionService for NotionClient {
             text,
             media_name,
             media_url,
+            meta,
         )
         .await
     }
+
+    #[instrument(skip_all)]
+    async fn upload_file(&self, file_path: &Path) -> Result<String> {
+        NotionClient::upload_file(self, file_path).await
+    }
+
+    #[instrument(skip_all)]
+    async fn create_resource_page_with_file_upload(
+        &self,
+        ids: &NotionIds,
+        parent_main_page_id: Option<&str>,
+        order: i64,
+        text: Option<&str>,
+        media_name: Option<&str>,
+        file_upload_id: Option<&str>,
+        meta: &ResourceMeta,
+    ) -> Result<String> {
+        NotionClient::create_resource_page_with_file_upload(
+            self,
+            ids,
+            parent_main_page_id,
+            order,
+            text,
+            media_name,
+            file_upload_id,
+            meta,
+        )
+        .await
+    }
+
+    #[instrument(skip_all)]
+    async fn create_resource_page_with_file_uploads(
+        &self,
+        ids: &NotionIds,
+        parent_main_page_id: Option<&str>,
+        order: i64,
+        text: Option<&str>,
+        files: &[(String, String)],
+        meta: &ResourceMeta,
+    ) -> Result<String> {
+        NotionClient::create_resource_page_with_file_uploads(
+            self,
+            ids,
+            parent_main_page_id,
+            order,
+            text,
+            files,
+            meta,
+        )
+        .await
+    }
+
+    #[instrument(skip_all)]
+    async fn retrieve_page(&self, page_id: &str) -> Result<RetrievePageResp> {
+        NotionClient::retrieve_page(self, page_id).await
+    }
+
+    #[instrument(skip_all)]
+    async fn append_block_children(&self, block_id: &str, children: &[Value]) -> Result<()> {
+        NotionClient::append_block_children(self, block_id, children).await
+    }
+
+    #[instrument(skip_all)]
+    async fn update_page_properties(&self, page_id: &str, properties: &Value) -> Result<()> {
+        NotionClient::update_page_properties(self, page_id, properties).await
+    }
+
+    #[instrument(skip_all)]
+    async fn update_page_cover(&self, page_id: &str, cover: ImageSource<'_>) -> Result<()> {
+        NotionClient::update_page_cover(self, page_id, cover).await
+    }
 }
 
-pub fn build_main_page_request(ids: &NotionIds, title: &str) -> Value {
+/// Main page body; `icon_emoji` and `cover_url` set the page's top-level
+/// `icon` and `cover` when given.
+pub fn build_main_page_request(
+    ids: &NotionIds,
+    title: &str,
+    icon_emoji: Option<&str>,
+    cover_url: Option<&str>,
+) -> Value {
+    let mut body = json!({
+        "parent": { "database_id": ids.main_db },
+        "properties": build_title_properties(ids, title),
+    });
+    if let Some(emoji) = icon_emoji {
+        body["icon"] = json!({ "type": "emoji", "emoji": emoji });
+    }
+    if let Some(url) = cover_url {
+        body["cover"] = build_file_object(ImageSource::External(url));
+    }
+    body
+}
+
+/// Properties that set a main page's title.
+pub fn build_title_properties(ids: &NotionIds, title: &str) -> Value {
     let mut properties = Map::new();
     properties.insert(
         ids.f_main_title.clone(),
@@ -399,13 +1054,157 @@ pub fn build_main_page_request(ids: &NotionIds, title: &str) -> Value {
             ]
         }),
     );
+    Value::Object(properties)
+}
 
+/// Database query filter matching main pages titled exactly `title`.
+pub fn main_title_filter(ids: &NotionIds, title: &str) -> Value {
     json!({
-        "parent": { "database_id": ids.main_db },
-        "properties": Value::Object(properties),
+        "property": ids.f_main_title,
+        "title": { "equals": title },
     })
 }
 
+/// Notion property type the resource `order` field must have; see [`order_property`].
+pub const ORDER_PROPERTY_TYPE: &str = "number";
+
+/// The resource `order` value, written as a Notion number so it sorts numerically.
+pub fn order_property(order: i64) -> Value {
+    json!({ "number": order })
+}
+
+/// Ensure the configured order property (name or id) exists in the resource
+/// database and has the type the page builders emit.
+pub fn check_order_property(db: &RetrieveDatabaseResp, name_or_id: &str) -> Result<()> {
+    check_property_type(db, "order", name_or_id, ORDER_PROPERTY_TYPE)
+}
+
+/// Property id of the property configured for `field` (by name or id) in
+/// `db`. A missing property is an error listing the names `db` does have.
+pub fn resolve_property_id(
+    db: &RetrieveDatabaseResp,
+    field: &str,
+    name_or_id: &str,
+) -> Result<String> {
+    if let Some((_, prop)) = db
+        .properties
+        .iter()
+        .find(|(name, p)| name.as_str() == name_or_id || p.id == name_or_id)
+    {
+        return Ok(prop.id.clone());
+    }
+    let mut available: Vec<&str> = db.properties.keys().map(String::as_str).collect();
+    available.sort_unstable();
+    Err(anyhow!(
+        "{} property '{}' not found in Notion database {}; available: {}",
+        field,
+        name_or_id,
+        db.id,
+        available.join(", ")
+    ))
+}
+
+/// `NotionIds` for `dbs` whose `f_*` fields are the property ids found in the
+/// `main` and `resource` schemas, whether configured by name or by id.
+pub fn resolve_schema_ids(
+    dbs: &Databases,
+    main: &RetrieveDatabaseResp,
+    resource: &RetrieveDatabaseResp,
+) -> Result<NotionIds> {
+    let rf = &dbs.resource.fields;
+    let optional = |field: &str, configured: &Option<String>| {
+        configured
+            .as_deref()
+            .map(|name_or_id| resolve_property_id(resource, field, name_or_id))
+            .transpose()
+    };
+    Ok(NotionIds {
+        main_db: dbs.main.id.clone(),
+        resource_db: dbs.resource.id.clone(),
+        f_main_title: resolve_property_id(main, "title", &dbs.main.fields.title)?,
+        f_rel_parent: resolve_property_id(resource, "relation", &rf.relation)?,
+        f_res_order: resolve_property_id(resource, "order", &rf.order)?,
+        f_res_text: resolve_property_id(resource, "text", &rf.text)?,
+        f_res_media: resolve_property_id(resource, "media", &rf.media)?,
+        f_res_source_url: optional("source_url", &rf.source_url)?,
+        f_res_date: optional("date", &rf.date)?,
+        f_res_kind: optional("kind", &rf.kind)?,
+        f_res_width: optional("width", &rf.width)?,
+        f_res_height: optional("height", &rf.height)?,
+        f_res_duration: optional("duration", &rf.duration)?,
+    })
+}
+
+/// Ensure the property configured for `field` (by name or id) exists in `db`
+/// and has type `expected`.
+pub fn check_property_type(
+    db: &RetrieveDatabaseResp,
+    field: &str,
+    name_or_id: &str,
+    expected: &str,
+) -> Result<()> {
+    let prop = db
+        .properties
+        .iter()
+        .find(|(name, p)| name.as_str() == name_or_id || p.id == name_or_id)
+        .map(|(_, p)| p)
+        .ok_or_else(|| {
+            anyhow!(
+                "{} property '{}' not found in Notion database {}",
+                field,
+                name_or_id,
+                db.id
+            )
+        })?;
+    if prop.typ != expected {
+        return Err(anyhow!(
+            "{} property '{}' in Notion database {} has type '{}'; change it to '{}'",
+            field,
+            name_or_id,
+            db.id,
+            prop.typ,
+            expected
+        ));
+    }
+    Ok(())
+}
+
+/// Check every mapped field of a database pair against the property types the
+/// page builders emit. The main `unique` field is only used for lookups and may
+/// be of several types, so it is not checked.
+pub fn check_schema(
+    dbs: &Databases,
+    main: &RetrieveDatabaseResp,
+    resource: &RetrieveDatabaseResp,
+) -> Result<()> {
+    let rf = &dbs.resource.fields;
+    check_property_type(main, "title", &dbs.main.fields.title, "title")?;
+    check_property_type(resource, "relation", &rf.relation, "relation")?;
+    check_order_property(resource, &rf.order)?;
+    check_property_type(resource, "text", &rf.text, "rich_text")?;
+    check_property_type(resource, "media", &rf.media, "files")?;
+    if let Some(source_url) = &rf.source_url {
+        check_property_type(resource, "source_url", source_url, "url")?;
+    }
+    if let Some(date) = &rf.date {
+        check_property_type(resource, "date", date, "date")?;
+    }
+    if let Some(kind) = &rf.kind {
+        check_property_type(resource, "kind", kind, "select")?;
+    }
+    for (field, configured) in [
+        ("width", &rf.width),
+        ("height", &rf.height),
+        ("duration", &rf.duration),
+    ] {
+        if let Some(name_or_id) = configured {
+            check_property_type(resource, field, name_or_id, "number")?;
+        }
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn build_resource_page_request(
     ids: &NotionIds,
     parent_main_page_id: Option<&str>,
@@ -414,6 +1213,7 @@ pub fn build_resource_page_request(
     media_name: Option<&str>,
     media_url: Option<&str>,
     file_upload_id: Option<&str>,
+    meta: &ResourceMeta,
 ) -> Value {
     let mut properties = Map::new();
     if let Some(parent_id) = parent_main_page_id {
@@ -423,31 +1223,12 @@ pub fn build_resource_page_request(
         );
     }
 
-    properties.insert(
-        ids.f_res_order.clone(),
-        json!({
-            "title": [
-                {
-                    "text": {
-                        "content": format!("#{}", order),
-                    }
-                }
-            ]
-        }),
-    );
+    properties.insert(ids.f_res_order.clone(), order_property(order));
 
     if let Some(text_content) = text.filter(|t| !t.is_empty()) {
         properties.insert(
             ids.f_res_text.clone(),
-            json!({
-                "rich_text": [
-                    {
-                        "text": {
-                            "content": text_content,
-                        }
-                    }
-                ]
-            }),
+            json!({ "rich_text": rich_text(text_content, meta.parse_markdown) }),
         );
     }
 
@@ -484,6 +1265,8 @@ pub fn build_resource_page_request(
         );
     }
 
+    insert_meta_properties(&mut properties, ids, meta);
+
     json!({
         "parent": { "database_id": ids.resource_db },
         "properties": Value::Object(properties),
@@ -497,6 +1280,7 @@ pub fn build_resource_page_request_with_uploads(
     order: i64,
     text: Option<&str>,
     files: &[(String, String)], // (name, file_upload_id)
+    meta: &ResourceMeta,
 ) -> Value {
     let mut properties = Map::new();
     if let Some(parent_id) = parent_main_page_id {
@@ -506,19 +1290,12 @@ pub fn build_resource_page_request_with_uploads(
         );
     }
 
-    properties.insert(
-        ids.f_res_order.clone(),
-        json!({
-            "title": [ { "text": { "content": format!("#{}", order) } } ]
-        }),
-    );
+    properties.insert(ids.f_res_order.clone(), order_property(order));
 
     if let Some(text_content) = text.filter(|t| !t.is_empty()) {
         properties.insert(
             ids.f_res_text.clone(),
-            json!({
-                "rich_text": [ { "text": { "content": text_content } } ]
-            }),
+            json!({ "rich_text": rich_text(text_content, meta.parse_markdown) }),
         );
     }
 
@@ -536,17 +1313,143 @@ pub fn build_resource_page_request_with_uploads(
         properties.insert(ids.f_res_media.clone(), json!({ "files": files_json }));
     }
 
+    insert_meta_properties(&mut properties, ids, meta);
+
     json!({
         "parent": { "database_id": ids.resource_db },
         "properties": Value::Object(properties),
     })
 }
 
+/// Properties that replace a resource page's text; empty text clears it.
+pub fn build_text_properties(ids: &NotionIds, text: &str, parse_markdown: bool) -> Value {
+    let rich_text = if text.is_empty() {
+        json!([])
+    } else {
+        rich_text(text, parse_markdown)
+    };
+    let mut properties = Map::new();
+    properties.insert(ids.f_res_text.clone(), json!({ "rich_text": rich_text }));
+    Value::Object(properties)
+}
+
+/// `rich_text` runs for `text`: one plain run, or styled runs from its markdown.
+fn rich_text(text: &str, parse_markdown: bool) -> Value {
+    if parse_markdown {
+        Value::Array(markdown_to_rich_text(text))
+    } else {
+        json!([ { "text": { "content": text } } ])
+    }
+}
+
+/// Browser link to a Notion page; Notion's short form drops the id's dashes.
+pub fn page_url(page_id: &str) -> String {
+    format!("https://www.notion.so/{}", page_id.replace('-', ""))
+}
+
+/// Plain text of the page's title property (`title_prop` by name or id).
+pub fn page_title(page: &RetrievePageResp, title_prop: &str) -> Option<String> {
+    let runs = page_property(page, title_prop)?.get("title")?.as_array()?;
+    Some(
+        runs.iter()
+            .filter_map(|run| run.get("plain_text").and_then(Value::as_str))
+            .collect(),
+    )
+}
+
+/// A page property value, looked up by property name or id.
+fn page_property<'a>(page: &'a RetrievePageResp, name_or_id: &str) -> Option<&'a Value> {
+    page.properties
+        .iter()
+        .find(|(name, v)| name.as_str() == name_or_id || v.get("id") == Some(&json!(name_or_id)))
+        .map(|(_, v)| v)
+}
+
+/// Where the content of an image block comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageSource<'a> {
+    External(&'a str),
+    /// A completed Notion file upload id.
+    FileUpload(&'a str),
+}
+
+/// An `image` block for a page body, rendered inline unlike `files` entries.
+pub fn build_image_block(source: ImageSource<'_>) -> Value {
+    json!({
+        "object": "block",
+        "type": "image",
+        "image": build_file_object(source),
+    })
+}
+
+/// Notion file object, as used by image blocks and page covers.
+fn build_file_object(source: ImageSource<'_>) -> Value {
+    match source {
+        ImageSource::External(url) => json!({ "type": "external", "external": { "url": url } }),
+        ImageSource::FileUpload(id) => {
+            json!({ "type": "file_upload", "file_upload": { "id": id } })
+        }
+    }
+}
+
+/// Write optional per-resource properties; each needs both a configured
+/// property and a value.
+fn insert_meta_properties(
+    properties: &mut Map<String, Value>,
+    ids: &NotionIds,
+    meta: &ResourceMeta,
+) {
+    if let (Some(prop), Some(url)) = (
+        ids.f_res_source_url.as_ref(),
+        meta.source_url.as_deref().filter(|u| !u.is_empty()),
+    ) {
+        properties.insert(prop.clone(), json!({ "url": url }));
+    }
+    if let (Some(prop), Some(date)) = (ids.f_res_date.as_ref(), meta.tg_date) {
+        properties.insert(
+            prop.clone(),
+            json!({ "date": { "start": date.to_rfc3339_opts(SecondsFormat::Secs, true) } }),
+        );
+    }
+    if let (Some(prop), Some(kind)) = (ids.f_res_kind.as_ref(), meta.kind) {
+        properties.insert(prop.clone(), json!({ "select": { "name": kind.as_str() } }));
+    }
+    let dims = meta.dimensions;
+    for (prop, value) in [
+        (&ids.f_res_width, dims.width),
+        (&ids.f_res_height, dims.height),
+        (&ids.f_res_duration, dims.duration),
+    ] {
+        if let (Some(prop), Some(value)) = (prop.as_ref(), value) {
+            properties.insert(prop.clone(), json!({ "number": value }));
+        }
+    }
+}
+
 #[derive(Deserialize)]
 struct CreatePageResponse {
     id: String,
 }
 
+/// Byte ranges of the parts a file of `file_size` is split into, `chunk_size`
+/// bytes each except possibly the last. An empty file still has one (empty) part.
+pub fn upload_parts(file_size: u64, chunk_size: u64) -> Vec<Range<u64>> {
+    assert!(chunk_size > 0, "chunk_size must be > 0");
+    if file_size == 0 {
+        return vec![Range { start: 0, end: 0 }];
+    }
+    (0..file_size)
+        .step_by(chunk_size as usize)
+        .map(|start| start..(start + chunk_size).min(file_size))
+        .collect()
+}
+
+/// Outcome of a failed file content POST; only transient failures are retried.
+enum SendError {
+    Transient(anyhow::Error),
+    Fatal(anyhow::Error),
+}
+
 #[derive(Deserialize)]
 struct CreateFileUploadResponse {
     id: String,
@@ -566,18 +1469,47 @@ mod tests {
             f_res_order: "res-order".into(),
             f_res_text: "res-text".into(),
             f_res_media: "res-media".into(),
+            f_res_source_url: None,
+            f_res_date: None,
+            f_res_kind: None,
+            f_res_width: None,
+            f_res_height: None,
+            f_res_duration: None,
         }
     }
 
     #[test]
     fn build_main_page_request_includes_title() {
         let ids = sample_ids();
-        let body = build_main_page_request(&ids, "hello");
+        let body = build_main_page_request(&ids, "hello", None, None);
         assert_eq!(body["parent"]["database_id"], "main-db");
         assert_eq!(
             body["properties"]["main-title"]["title"][0]["text"]["content"],
             "hello"
         );
+        assert!(body.get("icon").is_none());
+        assert!(body.get("cover").is_none());
+    }
+
+    #[test]
+    fn main_title_filter_matches_title_exactly() {
+        let ids = sample_ids();
+        assert_eq!(
+            main_title_filter(&ids, "Trip"),
+            json!({ "property": "main-title", "title": { "equals": "Trip" } })
+        );
+    }
+
+    #[test]
+    fn build_main_page_request_sets_icon_and_cover() {
+        let ids = sample_ids();
+        let body =
+            build_main_page_request(&ids, "hello", Some("📷"), Some("https://cdn/cover.jpg"));
+        assert_eq!(body["icon"], json!({ "type": "emoji", "emoji": "📷" }));
+        assert_eq!(
+            body["cover"],
+            json!({ "type": "external", "external": { "url": "https://cdn/cover.jpg" } })
+        );
     }
 
     #[test]
@@ -591,6 +1523,7 @@ mod tests {
             Some("a.jpg"),
             Some("https://cdn/a.jpg"),
             None,
+            &ResourceMeta::default(),
         );
 
         assert_eq!(body["parent"]["database_id"], "resource-db");
@@ -598,10 +1531,7 @@ mod tests {
             body["properties"]["rel-parent"]["relation"][0]["id"],
             "parent-1"
         );
-        assert_eq!(
-            body["properties"]["res-order"]["title"][0]["text"]["content"],
-            "#3"
-        );
+        assert_eq!(body["properties"]["res-order"]["number"], 3);
         assert_eq!(
             body["properties"]["res-text"]["rich_text"][0]["text"]["content"],
             "details"
@@ -615,16 +1545,379 @@ mod tests {
     #[test]
     fn build_resource_page_request_omits_optional_fields() {
         let ids = sample_ids();
-        let body = build_resource_page_request(&ids, None, 7, None, None, None, None);
-        assert_eq!(
-            body["properties"]["res-order"]["title"][0]["text"]["content"],
-            "#7"
+        let body = build_resource_page_request(
+            &ids,
+            None,
+            7,
+            None,
+            None,
+            None,
+            None,
+            &ResourceMeta::default(),
         );
+        assert_eq!(body["properties"]["res-order"]["number"], 7);
+        assert!(body["properties"]["res-order"].get("title").is_none());
         assert!(body["properties"].get("rel-parent").is_none());
         assert!(body["properties"].get("res-text").is_none());
         assert!(body["properties"].get("res-media").is_none());
     }
 
+    #[test]
+    fn build_resource_page_request_with_uploads_writes_number_order() {
+        let ids = sample_ids();
+        let files = vec![("a.jpg".to_string(), "up-1".to_string())];
+        let body = build_resource_page_request_with_uploads(
+            &ids,
+            None,
+            12,
+            None,
+            &files,
+            &ResourceMeta::default(),
+        );
+        assert_eq!(body["properties"]["res-order"]["number"], 12);
+        assert_eq!(
+            body["properties"]["res-media"]["files"][0]["file_upload"]["id"],
+            "up-1"
+        );
+    }
+
+    #[test]
+    fn source_url_is_written_only_when_configured_and_present() {
+        let meta = ResourceMeta {
+            source_url: Some("https://t.me/somechannel/42".into()),
+            ..Default::default()
+        };
+        let mut ids = sample_ids();
+
+        // Not configured: the value is dropped
+        let body = build_resource_page_request(&ids, None, 1, Some("hi"), None, None, None, &meta);
+        assert!(body["properties"].get("res-source").is_none());
+
+        ids.f_res_source_url = Some("res-source".into());
+        let body = build_resource_page_request(&ids, None, 1, Some("hi"), None, None, None, &meta);
+        assert_eq!(
+            body["properties"]["res-source"]["url"],
+            "https://t.me/somechannel/42"
+        );
+        let files = vec![("a.jpg".to_string(), "up-1".to_string())];
+        let body = build_resource_page_request_with_uploads(&ids, None, 1, None, &files, &meta);
+        assert_eq!(
+            body["properties"]["res-source"]["url"],
+            "https://t.me/somechannel/42"
+        );
+
+        // Configured but no value (e.g. private chat)
+        let body = build_resource_page_request(
+            &ids,
+            None,
+            1,
+            Some("hi"),
+            None,
+            None,
+            None,
+            &ResourceMeta::default(),
+        );
+        assert!(body["properties"].get("res-source").is_none());
+    }
+
+    #[test]
+    fn tg_date_is_written_as_rfc3339_date() {
+        let meta = ResourceMeta {
+            tg_date: Some("2024-01-31T08:15:00Z".parse().unwrap()),
+            ..Default::default()
+        };
+        let mut ids = sample_ids();
+        let body = build_resource_page_request(&ids, None, 1, Some("hi"), None, None, None, &meta);
+        assert!(body["properties"].get("res-date").is_none());
+
+        ids.f_res_date = Some("res-date".into());
+        let body = build_resource_page_request(&ids, None, 1, Some("hi"), None, None, None, &meta);
+        assert_eq!(
+            body["properties"]["res-date"],
+            json!({ "date": { "start": "2024-01-31T08:15:00Z" } })
+        );
+    }
+
+    #[test]
+    fn kind_is_written_as_select_only_when_configured() {
+        let meta = ResourceMeta {
+            kind: Some(ResourceKind::Photo),
+            ..Default::default()
+        };
+        let mut ids = sample_ids();
+        let body = build_resource_page_request(&ids, None, 1, None, None, None, None, &meta);
+        assert!(body["properties"].get("res-kind").is_none());
+
+        ids.f_res_kind = Some("res-kind".into());
+        let body = build_resource_page_request(&ids, None, 1, None, None, None, None, &meta);
+        assert_eq!(
+            body["properties"]["res-kind"],
+            json!({ "select": { "name": "photo" } })
+        );
+        let files = vec![("a.jpg".to_string(), "up-1".to_string())];
+        let body = build_resource_page_request_with_uploads(&ids, None, 1, None, &files, &meta);
+        assert_eq!(body["properties"]["res-kind"]["select"]["name"], "photo");
+    }
+
+    #[test]
+    fn dimensions_are_written_as_numbers_only_when_configured_and_present() {
+        let meta = ResourceMeta {
+            dimensions: MediaDimensions {
+                width: Some(1280),
+                height: Some(720),
+                duration: None,
+            },
+            ..Default::default()
+        };
+        let mut ids = sample_ids();
+        let body = build_resource_page_request(&ids, None, 1, None, None, None, None, &meta);
+        assert!(body["properties"].get("res-width").is_none());
+
+        ids.f_res_width = Some("res-width".into());
+        ids.f_res_duration = Some("res-duration".into());
+        let body = build_resource_page_request(&ids, None, 1, None, None, None, None, &meta);
+        assert_eq!(body["properties"]["res-width"], json!({ "number": 1280 }));
+        // Height has a value but no property; duration a property but no value
+        assert!(body["properties"].get("res-height").is_none());
+        assert!(body["properties"].get("res-duration").is_none());
+
+        let files = vec![("a.mp4".to_string(), "up-1".to_string())];
+        let body = build_resource_page_request_with_uploads(&ids, None, 1, None, &files, &meta);
+        assert_eq!(body["properties"]["res-width"]["number"], 1280);
+    }
+
+    #[test]
+    fn check_order_property_requires_number() {
+        let schema = |typ: &str| -> RetrieveDatabaseResp {
+            serde_json::from_value(json!({
+                "id": "resource-db",
+                "title": [],
+                "properties": { "Order": { "id": "ord1", "type": typ } }
+            }))
+            .unwrap()
+        };
+        check_order_property(&schema("number"), "Order").unwrap();
+        check_order_property(&schema("number"), "ord1").unwrap();
+
+        let err = check_order_property(&schema("title"), "Order").unwrap_err();
+        assert!(err.to_string().contains("has type 'title'"));
+        assert!(check_order_property(&schema("number"), "Missing").is_err());
+    }
+
+    #[test]
+    fn resolve_schema_ids_maps_names_and_ids_to_property_ids() {
+        let cfg: Config = serde_yaml::from_str(crate::config::example()).unwrap();
+        let mut dbs = cfg.notion.databases.clone();
+        let main: RetrieveDatabaseResp = serde_json::from_value(json!({
+            "id": "main-db",
+            "title": [],
+            "properties": { "Name": { "id": "title", "type": "title" } }
+        }))
+        .unwrap();
+        let resource: RetrieveDatabaseResp = serde_json::from_value(json!({
+            "id": "resource-db",
+            "title": [],
+            "properties": {
+                "rel-parent": { "id": "r%3B1", "type": "relation" },
+                "res-order": { "id": "o2", "type": "number" },
+                "res-text": { "id": "t3", "type": "rich_text" },
+                "res-media": { "id": "m4", "type": "files" },
+                "Source": { "id": "s5", "type": "url" }
+            }
+        }))
+        .unwrap();
+        // The title is configured by id, the rest by display name
+        dbs.main.fields.title = "title".into();
+        dbs.resource.fields.source_url = Some("Source".into());
+
+        let ids = resolve_schema_ids(&dbs, &main, &resource).unwrap();
+        assert_eq!(ids.f_main_title, "title");
+        assert_eq!(ids.f_rel_parent, "r%3B1");
+        assert_eq!(ids.f_res_order, "o2");
+        assert_eq!(ids.f_res_text, "t3");
+        assert_eq!(ids.f_res_media, "m4");
+        assert_eq!(ids.f_res_source_url.as_deref(), Some("s5"));
+        assert_eq!(ids.f_res_date, None);
+
+        dbs.resource.fields.text = "Body".into();
+        let err = resolve_schema_ids(&dbs, &main, &resource)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("text property 'Body' not found"), "{}", err);
+        assert!(
+            err.contains("available: Source, rel-parent, res-media, res-order"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn check_schema_names_mistyped_property() {
+        let cfg: Config = serde_yaml::from_str(crate::config::example()).unwrap();
+        let dbs = &cfg.notion.databases;
+        let main: RetrieveDatabaseResp = serde_json::from_value(json!({
+            "id": "main-db",
+            "title": [],
+            "properties": { "title": { "id": "t", "type": "title" } }
+        }))
+        .unwrap();
+        let resource = |text_type: &str| -> RetrieveDatabaseResp {
+            serde_json::from_value(json!({
+                "id": "resource-db",
+                "title": [],
+                "properties": {
+                    "rel-parent": { "id": "r", "type": "relation" },
+                    "res-order": { "id": "o", "type": "number" },
+                    "res-text": { "id": "x", "type": text_type },
+                    "res-media": { "id": "m", "type": "files" }
+                }
+            }))
+            .unwrap()
+        };
+
+        check_schema(dbs, &main, &resource("rich_text")).unwrap();
+
+        let err = check_schema(dbs, &main, &resource("title")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "text property 'res-text' in Notion database resource-db has type 'title'; change it to 'rich_text'"
+        );
+    }
+
+    #[test]
+    fn page_url_strips_dashes() {
+        assert_eq!(
+            page_url("1a2b3c4d-0000-1111-2222-333344445555"),
+            "https://www.notion.so/1a2b3c4d000011112222333344445555"
+        );
+    }
+
+    #[test]
+    fn page_title_joins_runs_of_title_property() {
+        let page: RetrievePageResp = serde_json::from_value(json!({
+            "id": "page-1",
+            "properties": {
+                "Name": {
+                    "id": "title",
+                    "type": "title",
+                    "title": [ { "plain_text": "Trip " }, { "plain_text": "2024" } ]
+                }
+            }
+        }))
+        .unwrap();
+        assert_eq!(page_title(&page, "title").as_deref(), Some("Trip 2024"));
+        assert_eq!(page_title(&page, "Name").as_deref(), Some("Trip 2024"));
+        assert_eq!(page_title(&page, "missing"), None);
+    }
+
+    #[test]
+    fn build_text_properties_replaces_or_clears_text() {
+        let ids = sample_ids();
+        let props = build_text_properties(&ids, "edited", false);
+        assert_eq!(
+            props["res-text"]["rich_text"][0]["text"]["content"],
+            "edited"
+        );
+        assert_eq!(props.as_object().unwrap().len(), 1);
+        let cleared = build_text_properties(&ids, "", false);
+        assert_eq!(cleared["res-text"]["rich_text"], json!([]));
+    }
+
+    #[test]
+    fn markdown_is_styled_only_when_enabled() {
+        let ids = sample_ids();
+        let mut meta = ResourceMeta::default();
+        let body =
+            build_resource_page_request(&ids, None, 1, Some("*hi*"), None, None, None, &meta);
+        assert_eq!(
+            body["properties"]["res-text"]["rich_text"],
+            json!([ { "text": { "content": "*hi*" } } ])
+        );
+
+        meta.parse_markdown = true;
+        let body =
+            build_resource_page_request(&ids, None, 1, Some("*hi*"), None, None, None, &meta);
+        let run = &body["properties"]["res-text"]["rich_text"][0];
+        assert_eq!(run["text"]["content"], "hi");
+        assert_eq!(run["annotations"]["bold"], true);
+        let props = build_text_properties(&ids, "_edited_", true);
+        assert_eq!(
+            props["res-text"]["rich_text"][0]["annotations"]["italic"],
+            true
+        );
+    }
+
+    #[test]
+    fn upload_parts_splits_evenly() {
+        assert_eq!(upload_parts(30, 10), vec![0..10, 10..20, 20..30]);
+    }
+
+    #[test]
+    fn upload_parts_keeps_short_last_part() {
+        assert_eq!(upload_parts(25, 10), vec![0..10, 10..20, 20..25]);
+        assert_eq!(upload_parts(5, 10), vec![Range { start: 0, end: 5 }]);
+        assert_eq!(upload_parts(0, 10), vec![Range { start: 0, end: 0 }]);
+        let mb = 1024 * 1024;
+        let parts = upload_parts(45 * mb, UPLOAD_PART_BYTES);
+        assert_eq!(parts.len(), 5);
+        assert_eq!(parts.last().unwrap().clone(), 40 * mb..45 * mb);
+    }
+
+    #[test]
+    fn build_image_block_external_and_upload() {
+        let block = build_image_block(ImageSource::External("https://cdn/a.jpg"));
+        assert_eq!(block["type"], "image");
+        assert_eq!(block["image"]["type"], "external");
+        assert_eq!(block["image"]["external"]["url"], "https://cdn/a.jpg");
+
+        let block = build_image_block(ImageSource::FileUpload("upload-1"));
+        assert_eq!(block["type"], "image");
+        assert_eq!(block["image"]["type"], "file_upload");
+        assert_eq!(block["image"]["file_upload"]["id"], "upload-1");
+    }
+
+    #[test]
+    fn notion_error_classifies_status_codes() {
+        let err =
+            |code: u16| NotionError::from_status(StatusCode::from_u16(code).unwrap(), "body", None);
+        assert!(matches!(
+            NotionError::from_status(
+                StatusCode::TOO_MANY_REQUESTS,
+                "",
+                Some(Duration::from_secs(3))
+            ),
+            NotionError::RateLimited { retry_after: Some(d) } if d == Duration::from_secs(3)
+        ));
+        assert!(matches!(err(404), NotionError::NotFound));
+        assert!(matches!(err(401), NotionError::Unauthorized));
+        assert!(matches!(err(403), NotionError::Unauthorized));
+        assert!(matches!(err(400), NotionError::BadRequest(ref m) if m == "400 Bad Request: body"));
+        assert!(matches!(err(409), NotionError::Conflict(ref m) if m == "body"));
+        assert!(
+            matches!(err(500), NotionError::Server(s) if s == StatusCode::INTERNAL_SERVER_ERROR)
+        );
+        assert!(matches!(err(503), NotionError::Server(_)));
+
+        assert!(err(429).is_retryable());
+        assert!(err(502).is_retryable());
+        assert!(err(409).is_retryable());
+        assert!(!err(400).is_retryable());
+        assert!(!err(404).is_retryable());
+
+        let invalid = NotionError::from_status(
+            StatusCode::BAD_REQUEST,
+            r#"{"object":"error","code":"validation_error","message":"bad"}"#,
+            None,
+        );
+        assert!(
+            matches!(invalid, NotionError::Invalid(ref m) if m.starts_with("400 Bad Request: "))
+        );
+        assert!(invalid.rejects_request());
+        for code in [400, 401, 403, 404, 409, 429, 503] {
+            assert!(!err(code).rejects_request(), "{}", code);
+        }
+    }
+
     #[test]
     fn build_request_sets_headers() {
         let client = NotionClient::new("token".into(), "2022-06-28".into());
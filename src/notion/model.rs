@@ -1,16 +1,15 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub struct DatabaseProperty {
     pub id: String,
 
-    #[allow(dead_code)]
     #[serde(rename = "type")]
     pub typ: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub struct RetrieveDatabaseResp {
     pub id: String,
 
@@ -19,3 +18,111 @@ pub struct RetrieveDatabaseResp {
 
     pub properties: std::collections::HashMap<String, DatabaseProperty>,
 }
+
+/// `GET /v1/pages/{id}`; property values are kept as raw JSON.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct RetrievePageResp {
+    pub id: String,
+
+    #[serde(default)]
+    pub url: Option<String>,
+
+    #[serde(default)]
+    pub archived: bool,
+
+    pub properties: std::collections::HashMap<String, Value>,
+}
+
+/// A block from `GET /v1/blocks/{id}/children`. The type-specific payload
+/// (e.g. `paragraph`, `image`) is kept in `content`.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Block {
+    pub id: String,
+
+    #[serde(rename = "type")]
+    pub typ: String,
+
+    #[serde(default)]
+    pub has_children: bool,
+
+    #[serde(flatten)]
+    pub content: std::collections::HashMap<String, Value>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BlockChildrenResp {
+    pub results: Vec<Block>,
+
+    #[serde(default)]
+    pub has_more: bool,
+
+    #[serde(default)]
+    pub next_cursor: Option<String>,
+}
+
+/// One page of `POST /v1/databases/{id}/query`; rows are kept as raw JSON.
+#[derive(Deserialize, Debug)]
+pub struct QueryResponse {
+    pub results: Vec<Value>,
+
+    #[serde(default)]
+    pub next_cursor: Option<String>,
+
+    #[serde(default)]
+    pub has_more: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_sample_page_and_blocks() {
+        let page: RetrievePageResp = serde_json::from_value(json!({
+            "object": "page",
+            "id": "page-1",
+            "url": "https://www.notion.so/page-1",
+            "archived": false,
+            "properties": {
+                "No": { "id": "ord1", "type": "number", "number": 3 },
+                "Text": { "id": "txt1", "type": "rich_text", "rich_text": [] }
+            }
+        }))
+        .unwrap();
+        assert_eq!(page.id, "page-1");
+        assert_eq!(page.url.as_deref(), Some("https://www.notion.so/page-1"));
+        assert_eq!(page.properties["No"]["number"], 3);
+
+        let children: BlockChildrenResp = serde_json::from_value(json!({
+            "object": "list",
+            "results": [
+                {
+                    "object": "block",
+                    "id": "b1",
+                    "type": "paragraph",
+                    "has_children": false,
+                    "paragraph": { "rich_text": [ { "plain_text": "hi" } ] }
+                },
+                {
+                    "object": "block",
+                    "id": "b2",
+                    "type": "image",
+                    "has_children": false,
+                    "image": { "type": "external", "external": { "url": "https://cdn/a.jpg" } }
+                }
+            ],
+            "has_more": true,
+            "next_cursor": "b3"
+        }))
+        .unwrap();
+        assert!(children.has_more);
+        assert_eq!(children.next_cursor.as_deref(), Some("b3"));
+        assert_eq!(children.results[0].typ, "paragraph");
+        assert_eq!(
+            children.results[0].content["paragraph"]["rich_text"][0]["plain_text"],
+            "hi"
+        );
+        assert_eq!(children.results[1].typ, "image");
+    }
+}
@@ -1,54 +1,309 @@
+use crate::config::{Config, MediaMode, ThumbnailFormat, DEFAULT_BATCH_TITLE};
 use crate::db::{self, BatchForOutbox, ResourceForOutbox};
-use crate::model::{BatchState, OutboxKind};
-use crate::notion::{NotionClient, NotionIds, NotionService};
+use crate::model::{BatchState, OutboxKind, ResourceKind};
+use crate::notion::{
+    build_image_block, build_text_properties, build_title_properties, page_title, ImageSource,
+    NotionError, NotionIds, NotionService, ResourceMeta,
+};
+use crate::validate;
 use anyhow::{anyhow, Result};
+use futures::stream::{self, StreamExt, TryStreamExt};
 use sqlx::SqlitePool;
-use tracing::{debug, info, instrument, warn};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::{debug, error, info, info_span, instrument, warn, Instrument};
 
-#[instrument(skip_all)]
+/// Lets the bot wake the idle outbox worker as soon as it queues a task
+/// (`app.poll_strategy: notify`). Clones signal the same worker.
+#[derive(Debug, Clone, Default)]
+pub struct OutboxSignal(Arc<Notify>);
+
+impl OutboxSignal {
+    /// Tell the worker tasks were queued. A signal sent while the worker is
+    /// busy is kept, so its next wait returns at once.
+    pub fn notify(&self) {
+        self.0.notify_one();
+    }
+
+    /// Wait for a signal, or at most `timeout`.
+    pub async fn wait(&self, timeout: Duration) {
+        let _ = tokio::time::timeout(timeout, self.0.notified()).await;
+    }
+}
+
+/// Worker tunables, usually derived from the loaded `Config`.
+#[derive(Debug, Clone)]
+pub struct WorkerOptions {
+    pub max_backoff_secs: i64,
+    /// Dead-letter a task once it has failed this many times; `<= 0` retries forever.
+    pub max_attempts: i32,
+    pub media_mode: MediaMode,
+    pub media_upload_max_bytes: u64,
+    /// Append photos to the page body as `image` blocks after creating the page.
+    pub images_as_blocks: bool,
+    /// Resolved `notion.routes` mappings by chat id; unrouted rows use the default ids.
+    pub notion_routes: HashMap<i64, NotionIds>,
+    /// `notion.default_title`, used for batches committed without a title.
+    pub default_title: String,
+    /// `notion.parse_markdown`: style resource text from its markdown.
+    pub parse_markdown: bool,
+    /// `notion.main_page_icon`: emoji icon of batch main pages.
+    pub main_page_icon: Option<String>,
+    /// `app.upload_concurrency`: files of one resource uploaded at once.
+    pub upload_concurrency: usize,
+    /// `notion.dedupe_main_pages`: reuse a main page with the batch's title.
+    pub dedupe_main_pages: bool,
+    /// `app.thumbnail_format`: extension looked for when a video has no stored thumbnail path.
+    pub thumbnail_format: ThumbnailFormat,
+    /// `notion.verify_after_create`: read new main pages back and check their title.
+    pub verify_after_create: bool,
+}
+
+impl Default for WorkerOptions {
+    fn default() -> Self {
+        Self {
+            max_backoff_secs: 60,
+            max_attempts: 10,
+            media_mode: MediaMode::External,
+            media_upload_max_bytes: 20 * 1024 * 1024,
+            images_as_blocks: false,
+            notion_routes: HashMap::new(),
+            default_title: DEFAULT_BATCH_TITLE.to_string(),
+            parse_markdown: false,
+            main_page_icon: None,
+            upload_concurrency: 1,
+            dedupe_main_pages: false,
+            thumbnail_format: ThumbnailFormat::Jpg,
+            verify_after_create: false,
+        }
+    }
+}
+
+impl WorkerOptions {
+    pub fn from_config(cfg: &Config) -> Self {
+        Self {
+            max_backoff_secs: cfg.app.max_backoff_seconds as i64,
+            max_attempts: cfg.app.max_attempts as i32,
+            media_mode: cfg.app.media_mode,
+            media_upload_max_bytes: cfg.app.media_upload_max_bytes,
+            images_as_blocks: cfg.notion.images_as_blocks,
+            notion_routes: HashMap::new(),
+            default_title: cfg.notion.default_title().to_string(),
+            parse_markdown: cfg.notion.parse_markdown,
+            main_page_icon: cfg.notion.main_page_icon.clone(),
+            upload_concurrency: cfg.app.upload_concurrency,
+            dedupe_main_pages: cfg.notion.dedupe_main_pages,
+            thumbnail_format: cfg.app.thumbnail_format,
+            verify_after_create: cfg.notion.verify_after_create,
+        }
+    }
+}
+
+/// Main page title of a batch: its own title, else `default_title` with
+/// `{date}` replaced by the batch's creation date (`YYYY-MM-DD`).
+pub fn batch_title(title: Option<&str>, default_title: &str, created_at: &str) -> String {
+    match title.filter(|t| !t.trim().is_empty()) {
+        Some(title) => title.to_string(),
+        None => {
+            let date = created_at.get(..10).unwrap_or(created_at);
+            default_title.replace("{date}", date)
+        }
+    }
+}
+
+/// Mapping for a batch/resource given the route stored when it was created.
+fn route_ids<'a>(
+    default: &'a NotionIds,
+    opts: &'a WorkerOptions,
+    notion_route: Option<i64>,
+) -> Result<&'a NotionIds> {
+    match notion_route {
+        None => Ok(default),
+        Some(chat_id) => opts
+            .notion_routes
+            .get(&chat_id)
+            .ok_or_else(|| anyhow!("no notion.routes entry for chat {}", chat_id)),
+    }
+}
+
+/// Where a resource's media should come from when creating its Notion page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaSource {
+    Upload,
+    External,
+}
+
+/// Decide per file whether to upload it to Notion or keep it as an external link.
+pub fn choose_media_source(mode: MediaMode, file_size: u64, upload_max_bytes: u64) -> MediaSource {
+    match mode {
+        MediaMode::External => MediaSource::External,
+        MediaMode::Upload => MediaSource::Upload,
+        MediaMode::Auto if file_size > upload_max_bytes => MediaSource::External,
+        MediaMode::Auto => MediaSource::Upload,
+    }
+}
+
+/// What [`run_next_task`] did with the next due task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskOutcome {
+    /// Nothing was due.
+    Idle,
+    Succeeded,
+    /// Dead-lettered because retrying cannot help (unknown kind or a request
+    /// Notion refused as invalid).
+    Rejected,
+    /// Failed at Notion or the network, including a rejected token or a
+    /// database the integration cannot see; backed off or dead-lettered after
+    /// the last attempt.
+    Failed,
+    /// Failed locally (e.g. a missing media file or a batch that is not
+    /// committed); handled like `Failed`, but says nothing about Notion.
+    FailedLocally,
+}
+
+/// Process the next due task; returns `false` when nothing was due.
+#[allow(dead_code)]
 pub async fn process_next_task(
     pool: &SqlitePool,
     notion: &dyn NotionService,
     notion_ids: &NotionIds,
-    max_backoff_secs: i64,
+    opts: &WorkerOptions,
 ) -> Result<bool> {
-    if let Some((id, _user_id, kind, ref_id, attempt)) = db::next_due_outbox(pool).await? {
-        let kind_enum = match kind.as_str() {
-            "push_batch" => OutboxKind::PushBatch,
-            _ => OutboxKind::PushResource,
+    Ok(run_next_task(pool, notion, notion_ids, opts).await? != TaskOutcome::Idle)
+}
+
+#[instrument(skip_all)]
+pub async fn run_next_task(
+    pool: &SqlitePool,
+    notion: &dyn NotionService,
+    notion_ids: &NotionIds,
+    opts: &WorkerOptions,
+) -> Result<TaskOutcome> {
+    let Some((id, _user_id, kind, ref_id, attempt)) = db::claim_next_due_outbox(pool).await? else {
+        return Ok(TaskOutcome::Idle);
+    };
+    // Every log line of this task, down to the Notion client, carries these fields.
+    let span = info_span!("outbox_task", outbox_id = id, kind = %kind, ref_id, attempt);
+    async move {
+        let Some(kind_enum) = OutboxKind::parse(&kind) else {
+            // Retrying cannot help; park it where an operator will see it.
+            error!(id, kind, ref_id, "unknown outbox kind; dead-lettering");
+            db::move_to_dead_letter(pool, id, &format!("unknown outbox kind: {}", kind)).await?;
+            return Ok(TaskOutcome::Rejected);
         };
         let res = match kind_enum {
-            OutboxKind::PushBatch => push_batch_task(pool, notion, notion_ids, ref_id).await,
-            OutboxKind::PushResource => push_resource_task(pool, notion, notion_ids, ref_id).await,
+            OutboxKind::PushBatch => push_batch_task(pool, notion, notion_ids, opts, ref_id).await,
+            OutboxKind::PushResource => {
+                push_resource_task(pool, notion, notion_ids, opts, ref_id).await
+            }
+            OutboxKind::UpdateResource => {
+                update_resource_task(pool, notion, notion_ids, opts, ref_id).await
+            }
         };
-        match res {
-            Ok(_) => {
-                db::delete_outbox(pool, id).await?;
+        let outcome = match res {
+            Ok(page_id) => {
+                match (kind_enum, page_id) {
+                    (OutboxKind::PushBatch, Some(page_id)) => {
+                        db::complete_outbox_batch(pool, id, ref_id, &page_id).await?
+                    }
+                    (OutboxKind::PushResource, Some(page_id)) => {
+                        db::complete_outbox_resource(pool, id, ref_id, &page_id).await?
+                    }
+                    (OutboxKind::UpdateResource, _) | (_, None) => {
+                        db::delete_outbox(pool, id).await?
+                    }
+                }
+                db::update_last_processed_outbox_id(pool, id).await?;
                 info!(id, kind, ref_id, "outbox task succeeded");
+                TaskOutcome::Succeeded
             }
             Err(err) => {
-                warn!(
-                    ?err,
-                    id, kind, ref_id, attempt, "outbox task failed; backoff"
-                );
-                db::backoff_outbox_with_cap(pool, id, attempt, max_backoff_secs).await?;
+                if rejected_by_notion(&err) {
+                    error!(
+                        ?err,
+                        id, kind, ref_id, attempt, "notion rejected outbox task; dead-lettering"
+                    );
+                    db::move_to_dead_letter(pool, id, &format!("{:#}", err)).await?;
+                    return Ok(TaskOutcome::Rejected);
+                }
+                if opts.max_attempts > 0 && attempt + 1 >= opts.max_attempts {
+                    error!(
+                        ?err,
+                        id, kind, ref_id, attempt, "outbox task exhausted attempts; dead-lettering"
+                    );
+                    db::move_to_dead_letter(pool, id, &format!("{:#}", err)).await?;
+                } else {
+                    warn!(
+                        ?err,
+                        id, kind, ref_id, attempt, "outbox task failed; backoff"
+                    );
+                    db::backoff_outbox_with_cap(
+                        pool,
+                        id,
+                        attempt,
+                        opts.max_backoff_secs,
+                        Some(&format!("{:#}", err)),
+                    )
+                    .await?;
+                }
+                if failed_at_notion(&err) {
+                    TaskOutcome::Failed
+                } else {
+                    TaskOutcome::FailedLocally
+                }
             }
-        }
-        return Ok(true);
+        };
+        Ok(outcome)
     }
-    Ok(false)
+    .instrument(span)
+    .await
 }
 
+/// Whether `err` comes from Notion refusing this task's request as invalid
+/// (a 400 `validation_error`), which no retry can fix.
+fn rejected_by_notion(err: &anyhow::Error) -> bool {
+    err.chain()
+        .filter_map(|cause| cause.downcast_ref::<NotionError>())
+        .any(NotionError::rejects_request)
+}
+
+/// Whether `err` comes from calling Notion (an error response or the
+/// connection) rather than from local state such as a missing file.
+fn failed_at_notion(err: &anyhow::Error) -> bool {
+    err.chain()
+        .any(|cause| cause.is::<NotionError>() || cause.is::<reqwest::Error>())
+}
+
+/// Create the batch's main page. Returns the new page id, or `None` when the
+/// batch was already synced; the caller persists it together with the outbox delete.
+/// A synced batch re-committed after a reopen gets its page retitled instead.
 async fn push_batch_task(
     pool: &SqlitePool,
     notion: &dyn NotionService,
     notion_ids: &NotionIds,
+    opts: &WorkerOptions,
     batch_id: i64,
-) -> Result<()> {
+) -> Result<Option<String>> {
     let batch: BatchForOutbox = db::fetch_batch_for_outbox(pool, batch_id).await?;
     if let Some(existing) = &batch.notion_page_id {
-        debug!(batch_id, notion_page_id=%existing, "batch already synced; skipping");
-        return Ok(());
+        if batch.state != BatchState::Committed {
+            debug!(batch_id, notion_page_id=%existing, "batch already synced; skipping");
+            return Ok(None);
+        }
+        let title = batch_title(
+            batch.title.as_deref(),
+            &opts.default_title,
+            &batch.created_at,
+        );
+        validate::check_title(&title)?;
+        let notion_ids = route_ids(notion_ids, opts, batch.notion_route)?;
+        info!(batch_id, title = %title, page_id = %existing, "updating main Notion page title");
+        notion
+            .update_page_properties(existing, &build_title_properties(notion_ids, &title))
+            .await?;
+        return Ok(None);
     }
 
     if batch.state != BatchState::Committed {
@@ -59,28 +314,100 @@ async fn push_batch_task(
         ));
     }
 
-    let title = batch
-        .title
-        .as_deref()
-        .filter(|t| !t.trim().is_empty())
-        .unwrap_or("Untitled");
-    info!(batch_id, title, "creating main Notion page");
-    let page_id = notion.create_main_page(notion_ids, title).await?;
-    db::mark_batch_notion_page_id(pool, batch_id, &page_id).await?;
+    let title = batch_title(
+        batch.title.as_deref(),
+        &opts.default_title,
+        &batch.created_at,
+    );
+    validate::check_title(&title)?;
+    let notion_ids = route_ids(notion_ids, opts, batch.notion_route)?;
+    if let Some(pending) = batch.pending_notion_page_id {
+        info!(batch_id, page_id = %pending, "verifying main Notion page from an earlier attempt");
+        verify_main_page(notion, notion_ids, &pending, &title).await?;
+        return Ok(Some(pending));
+    }
+    // Only a title the user chose identifies a batch; default titles such as
+    // "Untitled" are shared by unrelated batches.
+    let own_title = batch.title.as_deref().is_some_and(|t| !t.trim().is_empty())
+        && title != batch_title(None, &opts.default_title, &batch.created_at);
+    if opts.dedupe_main_pages && own_title {
+        if let Some(page_id) = notion.find_main_page(notion_ids, &title).await? {
+            info!(batch_id, title = %title, page_id = %page_id, "reusing existing main Notion page");
+            return Ok(Some(page_id));
+        }
+    }
+    let cover_url = batch_cover_url(pool, batch_id).await?;
+    info!(batch_id, title = %title, cover = cover_url.is_some(), "creating main Notion page");
+    let page_id = notion
+        .create_main_page(
+            notion_ids,
+            &title,
+            opts.main_page_icon.as_deref(),
+            cover_url.as_deref(),
+        )
+        .await?;
+    if opts.verify_after_create {
+        // Saved first: if the check fails, the retry verifies this page again
+        db::set_batch_pending_page(pool, batch_id, &page_id).await?;
+        verify_main_page(notion, notion_ids, &page_id, &title).await?;
+    }
+    Ok(Some(page_id))
+}
+
+/// Read a just-created main page back and fail unless its title is `title`,
+/// so the batch is retried instead of being marked synced.
+async fn verify_main_page(
+    notion: &dyn NotionService,
+    notion_ids: &NotionIds,
+    page_id: &str,
+    title: &str,
+) -> Result<()> {
+    let page = notion.retrieve_page(page_id).await?;
+    let found = page_title(&page, &notion_ids.f_main_title);
+    if found.as_deref().map(str::trim) != Some(title.trim()) {
+        return Err(anyhow!(
+            "main page {} has title {:?}, expected {:?}",
+            page_id,
+            found,
+            title
+        ));
+    }
+    debug!(page_id, "verified main page title");
     Ok(())
 }
 
+/// Cover for a batch's main page: the public URL of its first resource when
+/// that is a photo. A photo that must be uploaded has no URL before the main
+/// page exists, so [`push_resource_task`] sets it as the cover afterwards.
+pub async fn batch_cover_url(pool: &SqlitePool, batch_id: i64) -> Result<Option<String>> {
+    let Some(first_id) = db::first_batch_resource_id(pool, batch_id).await? else {
+        return Ok(None);
+    };
+    let first = db::fetch_resource_for_outbox(pool, first_id).await?;
+    if first.kind != ResourceKind::Photo {
+        return Ok(None);
+    }
+    Ok(sanitize_media_url(first.media_url.as_deref()))
+}
+
+/// Create the resource's page (uploading media when needed). Same return
+/// contract as [`push_batch_task`].
 async fn push_resource_task(
     pool: &SqlitePool,
     notion: &dyn NotionService,
     notion_ids: &NotionIds,
+    opts: &WorkerOptions,
     resource_id: i64,
-) -> Result<()> {
+) -> Result<Option<String>> {
     let resource: ResourceForOutbox = db::fetch_resource_for_outbox(pool, resource_id).await?;
     if let Some(existing) = &resource.notion_page_id {
         debug!(resource_id, notion_page_id=%existing, "resource already synced; skipping");
-        return Ok(());
+        return Ok(None);
     }
+    if let Some(problem) = validate::validate_resource(&resource).into_iter().next() {
+        return Err(problem.into());
+    }
+    let notion_ids = route_ids(notion_ids, opts, resource.notion_route)?;
 
     let parent_page_id = if let Some(batch_id) = resource.batch_id {
         let state = resource.batch_state.ok_or_else(|| {
@@ -112,106 +439,166 @@ async fn push_resource_task(
 
     let text = resource.text.as_deref();
     let media_url = sanitize_media_url(resource.media_url.as_deref());
+    // Text and locations live in the row itself; only media has a file to upload
+    let has_file = match resource.kind {
+        ResourceKind::Text | ResourceKind::Location => false,
+        ResourceKind::Photo | ResourceKind::Video => true,
+    };
+    // Standalone media has no batch title for context; name it after its caption
     let media_name = resource
         .media_name
-        .as_deref()
-        .filter(|name| !name.is_empty());
+        .clone()
+        .filter(|name| !name.is_empty())
+        .or_else(|| {
+            text.filter(|_| has_file && resource.batch_id.is_none())
+                .and_then(caption_media_name)
+        });
+    let meta = ResourceMeta {
+        source_url: resource.source_url.clone(),
+        tg_date: resource.tg_date,
+        kind: Some(resource.kind),
+        dimensions: resource.dimensions,
+        parse_markdown: opts.parse_markdown,
+    };
 
     info!(
         resource_id,
         order = resource.sequence,
-        kind = %resource.kind,
+        kind = resource.kind.as_str(),
         "creating resource Notion page"
     );
 
-    // Prefer external URL if present; otherwise, attempt to upload a local file if available
-    let page_id = if media_url.is_some() || resource.kind == "text" {
+    // Pick external URL vs upload per file; without a URL we can only upload
+    let local_size = std::fs::metadata(&resource.content).ok().map(|m| m.len());
+    let use_external = match (media_url.is_some(), local_size) {
+        (true, Some(size)) => {
+            choose_media_source(opts.media_mode, size, opts.media_upload_max_bytes)
+                == MediaSource::External
+        }
+        (true, None) => true,
+        (false, Some(size)) => {
+            if has_file && opts.media_mode == MediaMode::Auto && size > opts.media_upload_max_bytes
+            {
+                warn!(
+                    resource_id,
+                    size,
+                    max = opts.media_upload_max_bytes,
+                    "no external URL to link; uploading media above media_upload_max_bytes"
+                );
+            }
+            false
+        }
+        (false, None) => false,
+    };
+    // Image block for the page body, set wherever the photo's source is known
+    let mut image_block = None;
+    let mut photo_upload_id = None;
+    let mut uploaded = false;
+    let page_id = if use_external || !has_file {
+        image_block = media_url
+            .as_deref()
+            .map(|url| build_image_block(ImageSource::External(url)));
         notion
             .create_resource_page(
                 notion_ids,
                 parent_page_id.as_deref(),
                 resource.sequence,
                 text,
-                media_name,
+                media_name.as_deref(),
                 media_url.as_deref(),
+                &meta,
             )
             .await?
     } else {
-        // Try to downcast to a concrete NotionClient for file uploads
-        if let Some(client) = (notion as &dyn std::any::Any).downcast_ref::<NotionClient>() {
-            // Use the DB `content` as a local file path if it exists
-            let path = std::path::Path::new(&resource.content);
-            if path.exists() {
-                // If this is a video, attempt to also attach its generated thumbnail first
-                if resource.kind == "video" {
-                    let mut files: Vec<(String, String)> = Vec::new();
-
-                    // Derive thumbnail path: data_dir/media/thumbs/{video_stem}.jpg
-                    if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
-                        // Try to locate the 'media' directory ancestor to infer data_dir
-                        let thumb_path = derive_thumb_path_from_video(path, stem);
-                        if let Some(tp) = thumb_path {
-                            if tp.exists() {
-                                let tname = tp
-                                    .file_name()
-                                    .and_then(|n| n.to_str())
-                                    .unwrap_or("thumb.jpg");
-                                let tid = client.upload_file(&tp).await?;
-                                files.push((tname.to_string(), tid));
-                            }
-                        }
-                    }
+        // Use the DB `content` as a local file path if it exists
+        let path = std::path::Path::new(&resource.content);
+        if path.exists() {
+            // If this is a video, attempt to also attach its generated thumbnail first
+            if resource.kind == ResourceKind::Video {
+                let mut sources: Vec<(String, std::path::PathBuf)> = Vec::new();
 
-                    // Always upload the video itself second
-                    let vname = path
-                        .file_name()
-                        .and_then(|n| n.to_str())
-                        .unwrap_or("video.bin");
-                    let vid = client.upload_file(path).await?;
-                    files.push((vname.to_string(), vid));
-
-                    client
-                        .create_resource_page_with_file_uploads(
-                            notion_ids,
-                            parent_page_id.as_deref(),
-                            resource.sequence,
-                            text,
-                            &files,
-                        )
-                        .await?
-                } else {
-                    // Non-video: single file upload
-                    let file_name = path
+                // Prefer the path stored at save time; rows from before it was
+                // recorded fall back to data_dir/media/thumbs/{video_stem}.{ext},
+                // in the configured format or the JPEG those rows were made in
+                let thumb_path = resource
+                    .thumb_path
+                    .as_deref()
+                    .map(std::path::PathBuf::from)
+                    .or_else(|| {
+                        let stem = path.file_stem().and_then(|s| s.to_str())?;
+                        [opts.thumbnail_format, ThumbnailFormat::Jpg]
+                            .into_iter()
+                            .filter_map(|format| derive_thumb_path_from_video(path, stem, format))
+                            .find(|tp| tp.exists())
+                    });
+                if let Some(tp) = thumb_path.filter(|tp| tp.exists()) {
+                    let tname = tp
                         .file_name()
                         .and_then(|n| n.to_str())
-                        .unwrap_or("uploaded.bin");
-                    let upload_id = client.upload_file(path).await?;
-                    client
-                        .create_resource_page_with_file_upload(
-                            notion_ids,
-                            parent_page_id.as_deref(),
-                            resource.sequence,
-                            text,
-                            Some(file_name),
-                            Some(&upload_id),
-                        )
-                        .await?
+                        .unwrap_or("thumb.jpg")
+                        .to_string();
+                    sources.push((tname, tp));
                 }
+
+                // Always attach the video itself second
+                let vname = media_name.as_deref().unwrap_or_else(|| {
+                    path.file_name()
+                        .and_then(|n| n.to_str())
+                        .unwrap_or("video.bin")
+                });
+                sources.push((vname.to_string(), path.to_path_buf()));
+
+                // Uploads may overlap, but `buffered` yields ids in source order
+                let uploads: Vec<_> = sources
+                    .iter()
+                    .map(|(_, source)| notion.upload_file(source))
+                    .collect();
+                let upload_ids: Vec<String> = stream::iter(uploads)
+                    .buffered(opts.upload_concurrency.max(1))
+                    .try_collect()
+                    .await?;
+                uploaded = true;
+                let files: Vec<(String, String)> = sources
+                    .into_iter()
+                    .map(|(name, _)| name)
+                    .zip(upload_ids)
+                    .collect();
+
+                notion
+                    .create_resource_page_with_file_uploads(
+                        notion_ids,
+                        parent_page_id.as_deref(),
+                        resource.sequence,
+                        text,
+                        &files,
+                        &meta,
+                    )
+                    .await?
             } else {
-                // Fallback: create without media
+                // Non-video: single file upload
+                let file_name = media_name.as_deref().unwrap_or_else(|| {
+                    path.file_name()
+                        .and_then(|n| n.to_str())
+                        .unwrap_or("uploaded.bin")
+                });
+                let upload_id = notion.upload_file(path).await?;
+                image_block = Some(build_image_block(ImageSource::FileUpload(&upload_id)));
+                uploaded = true;
+                photo_upload_id = Some(upload_id.clone());
                 notion
-                    .create_resource_page(
+                    .create_resource_page_with_file_upload(
                         notion_ids,
                         parent_page_id.as_deref(),
                         resource.sequence,
                         text,
-                        None,
-                        None,
+                        Some(file_name),
+                        Some(&upload_id),
+                        &meta,
                     )
                     .await?
             }
         } else {
-            // Fallback for mock implementations without upload support
+            // Fallback: create without media
             notion
                 .create_resource_page(
                     notion_ids,
@@ -220,12 +607,86 @@ async fn push_resource_task(
                     text,
                     None,
                     None,
+                    &meta,
                 )
                 .await?
         }
     };
-    db::mark_resource_notion_page_id(pool, resource_id, &page_id).await?;
-    Ok(())
+
+    if let Some(block) =
+        image_block.filter(|_| opts.images_as_blocks && resource.kind == ResourceKind::Photo)
+    {
+        // The page already exists; failing here would re-create it on retry
+        if let Err(err) = notion.append_block_children(&page_id, &[block]).await {
+            warn!(?err, resource_id, page_id=%page_id, "failed to append image block");
+        }
+    }
+    if let (Some(upload_id), Some(batch_id), Some(main_page_id)) = (
+        photo_upload_id.as_deref(),
+        resource.batch_id,
+        parent_page_id.as_deref(),
+    ) {
+        // Best effort, like the image block: the resource page already exists
+        match db::first_batch_resource_id(pool, batch_id).await {
+            Ok(Some(first_id)) if first_id == resource_id => {
+                if let Err(err) = notion
+                    .update_page_cover(main_page_id, ImageSource::FileUpload(upload_id))
+                    .await
+                {
+                    warn!(?err, batch_id, main_page_id, "failed to set batch cover");
+                }
+            }
+            Ok(_) => {}
+            Err(err) => warn!(?err, batch_id, "failed to look up batch cover"),
+        }
+    }
+    if uploaded {
+        if let Err(err) = db::mark_resource_media_uploaded(pool, resource_id).await {
+            warn!(?err, resource_id, "failed to record media upload");
+        }
+    }
+    Ok(Some(page_id))
+}
+
+/// Rewrite the text property of an already-synced resource page after the
+/// Telegram message was edited. Always returns `None`: the page id is unchanged.
+async fn update_resource_task(
+    pool: &SqlitePool,
+    notion: &dyn NotionService,
+    notion_ids: &NotionIds,
+    opts: &WorkerOptions,
+    resource_id: i64,
+) -> Result<Option<String>> {
+    let resource: ResourceForOutbox = db::fetch_resource_for_outbox(pool, resource_id).await?;
+    let Some(page_id) = resource.notion_page_id.as_deref() else {
+        warn!(resource_id, "resource not synced; nothing to update");
+        return Ok(None);
+    };
+    let notion_ids = route_ids(notion_ids, opts, resource.notion_route)?;
+    let text = resource.text.as_deref().unwrap_or_default();
+    info!(resource_id, page_id, "updating resource text in Notion");
+    notion
+        .update_page_properties(
+            page_id,
+            &build_text_properties(notion_ids, text, opts.parse_markdown),
+        )
+        .await?;
+    Ok(None)
+}
+
+/// Longest caption line used as a media file name.
+const CAPTION_NAME_CHARS: usize = 60;
+
+/// Name for media from the first non-blank line of its caption, truncated to
+/// [`CAPTION_NAME_CHARS`].
+fn caption_media_name(caption: &str) -> Option<String> {
+    let line = caption.lines().map(str::trim).find(|l| !l.is_empty())?;
+    if line.chars().count() <= CAPTION_NAME_CHARS {
+        return Some(line.to_string());
+    }
+    let mut name: String = line.chars().take(CAPTION_NAME_CHARS).collect();
+    name.push('…');
+    Some(name)
 }
 
 fn sanitize_media_url(raw: Option<&str>) -> Option<String> {
@@ -242,11 +703,13 @@ fn sanitize_media_url(raw: Option<&str>) -> Option<String> {
     Some(url.to_string())
 }
 
-/// Try to derive `{data_dir}/media/thumbs/{stem}.jpg` from a video path like
-/// `{data_dir}/media/{user_id}/{stem}.{ext}`.
+/// Try to derive `{data_dir}/media/thumbs/{stem}.{format ext}` from a video
+/// path like `{data_dir}/media/{user_id}/{stem}.{ext}`. Only used for
+/// resources saved before `thumb_path` was recorded.
 fn derive_thumb_path_from_video(
     video_path: &std::path::Path,
     stem: &str,
+    format: ThumbnailFormat,
 ) -> Option<std::path::PathBuf> {
     // Find the "media" directory in the ancestors
     let mut cur = video_path.parent();
@@ -254,14 +717,115 @@ fn derive_thumb_path_from_video(
         if p.file_name().and_then(|n| n.to_str()) == Some("media") {
             // data_dir is parent of media
             let data_dir = p.parent()?;
-            return Some(
-                data_dir
-                    .join("media")
-                    .join("thumbs")
-                    .join(format!("{}.jpg", stem)),
-            );
+            return Some(data_dir.join("media").join("thumbs").join(format!(
+                "{}.{}",
+                stem,
+                format.extension()
+            )));
         }
         cur = p.parent();
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn signal_wakes_a_waiter_promptly() {
+        let signal = OutboxSignal::default();
+        let waiter = tokio::spawn({
+            let signal = signal.clone();
+            async move {
+                let started = std::time::Instant::now();
+                signal.wait(Duration::from_secs(30)).await;
+                started.elapsed()
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        signal.notify();
+        let waited = tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .expect("waiter was not woken")
+            .unwrap();
+        assert!(waited < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn signal_sent_while_busy_is_kept_for_the_next_wait() {
+        let signal = OutboxSignal::default();
+        signal.notify();
+        tokio::time::timeout(Duration::from_secs(1), signal.wait(Duration::from_secs(30)))
+            .await
+            .expect("stored signal was lost");
+    }
+
+    #[test]
+    fn caption_media_name_uses_first_line_truncated() {
+        assert_eq!(
+            caption_media_name("\n  Sunset at the pier \nsecond line").as_deref(),
+            Some("Sunset at the pier")
+        );
+        assert_eq!(caption_media_name(" \n "), None);
+        let long = "a".repeat(CAPTION_NAME_CHARS + 5);
+        assert_eq!(
+            caption_media_name(&long),
+            Some(format!("{}…", "a".repeat(CAPTION_NAME_CHARS)))
+        );
+    }
+
+    #[test]
+    fn only_notion_failures_count_as_failed_at_notion() {
+        let server = anyhow::Error::new(NotionError::Server(reqwest::StatusCode::BAD_GATEWAY))
+            .context("notion create page failed");
+        assert!(failed_at_notion(&server));
+        assert!(!failed_at_notion(&anyhow!(
+            "batch 3 not committed (state Open)"
+        )));
+        let missing = std::io::Error::new(std::io::ErrorKind::NotFound, "no such file");
+        assert!(!failed_at_notion(
+            &anyhow::Error::new(missing).context("failed to read file")
+        ));
+    }
+
+    #[test]
+    fn batch_title_prefers_own_title_then_expands_default() {
+        let created = "2024-01-31 12:34:56";
+        assert_eq!(batch_title(Some("Trip"), "Batch {date}", created), "Trip");
+        assert_eq!(batch_title(Some("  "), "Untitled", created), "Untitled");
+        assert_eq!(
+            batch_title(None, "Batch {date}", created),
+            "Batch 2024-01-31"
+        );
+    }
+
+    #[test]
+    fn auto_mode_uploads_small_and_links_large() {
+        let threshold = 1024;
+        assert_eq!(
+            choose_media_source(MediaMode::Auto, 512, threshold),
+            MediaSource::Upload
+        );
+        assert_eq!(
+            choose_media_source(MediaMode::Auto, threshold, threshold),
+            MediaSource::Upload
+        );
+        assert_eq!(
+            choose_media_source(MediaMode::Auto, threshold + 1, threshold),
+            MediaSource::External
+        );
+    }
+
+    #[test]
+    fn fixed_modes_ignore_size() {
+        assert_eq!(
+            choose_media_source(MediaMode::Upload, u64::MAX, 1),
+            MediaSource::Upload
+        );
+        assert_eq!(
+            choose_media_source(MediaMode::External, 0, 1),
+            MediaSource::External
+        );
+    }
+}
@@ -1,7 +1,25 @@
+use crate::config::ThumbnailFormat;
 use anyhow::{anyhow, Context, Result};
 use std::path::{Path, PathBuf};
 use tokio::process::Command;
 
+/// Whether video thumbnails can be generated on this host.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FfmpegStatus {
+    #[default]
+    Available,
+    Missing,
+}
+
+/// Probe for `ffmpeg` without failing; see [`ensure_ffmpeg_available`].
+#[allow(dead_code)]
+pub async fn ffmpeg_status() -> FfmpegStatus {
+    match ensure_ffmpeg_available().await {
+        Ok(()) => FfmpegStatus::Available,
+        Err(_) => FfmpegStatus::Missing,
+    }
+}
+
 /// Ensure `ffmpeg` binary is available on PATH by invoking `ffmpeg -version`.
 pub async fn ensure_ffmpeg_available() -> Result<()> {
     let status = Command::new("ffmpeg")
@@ -16,25 +34,43 @@ pub async fn ensure_ffmpeg_available() -> Result<()> {
     }
 }
 
-/// Generate a JPEG thumbnail for a given video into `{data_dir}/media/thumbs/`.
-/// The thumbnail file name uses the video's file stem, with `.jpg` extension.
-/// If the thumbnail already exists, returns its path without re-generating.
-pub async fn generate_thumbnail<P: AsRef<Path>>(video_path: P, data_dir: &str) -> Result<PathBuf> {
-    let video_path = video_path.as_ref();
+/// `{data_dir}/media/thumbs/{video_stem}.{ext}`, where a thumbnail in `format` is stored.
+pub fn thumbnail_path(
+    video_path: &Path,
+    data_dir: &str,
+    format: ThumbnailFormat,
+) -> Result<PathBuf> {
     let stem = video_path
         .file_stem()
         .and_then(|s| s.to_str())
         .ok_or_else(|| anyhow!("invalid video file name"))?;
+    Ok(Path::new(data_dir)
+        .join("media")
+        .join("thumbs")
+        .join(format!("{}.{}", stem, format.extension())))
+}
 
-    let thumbs_dir = Path::new(data_dir).join("media").join("thumbs");
-    tokio::fs::create_dir_all(&thumbs_dir)
+/// Generate a thumbnail for a given video at [`thumbnail_path`].
+/// If the thumbnail already exists, returns its path without re-generating.
+pub async fn generate_thumbnail<P: AsRef<Path>>(
+    video_path: P,
+    data_dir: &str,
+    format: ThumbnailFormat,
+) -> Result<PathBuf> {
+    let video_path = video_path.as_ref();
+    let thumb_path = thumbnail_path(video_path, data_dir, format)?;
+    let thumbs_dir = thumb_path.parent().expect("thumbnail path has a parent");
+    tokio::fs::create_dir_all(thumbs_dir)
         .await
         .with_context(|| format!("failed to create thumbs dir: {}", thumbs_dir.display()))?;
 
-    let thumb_path = thumbs_dir.join(format!("{}.jpg", stem));
     if tokio::fs::try_exists(&thumb_path).await.unwrap_or(false) {
         return Ok(thumb_path);
     }
+    let quality: &[&str] = match format {
+        ThumbnailFormat::Jpg => &["-q:v", "6"],
+        ThumbnailFormat::Webp => &["-c:v", "libwebp", "-quality", "75"],
+    };
 
     // Run ffmpeg: first frame, scale to max width 480, keep aspect, good quality.
     // Use simple scale=480:-2 to avoid shell quoting issues.
@@ -48,8 +84,7 @@ pub async fn generate_thumbnail<P: AsRef<Path>>(video_path: P, data_dir: &str) -
         .arg("1")
         .arg("-vf")
         .arg("scale=480:-2:flags=lanczos")
-        .arg("-q:v")
-        .arg("6")
+        .args(quality)
         .arg(thumb_path.as_os_str())
         .kill_on_drop(true)
         .status()
@@ -66,3 +101,20 @@ pub async fn generate_thumbnail<P: AsRef<Path>>(video_path: P, data_dir: &str) -
 
     Ok(thumb_path)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notion::content_type_for_path;
+
+    #[test]
+    fn thumbnail_path_and_content_type_follow_format() {
+        let video = Path::new("/data/media/7/3_abc.mp4");
+        let jpg = thumbnail_path(video, "/data", ThumbnailFormat::Jpg).unwrap();
+        assert_eq!(jpg, Path::new("/data/media/thumbs/3_abc.jpg"));
+        assert_eq!(content_type_for_path(&jpg), "image/jpeg");
+        let webp = thumbnail_path(video, "/data", ThumbnailFormat::Webp).unwrap();
+        assert_eq!(webp, Path::new("/data/media/thumbs/3_abc.webp"));
+        assert_eq!(content_type_for_path(&webp), "image/webp");
+    }
+}
@@ -0,0 +1,230 @@
+//! Pre-sync checks shared by the outbox worker and the `/validate` command.
+//!
+//! Every check mirrors a limit Notion enforces at request time, so a batch that
+//! passes here should not be rejected when the worker pushes it.
+
+use crate::db::ResourceForOutbox;
+use crate::model::ResourceKind;
+use crate::notion::{content_type_for_path, NotionIds};
+use std::path::Path;
+use thiserror::Error;
+
+/// Notion caps each rich_text/title text object at 2000 characters.
+pub const MAX_TEXT_CHARS: usize = 2000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Problem {
+    #[error("title is {len} characters (limit {})", MAX_TEXT_CHARS)]
+    TitleTooLong { len: usize },
+    #[error(
+        "item #{sequence}: text is {len} characters (limit {})",
+        MAX_TEXT_CHARS
+    )]
+    TextTooLong { sequence: i64, len: usize },
+    #[error("item #{sequence}: unsupported media type ({name})")]
+    UnsupportedMedia { sequence: i64, name: String },
+    #[error("Notion property for {field} is not resolved")]
+    UnresolvedProperty { field: &'static str },
+}
+
+pub fn check_title(title: &str) -> Result<(), Problem> {
+    let len = title.chars().count();
+    if len > MAX_TEXT_CHARS {
+        return Err(Problem::TitleTooLong { len });
+    }
+    Ok(())
+}
+
+pub fn check_text(sequence: i64, text: &str) -> Result<(), Problem> {
+    let len = text.chars().count();
+    if len > MAX_TEXT_CHARS {
+        return Err(Problem::TextTooLong { sequence, len });
+    }
+    Ok(())
+}
+
+/// Media without an external URL must be a file type we know how to upload.
+pub fn check_media(resource: &ResourceForOutbox) -> Result<(), Problem> {
+    match resource.kind {
+        ResourceKind::Text | ResourceKind::Location => return Ok(()),
+        ResourceKind::Photo | ResourceKind::Video => {}
+    }
+    let has_url = resource
+        .media_url
+        .as_deref()
+        .is_some_and(|u| !u.trim().is_empty());
+    if has_url {
+        return Ok(());
+    }
+    if content_type_for_path(Path::new(&resource.content)) == "application/octet-stream" {
+        let name = Path::new(&resource.content)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or(&resource.content)
+            .to_string();
+        return Err(Problem::UnsupportedMedia {
+            sequence: resource.sequence,
+            name,
+        });
+    }
+    Ok(())
+}
+
+pub fn check_property_ids(ids: &NotionIds) -> Vec<Problem> {
+    let fields: [(&'static str, &str); 7] = [
+        ("main database", ids.main_db.as_str()),
+        ("resource database", ids.resource_db.as_str()),
+        ("main title", ids.f_main_title.as_str()),
+        ("resource relation", ids.f_rel_parent.as_str()),
+        ("resource order", ids.f_res_order.as_str()),
+        ("resource text", ids.f_res_text.as_str()),
+        ("resource media", ids.f_res_media.as_str()),
+    ];
+    fields
+        .into_iter()
+        .filter(|(_, value)| value.trim().is_empty())
+        .map(|(field, _)| Problem::UnresolvedProperty { field })
+        .collect()
+}
+
+/// All problems the worker would hit when pushing a single resource.
+pub fn validate_resource(resource: &ResourceForOutbox) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    if let Some(text) = resource.text.as_deref() {
+        if let Err(p) = check_text(resource.sequence, text) {
+            problems.push(p);
+        }
+    }
+    if let Err(p) = check_media(resource) {
+        problems.push(p);
+    }
+    problems
+}
+
+/// All problems the worker would hit when pushing a batch and its resources.
+pub fn validate_batch(
+    title: Option<&str>,
+    resources: &[ResourceForOutbox],
+    ids: Option<&NotionIds>,
+) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    if let Some(ids) = ids {
+        problems.extend(check_property_ids(ids));
+    }
+    if let Some(title) = title {
+        if let Err(p) = check_title(title) {
+            problems.push(p);
+        }
+    }
+    for resource in resources {
+        problems.extend(validate_resource(resource));
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::BatchState;
+
+    fn ids() -> NotionIds {
+        NotionIds {
+            main_db: "main-db".into(),
+            resource_db: "resource-db".into(),
+            f_main_title: "title".into(),
+            f_rel_parent: "rel".into(),
+            f_res_order: "order".into(),
+            f_res_text: "text".into(),
+            f_res_media: "media".into(),
+            f_res_source_url: None,
+            f_res_date: None,
+            f_res_kind: None,
+            f_res_width: None,
+            f_res_height: None,
+            f_res_duration: None,
+        }
+    }
+
+    fn resource(sequence: i64, kind: ResourceKind, content: &str) -> ResourceForOutbox {
+        ResourceForOutbox {
+            batch_id: Some(1),
+            sequence,
+            kind,
+            content: content.into(),
+            text: (kind == ResourceKind::Text).then(|| content.to_string()),
+            media_name: None,
+            media_url: None,
+            source_url: None,
+            tg_date: None,
+            thumb_path: None,
+            notion_page_id: None,
+            batch_state: Some(BatchState::Open),
+            batch_notion_page_id: None,
+            notion_route: None,
+            chat_id: None,
+            dimensions: Default::default(),
+        }
+    }
+
+    #[test]
+    fn valid_batch_has_no_problems() {
+        let resources = vec![
+            resource(1, ResourceKind::Text, "hello"),
+            resource(2, ResourceKind::Photo, "/data/media/1/2_abc.jpg"),
+            resource(3, ResourceKind::Video, "/data/media/1/3_def.mp4"),
+        ];
+        assert!(validate_batch(Some("Trip"), &resources, Some(&ids())).is_empty());
+    }
+
+    #[test]
+    fn long_title_is_reported() {
+        let title = "x".repeat(MAX_TEXT_CHARS + 1);
+        let problems = validate_batch(Some(&title), &[], None);
+        assert_eq!(
+            problems,
+            vec![Problem::TitleTooLong {
+                len: MAX_TEXT_CHARS + 1
+            }]
+        );
+    }
+
+    #[test]
+    fn long_text_is_reported() {
+        let text = "é".repeat(MAX_TEXT_CHARS + 5);
+        let problems = validate_batch(None, &[resource(4, ResourceKind::Text, &text)], None);
+        assert_eq!(
+            problems,
+            vec![Problem::TextTooLong {
+                sequence: 4,
+                len: MAX_TEXT_CHARS + 5
+            }]
+        );
+    }
+
+    #[test]
+    fn unknown_media_type_is_reported_unless_external() {
+        let mut r = resource(2, ResourceKind::Photo, "/data/media/1/2_abc.heic");
+        assert_eq!(
+            validate_batch(None, std::slice::from_ref(&r), None),
+            vec![Problem::UnsupportedMedia {
+                sequence: 2,
+                name: "2_abc.heic".into()
+            }]
+        );
+
+        r.media_url = Some("https://cdn.example/a.heic".into());
+        assert!(validate_batch(None, &[r], None).is_empty());
+    }
+
+    #[test]
+    fn unresolved_property_is_reported() {
+        let mut ids = ids();
+        ids.f_res_media = "".into();
+        assert_eq!(
+            validate_batch(None, &[], Some(&ids)),
+            vec![Problem::UnresolvedProperty {
+                field: "resource media"
+            }]
+        );
+    }
+}
@@ -0,0 +1,152 @@
+mod common;
+
+use chrono::{TimeZone, Utc};
+use common::{MockResponse, MockServer};
+use reqwest::Url;
+use serde_json::json;
+use std::time::Duration;
+use tg_watchbot::notion::{NotionClient, NotionError, NotionIds, ResourceMeta};
+
+fn ids() -> NotionIds {
+    NotionIds {
+        main_db: "main-db".into(),
+        resource_db: "res-db".into(),
+        f_main_title: "title".into(),
+        f_rel_parent: "rel".into(),
+        f_res_order: "order".into(),
+        f_res_text: "text".into(),
+        f_res_media: "media".into(),
+        f_res_source_url: Some("source".into()),
+        f_res_date: Some("date".into()),
+        f_res_kind: None,
+        f_res_width: None,
+        f_res_height: None,
+        f_res_duration: None,
+    }
+}
+
+fn client(server: &MockServer) -> NotionClient {
+    let http = reqwest::Client::builder()
+        .no_proxy()
+        .timeout(Duration::from_secs(5))
+        .build()
+        .unwrap();
+    NotionClient::with_client(
+        http,
+        "token".into(),
+        "2022-06-28".into(),
+        Url::parse(&server.url).unwrap(),
+    )
+}
+
+#[tokio::test]
+async fn create_resource_page_sends_expected_body() {
+    let server =
+        MockServer::start(|_req, _base| MockResponse::json(200, json!({ "id": "res-page" }))).await;
+    let notion = client(&server);
+    let meta = ResourceMeta {
+        source_url: Some("https://t.me/chan/7".into()),
+        tg_date: Some(Utc.with_ymd_and_hms(2024, 1, 31, 12, 0, 0).unwrap()),
+        parse_markdown: false,
+        kind: None,
+        dimensions: Default::default(),
+    };
+
+    let page_id = notion
+        .create_resource_page(
+            &ids(),
+            Some("main-page"),
+            3,
+            Some("hello"),
+            Some("map"),
+            Some("https://example.com/map"),
+            &meta,
+        )
+        .await
+        .unwrap();
+    assert_eq!(page_id, "res-page");
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method, "POST");
+    assert_eq!(requests[0].path, "/v1/pages");
+    assert_eq!(requests[0].header("Authorization"), Some("Bearer token"));
+    assert_eq!(requests[0].header("Notion-Version"), Some("2022-06-28"));
+    assert_eq!(
+        requests[0].json(),
+        json!({
+            "parent": { "database_id": "res-db" },
+            "properties": {
+                "rel": { "relation": [ { "id": "main-page" } ] },
+                "order": { "number": 3 },
+                "text": { "rich_text": [ { "text": { "content": "hello" } } ] },
+                "media": {
+                    "files": [
+                        { "name": "map", "type": "external", "external": { "url": "https://example.com/map" } }
+                    ]
+                },
+                "source": { "url": "https://t.me/chan/7" },
+                "date": { "date": { "start": "2024-01-31T12:00:00Z" } }
+            }
+        })
+    );
+}
+
+#[tokio::test]
+async fn rate_limited_create_reports_retry_after() {
+    let server = MockServer::start(|_req, _base| {
+        MockResponse::json(429, json!({ "code": "rate_limited" })).with_header("Retry-After", "7")
+    })
+    .await;
+    let notion = client(&server);
+
+    let err = notion
+        .create_resource_page(
+            &ids(),
+            None,
+            1,
+            Some("hello"),
+            None,
+            None,
+            &ResourceMeta::default(),
+        )
+        .await
+        .unwrap_err();
+    match err.downcast_ref::<NotionError>() {
+        Some(NotionError::RateLimited { retry_after }) => {
+            assert_eq!(*retry_after, Some(Duration::from_secs(7)))
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn page_update_and_retrieve_errors_are_classified() {
+    let server = MockServer::start(|req, _base| match req.method.as_str() {
+        "PATCH" => MockResponse::json(
+            409,
+            json!({ "object": "error", "code": "conflict_error", "message": "try again" }),
+        ),
+        _ => MockResponse::json(
+            404,
+            json!({ "object": "error", "code": "object_not_found", "message": "gone" }),
+        ),
+    })
+    .await;
+    let notion = client(&server);
+
+    let err = notion
+        .update_page_properties("page-1", &json!({}))
+        .await
+        .unwrap_err();
+    match err.downcast_ref::<NotionError>() {
+        Some(conflict @ NotionError::Conflict(_)) => assert!(conflict.is_retryable()),
+        other => panic!("unexpected error: {:?}", other),
+    }
+
+    let err = notion.retrieve_page("page-1").await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<NotionError>(),
+        Some(NotionError::NotFound)
+    ));
+}
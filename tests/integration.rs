@@ -1,10 +1,15 @@
 use anyhow::{anyhow, Result};
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use tg_watchbot::config;
 use tg_watchbot::db;
-use tg_watchbot::notion::{NotionIds, NotionService};
-use tg_watchbot::outbox::process_next_task;
+use tg_watchbot::handlers;
+use tg_watchbot::model::ResourceKind;
+use tg_watchbot::notion::model::RetrievePageResp;
+use tg_watchbot::notion::{ImageSource, NotionIds, NotionService, ResourceMeta};
+use tg_watchbot::outbox::{process_next_task, WorkerOptions};
 use tokio::sync::Mutex;
 use tokio::time::Duration;
 
@@ -23,10 +28,13 @@ async fn setup_pool() -> sqlx::SqlitePool {
 #[derive(Debug, Clone, Default)]
 struct MainCall {
     title: String,
+    icon_emoji: Option<String>,
+    cover_url: Option<String>,
 }
 
 #[derive(Debug, Clone, Default)]
 struct ResourceCall {
+    resource_db: String,
     parent: Option<String>,
     order: i64,
     text: Option<String>,
@@ -34,11 +42,31 @@ struct ResourceCall {
     media_url: Option<String>,
 }
 
+#[derive(Debug, Clone, Default)]
+struct UploadPageCall {
+    order: i64,
+    files: Vec<(String, String)>,
+}
+
 #[derive(Clone, Default)]
 struct RecordingNotion {
     responses: Arc<Mutex<VecDeque<Result<String>>>>,
     main_calls: Arc<Mutex<Vec<MainCall>>>,
     resource_calls: Arc<Mutex<Vec<ResourceCall>>>,
+    uploads: Arc<Mutex<Vec<String>>>,
+    upload_page_calls: Arc<Mutex<Vec<UploadPageCall>>>,
+    page_updates: Arc<Mutex<Vec<(String, serde_json::Value)>>>,
+    /// `(page_id, file_upload_id)` of every cover update
+    cover_updates: Arc<Mutex<Vec<(String, String)>>>,
+    /// Main pages already in Notion, by title
+    existing_main_pages: Arc<Mutex<HashMap<String, String>>>,
+    /// Simulated latency of page creation, so concurrent workers overlap
+    delay: Duration,
+    /// Simulated latency of file uploads, so concurrent uploads overlap
+    upload_delay: Duration,
+    /// Uploads currently running, and the most ever running at once
+    uploads_in_flight: Arc<AtomicUsize>,
+    max_uploads_in_flight: Arc<AtomicUsize>,
 }
 
 impl RecordingNotion {
@@ -50,6 +78,9 @@ impl RecordingNotion {
     }
 
     async fn pop_response(&self) -> Result<String> {
+        if !self.delay.is_zero() {
+            tokio::time::sleep(self.delay).await;
+        }
         let mut guard = self.responses.lock().await;
         guard.pop_front().unwrap_or_else(|| Ok("page-id".into()))
     }
@@ -61,27 +92,57 @@ impl RecordingNotion {
     async fn resource_calls(&self) -> Vec<ResourceCall> {
         self.resource_calls.lock().await.clone()
     }
+
+    async fn uploads(&self) -> Vec<String> {
+        self.uploads.lock().await.clone()
+    }
+
+    async fn upload_page_calls(&self) -> Vec<UploadPageCall> {
+        self.upload_page_calls.lock().await.clone()
+    }
+
+    async fn page_updates(&self) -> Vec<(String, serde_json::Value)> {
+        self.page_updates.lock().await.clone()
+    }
+
+    async fn cover_updates(&self) -> Vec<(String, String)> {
+        self.cover_updates.lock().await.clone()
+    }
 }
 
 #[async_trait::async_trait]
 impl NotionService for RecordingNotion {
-    async fn create_main_page(&self, _ids: &NotionIds, title: &str) -> Result<String> {
+    async fn create_main_page(
+        &self,
+        _ids: &NotionIds,
+        title: &str,
+        icon_emoji: Option<&str>,
+        cover_url: Option<&str>,
+    ) -> Result<String> {
         self.main_calls.lock().await.push(MainCall {
             title: title.to_string(),
+            icon_emoji: icon_emoji.map(str::to_string),
+            cover_url: cover_url.map(str::to_string),
         });
         self.pop_response().await
     }
 
+    async fn find_main_page(&self, _ids: &NotionIds, title: &str) -> Result<Option<String>> {
+        Ok(self.existing_main_pages.lock().await.get(title).cloned())
+    }
+
     async fn create_resource_page(
         &self,
-        _ids: &NotionIds,
+        ids: &NotionIds,
         parent_main_page_id: Option<&str>,
         order: i64,
         text: Option<&str>,
         media_name: Option<&str>,
         media_url: Option<&str>,
+        _meta: &ResourceMeta,
     ) -> Result<String> {
         self.resource_calls.lock().await.push(ResourceCall {
+            resource_db: ids.resource_db.clone(),
             parent: parent_main_page_id.map(str::to_string),
             order,
             text: text.map(str::to_string),
@@ -90,6 +151,88 @@ impl NotionService for RecordingNotion {
         });
         self.pop_response().await
     }
+
+    async fn upload_file(&self, file_path: &Path) -> Result<String> {
+        let running = self.uploads_in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_uploads_in_flight
+            .fetch_max(running, Ordering::SeqCst);
+        let id = {
+            let mut uploads = self.uploads.lock().await;
+            uploads.push(
+                file_path
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned(),
+            );
+            format!("upload-{}", uploads.len())
+        };
+        if !self.upload_delay.is_zero() {
+            tokio::time::sleep(self.upload_delay).await;
+        }
+        self.uploads_in_flight.fetch_sub(1, Ordering::SeqCst);
+        Ok(id)
+    }
+
+    async fn create_resource_page_with_file_upload(
+        &self,
+        _ids: &NotionIds,
+        _parent_main_page_id: Option<&str>,
+        order: i64,
+        _text: Option<&str>,
+        media_name: Option<&str>,
+        file_upload_id: Option<&str>,
+        _meta: &ResourceMeta,
+    ) -> Result<String> {
+        let files = media_name
+            .zip(file_upload_id)
+            .map(|(name, id)| vec![(name.to_string(), id.to_string())])
+            .unwrap_or_default();
+        self.upload_page_calls
+            .lock()
+            .await
+            .push(UploadPageCall { order, files });
+        self.pop_response().await
+    }
+
+    async fn create_resource_page_with_file_uploads(
+        &self,
+        _ids: &NotionIds,
+        _parent_main_page_id: Option<&str>,
+        order: i64,
+        _text: Option<&str>,
+        files: &[(String, String)],
+        _meta: &ResourceMeta,
+    ) -> Result<String> {
+        self.upload_page_calls.lock().await.push(UploadPageCall {
+            order,
+            files: files.to_vec(),
+        });
+        self.pop_response().await
+    }
+
+    async fn update_page_properties(
+        &self,
+        page_id: &str,
+        properties: &serde_json::Value,
+    ) -> Result<()> {
+        self.page_updates
+            .lock()
+            .await
+            .push((page_id.to_string(), properties.clone()));
+        Ok(())
+    }
+
+    async fn update_page_cover(&self, page_id: &str, cover: ImageSource<'_>) -> Result<()> {
+        let ImageSource::FileUpload(id) = cover else {
+            return Err(anyhow!("expected an uploaded cover"));
+        };
+        self.cover_updates
+            .lock()
+            .await
+            .push((page_id.to_string(), id.to_string()));
+        Ok(())
+    }
 }
 
 #[tokio::test]
@@ -101,14 +244,19 @@ async fn single_message_creates_resource_page() {
     let user_id = db::get_or_create_user(&pool, 42, Some("tester"), Some("Tester"))
         .await
         .unwrap();
-    let resource_id = db::insert_resource(&pool, user_id, None, "text", "hello world", 7)
+    let resource_id =
+        db::insert_resource(&pool, user_id, None, ResourceKind::Text, "hello world", 7)
+            .await
+            .unwrap();
+
+    let processed = process_next_task(&pool, &notion, &ids, &WorkerOptions::default())
         .await
         .unwrap();
-
-    let processed = process_next_task(&pool, &notion, &ids, 60).await.unwrap();
     assert!(processed);
 
-    let processed = process_next_task(&pool, &notion, &ids, 60).await.unwrap();
+    let processed = process_next_task(&pool, &notion, &ids, &WorkerOptions::default())
+        .await
+        .unwrap();
     assert!(!processed);
 
     let stored: Option<String> =
@@ -143,12 +291,26 @@ async fn transactional_flow_creates_main_and_resources() {
         .unwrap();
     let batch_id = db::open_batch(&pool, user_id).await.unwrap();
 
-    let r1 = db::insert_resource(&pool, user_id, Some(batch_id), "text", "note", 10)
-        .await
-        .unwrap();
-    let r2 = db::insert_resource(&pool, user_id, Some(batch_id), "photo", "ignored", 11)
-        .await
-        .unwrap();
+    let r1 = db::insert_resource(
+        &pool,
+        user_id,
+        Some(batch_id),
+        ResourceKind::Text,
+        "note",
+        10,
+    )
+    .await
+    .unwrap();
+    let r2 = db::insert_resource(
+        &pool,
+        user_id,
+        Some(batch_id),
+        ResourceKind::Photo,
+        "ignored",
+        11,
+    )
+    .await
+    .unwrap();
     sqlx::query("UPDATE resources SET media_name = ?, media_url = ? WHERE id = ?")
         .bind("a.jpg")
         .bind("https://cdn.example/a.jpg")
@@ -162,7 +324,10 @@ async fn transactional_flow_creates_main_and_resources() {
         .unwrap();
 
     for _ in 0..10 {
-        if process_next_task(&pool, &notion, &ids, 60).await.unwrap() {
+        if process_next_task(&pool, &notion, &ids, &WorkerOptions::default())
+            .await
+            .unwrap()
+        {
             continue;
         }
 
@@ -174,7 +339,7 @@ async fn transactional_flow_creates_main_and_resources() {
             break;
         }
 
-        sqlx::query("UPDATE outbox SET due_at = datetime('now', '-1 seconds')")
+        sqlx::query("UPDATE outbox SET due_at = 0")
             .execute(&pool)
             .await
             .unwrap();
@@ -230,11 +395,13 @@ async fn notion_retry_on_failure() {
     let user_id = db::get_or_create_user(&pool, 55, Some("retry"), Some("Retry"))
         .await
         .unwrap();
-    let resource_id = db::insert_resource(&pool, user_id, None, "text", "retry me", 21)
+    let resource_id = db::insert_resource(&pool, user_id, None, ResourceKind::Text, "retry me", 21)
         .await
         .unwrap();
 
-    let processed = process_next_task(&pool, &notion, &ids, 60).await.unwrap();
+    let processed = process_next_task(&pool, &notion, &ids, &WorkerOptions::default())
+        .await
+        .unwrap();
     assert!(processed);
 
     let attempt: i32 = sqlx::query_scalar("SELECT attempt FROM outbox LIMIT 1")
@@ -243,14 +410,16 @@ async fn notion_retry_on_failure() {
         .unwrap();
     assert_eq!(attempt, 1);
 
-    sqlx::query("UPDATE outbox SET due_at = datetime('now', '-1 seconds')")
+    sqlx::query("UPDATE outbox SET due_at = 0")
         .execute(&pool)
         .await
         .unwrap();
 
     tokio::time::sleep(Duration::from_millis(10)).await;
 
-    let processed = process_next_task(&pool, &notion, &ids, 60).await.unwrap();
+    let processed = process_next_task(&pool, &notion, &ids, &WorkerOptions::default())
+        .await
+        .unwrap();
     assert!(processed);
 
     let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM outbox")
@@ -272,3 +441,917 @@ async fn notion_retry_on_failure() {
     assert_eq!(calls[0].text.as_deref(), Some("retry me"));
     assert_eq!(calls[1].text.as_deref(), Some("retry me"));
 }
+
+#[tokio::test]
+async fn persistent_failure_is_dead_lettered() {
+    let pool = setup_pool().await;
+    let ids = load_notion_ids();
+    let notion = RecordingNotion::with_responses(vec![
+        Err(anyhow!("boom 1")),
+        Err(anyhow!("boom 2")),
+        Err(anyhow!("boom 3")),
+    ]);
+    let opts = WorkerOptions {
+        max_attempts: 3,
+        ..WorkerOptions::default()
+    };
+
+    let user_id = db::get_or_create_user(&pool, 77, Some("dead"), Some("Dead"))
+        .await
+        .unwrap();
+    let resource_id =
+        db::insert_resource(&pool, user_id, None, ResourceKind::Text, "never syncs", 5)
+            .await
+            .unwrap();
+
+    for _ in 0..3 {
+        assert!(process_next_task(&pool, &notion, &ids, &opts)
+            .await
+            .unwrap());
+        sqlx::query("UPDATE outbox SET due_at = 0")
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(db::count_remaining_outbox_tasks(&pool).await.unwrap(), 0);
+    assert!(!process_next_task(&pool, &notion, &ids, &opts)
+        .await
+        .unwrap());
+
+    let dead = db::list_dead_letters(&pool).await.unwrap();
+    assert_eq!(dead.len(), 1);
+    assert_eq!(dead[0].kind, "push_resource");
+    assert_eq!(dead[0].ref_id, resource_id);
+    assert_eq!(dead[0].attempt, 3);
+    assert_eq!(dead[0].last_error.as_deref(), Some("boom 3"));
+    assert_eq!(notion.resource_calls().await.len(), 3);
+}
+
+#[tokio::test]
+async fn video_note_uploads_thumbnail_then_video() {
+    let pool = setup_pool().await;
+    let ids = load_notion_ids();
+    let notion = RecordingNotion::with_responses(vec![Ok("note-page".into())]);
+
+    let data_dir = tempfile::tempdir().unwrap();
+    let name = handlers::video_note_file_name(5, "rnd");
+    assert_eq!(name, "video_note_5_rnd.mp4");
+    let video = data_dir.path().join("media").join("42").join(&name);
+    let thumb = data_dir
+        .path()
+        .join("media")
+        .join("thumbs")
+        .join("video_note_5_rnd.jpg");
+    std::fs::create_dir_all(video.parent().unwrap()).unwrap();
+    std::fs::create_dir_all(thumb.parent().unwrap()).unwrap();
+    std::fs::write(&video, b"round").unwrap();
+    std::fs::write(&thumb, b"thumb").unwrap();
+
+    let user_id = db::get_or_create_user(&pool, 42, Some("note"), Some("Note"))
+        .await
+        .unwrap();
+    let thumb_path = thumb.to_string_lossy();
+    let origin = db::ResourceOrigin {
+        unique_id: Some("rnd"),
+        thumb_path: Some(&thumb_path),
+        ..db::ResourceOrigin::default()
+    };
+    db::insert_resource_from(
+        &pool,
+        user_id,
+        None,
+        ResourceKind::Video,
+        &video.to_string_lossy(),
+        5,
+        &origin,
+    )
+    .await
+    .unwrap();
+
+    assert!(
+        process_next_task(&pool, &notion, &ids, &WorkerOptions::default())
+            .await
+            .unwrap()
+    );
+
+    assert_eq!(
+        notion.uploads().await,
+        vec!["video_note_5_rnd.jpg", "video_note_5_rnd.mp4"]
+    );
+    let calls = notion.upload_page_calls().await;
+    assert_eq!(calls.len(), 1);
+    assert_eq!(
+        calls[0].files,
+        vec![
+            ("video_note_5_rnd.jpg".to_string(), "upload-1".to_string()),
+            ("video_note_5_rnd.mp4".to_string(), "upload-2".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn local_video_uploads_thumbnail_then_video() {
+    let pool = setup_pool().await;
+    let ids = load_notion_ids();
+    let notion = RecordingNotion::with_responses(vec![Ok("video-page".into())]);
+
+    let data_dir = tempfile::tempdir().unwrap();
+    let video = data_dir.path().join("media").join("42").join("3_abc.mp4");
+    let thumb = data_dir
+        .path()
+        .join("media")
+        .join("thumbs")
+        .join("3_abc.jpg");
+    std::fs::create_dir_all(video.parent().unwrap()).unwrap();
+    std::fs::create_dir_all(thumb.parent().unwrap()).unwrap();
+    std::fs::write(&video, b"video").unwrap();
+    std::fs::write(&thumb, b"thumb").unwrap();
+
+    let user_id = db::get_or_create_user(&pool, 42, Some("video"), Some("Video"))
+        .await
+        .unwrap();
+    let resource_id = db::insert_resource(
+        &pool,
+        user_id,
+        None,
+        ResourceKind::Video,
+        &video.to_string_lossy(),
+        3,
+    )
+    .await
+    .unwrap();
+
+    assert!(
+        process_next_task(&pool, &notion, &ids, &WorkerOptions::default())
+            .await
+            .unwrap()
+    );
+
+    assert_eq!(notion.uploads().await, vec!["3_abc.jpg", "3_abc.mp4"]);
+    let calls = notion.upload_page_calls().await;
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].order, 1);
+    assert_eq!(
+        calls[0].files,
+        vec![
+            ("3_abc.jpg".to_string(), "upload-1".to_string()),
+            ("3_abc.mp4".to_string(), "upload-2".to_string()),
+        ]
+    );
+    assert!(notion.resource_calls().await.is_empty());
+
+    let stored: Option<String> =
+        sqlx::query_scalar("SELECT notion_page_id FROM resources WHERE id = ?")
+            .bind(resource_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(stored.as_deref(), Some("video-page"));
+}
+
+#[tokio::test]
+async fn upload_concurrency_overlaps_thumbnail_and_video_uploads() {
+    let pool = setup_pool().await;
+    let ids = load_notion_ids();
+
+    let data_dir = tempfile::tempdir().unwrap();
+    let video = data_dir.path().join("media").join("43").join("5_ghi.mp4");
+    let thumb = data_dir
+        .path()
+        .join("media")
+        .join("thumbs")
+        .join("5_ghi.jpg");
+    std::fs::create_dir_all(video.parent().unwrap()).unwrap();
+    std::fs::create_dir_all(thumb.parent().unwrap()).unwrap();
+    std::fs::write(&video, b"video").unwrap();
+    std::fs::write(&thumb, b"thumb").unwrap();
+
+    let user_id = db::get_or_create_user(&pool, 43, None, None).await.unwrap();
+    for (message_id, concurrency) in [(5, 1), (6, 2)] {
+        let notion = RecordingNotion {
+            upload_delay: Duration::from_millis(50),
+            ..Default::default()
+        };
+        db::insert_resource(
+            &pool,
+            user_id,
+            None,
+            ResourceKind::Video,
+            &video.to_string_lossy(),
+            message_id,
+        )
+        .await
+        .unwrap();
+        let opts = WorkerOptions {
+            upload_concurrency: concurrency,
+            ..WorkerOptions::default()
+        };
+        assert!(process_next_task(&pool, &notion, &ids, &opts)
+            .await
+            .unwrap());
+
+        assert_eq!(
+            notion.max_uploads_in_flight.load(Ordering::SeqCst),
+            concurrency
+        );
+        // Files stay in thumbnail-then-video order however the uploads interleave
+        let calls = notion.upload_page_calls().await;
+        assert_eq!(
+            calls[0].files,
+            vec![
+                ("5_ghi.jpg".to_string(), "upload-1".to_string()),
+                ("5_ghi.mp4".to_string(), "upload-2".to_string()),
+            ]
+        );
+    }
+}
+
+#[tokio::test]
+async fn stored_thumb_path_is_uploaded_without_media_dir() {
+    let pool = setup_pool().await;
+    let ids = load_notion_ids();
+    let notion = RecordingNotion::with_responses(vec![Ok("video-page".into())]);
+
+    // Neither path has a `media` ancestor, so only the stored path can find the thumb
+    let data_dir = tempfile::tempdir().unwrap();
+    let video = data_dir.path().join("clips").join("4_def.mp4");
+    let thumb = data_dir.path().join("previews").join("4_def-thumb.jpg");
+    std::fs::create_dir_all(video.parent().unwrap()).unwrap();
+    std::fs::create_dir_all(thumb.parent().unwrap()).unwrap();
+    std::fs::write(&video, b"video").unwrap();
+    std::fs::write(&thumb, b"thumb").unwrap();
+
+    let user_id = db::get_or_create_user(&pool, 80, None, None).await.unwrap();
+    let thumb_str = thumb.to_string_lossy();
+    let origin = db::ResourceOrigin {
+        thumb_path: Some(&thumb_str),
+        ..Default::default()
+    };
+    db::insert_resource_from(
+        &pool,
+        user_id,
+        None,
+        ResourceKind::Video,
+        &video.to_string_lossy(),
+        4,
+        &origin,
+    )
+    .await
+    .unwrap();
+
+    assert!(
+        process_next_task(&pool, &notion, &ids, &WorkerOptions::default())
+            .await
+            .unwrap()
+    );
+
+    assert_eq!(notion.uploads().await, vec!["4_def-thumb.jpg", "4_def.mp4"]);
+    let calls = notion.upload_page_calls().await;
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].files[0].0, "4_def-thumb.jpg");
+}
+
+#[tokio::test]
+async fn routed_resources_use_their_own_database() {
+    let pool = setup_pool().await;
+    let ids = load_notion_ids();
+    let routed = NotionIds {
+        main_db: "MAIN_ROUTED".into(),
+        resource_db: "RES_ROUTED".into(),
+        ..ids.clone()
+    };
+    let opts = WorkerOptions {
+        notion_routes: HashMap::from([(-1001, routed)]),
+        ..WorkerOptions::default()
+    };
+    let notion = RecordingNotion::default();
+
+    let user_id = db::get_or_create_user(&pool, 55, Some("router"), Some("Router"))
+        .await
+        .unwrap();
+    db::insert_resource(&pool, user_id, None, ResourceKind::Text, "default chat", 1)
+        .await
+        .unwrap();
+    let origin = db::ResourceOrigin {
+        notion_route: Some(-1001),
+        ..Default::default()
+    };
+    db::insert_resource_from(
+        &pool,
+        user_id,
+        None,
+        ResourceKind::Text,
+        "routed chat",
+        2,
+        &origin,
+    )
+    .await
+    .unwrap();
+
+    while process_next_task(&pool, &notion, &ids, &opts)
+        .await
+        .unwrap()
+    {}
+
+    let calls = notion.resource_calls().await;
+    assert_eq!(calls.len(), 2);
+    let db_for = |text: &str| {
+        calls
+            .iter()
+            .find(|c| c.text.as_deref() == Some(text))
+            .map(|c| c.resource_db.clone())
+            .unwrap()
+    };
+    assert_eq!(db_for("default chat"), ids.resource_db);
+    assert_eq!(db_for("routed chat"), "RES_ROUTED");
+}
+
+#[tokio::test]
+async fn unknown_outbox_kind_is_dead_lettered() {
+    let pool = setup_pool().await;
+    let ids = load_notion_ids();
+    let notion = RecordingNotion::default();
+
+    let user_id = db::get_or_create_user(&pool, 78, None, None).await.unwrap();
+    sqlx::query(
+        "INSERT INTO outbox (user_id, kind, ref_id, attempt, due_at) VALUES (?, 'push_delete', 1, 0, 0)",
+    )
+    .bind(user_id)
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    assert!(
+        process_next_task(&pool, &notion, &ids, &WorkerOptions::default())
+            .await
+            .unwrap()
+    );
+
+    assert_eq!(db::count_remaining_outbox_tasks(&pool).await.unwrap(), 0);
+    let dead = db::list_dead_letters(&pool).await.unwrap();
+    assert_eq!(dead.len(), 1);
+    assert_eq!(dead[0].kind, "push_delete");
+    assert_eq!(
+        dead[0].last_error.as_deref(),
+        Some("unknown outbox kind: push_delete")
+    );
+    assert!(notion.resource_calls().await.is_empty());
+}
+
+#[tokio::test]
+async fn edited_text_updates_synced_page() {
+    let pool = setup_pool().await;
+    let ids = load_notion_ids();
+    let notion = RecordingNotion::with_responses(vec![Ok("resource-1".into())]);
+    let opts = WorkerOptions::default();
+
+    let user_id = db::get_or_create_user(&pool, 79, None, None).await.unwrap();
+    let origin = db::ResourceOrigin {
+        chat_id: Some(-100),
+        ..Default::default()
+    };
+    let resource_id =
+        db::insert_resource_from(&pool, user_id, None, ResourceKind::Text, "helo", 8, &origin)
+            .await
+            .unwrap()
+            .id;
+    assert!(process_next_task(&pool, &notion, &ids, &opts)
+        .await
+        .unwrap());
+
+    let updated = db::update_resource_text(&pool, user_id, -100, 8, "hello")
+        .await
+        .unwrap();
+    assert_eq!(updated, Some(resource_id));
+    assert!(process_next_task(&pool, &notion, &ids, &opts)
+        .await
+        .unwrap());
+    assert!(!process_next_task(&pool, &notion, &ids, &opts)
+        .await
+        .unwrap());
+
+    let updates = notion.page_updates().await;
+    assert_eq!(updates.len(), 1);
+    assert_eq!(updates[0].0, "resource-1");
+    assert_eq!(
+        updates[0].1[&ids.f_res_text]["rich_text"][0]["text"]["content"],
+        "hello"
+    );
+    // No second page is created for the edit
+    assert_eq!(notion.resource_calls().await.len(), 1);
+}
+
+#[tokio::test]
+async fn reopened_batch_recommit_renames_main_page() {
+    let pool = setup_pool().await;
+    let ids = load_notion_ids();
+    let notion = RecordingNotion::with_responses(vec![
+        Ok("main-1".into()),
+        Ok("res-1".into()),
+        Ok("res-2".into()),
+    ]);
+    let opts = WorkerOptions::default();
+    let drain = || async {
+        while process_next_task(&pool, &notion, &ids, &opts)
+            .await
+            .unwrap()
+        {}
+    };
+
+    let user_id = db::get_or_create_user(&pool, 80, None, None).await.unwrap();
+    let batch_id = db::open_batch(&pool, user_id).await.unwrap();
+    db::insert_resource(&pool, user_id, Some(batch_id), ResourceKind::Text, "a", 1)
+        .await
+        .unwrap();
+    db::commit_batch(&pool, user_id, Some("Trip"))
+        .await
+        .unwrap();
+    drain().await;
+
+    db::reopen_batch(&pool, user_id, batch_id).await.unwrap();
+    db::insert_resource(&pool, user_id, Some(batch_id), ResourceKind::Text, "b", 2)
+        .await
+        .unwrap();
+    db::commit_batch(&pool, user_id, Some("Trip, day 2"))
+        .await
+        .unwrap();
+    drain().await;
+
+    assert_eq!(db::count_remaining_outbox_tasks(&pool).await.unwrap(), 0);
+    assert_eq!(notion.main_calls().await.len(), 1);
+    let resource_calls = notion.resource_calls().await;
+    assert_eq!(resource_calls.len(), 2);
+    assert_eq!(resource_calls[1].parent.as_deref(), Some("main-1"));
+    let updates = notion.page_updates().await;
+    assert_eq!(updates.len(), 1);
+    assert_eq!(updates[0].0, "main-1");
+    assert_eq!(
+        updates[0].1[&ids.f_main_title]["title"][0]["text"]["content"],
+        "Trip, day 2"
+    );
+}
+
+#[tokio::test]
+async fn concurrent_workers_process_each_task_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let url = format!("sqlite://{}/watchbot.db", dir.path().display());
+    let pool = db::init_pool_with(&url, 4).await.unwrap();
+    db::run_migrations(&pool).await.unwrap();
+    let notion = RecordingNotion {
+        delay: Duration::from_millis(50),
+        ..Default::default()
+    };
+    let notion_ids = load_notion_ids();
+    let opts = WorkerOptions::default();
+    let uid = db::get_or_create_user(&pool, 81, None, None).await.unwrap();
+    db::insert_resource(&pool, uid, None, ResourceKind::Text, "once", 1)
+        .await
+        .unwrap();
+
+    let (first, second) = tokio::join!(
+        process_next_task(&pool, &notion, &notion_ids, &opts),
+        process_next_task(&pool, &notion, &notion_ids, &opts)
+    );
+
+    let claimed = [first.unwrap(), second.unwrap()];
+    assert_eq!(claimed.iter().filter(|c| **c).count(), 1);
+    assert_eq!(notion.resource_calls().await.len(), 1);
+    assert_eq!(db::count_remaining_outbox_tasks(&pool).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn untitled_batch_uses_configured_default_title() {
+    let pool = setup_pool().await;
+    let ids = load_notion_ids();
+    let notion = RecordingNotion::default();
+    let opts = WorkerOptions {
+        default_title: "Batch {date}".into(),
+        ..WorkerOptions::default()
+    };
+    let uid = db::get_or_create_user(&pool, 82, None, None).await.unwrap();
+    let batch_id = db::open_batch(&pool, uid).await.unwrap();
+    db::commit_batch(&pool, uid, None).await.unwrap();
+    sqlx::query("UPDATE batches SET created_at = '2024-01-31 23:59:00' WHERE id = ?")
+        .bind(batch_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    assert!(process_next_task(&pool, &notion, &ids, &opts)
+        .await
+        .unwrap());
+
+    let main_calls = notion.main_calls().await;
+    assert_eq!(main_calls.len(), 1);
+    assert_eq!(main_calls[0].title, "Batch 2024-01-31");
+}
+
+#[tokio::test]
+async fn dedupe_main_pages_reuses_page_with_same_title() {
+    let pool = setup_pool().await;
+    let ids = load_notion_ids();
+    let notion = RecordingNotion::default();
+    notion
+        .existing_main_pages
+        .lock()
+        .await
+        .insert("Trip".into(), "existing-page".into());
+    let opts = WorkerOptions {
+        dedupe_main_pages: true,
+        ..WorkerOptions::default()
+    };
+    let uid = db::get_or_create_user(&pool, 84, None, None).await.unwrap();
+    let batch_id = db::open_batch(&pool, uid).await.unwrap();
+    db::commit_batch(&pool, uid, Some("Trip")).await.unwrap();
+
+    assert!(process_next_task(&pool, &notion, &ids, &opts)
+        .await
+        .unwrap());
+
+    assert!(notion.main_calls().await.is_empty());
+    let stored: Option<String> =
+        sqlx::query_scalar("SELECT notion_page_id FROM batches WHERE id = ?")
+            .bind(batch_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(stored.as_deref(), Some("existing-page"));
+}
+
+#[tokio::test]
+async fn dedupe_main_pages_skips_default_titles() {
+    let pool = setup_pool().await;
+    let ids = load_notion_ids();
+    let notion = RecordingNotion::with_responses(vec![Ok("main-1".into()), Ok("main-2".into())]);
+    notion
+        .existing_main_pages
+        .lock()
+        .await
+        .insert("Untitled".into(), "other-batch".into());
+    let opts = WorkerOptions {
+        dedupe_main_pages: true,
+        ..WorkerOptions::default()
+    };
+    let uid = db::get_or_create_user(&pool, 85, None, None).await.unwrap();
+    for title in [None, Some("Untitled")] {
+        db::open_batch(&pool, uid).await.unwrap();
+        db::commit_batch(&pool, uid, title).await.unwrap();
+        assert!(process_next_task(&pool, &notion, &ids, &opts)
+            .await
+            .unwrap());
+    }
+
+    assert_eq!(notion.main_calls().await.len(), 2);
+    let stored: Vec<Option<String>> =
+        sqlx::query_scalar("SELECT notion_page_id FROM batches ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(
+        stored,
+        vec![Some("main-1".to_string()), Some("main-2".to_string())]
+    );
+}
+
+/// Creates main pages that read back with `title`, whatever was sent.
+/// Numbers each created main page and reads every page back with `title`.
+struct ReadBackNotion {
+    title: std::sync::Mutex<&'static str>,
+    created: AtomicUsize,
+}
+
+impl ReadBackNotion {
+    fn new(title: &'static str) -> Self {
+        Self {
+            title: std::sync::Mutex::new(title),
+            created: AtomicUsize::new(0),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl NotionService for ReadBackNotion {
+    async fn create_main_page(
+        &self,
+        _ids: &NotionIds,
+        _title: &str,
+        _icon_emoji: Option<&str>,
+        _cover_url: Option<&str>,
+    ) -> Result<String> {
+        let n = self.created.fetch_add(1, Ordering::SeqCst) + 1;
+        Ok(format!("main-page-{}", n))
+    }
+
+    async fn create_resource_page(
+        &self,
+        _ids: &NotionIds,
+        _parent_main_page_id: Option<&str>,
+        _order: i64,
+        _text: Option<&str>,
+        _media_name: Option<&str>,
+        _media_url: Option<&str>,
+        _meta: &ResourceMeta,
+    ) -> Result<String> {
+        Ok("resource-page".into())
+    }
+
+    async fn retrieve_page(&self, page_id: &str) -> Result<RetrievePageResp> {
+        Ok(serde_json::from_value(serde_json::json!({
+            "id": page_id,
+            "properties": {
+                "Name": {
+                    "id": "title",
+                    "type": "title",
+                    "title": [ { "plain_text": *self.title.lock().unwrap() } ]
+                }
+            }
+        }))?)
+    }
+}
+
+#[tokio::test]
+async fn verify_after_create_retries_main_page_with_wrong_title() {
+    let pool = setup_pool().await;
+    let ids = load_notion_ids();
+    let opts = WorkerOptions {
+        verify_after_create: true,
+        ..WorkerOptions::default()
+    };
+    let uid = db::get_or_create_user(&pool, 94, None, None).await.unwrap();
+    let batch_id = db::open_batch(&pool, uid).await.unwrap();
+    db::commit_batch(&pool, uid, Some("Trip")).await.unwrap();
+    let stored_page = || async {
+        sqlx::query_scalar::<_, Option<String>>("SELECT notion_page_id FROM batches WHERE id = ?")
+            .bind(batch_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+    };
+
+    let notion = ReadBackNotion::new("Untitled");
+    assert!(process_next_task(&pool, &notion, &ids, &opts)
+        .await
+        .unwrap());
+    assert_eq!(stored_page().await, None);
+    let (attempt, last_error): (i64, Option<String>) = sqlx::query_as(
+        "SELECT attempt, last_error FROM outbox WHERE kind = 'push_batch' AND ref_id = ?",
+    )
+    .bind(batch_id)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(attempt, 1);
+    assert!(last_error.unwrap().contains("expected \"Trip\""));
+
+    // Once the title reads back as sent, the page from the first attempt is
+    // marked synced without creating another one
+    sqlx::query("UPDATE outbox SET due_at = 0")
+        .execute(&pool)
+        .await
+        .unwrap();
+    *notion.title.lock().unwrap() = "Trip";
+    assert!(process_next_task(&pool, &notion, &ids, &opts)
+        .await
+        .unwrap());
+    assert_eq!(stored_page().await.as_deref(), Some("main-page-1"));
+    assert_eq!(notion.created.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn captioned_photo_syncs_as_one_page_with_text_and_media() {
+    let pool = setup_pool().await;
+    let ids = load_notion_ids();
+    let notion = RecordingNotion::default();
+    let uid = db::get_or_create_user(&pool, 92, None, None).await.unwrap();
+    let origin = db::ResourceOrigin {
+        media_url: Some("https://cdn.example/sunset.jpg"),
+        text: Some("Sunset"),
+        ..Default::default()
+    };
+    db::insert_resource_from(
+        &pool,
+        uid,
+        None,
+        ResourceKind::Photo,
+        "missing.jpg",
+        3,
+        &origin,
+    )
+    .await
+    .unwrap();
+
+    while process_next_task(&pool, &notion, &ids, &WorkerOptions::default())
+        .await
+        .unwrap()
+    {}
+
+    let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM resources")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(stored, 1);
+    let calls = notion.resource_calls().await;
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].text.as_deref(), Some("Sunset"));
+    assert_eq!(
+        calls[0].media_url.as_deref(),
+        Some("https://cdn.example/sunset.jpg")
+    );
+}
+
+#[tokio::test]
+async fn standalone_caption_names_the_media() {
+    let pool = setup_pool().await;
+    let ids = load_notion_ids();
+    let notion = RecordingNotion::default();
+    let uid = db::get_or_create_user(&pool, 93, None, None).await.unwrap();
+    let batch_id = db::open_batch(&pool, uid).await.unwrap();
+    for (message_id, batch, caption) in [
+        (1, None, "Sunset at the pier\nshot on film"),
+        (2, Some(batch_id), "Batched"),
+    ] {
+        let origin = db::ResourceOrigin {
+            media_url: Some("https://cdn.example/sunset.jpg"),
+            text: Some(caption),
+            unique_id: Some(caption),
+            ..Default::default()
+        };
+        db::insert_resource_from(
+            &pool,
+            uid,
+            batch,
+            ResourceKind::Photo,
+            "missing.jpg",
+            message_id,
+            &origin,
+        )
+        .await
+        .unwrap();
+    }
+    db::commit_batch(&pool, uid, Some("Trip")).await.unwrap();
+
+    while process_next_task(&pool, &notion, &ids, &WorkerOptions::default())
+        .await
+        .unwrap()
+    {}
+
+    let calls = notion.resource_calls().await;
+    assert_eq!(calls.len(), 2);
+    let standalone = calls.iter().find(|c| c.parent.is_none()).unwrap();
+    assert_eq!(standalone.media_name.as_deref(), Some("Sunset at the pier"));
+    // Batched media keeps its default name under the batch's main page
+    let batched = calls.iter().find(|c| c.parent.is_some()).unwrap();
+    assert_eq!(batched.media_name, None);
+}
+
+#[tokio::test]
+async fn first_photo_url_becomes_main_page_cover_with_icon() {
+    let pool = setup_pool().await;
+    let ids = load_notion_ids();
+    let notion = RecordingNotion::default();
+    let opts = WorkerOptions {
+        main_page_icon: Some("📦".into()),
+        ..WorkerOptions::default()
+    };
+    let uid = db::get_or_create_user(&pool, 91, None, None).await.unwrap();
+    let batch_id = db::open_batch(&pool, uid).await.unwrap();
+    let origin = db::ResourceOrigin {
+        media_url: Some("https://cdn.example/cover.jpg"),
+        ..Default::default()
+    };
+    db::insert_resource_from(
+        &pool,
+        uid,
+        Some(batch_id),
+        ResourceKind::Photo,
+        "missing.jpg",
+        1,
+        &origin,
+    )
+    .await
+    .unwrap();
+    db::commit_batch(&pool, uid, Some("Album")).await.unwrap();
+
+    while process_next_task(&pool, &notion, &ids, &opts)
+        .await
+        .unwrap()
+    {}
+
+    let main_calls = notion.main_calls().await;
+    assert_eq!(main_calls.len(), 1);
+    assert_eq!(main_calls[0].icon_emoji.as_deref(), Some("📦"));
+    assert_eq!(
+        main_calls[0].cover_url.as_deref(),
+        Some("https://cdn.example/cover.jpg")
+    );
+    assert!(notion.cover_updates().await.is_empty());
+}
+
+#[tokio::test]
+async fn uploaded_first_photo_is_set_as_cover_after_sync() {
+    let pool = setup_pool().await;
+    let ids = load_notion_ids();
+    let notion =
+        RecordingNotion::with_responses(vec![Ok("main-page".into()), Ok("photo-page".into())]);
+
+    let data_dir = tempfile::tempdir().unwrap();
+    let photo = data_dir.path().join("1_cover.jpg");
+    std::fs::write(&photo, b"jpeg").unwrap();
+
+    let uid = db::get_or_create_user(&pool, 92, None, None).await.unwrap();
+    let batch_id = db::open_batch(&pool, uid).await.unwrap();
+    db::insert_resource(
+        &pool,
+        uid,
+        Some(batch_id),
+        ResourceKind::Photo,
+        &photo.to_string_lossy(),
+        1,
+    )
+    .await
+    .unwrap();
+    db::insert_resource(&pool, uid, Some(batch_id), ResourceKind::Text, "caption", 2)
+        .await
+        .unwrap();
+    db::commit_batch(&pool, uid, Some("Album")).await.unwrap();
+
+    while process_next_task(&pool, &notion, &ids, &WorkerOptions::default())
+        .await
+        .unwrap()
+    {}
+
+    let main_calls = notion.main_calls().await;
+    assert_eq!(main_calls.len(), 1);
+    assert_eq!(main_calls[0].cover_url, None);
+    assert_eq!(
+        notion.cover_updates().await,
+        vec![("main-page".to_string(), "upload-1".to_string())]
+    );
+}
+
+#[tokio::test]
+async fn auto_media_mode_links_large_files_and_uploads_when_there_is_no_url() {
+    let pool = setup_pool().await;
+    let ids = load_notion_ids();
+    let notion = RecordingNotion::default();
+    let opts = WorkerOptions {
+        media_mode: config::MediaMode::Auto,
+        media_upload_max_bytes: 1024,
+        ..WorkerOptions::default()
+    };
+
+    let data_dir = tempfile::tempdir().unwrap();
+    let small = data_dir.path().join("1_small.jpg");
+    let large = data_dir.path().join("2_large.jpg");
+    let unlinked = data_dir.path().join("3_unlinked.jpg");
+    std::fs::write(&small, vec![0u8; 1024]).unwrap();
+    std::fs::write(&large, vec![0u8; 1025]).unwrap();
+    std::fs::write(&unlinked, vec![0u8; 1025]).unwrap();
+
+    let uid = db::get_or_create_user(&pool, 92, None, None).await.unwrap();
+    for (path, media_url) in [
+        (&small, Some("https://cdn.example/small.jpg")),
+        (&large, Some("https://cdn.example/large.jpg")),
+        (&unlinked, None),
+    ] {
+        let origin = db::ResourceOrigin {
+            media_url,
+            ..Default::default()
+        };
+        db::insert_resource_from(
+            &pool,
+            uid,
+            None,
+            ResourceKind::Photo,
+            &path.to_string_lossy(),
+            0,
+            &origin,
+        )
+        .await
+        .unwrap();
+    }
+
+    while process_next_task(&pool, &notion, &ids, &opts)
+        .await
+        .unwrap()
+    {}
+
+    // At the threshold the file is uploaded; above it the URL is linked
+    // unless there is none, in which case it is uploaded anyway
+    assert_eq!(
+        notion.uploads().await,
+        vec!["1_small.jpg", "3_unlinked.jpg"]
+    );
+    let linked = notion.resource_calls().await;
+    assert_eq!(linked.len(), 1);
+    assert_eq!(
+        linked[0].media_url.as_deref(),
+        Some("https://cdn.example/large.jpg")
+    );
+    let uploaded: Vec<i64> = sqlx::query_scalar("SELECT media_uploaded FROM resources ORDER BY id")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(uploaded, vec![1, 0, 1]);
+}
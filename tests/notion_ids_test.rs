@@ -0,0 +1,120 @@
+mod common;
+
+use common::{MockResponse, MockServer, RecordedRequest};
+use reqwest::Url;
+use serde_json::json;
+use tg_watchbot::config::{self, Config, NotionRoute};
+use tg_watchbot::notion::NotionClient;
+
+fn client(server: &MockServer) -> NotionClient {
+    NotionClient::with_base_url(
+        "token".into(),
+        "2022-06-28".into(),
+        Url::parse(&server.url).unwrap(),
+    )
+}
+
+fn config(data_dir: &std::path::Path) -> Config {
+    let mut cfg: Config = serde_yaml::from_str(config::example()).unwrap();
+    cfg.app.data_dir = data_dir.to_string_lossy().into_owned();
+    cfg
+}
+
+/// Serve schemas matching `config::example()` (fields by property id), with `text_type` as the type of
+/// the resource "Text" property.
+fn schema_server(
+    text_type: &'static str,
+) -> impl Fn(&RecordedRequest, &str) -> MockResponse + Send + Sync + 'static {
+    move |req, _base| match req.path.rsplit('/').next().unwrap_or_default() {
+        id if id.contains("MAIN") => MockResponse::json(
+            200,
+            json!({
+                "id": id,
+                "title": [],
+                "properties": { "Title": { "id": "title", "type": "title" } }
+            }),
+        ),
+        id if id.contains("RESOURCE") => MockResponse::json(
+            200,
+            json!({
+                "id": id,
+                "title": [],
+                "properties": {
+                    "Main": { "id": "rel-parent", "type": "relation" },
+                    "No": { "id": "res-order", "type": "number" },
+                    "Text": { "id": "res-text", "type": text_type },
+                    "Media": { "id": "res-media", "type": "files" }
+                }
+            }),
+        ),
+        _ => MockResponse::json(404, json!({ "code": "object_not_found" })),
+    }
+}
+
+#[tokio::test]
+async fn resolving_ids_checks_property_types() {
+    let server = MockServer::start(schema_server("title")).await;
+    let td = tempfile::tempdir().unwrap();
+    let cfg = config(td.path());
+
+    let err = client(&server)
+        .resolve_property_ids_cached(&cfg, false)
+        .await
+        .unwrap_err();
+    assert!(format!("{err:#}").contains("change it to 'rich_text'"));
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn cache_hit_skips_schema_fetch() {
+    let server = MockServer::start(schema_server("rich_text")).await;
+    let td = tempfile::tempdir().unwrap();
+    let cfg = config(td.path());
+    let notion = client(&server);
+
+    let fetched = notion
+        .resolve_property_ids_cached(&cfg, false)
+        .await
+        .unwrap();
+    assert_eq!(fetched.f_res_text, "res-text");
+    assert_eq!(server.requests().len(), 2);
+
+    let cached = notion
+        .resolve_property_ids_cached(&cfg, false)
+        .await
+        .unwrap();
+    assert_eq!(cached, fetched);
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn route_ids_are_cached_per_route_mapping() {
+    let server = MockServer::start(schema_server("rich_text")).await;
+    let td = tempfile::tempdir().unwrap();
+    let mut cfg = config(td.path());
+    let mut databases = cfg.notion.databases.clone();
+    databases.main.id = "ROUTE_MAIN".into();
+    databases.resource.id = "ROUTE_RESOURCE".into();
+    cfg.notion.routes.push(NotionRoute {
+        chat_id: -1001,
+        databases,
+    });
+    let notion = client(&server);
+
+    let routes = notion.resolve_route_ids_cached(&cfg, false).await.unwrap();
+    assert_eq!(routes[&-1001].main_db, "ROUTE_MAIN");
+    assert_eq!(routes[&-1001].f_res_text, "res-text");
+    assert_eq!(server.requests().len(), 2);
+
+    let cached = notion.resolve_route_ids_cached(&cfg, false).await.unwrap();
+    assert_eq!(cached, routes);
+    assert_eq!(server.requests().len(), 2);
+
+    cfg.notion.routes[0].chat_id = -1002;
+    let moved = notion.resolve_route_ids_cached(&cfg, false).await.unwrap();
+    assert!(moved.contains_key(&-1002));
+    assert_eq!(server.requests().len(), 4);
+
+    notion.resolve_route_ids_cached(&cfg, true).await.unwrap();
+    assert_eq!(server.requests().len(), 6);
+}
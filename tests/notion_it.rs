@@ -2,7 +2,7 @@ use anyhow::{anyhow, Result};
 use std::path::Path;
 
 use tg_watchbot::config::{self, Config};
-use tg_watchbot::notion::{NotionClient, NotionIds};
+use tg_watchbot::notion::{NotionClient, NotionIds, ResourceMeta};
 
 #[tokio::test]
 #[ignore]
@@ -83,7 +83,9 @@ impl NotionFacade {
 
     /// Create a main page and return its Notion page ID.
     pub async fn create_main_page(&self, title: &str) -> Result<String> {
-        self.client.create_main_page(&self.ids, title).await
+        self.client
+            .create_main_page(&self.ids, title, None, None)
+            .await
     }
 
     /// Create a text resource under the optional main page.
@@ -94,7 +96,15 @@ impl NotionFacade {
         content: &str,
     ) -> Result<String> {
         self.client
-            .create_resource_page(&self.ids, main_page_id, order, Some(content), None, None)
+            .create_resource_page(
+                &self.ids,
+                main_page_id,
+                order,
+                Some(content),
+                None,
+                None,
+                &ResourceMeta::default(),
+            )
             .await
     }
 
@@ -114,6 +124,7 @@ impl NotionFacade {
                 None,
                 Some(name),
                 Some(external_url),
+                &ResourceMeta::default(),
             )
             .await
     }
@@ -143,6 +154,7 @@ impl NotionFacade {
                 None,
                 Some(file_name),
                 Some(&file_upload_id),
+                &ResourceMeta::default(),
             )
             .await
     }
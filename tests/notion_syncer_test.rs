@@ -3,7 +3,8 @@ use std::collections::VecDeque;
 use std::sync::Arc;
 use tg_watchbot::config;
 use tg_watchbot::db;
-use tg_watchbot::notion::{NotionIds, NotionService};
+use tg_watchbot::model::ResourceKind;
+use tg_watchbot::notion::{NotionIds, NotionService, ResourceMeta};
 use tg_watchbot::outbox;
 use tokio::sync::Mutex;
 
@@ -44,7 +45,13 @@ impl RecordingNotion {
 
 #[async_trait::async_trait]
 impl NotionService for RecordingNotion {
-    async fn create_main_page(&self, _ids: &NotionIds, title: &str) -> Result<String> {
+    async fn create_main_page(
+        &self,
+        _ids: &NotionIds,
+        title: &str,
+        _icon_emoji: Option<&str>,
+        _cover_url: Option<&str>,
+    ) -> Result<String> {
         self.main_calls.lock().await.push(title.to_string());
         self.pop_response().await
     }
@@ -57,6 +64,7 @@ impl NotionService for RecordingNotion {
         text: Option<&str>,
         _media_name: Option<&str>,
         _media_url: Option<&str>,
+        _meta: &ResourceMeta,
     ) -> Result<String> {
         self.resource_calls.lock().await.push((
             parent_main_page_id.map(str::to_string),
@@ -86,12 +94,26 @@ async fn test_syncer_logic_with_real_tasks() {
         .unwrap();
     let batch_id = db::open_batch(&pool, user_id).await.unwrap();
 
-    let _r1 = db::insert_resource(&pool, user_id, Some(batch_id), "text", "note1", 10)
-        .await
-        .unwrap();
-    let _r2 = db::insert_resource(&pool, user_id, Some(batch_id), "text", "note2", 11)
-        .await
-        .unwrap();
+    let _r1 = db::insert_resource(
+        &pool,
+        user_id,
+        Some(batch_id),
+        ResourceKind::Text,
+        "note1",
+        10,
+    )
+    .await
+    .unwrap();
+    let _r2 = db::insert_resource(
+        &pool,
+        user_id,
+        Some(batch_id),
+        ResourceKind::Text,
+        "note2",
+        11,
+    )
+    .await
+    .unwrap();
 
     db::commit_batch(&pool, user_id, Some("Test Batch"))
         .await
@@ -115,12 +137,12 @@ async fn test_syncer_logic_with_real_tasks() {
             .unwrap();
     println!("Outbox tasks before processing: {:?}", outbox_tasks);
 
-    let max_backoff = 60i64;
+    let opts = outbox::WorkerOptions::default();
     let mut processed_count = 0;
 
     // Process tasks one by one (simulating syncer logic)
     loop {
-        let processed = outbox::process_next_task(&pool, &notion, &notion_ids, max_backoff)
+        let processed = outbox::process_next_task(&pool, &notion, &notion_ids, &opts)
             .await
             .unwrap();
 
@@ -150,9 +172,6 @@ async fn test_syncer_logic_with_real_tasks() {
 
     assert_eq!(main_calls.len(), 1);
     assert_eq!(resource_calls.len(), 2);
-
-    // The issue: how should we properly track last_processed_id?
-    // Current syncer logic is flawed because deleted tasks can't be tracked this way
 }
 
 #[tokio::test]
@@ -160,7 +179,7 @@ async fn test_syncer_tracks_processed_ids_correctly() {
     let pool = setup_pool().await;
     let cfg = config::load(Some(std::path::Path::new("config.yaml"))).unwrap();
     let notion_ids = cfg.notion_ids();
-    let max_backoff = 60i64;
+    let opts = outbox::WorkerOptions::default();
 
     // Create a mock notion service that succeeds
     let notion = RecordingNotion::with_responses(vec![
@@ -175,12 +194,26 @@ async fn test_syncer_tracks_processed_ids_correctly() {
         .unwrap();
     let batch_id = db::open_batch(&pool, user_id).await.unwrap();
 
-    let _r1 = db::insert_resource(&pool, user_id, Some(batch_id), "text", "note1", 10)
-        .await
-        .unwrap();
-    let _r2 = db::insert_resource(&pool, user_id, Some(batch_id), "text", "note2", 11)
-        .await
-        .unwrap();
+    let _r1 = db::insert_resource(
+        &pool,
+        user_id,
+        Some(batch_id),
+        ResourceKind::Text,
+        "note1",
+        10,
+    )
+    .await
+    .unwrap();
+    let _r2 = db::insert_resource(
+        &pool,
+        user_id,
+        Some(batch_id),
+        ResourceKind::Text,
+        "note2",
+        11,
+    )
+    .await
+    .unwrap();
 
     db::commit_batch(&pool, user_id, Some("Test Batch"))
         .await
@@ -205,7 +238,7 @@ async fn test_syncer_tracks_processed_ids_correctly() {
         if let Some((next_task_id, _, _, _, _)) = db::next_due_outbox(&pool).await.unwrap() {
             println!("About to process task ID: {}", next_task_id);
 
-            let processed = outbox::process_next_task(&pool, &notion, &notion_ids, max_backoff)
+            let processed = outbox::process_next_task(&pool, &notion, &notion_ids, &opts)
                 .await
                 .unwrap();
 
@@ -248,12 +281,88 @@ async fn test_syncer_tracks_processed_ids_correctly() {
     assert_eq!(remaining, 0);
 }
 
+#[tokio::test]
+async fn test_cursor_tracks_highest_completed_task() {
+    let pool = setup_pool().await;
+    let cfg = config::load(Some(std::path::Path::new("config.yaml"))).unwrap();
+    let notion_ids = cfg.notion_ids();
+    let opts = outbox::WorkerOptions::default();
+
+    // The first task fails and stays in backoff; the later ones complete
+    let notion = RecordingNotion::with_responses(vec![
+        Err(anyhow!("transient failure")),
+        Ok("res-2".into()),
+        Ok("res-3".into()),
+        Ok("res-1".into()),
+    ]);
+    let user_id = db::get_or_create_user(&pool, 98, Some("test"), Some("Test"))
+        .await
+        .unwrap();
+    for i in 1..=3 {
+        db::insert_resource(
+            &pool,
+            user_id,
+            None,
+            ResourceKind::Text,
+            &format!("note{}", i),
+            i,
+        )
+        .await
+        .unwrap();
+    }
+    let task_ids: Vec<i64> = sqlx::query_scalar("SELECT id FROM outbox ORDER BY id")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(task_ids.len(), 3);
+
+    while outbox::process_next_task(&pool, &notion, &notion_ids, &opts)
+        .await
+        .unwrap()
+    {}
+
+    let failed: Vec<i64> = sqlx::query_scalar("SELECT id FROM outbox")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(failed.len(), 1);
+    let last_completed = task_ids
+        .iter()
+        .copied()
+        .filter(|id| *id != failed[0])
+        .max()
+        .unwrap();
+    assert_eq!(
+        db::get_last_processed_outbox_id(&pool).await.unwrap(),
+        last_completed
+    );
+
+    // Completing the failed task later never moves the cursor back
+    sqlx::query("UPDATE outbox SET due_at = 0")
+        .execute(&pool)
+        .await
+        .unwrap();
+    assert!(
+        outbox::process_next_task(&pool, &notion, &notion_ids, &opts)
+            .await
+            .unwrap()
+    );
+    assert_eq!(db::count_remaining_outbox_tasks(&pool).await.unwrap(), 0);
+    assert_eq!(
+        db::get_last_processed_outbox_id(&pool).await.unwrap(),
+        last_completed.max(failed[0])
+    );
+
+    db::reset_outbox_cursor(&pool).await.unwrap();
+    assert_eq!(db::get_last_processed_outbox_id(&pool).await.unwrap(), 0);
+}
+
 #[tokio::test]
 async fn test_syncer_respects_failure_threshold() {
     let pool = setup_pool().await;
     let cfg = config::load(Some(std::path::Path::new("config.yaml"))).unwrap();
     let notion_ids = cfg.notion_ids();
-    let max_backoff = 60i64;
+    let opts = outbox::WorkerOptions::default();
 
     // Create a mock notion service that always fails
     let notion = RecordingNotion::with_responses(vec![
@@ -271,7 +380,7 @@ async fn test_syncer_respects_failure_threshold() {
         .unwrap();
 
     // Create a single resource (no batch for simplicity)
-    let _resource_id = db::insert_resource(&pool, user_id, None, "text", "test", 1)
+    let _resource_id = db::insert_resource(&pool, user_id, None, ResourceKind::Text, "test", 1)
         .await
         .unwrap();
 
@@ -283,7 +392,7 @@ async fn test_syncer_respects_failure_threshold() {
         if let Some((task_id, _, _, _, _)) = db::next_due_outbox(&pool).await.unwrap() {
             println!("Processing task {} (attempt {})", task_id, attempts + 1);
 
-            let processed = outbox::process_next_task(&pool, &notion, &notion_ids, max_backoff)
+            let processed = outbox::process_next_task(&pool, &notion, &notion_ids, &opts)
                 .await
                 .unwrap();
 
@@ -308,14 +417,12 @@ async fn test_syncer_respects_failure_threshold() {
                         break;
                     }
 
-                    // Reset due_at to now so we can retry immediately in test
-                    sqlx::query(
-                        "UPDATE outbox SET due_at = datetime('now', '-1 seconds') WHERE id = ?",
-                    )
-                    .bind(task_id)
-                    .execute(&pool)
-                    .await
-                    .unwrap();
+                    // Make the task due again so we can retry immediately in test
+                    sqlx::query("UPDATE outbox SET due_at = 0 WHERE id = ?")
+                        .bind(task_id)
+                        .execute(&pool)
+                        .await
+                        .unwrap();
                 } else {
                     println!(
                         "Task {} completed successfully and was removed from outbox",
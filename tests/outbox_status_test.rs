@@ -0,0 +1,199 @@
+mod common;
+
+use common::{MockResponse, MockServer};
+use reqwest::Url;
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tg_watchbot::config;
+use tg_watchbot::db;
+use tg_watchbot::model::ResourceKind;
+use tg_watchbot::notion::NotionClient;
+use tg_watchbot::outbox::{run_next_task, TaskOutcome, WorkerOptions};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+
+async fn setup_pool() -> sqlx::SqlitePool {
+    let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+    sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+    pool
+}
+
+fn client(server: &MockServer) -> NotionClient {
+    let http = reqwest::Client::builder()
+        .no_proxy()
+        .timeout(Duration::from_secs(5))
+        .build()
+        .unwrap();
+    NotionClient::with_client(
+        http,
+        "token".into(),
+        "2022-06-28".into(),
+        Url::parse(&server.url).unwrap(),
+    )
+}
+
+/// An event's message and the `outbox_id` of its span, if any.
+type CapturedEvent = (String, Option<i64>);
+
+/// Records each crate event's message with the `outbox_id` of its closest
+/// enclosing span that has one.
+#[derive(Clone, Default)]
+struct CaptureOutboxIds(Arc<Mutex<Vec<CapturedEvent>>>);
+
+struct OutboxId(i64);
+
+#[derive(Default)]
+struct Fields {
+    outbox_id: Option<i64>,
+    message: String,
+}
+
+impl Visit for Fields {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        if field.name() == "outbox_id" {
+            self.outbox_id = Some(value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        }
+    }
+}
+
+impl<S> Layer<S> for CaptureOutboxIds
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        if let (Some(outbox_id), Some(span)) = (fields.outbox_id, ctx.span(id)) {
+            span.extensions_mut().insert(OutboxId(outbox_id));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let outbox_id = ctx.event_scope(event).and_then(|scope| {
+            scope
+                .into_iter()
+                .find_map(|span| span.extensions().get::<OutboxId>().map(|id| id.0))
+        });
+        // Only our own lines; sqlx logs its queries outside any task
+        if event.metadata().target().starts_with("tg_watchbot") {
+            self.0.lock().unwrap().push((fields.message, outbox_id));
+        }
+    }
+}
+
+/// Queue one text resource and run a single worker pass against a Notion mock
+/// that answers every request with `status` and error `code`.
+async fn push_against_status(status: u16, code: &'static str) -> (sqlx::SqlitePool, TaskOutcome) {
+    let pool = setup_pool().await;
+    let cfg: config::Config = serde_yaml::from_str(config::example()).unwrap();
+    let ids = cfg.notion_ids();
+    let server = MockServer::start(move |_, _| {
+        MockResponse::json(
+            status,
+            json!({"object": "error", "code": code, "message": "nope"}),
+        )
+    })
+    .await;
+    let notion = client(&server);
+    let opts = WorkerOptions {
+        max_attempts: 5,
+        ..WorkerOptions::default()
+    };
+
+    let user_id = db::get_or_create_user(&pool, 100, Some("status"), Some("Status"))
+        .await
+        .unwrap();
+    db::insert_resource(&pool, user_id, None, ResourceKind::Text, "hello", 1)
+        .await
+        .unwrap();
+
+    let outcome = run_next_task(&pool, &notion, &ids, &opts).await.unwrap();
+    assert_eq!(server.count("POST", "/v1/pages"), 1);
+    (pool, outcome)
+}
+
+#[tokio::test]
+async fn validation_error_is_dead_lettered_immediately() {
+    let (pool, outcome) = push_against_status(400, "validation_error").await;
+    assert_eq!(outcome, TaskOutcome::Rejected);
+
+    assert_eq!(db::count_remaining_outbox_tasks(&pool).await.unwrap(), 0);
+    let dead = db::list_dead_letters(&pool).await.unwrap();
+    assert_eq!(dead.len(), 1);
+    assert_eq!(dead[0].kind, "push_resource");
+    assert_eq!(dead[0].attempt, 1);
+    assert!(dead[0]
+        .last_error
+        .as_deref()
+        .unwrap()
+        .contains("notion error 400"));
+}
+
+#[tokio::test]
+async fn server_error_backs_off() {
+    let (pool, outcome) = push_against_status(503, "service_unavailable").await;
+    assert_eq!(outcome, TaskOutcome::Failed);
+
+    assert!(db::list_dead_letters(&pool).await.unwrap().is_empty());
+    let (attempt, last_error): (i64, Option<String>) =
+        sqlx::query_as("SELECT attempt, last_error FROM outbox")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(attempt, 1);
+    assert!(last_error.unwrap().contains("notion error 503"));
+}
+
+/// A revoked token or a database no longer shared with the integration fails
+/// every task alike: each stays queued and counts toward the breaker.
+#[tokio::test]
+async fn access_errors_back_off_instead_of_dead_lettering() {
+    for (status, code) in [
+        (401, "unauthorized"),
+        (403, "restricted_resource"),
+        (404, "object_not_found"),
+    ] {
+        let (pool, outcome) = push_against_status(status, code).await;
+        assert_eq!(outcome, TaskOutcome::Failed, "{}", status);
+
+        assert!(db::list_dead_letters(&pool).await.unwrap().is_empty());
+        let attempt: i64 = sqlx::query_scalar("SELECT attempt FROM outbox")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(attempt, 1, "{}", status);
+    }
+}
+
+#[tokio::test]
+async fn failing_task_log_lines_carry_outbox_id() {
+    let capture = CaptureOutboxIds::default();
+    let subscriber = tracing_subscriber::registry().with(capture.clone());
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let (pool, _) = push_against_status(503, "service_unavailable").await;
+    let outbox_id: i64 = sqlx::query_scalar("SELECT id FROM outbox")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+    let events = capture.0.lock().unwrap().clone();
+    // From the Notion client up to the worker's backoff decision
+    assert!(events.iter().any(|(m, _)| m.contains("NOTION API REQUEST")));
+    assert!(events.iter().any(|(m, _)| m.contains("outbox task failed")));
+    for (message, id) in &events {
+        assert_eq!(*id, Some(outbox_id), "{}", message);
+    }
+}
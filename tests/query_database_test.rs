@@ -0,0 +1,94 @@
+mod common;
+
+use common::{MockResponse, MockServer};
+use reqwest::Url;
+use serde_json::json;
+use tg_watchbot::notion::{NotionClient, NotionError};
+
+const QUERY_PATH: &str = "/v1/databases/db-1/query";
+
+fn client(server: &MockServer) -> NotionClient {
+    NotionClient::with_base_url(
+        "token".into(),
+        "2022-06-28".into(),
+        Url::parse(&server.url).unwrap(),
+    )
+}
+
+#[tokio::test]
+async fn query_database_follows_cursor() {
+    let server = MockServer::start(|req, _base| {
+        if req.path != QUERY_PATH {
+            return MockResponse::json(404, json!({}));
+        }
+        match req.json().get("start_cursor").and_then(|c| c.as_str()) {
+            None => MockResponse::json(
+                200,
+                json!({ "results": [ { "id": "p1" } ], "has_more": true, "next_cursor": "c2" }),
+            ),
+            Some("c2") => MockResponse::json(
+                200,
+                json!({ "results": [ { "id": "p2" } ], "has_more": false, "next_cursor": null }),
+            ),
+            Some(_) => MockResponse::json(400, json!({ "message": "bad cursor" })),
+        }
+    })
+    .await;
+    let notion = client(&server);
+    let filter = json!({ "property": "Main", "relation": { "contains": "page-1" } });
+    let sorts = json!([ { "property": "No", "direction": "ascending" } ]);
+
+    let first = notion
+        .query_database("db-1", Some(filter.clone()), Some(sorts.clone()), None)
+        .await
+        .unwrap();
+    assert_eq!(first.results[0]["id"], "p1");
+    assert!(first.has_more);
+    assert_eq!(first.next_cursor.as_deref(), Some("c2"));
+
+    let second = notion
+        .query_database(
+            "db-1",
+            Some(filter.clone()),
+            Some(sorts),
+            first.next_cursor.as_deref(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(second.results[0]["id"], "p2");
+    assert!(!second.has_more);
+    assert_eq!(second.next_cursor, None);
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].header("Authorization"), Some("Bearer token"));
+    assert_eq!(requests[0].header("Notion-Version"), Some("2022-06-28"));
+    assert!(requests[0].json().get("start_cursor").is_none());
+    assert_eq!(requests[1].json()["start_cursor"], "c2");
+    assert_eq!(requests[1].json()["filter"], filter);
+}
+
+#[tokio::test]
+async fn query_database_maps_non_2xx_to_error() {
+    let server = MockServer::start(|_req, _base| {
+        MockResponse::json(
+            400,
+            json!({ "object": "error", "code": "validation_error", "message": "bad filter" }),
+        )
+    })
+    .await;
+
+    let err = client(&server)
+        .query_database("db-1", None, None, None)
+        .await
+        .unwrap_err();
+
+    let msg = format!("{:#}", err);
+    assert!(msg.contains("notion query database db-1 failed"), "{}", msg);
+    assert!(msg.contains("bad filter"), "{}", msg);
+    let notion = err.downcast_ref::<NotionError>().unwrap();
+    assert!(matches!(notion, NotionError::Invalid(_)), "{:?}", notion);
+    assert!(notion.rejects_request());
+    assert!(!notion.is_retryable());
+    assert_eq!(server.count("POST", QUERY_PATH), 1);
+}
@@ -1,4 +1,5 @@
 use tg_watchbot::db;
+use tg_watchbot::model::ResourceKind;
 
 async fn setup_pool() -> sqlx::SqlitePool {
     let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
@@ -17,15 +18,36 @@ async fn test_sequence_generation_with_batch() {
     let batch_id = db::open_batch(&pool, user_id).await.unwrap();
 
     // Insert resources in batch - should get sequence 1, 2, 3
-    let r1 = db::insert_resource(&pool, user_id, Some(batch_id), "text", "first", 100)
-        .await
-        .unwrap();
-    let r2 = db::insert_resource(&pool, user_id, Some(batch_id), "text", "second", 200)
-        .await
-        .unwrap();
-    let r3 = db::insert_resource(&pool, user_id, Some(batch_id), "text", "third", 300)
-        .await
-        .unwrap();
+    let r1 = db::insert_resource(
+        &pool,
+        user_id,
+        Some(batch_id),
+        ResourceKind::Text,
+        "first",
+        100,
+    )
+    .await
+    .unwrap();
+    let r2 = db::insert_resource(
+        &pool,
+        user_id,
+        Some(batch_id),
+        ResourceKind::Text,
+        "second",
+        200,
+    )
+    .await
+    .unwrap();
+    let r3 = db::insert_resource(
+        &pool,
+        user_id,
+        Some(batch_id),
+        ResourceKind::Text,
+        "third",
+        300,
+    )
+    .await
+    .unwrap();
 
     // Check sequences
     let sequences: Vec<(i64, Option<i64>, i64)> = sqlx::query_as(
@@ -61,13 +83,13 @@ async fn test_sequence_generation_without_batch() {
         .unwrap();
 
     // Insert resources without batch - should all get sequence 1
-    let r1 = db::insert_resource(&pool, user_id, None, "text", "standalone1", 400)
+    let r1 = db::insert_resource(&pool, user_id, None, ResourceKind::Text, "standalone1", 400)
         .await
         .unwrap();
-    let r2 = db::insert_resource(&pool, user_id, None, "text", "standalone2", 500)
+    let r2 = db::insert_resource(&pool, user_id, None, ResourceKind::Text, "standalone2", 500)
         .await
         .unwrap();
-    let r3 = db::insert_resource(&pool, user_id, None, "text", "standalone3", 600)
+    let r3 = db::insert_resource(&pool, user_id, None, ResourceKind::Text, "standalone3", 600)
         .await
         .unwrap();
 
@@ -106,12 +128,26 @@ async fn test_sequence_generation_multiple_batches() {
 
     // Create first batch
     let batch1_id = db::open_batch(&pool, user_id).await.unwrap();
-    let _r1 = db::insert_resource(&pool, user_id, Some(batch1_id), "text", "batch1_item1", 700)
-        .await
-        .unwrap();
-    let _r2 = db::insert_resource(&pool, user_id, Some(batch1_id), "text", "batch1_item2", 800)
-        .await
-        .unwrap();
+    let _r1 = db::insert_resource(
+        &pool,
+        user_id,
+        Some(batch1_id),
+        ResourceKind::Text,
+        "batch1_item1",
+        700,
+    )
+    .await
+    .unwrap();
+    let _r2 = db::insert_resource(
+        &pool,
+        user_id,
+        Some(batch1_id),
+        ResourceKind::Text,
+        "batch1_item2",
+        800,
+    )
+    .await
+    .unwrap();
 
     // Commit first batch before opening second
     db::commit_batch(&pool, user_id, Some("Batch 1"))
@@ -120,14 +156,21 @@ async fn test_sequence_generation_multiple_batches() {
 
     // Create second batch
     let batch2_id = db::open_batch(&pool, user_id).await.unwrap();
-    let _r3 = db::insert_resource(&pool, user_id, Some(batch2_id), "text", "batch2_item1", 900)
-        .await
-        .unwrap();
+    let _r3 = db::insert_resource(
+        &pool,
+        user_id,
+        Some(batch2_id),
+        ResourceKind::Text,
+        "batch2_item1",
+        900,
+    )
+    .await
+    .unwrap();
     let _r4 = db::insert_resource(
         &pool,
         user_id,
         Some(batch2_id),
-        "text",
+        ResourceKind::Text,
         "batch2_item2",
         1000,
     )
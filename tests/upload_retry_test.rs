@@ -0,0 +1,68 @@
+mod common;
+
+use common::{MockResponse, MockServer};
+use reqwest::Url;
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tg_watchbot::notion::NotionClient;
+
+const SEND_PATH: &str = "/v1/file_uploads/up-1/send";
+
+async fn upload_server(failures: usize) -> MockServer {
+    let sends = Arc::new(AtomicUsize::new(0));
+    MockServer::start(move |req, base| match req.path.as_str() {
+        "/v1/file_uploads" => MockResponse::json(
+            200,
+            json!({ "id": "up-1", "upload_url": format!("{}v1/file_uploads/up-1/send", base) }),
+        ),
+        SEND_PATH if sends.fetch_add(1, Ordering::SeqCst) < failures => {
+            MockResponse::json(503, json!({ "message": "try again" }))
+        }
+        SEND_PATH => MockResponse::json(200, json!({ "id": "up-1", "status": "uploaded" })),
+        _ => MockResponse::json(404, json!({})),
+    })
+    .await
+}
+
+fn client(server: &MockServer, retries: u32) -> NotionClient {
+    NotionClient::with_base_url(
+        "token".into(),
+        "2022-06-28".into(),
+        Url::parse(&server.url).unwrap(),
+    )
+    .with_upload_retries(retries)
+}
+
+fn sample_file() -> (tempfile::TempDir, std::path::PathBuf) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("photo.jpg");
+    std::fs::write(&path, b"not really a jpeg").unwrap();
+    (dir, path)
+}
+
+#[tokio::test]
+async fn upload_retries_send_step_until_success() {
+    let server = upload_server(2).await;
+    let (_dir, path) = sample_file();
+
+    let id = client(&server, 2).upload_file(&path).await.unwrap();
+
+    assert_eq!(id, "up-1");
+    assert_eq!(server.count("POST", "/v1/file_uploads"), 1);
+    assert_eq!(server.count("POST", SEND_PATH), 3);
+}
+
+#[tokio::test]
+async fn upload_gives_up_after_configured_retries() {
+    let server = upload_server(usize::MAX).await;
+    let (_dir, path) = sample_file();
+
+    let err = client(&server, 1).upload_file(&path).await.unwrap_err();
+
+    let msg = format!("{:#}", err);
+    assert!(msg.contains("after 2 attempt(s)"), "{}", msg);
+    assert!(msg.contains("503"), "{}", msg);
+    assert_eq!(server.count("POST", "/v1/file_uploads"), 1);
+    assert_eq!(server.count("POST", SEND_PATH), 2);
+}